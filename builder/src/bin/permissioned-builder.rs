@@ -1,6 +1,6 @@
 use anyhow::{bail, Context};
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
-use builder::permissioned::init_node;
+use builder::permissioned::{init_node, BuilderContext};
 use clap::Parser;
 use cld::ClDuration;
 use es_version::SEQUENCER_VERSION;
@@ -11,8 +11,17 @@ use hotshot_types::light_client::StateSignKey;
 use hotshot_types::signature_key::BLSPrivKey;
 use hotshot_types::traits::metrics::NoMetrics;
 use hotshot_types::traits::node_implementation::ConsensusTime;
-use sequencer::{BuilderParams, L1Params, NetworkParams};
+use libp2p::Multiaddr;
+use sequencer::{
+    network,
+    options::{
+        config::{self, ConfigArgs},
+        NetworkType,
+    },
+    BuilderParams, L1Params, NetworkParams,
+};
 use snafu::Snafu;
+use std::net::SocketAddr;
 use std::num::NonZeroUsize;
 use std::{collections::HashMap, path::PathBuf, str::FromStr, time::Duration};
 use url::Url;
@@ -70,6 +79,43 @@ pub struct PermissionedBuilderOptions {
     )]
     pub webserver_poll_interval: Duration,
 
+    /// The type of network to use for consensus messages.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_NETWORK",
+        value_enum,
+        default_value = "web"
+    )]
+    pub network: NetworkType,
+
+    /// Local address for the libp2p network to listen on.
+    ///
+    /// Only used if NETWORK is `libp2p` or `combined`.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_LIBP2P_BIND_ADDRESS",
+        default_value = "0.0.0.0:1769"
+    )]
+    pub libp2p_bind_address: SocketAddr,
+
+    /// Address at which other nodes can reach this node over libp2p.
+    ///
+    /// This is reported to the orchestrator, which shares it with other nodes. It defaults to the
+    /// bind address, which is only suitable if this node is reachable at that address.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_LIBP2P_ADVERTISE_ADDRESS")]
+    pub libp2p_advertise_address: Option<SocketAddr>,
+
+    /// Peers to bootstrap the libp2p network from.
+    ///
+    /// Comma-separated list of multiaddrs, each ending in a `/p2p/<peer id>` component. If not
+    /// provided, the bootstrap nodes from the orchestrator's network config are used.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_LIBP2P_BOOTSTRAP_NODES",
+        value_delimiter = ','
+    )]
+    pub libp2p_bootstrap_nodes: Vec<Multiaddr>,
+
     /// Path to file containing private keys.
     ///
     /// The file should follow the .env format, with two keys:
@@ -86,7 +132,8 @@ pub struct PermissionedBuilderOptions {
     #[clap(
         long,
         env = "ESPRESSO_BUILDER_PRIVATE_STAKING_KEY",
-        conflicts_with = "key_file"
+        conflicts_with = "key_file",
+        hide_env_values = true
     )]
     pub private_staking_key: Option<BLSPrivKey>,
 
@@ -96,7 +143,8 @@ pub struct PermissionedBuilderOptions {
     #[clap(
         long,
         env = "ESPRESSO_BUILDER_PRIVATE_STATE_KEY",
-        conflicts_with = "key_file"
+        conflicts_with = "key_file",
+        hide_env_values = true
     )]
     pub private_state_key: Option<StateSignKey>,
 
@@ -104,7 +152,7 @@ pub struct PermissionedBuilderOptions {
     ///
     /// This is the address fees will be charged to.
     /// It must be funded with ETH in the Espresso fee ledger
-    #[clap(long, env = "ESPRESSO_BUILDER_ETH_MNEMONIC")]
+    #[clap(long, env = "ESPRESSO_BUILDER_ETH_MNEMONIC", hide_env_values = true)]
     pub eth_mnemonic: String,
 
     /// Index of a funded account derived from eth-mnemonic.
@@ -138,6 +186,9 @@ pub struct PermissionedBuilderOptions {
     /// Url a builder can use to stream hotshot events
     #[clap(long, env = "ESPRESSO_SEQUENCER_HOTSHOT_EVENTS_PROVIDER")]
     pub hotshot_events_streaming_server_url: Url,
+
+    #[clap(flatten)]
+    pub config: ConfigArgs,
 }
 
 #[derive(Clone, Debug, Snafu)]
@@ -180,8 +231,15 @@ async fn main() -> anyhow::Result<()> {
     setup_logging();
     setup_backtrace();
 
-    let opt = PermissionedBuilderOptions::parse();
+    let opt = config::parse::<PermissionedBuilderOptions>();
+    match opt.network {
+        NetworkType::Web => run::<network::Web>(opt).await,
+        NetworkType::Libp2p => run::<network::Libp2p>(opt).await,
+        NetworkType::Combined => run::<network::Combined>(opt).await,
+    }
+}
 
+async fn run<N: network::Init>(opt: PermissionedBuilderOptions) -> anyhow::Result<()> {
     let (private_staking_key, private_state_key) = opt.private_keys()?;
 
     let l1_params = L1Params {
@@ -206,6 +264,10 @@ async fn main() -> anyhow::Result<()> {
         private_staking_key: private_staking_key.clone(),
         private_state_key,
         state_peers: opt.state_peers,
+        snapshot_trusted_url: None,
+        libp2p_bind_address: opt.libp2p_bind_address,
+        libp2p_advertise_address: opt.libp2p_advertise_address,
+        libp2p_bootstrap_nodes: opt.libp2p_bootstrap_nodes,
     };
 
     let sequencer_version = SEQUENCER_VERSION;
@@ -215,7 +277,7 @@ async fn main() -> anyhow::Result<()> {
     let bootstrapped_view = ViewNumber::new(opt.view_number);
 
     // it will internally spawn the builder web server
-    let ctx = init_node(
+    let ctx: BuilderContext<N, _> = init_node(
         network_params,
         &NoMetrics,
         builder_params,
@@ -10,7 +10,10 @@ use hotshot_types::data::ViewNumber;
 use hotshot_types::light_client::StateSignKey;
 use hotshot_types::signature_key::BLSPrivKey;
 use hotshot_types::traits::node_implementation::ConsensusTime;
-use sequencer::{BuilderParams, L1Params};
+use sequencer::{
+    options::config::{self, ConfigArgs},
+    BuilderParams, L1Params,
+};
 use snafu::Snafu;
 use std::num::NonZeroUsize;
 use std::{collections::HashMap, path::PathBuf, str::FromStr, time::Duration};
@@ -68,7 +71,8 @@ pub struct NonPermissionedBuilderOptions {
     #[clap(
         long,
         env = "ESPRESSO_BUILDER_PRIVATE_STAKING_KEY",
-        conflicts_with = "key_file"
+        conflicts_with = "key_file",
+        hide_env_values = true
     )]
     pub private_staking_key: Option<BLSPrivKey>,
 
@@ -78,7 +82,8 @@ pub struct NonPermissionedBuilderOptions {
     #[clap(
         long,
         env = "ESPRESSO_BUILDER_PRIVATE_STATE_KEY",
-        conflicts_with = "key_file"
+        conflicts_with = "key_file",
+        hide_env_values = true
     )]
     pub private_state_key: Option<StateSignKey>,
 
@@ -86,7 +91,7 @@ pub struct NonPermissionedBuilderOptions {
     ///
     /// This is the address fees will be charged to.
     /// It must be funded with ETH in the Espresso fee ledger
-    #[clap(long, env = "ESPRESSO_BUILDER_ETH_MNEMONIC")]
+    #[clap(long, env = "ESPRESSO_BUILDER_ETH_MNEMONIC", hide_env_values = true)]
     pub eth_mnemonic: String,
 
     /// Index of a funded account derived from eth-mnemonic.
@@ -116,6 +121,9 @@ pub struct NonPermissionedBuilderOptions {
     /// BUILDER CHANNEL CAPACITY
     #[clap(short, long, env = "BUILDER_CHANNEL_CAPACITY")]
     pub channel_capacity: NonZeroUsize,
+
+    #[clap(flatten)]
+    pub config: ConfigArgs,
 }
 
 #[derive(Clone, Debug, Snafu)]
@@ -159,7 +167,7 @@ async fn main() -> anyhow::Result<()> {
     setup_logging();
     setup_backtrace();
 
-    let opt = NonPermissionedBuilderOptions::parse();
+    let opt = config::parse::<NonPermissionedBuilderOptions>();
 
     let sequencer_version = SEQUENCER_VERSION;
 
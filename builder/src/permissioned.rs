@@ -8,10 +8,7 @@ use futures::{
     stream::{Stream, StreamExt},
 };
 use hotshot::{
-    traits::{
-        election::static_committee::{GeneralStaticCommittee, StaticElectionConfig},
-        implementations::{NetworkingMetricsValue, WebServerNetwork},
-    },
+    traits::election::static_committee::{GeneralStaticCommittee, StaticElectionConfig},
     types::{SignatureKey, SystemContextHandle},
     HotShotInitializer, Memberships, Networks, SystemContext,
 };
@@ -66,15 +63,15 @@ use sequencer::{
     context::{Consensus, SequencerContext},
     l1_client::L1Client,
     network,
-    persistence::SequencerPersistence,
+    persistence::{no_storage::NoStorage, PersistentStorage, SequencerPersistence},
     state::FeeAccount,
     state::ValidatedState,
     state_signature::{static_stake_table_commitment, StateSigner},
     BuilderParams, L1Params, NetworkParams, Node, NodeState, PrivKey, PubKey, SeqTypes,
 };
+use std::marker::PhantomData;
+use std::thread::Builder;
 use std::{alloc::System, any, fmt::Debug, mem};
-use std::{marker::PhantomData, net::IpAddr};
-use std::{net::Ipv4Addr, thread::Builder};
 use tide_disco::{app, method::ReadState, App, Url};
 use versioned_binary_serialization::version::StaticVersionType;
 
@@ -117,7 +114,7 @@ pub struct BuilderContext<N: network::Type, Ver: StaticVersionType + 'static> {
 }
 
 #[allow(clippy::too_many_arguments)]
-pub async fn init_node<Ver: StaticVersionType + 'static>(
+pub async fn init_node<N: network::Init, Ver: StaticVersionType + 'static>(
     network_params: NetworkParams,
     metrics: &dyn Metrics,
     builder_params: BuilderParams,
@@ -128,24 +125,22 @@ pub async fn init_node<Ver: StaticVersionType + 'static>(
     bootstrapped_view: ViewNumber,
     channel_capacity: NonZeroUsize,
     bind_version: Ver,
-) -> anyhow::Result<BuilderContext<network::Web, Ver>> {
+) -> anyhow::Result<BuilderContext<N, Ver>> {
     let validator_args = ValidatorArgs {
-        url: network_params.orchestrator_url,
-        advertise_address: None,
+        url: network_params.orchestrator_url.clone(),
+        advertise_address: network_params.libp2p_advertise_address,
         network_config_file: None,
     };
-    // This "public" IP only applies to libp2p network configurations, so we can supply any value here
-    let _public_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
     // Orchestrator client
     let orchestrator_client = OrchestratorClient::new(validator_args);
 
     let private_staking_key = network_params.private_staking_key.clone();
     let _public_staking_key = BLSPubKey::from_private(&private_staking_key);
-    let state_key_pair = StateKeyPair::from_sign_key(network_params.private_state_key);
+    let state_key_pair = StateKeyPair::from_sign_key(network_params.private_state_key.clone());
 
     let my_config = ValidatorConfig {
         public_key: BLSPubKey::from_private(&network_params.private_staking_key),
-        private_key: network_params.private_staking_key,
+        private_key: network_params.private_staking_key.clone(),
         stake_value: 1,
         state_key_pair: state_key_pair.clone(),
     };
@@ -176,27 +171,7 @@ pub async fn init_node<Ver: StaticVersionType + 'static>(
     tracing::info!("loaded config, we are node {}", config.node_index);
 
     // Initialize networking.
-    let networks = Networks {
-        da_network: Arc::new(WebServerNetwork::create(
-            network_params.da_server_url,
-            network_params.webserver_poll_interval,
-            my_config.public_key,
-            true,
-        )),
-        quorum_network: Arc::new(WebServerNetwork::create(
-            network_params.consensus_server_url,
-            network_params.webserver_poll_interval,
-            my_config.public_key,
-            false,
-        )),
-        _pd: Default::default(),
-    };
-
-    // The web server network doesn't have any metrics. By creating and dropping a
-    // `NetworkingMetricsValue`, we ensure the networking metrics are created, but just not
-    // populated, so that monitoring software built to work with network-related metrics doesn't
-    // crash horribly just because we're not using the P2P network yet.
-    let _ = NetworkingMetricsValue::new(metrics);
+    let networks = N::connect(&network_params, &config, &my_config, metrics).await?;
 
     // creating the instance state without any builder mnemonic
     let wallet = MnemonicBuilder::<English>::default()
@@ -294,7 +269,8 @@ pub async fn init_hotshot<N: network::Type, Ver: StaticVersionType + 'static>(
     };
     let state_key_pair = config.my_own_validator_config.state_key_pair.clone();
 
-    let da_storage = Default::default();
+    // The builder does not persist consensus state, it always starts from genesis.
+    let da_storage = PersistentStorage::new(NoStorage);
     tracing::debug!("Before hotshot handle initialisation");
     let hotshot_handle = SystemContext::init(
         config.my_own_validator_config.public_key,
@@ -0,0 +1,565 @@
+//! A client for rollups which derive their state from the Espresso sequencer.
+//!
+//! A rollup node follows the sequencer chain one block at a time. For each block, it needs the
+//! transactions in its own namespace, a proof that these are exactly the transactions the block
+//! commits to, and the L1 blocks referenced by the header, which determine the deposits the rollup
+//! must process. [`SequencerClient`] wraps the query service API with typed methods for these
+//! routes, checks every namespace proof against its block header before returning anything, and
+//! can follow the chain from any height, reconnecting and resuming where it left off if the
+//! connection to the query service drops.
+
+use async_std::task::sleep;
+use commit::{Commitment, Committable};
+use futures::{
+    future,
+    stream::{self, BoxStream, StreamExt, TryStreamExt},
+};
+use hotshot_query_service::availability::LeafQueryData;
+use hotshot_types::vid::{vid_scheme, VidSchemeType};
+use jf_primitives::vid::VidScheme;
+use sequencer::{
+    api::endpoints::{NamespaceProofQueryData, MAX_NAMESPACE_PROOF_RANGE},
+    block::payload::NamespaceProof,
+    Header, L1BlockInfo, Leaf, NamespaceId, SeqTypes, Transaction,
+};
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::time::Duration;
+use surf_disco::Url;
+use versioned_binary_serialization::version::StaticVersionType;
+
+pub use hotshot_query_service::Error as QueryError;
+
+/// How long to wait before reconnecting or retrying a failed request while following the chain.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// How many chunks of a large range to fetch at once.
+const RANGE_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    /// A request to the query service failed.
+    #[snafu(display("request failed: {source}"))]
+    Request { source: QueryError },
+
+    /// The query service returned a namespace proof which does not match the block header.
+    #[snafu(display("invalid namespace proof for block {height}"))]
+    InvalidProof { height: u64 },
+
+    /// The query service returned a leaf which is not the one its QC signs.
+    #[snafu(display("leaf {height} is not signed by its QC"))]
+    InvalidQc { height: u64 },
+
+    /// The query service returned a leaf which does not extend the leaf before it.
+    #[snafu(display("leaf {height} does not extend its parent"))]
+    BrokenChain { height: u64 },
+
+    /// The query service returned data for a different block than the one requested.
+    #[snafu(display("expected block {expected}, got block {actual}"))]
+    WrongBlock { expected: u64, actual: u64 },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The transactions in one namespace of a block, verified against the block header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamespaceBlock {
+    pub header: Header,
+    pub transactions: Vec<Transaction>,
+}
+
+impl NamespaceBlock {
+    /// The height of this block in the sequencer chain.
+    pub fn height(&self) -> u64 {
+        self.header.height
+    }
+
+    /// The L1 head at the time this block was sequenced.
+    pub fn l1_head(&self) -> u64 {
+        self.header.l1_head
+    }
+
+    /// The latest finalized L1 block at the time this block was sequenced, if any.
+    pub fn l1_finalized(&self) -> Option<&L1BlockInfo> {
+        self.header.l1_finalized.as_ref()
+    }
+}
+
+/// A client for the query service of a sequencer node.
+#[derive(Clone)]
+pub struct SequencerClient<Ver: StaticVersionType> {
+    inner: surf_disco::Client<QueryError, Ver>,
+}
+
+impl<Ver: StaticVersionType + 'static> SequencerClient<Ver> {
+    pub fn new(url: Url) -> Self {
+        Self {
+            inner: surf_disco::Client::new(url),
+        }
+    }
+
+    /// Wait for the query service to become available, up to `timeout`.
+    ///
+    /// Returns whether the service is available.
+    pub async fn connect(&self, timeout: Option<Duration>) -> bool {
+        self.inner.connect(timeout).await
+    }
+
+    /// The number of blocks currently in the chain.
+    pub async fn block_height(&self) -> Result<u64> {
+        self.inner
+            .get("status/block-height")
+            .send()
+            .await
+            .context(RequestSnafu)
+    }
+
+    /// Get the header of the block at `height`.
+    pub async fn header(&self, height: u64) -> Result<Header> {
+        let header: Header = self
+            .inner
+            .get(&format!("availability/header/{height}"))
+            .send()
+            .await
+            .context(RequestSnafu)?;
+        if header.height != height {
+            return WrongBlockSnafu {
+                expected: height,
+                actual: header.height,
+            }
+            .fail();
+        }
+        Ok(header)
+    }
+
+    /// Submit a transaction to the sequencer.
+    pub async fn submit(&self, txn: &Transaction) -> Result<Commitment<Transaction>> {
+        self.inner
+            .post("submit/submit")
+            .body_json(txn)
+            .context(RequestSnafu)?
+            .send()
+            .await
+            .context(RequestSnafu)
+    }
+
+    /// Get the transactions in namespace `ns` of the block at `height`.
+    ///
+    /// The transactions are verified against the header of the block.
+    pub async fn namespace(&self, height: u64, ns: NamespaceId) -> Result<NamespaceBlock> {
+        let header = self.header(height).await?;
+        self.namespace_in(header, ns).await
+    }
+
+    /// Get the transactions in namespace `ns` for each block in `[from, until)`.
+    ///
+    /// The range is split into chunks of at most [`MAX_NAMESPACE_PROOF_RANGE`] blocks, which are
+    /// fetched in parallel. Each leaf in the range must be signed by its QC and extend the leaf
+    /// before it, and the transactions are verified against the header of each block. This makes
+    /// the range as trustworthy as its first block; QC signatures are not checked, since that
+    /// requires the stake table.
+    pub async fn namespace_range(
+        &self,
+        ns: NamespaceId,
+        from: u64,
+        until: u64,
+    ) -> Result<Vec<NamespaceBlock>> {
+        let chunks = stream::iter((from..until).step_by(MAX_NAMESPACE_PROOF_RANGE))
+            .map(move |start| {
+                let end = until.min(start + MAX_NAMESPACE_PROOF_RANGE as u64);
+                self.namespace_chunk(ns, start, end)
+            })
+            .buffered(RANGE_CONCURRENCY)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let (leaves, responses): (Vec<_>, Vec<_>) = chunks.into_iter().flatten().unzip();
+        let mut parent: Option<Commitment<Leaf>> = None;
+        for leaf in &leaves {
+            check_parent(leaf, parent)?;
+            parent = Some(leaf.leaf().commit());
+        }
+
+        let headers = leaves
+            .into_iter()
+            .map(|leaf| leaf.leaf().get_block_header().clone())
+            .collect::<Vec<_>>();
+        let vid = responses
+            .iter()
+            .find_map(|res| match &res.proof {
+                NamespaceProof::Existence { vid_common, .. } => {
+                    Some(vid_scheme(VidSchemeType::get_num_storage_nodes(vid_common)))
+                }
+                NamespaceProof::NonExistence { .. } => None,
+            })
+            // If no block contains the namespace, the proofs are checked against the namespace
+            // tables alone, so the VID parameters do not matter.
+            .unwrap_or_else(|| vid_scheme(2));
+        let Some(transactions) = NamespaceProof::verify_range(
+            &vid,
+            ns,
+            responses.iter().map(|res| &res.proof),
+            &headers,
+        ) else {
+            // Find the block which failed, to report it.
+            for (header, res) in headers.into_iter().zip(responses) {
+                verify_namespace(header, ns, res)?;
+            }
+            return InvalidProofSnafu { height: from }.fail();
+        };
+
+        headers
+            .into_iter()
+            .zip(transactions)
+            .zip(responses)
+            .map(|((header, transactions), res)| {
+                if transactions != res.transactions {
+                    return InvalidProofSnafu {
+                        height: header.height,
+                    }
+                    .fail();
+                }
+                Ok(NamespaceBlock {
+                    header,
+                    transactions,
+                })
+            })
+            .collect()
+    }
+
+    /// Fetch the leaves and namespace proofs for `[from, until)`, a range small enough for a
+    /// single request.
+    async fn namespace_chunk(
+        &self,
+        ns: NamespaceId,
+        from: u64,
+        until: u64,
+    ) -> Result<Vec<(LeafQueryData<SeqTypes>, NamespaceProofQueryData)>> {
+        let (leaves, proofs): (Vec<LeafQueryData<SeqTypes>>, Vec<NamespaceProofQueryData>) =
+            future::try_join(
+                self.inner
+                    .get(&format!("availability/leaf/{from}/{until}"))
+                    .send(),
+                self.inner
+                    .get(&format!("availability/block/{from}/{until}/namespace/{ns}"))
+                    .send(),
+            )
+            .await
+            .context(RequestSnafu)?;
+        if leaves.len() != proofs.len() || leaves.len() as u64 != until - from {
+            return WrongBlockSnafu {
+                expected: until,
+                actual: from + leaves.len().min(proofs.len()) as u64,
+            }
+            .fail();
+        }
+        for (expected, leaf) in (from..until).zip(&leaves) {
+            let actual = leaf.leaf().get_height();
+            if actual != expected {
+                return WrongBlockSnafu { expected, actual }.fail();
+            }
+        }
+        Ok(leaves.into_iter().zip(proofs).collect())
+    }
+
+    /// Follow the transactions in namespace `ns`, starting from the block at `from`.
+    ///
+    /// The stream yields one item for every block, in order, including blocks which do not contain
+    /// the namespace, so a rollup can track the L1 blocks referenced by every header. If the
+    /// connection to the query service drops, the stream reconnects and resumes from the next block
+    /// it has not yet yielded. Each leaf must be signed by its QC and extend the leaf of the block
+    /// yielded before it. An error is yielded if a block cannot be verified; the stream then
+    /// retries the same block, so it never skips a block.
+    pub fn subscribe_namespace(
+        &self,
+        ns: NamespaceId,
+        from: u64,
+    ) -> BoxStream<'static, Result<NamespaceBlock>> {
+        let follower = Follower {
+            client: self.clone(),
+            ns,
+            height: from,
+            parent: None,
+            leaves: None,
+            pending: None,
+        };
+        stream::unfold(follower, |mut follower| async move {
+            let res = follower.next().await;
+            Some((res, follower))
+        })
+        .boxed()
+    }
+
+    /// Fetch and verify the transactions in namespace `ns` of the block with `header`.
+    async fn namespace_in(&self, header: Header, ns: NamespaceId) -> Result<NamespaceBlock> {
+        let res = self
+            .inner
+            .get(&format!(
+                "availability/block/{}/namespace/{ns}",
+                header.height
+            ))
+            .send()
+            .await
+            .context(RequestSnafu)?;
+        verify_namespace(header, ns, res)
+    }
+
+    async fn subscribe_leaves(
+        &self,
+        from: u64,
+    ) -> Result<BoxStream<'static, Result<LeafQueryData<SeqTypes>>>> {
+        let leaves = self
+            .inner
+            .socket(&format!("availability/stream/leaves/{from}"))
+            .subscribe::<LeafQueryData<SeqTypes>>()
+            .await
+            .context(RequestSnafu)?;
+        Ok(leaves.map(|res| res.context(RequestSnafu)).boxed())
+    }
+}
+
+/// State of a stream following a namespace.
+struct Follower<Ver: StaticVersionType> {
+    client: SequencerClient<Ver>,
+    ns: NamespaceId,
+    /// The next block to yield.
+    height: u64,
+    /// The commitment of the last leaf we yielded, which the next leaf must extend.
+    parent: Option<Commitment<Leaf>>,
+    /// Stream of leaves starting from `height`, if connected.
+    leaves: Option<BoxStream<'static, Result<LeafQueryData<SeqTypes>>>>,
+    /// The leaf of block `height`, if we have received it but failed to process the block.
+    pending: Option<LeafQueryData<SeqTypes>>,
+}
+
+impl<Ver: StaticVersionType + 'static> Follower<Ver> {
+    async fn next(&mut self) -> Result<NamespaceBlock> {
+        let leaf = match self.pending.take() {
+            Some(leaf) => {
+                // We failed to process this block last time; wait a bit before retrying.
+                sleep(RETRY_DELAY).await;
+                leaf
+            }
+            None => {
+                let leaf = self.next_leaf().await;
+                if let Err(err) = check_parent(&leaf, self.parent) {
+                    // Drop the connection, so that we fetch this block again on the next attempt.
+                    self.leaves = None;
+                    return Err(err);
+                }
+                leaf
+            }
+        };
+
+        let header = leaf.leaf().get_block_header().clone();
+        match self.client.namespace_in(header, self.ns).await {
+            Ok(block) => {
+                self.height += 1;
+                self.parent = Some(leaf.leaf().commit());
+                Ok(block)
+            }
+            Err(err) => {
+                self.pending = Some(leaf);
+                Err(err)
+            }
+        }
+    }
+
+    /// Get the leaf of block `height`, reconnecting as many times as necessary.
+    async fn next_leaf(&mut self) -> LeafQueryData<SeqTypes> {
+        loop {
+            if self.leaves.is_none() {
+                match self.client.subscribe_leaves(self.height).await {
+                    Ok(leaves) => self.leaves = Some(leaves),
+                    Err(err) => {
+                        tracing::warn!(height = self.height, "error subscribing to leaves: {err}");
+                        sleep(RETRY_DELAY).await;
+                        continue;
+                    }
+                }
+            }
+            let leaves = self.leaves.as_mut().unwrap();
+            match leaves.next().await {
+                Some(Ok(leaf)) if leaf.height() == self.height => return leaf,
+                Some(Ok(leaf)) => {
+                    tracing::warn!(
+                        expected = self.height,
+                        actual = leaf.height(),
+                        "received leaf out of order, reconnecting"
+                    );
+                }
+                Some(Err(err)) => {
+                    tracing::warn!(
+                        height = self.height,
+                        "error in leaf stream, reconnecting: {err}"
+                    );
+                }
+                None => {
+                    tracing::warn!(height = self.height, "leaf stream closed, reconnecting");
+                }
+            }
+            self.leaves = None;
+            sleep(RETRY_DELAY).await;
+        }
+    }
+}
+
+/// Check that `leaf` is the one its QC signs, and that it extends the leaf with commitment
+/// `parent`, if there is one.
+fn check_parent(leaf: &LeafQueryData<SeqTypes>, parent: Option<Commitment<Leaf>>) -> Result<()> {
+    let height = leaf.height();
+    if leaf.qc().data.leaf_commit != leaf.leaf().commit() {
+        return InvalidQcSnafu { height }.fail();
+    }
+    if let Some(parent) = parent {
+        if leaf.leaf().get_parent_commitment() != parent {
+            return BrokenChainSnafu { height }.fail();
+        }
+    }
+    Ok(())
+}
+
+/// Check a namespace proof from the query service against the header of its block.
+fn verify_namespace(
+    header: Header,
+    ns: NamespaceId,
+    res: NamespaceProofQueryData,
+) -> Result<NamespaceBlock> {
+    let height = header.height;
+    let vid = match &res.proof {
+        NamespaceProof::Existence { vid_common, .. } => {
+            vid_scheme(VidSchemeType::get_num_storage_nodes(vid_common))
+        }
+        // A proof of non-existence is checked against the namespace table alone, so the VID
+        // parameters do not matter.
+        NamespaceProof::NonExistence { .. } => vid_scheme(2),
+    };
+    let (transactions, proof_ns) = res
+        .proof
+        .verify(&vid, &header.payload_commitment, &header.ns_table)
+        .context(InvalidProofSnafu { height })?;
+    if proof_ns != ns || transactions != res.transactions {
+        return InvalidProofSnafu { height }.fail();
+    }
+    Ok(NamespaceBlock {
+        header,
+        transactions,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use es_version::SequencerVersion;
+    use portpicker::pick_unused_port;
+    use sequencer::{
+        api::{self, options, testing::TestNetwork},
+        persistence,
+    };
+    use tempfile::TempDir;
+
+    #[async_std::test]
+    async fn test_follow_namespace() {
+        setup_logging();
+        setup_backtrace();
+
+        // Start a sequencer network with a query service.
+        let port = pick_unused_port().expect("No ports free");
+        let storage = TempDir::new().unwrap();
+        let _network = TestNetwork::new(
+            api::Options::from(options::Http { port })
+                .submit(Default::default())
+                .query_fs(
+                    Default::default(),
+                    persistence::fs::Options {
+                        path: storage.path().into(),
+                    },
+                ),
+        )
+        .await;
+
+        let client = SequencerClient::<SequencerVersion>::new(
+            format!("http://localhost:{port}").parse().unwrap(),
+        );
+        client.connect(None).await;
+
+        // Follow a namespace from genesis, then submit a transaction to it.
+        let ns = NamespaceId::from(1u64);
+        let txn = Transaction::new(ns, vec![1, 2, 3, 4]);
+        let mut blocks = client.subscribe_namespace(ns, 0);
+        assert_eq!(client.submit(&txn).await.unwrap(), txn.commit());
+
+        // We get every block, in order, until the one containing our transaction.
+        let mut followed = vec![];
+        loop {
+            let block = blocks.next().await.unwrap().unwrap();
+            assert_eq!(block.height(), followed.len() as u64);
+            let found = !block.transactions.is_empty();
+            followed.push(block);
+            if found {
+                break;
+            }
+        }
+        let last = followed.last().unwrap();
+        assert_eq!(last.transactions, vec![txn]);
+
+        // L1 references never go backwards along the chain.
+        for pair in followed.windows(2) {
+            assert!(pair[0].l1_head() <= pair[1].l1_head());
+            assert!(pair[0].l1_finalized() <= pair[1].l1_finalized());
+        }
+
+        // Resuming from the middle of the chain picks up where we ask it to.
+        let resumed = client
+            .subscribe_namespace(ns, last.height())
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&resumed, last);
+
+        // The same blocks can be fetched individually or as a range.
+        assert_eq!(&client.namespace(last.height(), ns).await.unwrap(), last);
+        let range = client
+            .namespace_range(ns, 0, last.height() + 1)
+            .await
+            .unwrap();
+        assert_eq!(range, followed);
+
+        // A proof does not verify against the header of a different block.
+        let res: NamespaceProofQueryData = client
+            .inner
+            .get(&format!(
+                "availability/block/{}/namespace/{ns}",
+                last.height()
+            ))
+            .send()
+            .await
+            .unwrap();
+        let header = client.header(last.height() - 1).await.unwrap();
+        assert!(matches!(
+            verify_namespace(header, ns, res),
+            Err(Error::InvalidProof { .. })
+        ));
+
+        // A leaf must extend the leaf before it.
+        let leaf = |height: u64| {
+            let client = &client;
+            async move {
+                client
+                    .inner
+                    .get::<LeafQueryData<SeqTypes>>(&format!("availability/leaf/{height}"))
+                    .send()
+                    .await
+                    .unwrap()
+            }
+        };
+        let parent = leaf(last.height() - 1).await;
+        let child = leaf(last.height()).await;
+        check_parent(&child, Some(parent.leaf().commit())).unwrap();
+        assert!(matches!(
+            check_parent(&child, Some(child.leaf().commit())),
+            Err(Error::BrokenChain { .. })
+        ));
+    }
+}
@@ -0,0 +1,11 @@
+-- This node's VID share for each undecided view. Rows are deleted once the view is decided.
+CREATE TABLE vid_share (
+    view BIGINT PRIMARY KEY,
+    data BYTEA
+);
+
+-- The DA proposal for each undecided view. Rows are deleted once the view is decided.
+CREATE TABLE da_proposal (
+    view BIGINT PRIMARY KEY,
+    data BYTEA
+);
@@ -0,0 +1,17 @@
+CREATE TABLE high_qc (
+    -- The ID is always set to 0. Setting it explicitly allows us to enforce with every insert or
+    -- update that there is only a single entry in this table: the highest known QC.
+    id INT PRIMARY KEY,
+
+    view BIGINT,
+    data BYTEA
+);
+
+CREATE TABLE undecided_state (
+    -- The ID is always set to 0. Setting it explicitly allows us to enforce with every insert or
+    -- update that there is only a single entry in this table: the latest undecided state.
+    id INT PRIMARY KEY,
+
+    leaves BYTEA,
+    state  BYTEA
+);
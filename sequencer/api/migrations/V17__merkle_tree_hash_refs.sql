@@ -0,0 +1,7 @@
+-- Indexes for finding the nodes which reference a hash, either as the hash of the node itself or of
+-- one of its children. Pruning uses these to find hashes which are no longer referenced by any node.
+CREATE INDEX fee_merkle_tree_hash ON fee_merkle_tree (hash_id);
+CREATE INDEX fee_merkle_tree_children ON fee_merkle_tree USING GIN (children);
+
+CREATE INDEX block_merkle_tree_hash ON block_merkle_tree (hash_id);
+CREATE INDEX block_merkle_tree_children ON block_merkle_tree USING GIN (children);
@@ -1,14 +1,28 @@
-use self::data_source::StateSignatureDataSource;
+use self::{
+    data_source::{AdminDataSource, HealthDataSource, StateSignatureDataSource},
+    endpoints::AccountQueryData,
+};
 use crate::{
-    context::SequencerContext, network, state::ValidatedState, state_signature::StateSigner, Node,
-    SeqTypes,
+    catchup::StateCatchup,
+    context::{SequencerContext, SubmitSwitch},
+    health::{HealthReport, NodeHealth},
+    network,
+    state::ValidatedState,
+    state_signature::StateSigner,
+    Leaf, Node, SeqTypes,
 };
+use anyhow::bail;
 use async_std::sync::Arc;
 use async_trait::async_trait;
 use data_source::{StateDataSource, SubmitDataSource};
 use hotshot::types::SystemContextHandle;
 use hotshot_query_service::data_source::ExtensibleDataSource;
-use hotshot_types::{data::ViewNumber, light_client::StateSignatureRequestBody};
+use hotshot_types::{
+    data::ViewNumber,
+    light_client::StateSignatureRequestBody,
+    utils::{View, ViewInner},
+};
+use std::time::Duration;
 use versioned_binary_serialization::version::StaticVersionType;
 
 pub mod data_source;
@@ -22,6 +36,9 @@ pub use options::Options;
 
 struct State<N: network::Type, Ver: StaticVersionType> {
     state_signer: Arc<StateSigner<Ver>>,
+    health: Arc<NodeHealth<Ver>>,
+    submit_switch: SubmitSwitch,
+    catchup: Arc<dyn StateCatchup>,
     handle: SystemContextHandle<SeqTypes, Node<N>>,
 }
 
@@ -31,6 +48,9 @@ impl<N: network::Type, Ver: StaticVersionType + 'static> From<&SequencerContext<
     fn from(ctx: &SequencerContext<N, Ver>) -> Self {
         Self {
             state_signer: ctx.state_signer(),
+            health: ctx.health(),
+            submit_switch: ctx.submit_switch(),
+            catchup: ctx.catchup(),
             handle: ctx.consensus().clone(),
         }
     }
@@ -41,17 +61,29 @@ impl<N: network::Type, D, Ver: StaticVersionType> SubmitDataSource<N> for Storag
     fn consensus(&self) -> &SystemContextHandle<SeqTypes, Node<N>> {
         self.as_ref().consensus()
     }
+
+    fn submissions_enabled(&self) -> bool {
+        self.as_ref().submissions_enabled()
+    }
 }
 
 impl<N: network::Type, Ver: StaticVersionType> SubmitDataSource<N> for State<N, Ver> {
     fn consensus(&self) -> &SystemContextHandle<SeqTypes, Node<N>> {
         &self.handle
     }
+
+    fn submissions_enabled(&self) -> bool {
+        self.submit_switch.is_enabled()
+    }
 }
 
 impl<N: network::Type, D: Send + Sync, Ver: StaticVersionType> StateDataSource
     for StorageState<N, D, Ver>
 {
+    async fn get_decided_leaf(&self) -> Leaf {
+        self.as_ref().get_decided_leaf().await
+    }
+
     async fn get_decided_state(&self) -> Arc<ValidatedState> {
         self.as_ref().get_decided_state().await
     }
@@ -59,9 +91,21 @@ impl<N: network::Type, D: Send + Sync, Ver: StaticVersionType> StateDataSource
     async fn get_undecided_state(&self, view: ViewNumber) -> Option<Arc<ValidatedState>> {
         self.as_ref().get_undecided_state(view).await
     }
+
+    async fn remember_accounts(
+        &self,
+        view: ViewNumber,
+        accounts: &[AccountQueryData],
+    ) -> anyhow::Result<()> {
+        self.as_ref().remember_accounts(view, accounts).await
+    }
 }
 
 impl<N: network::Type, Ver: StaticVersionType> StateDataSource for State<N, Ver> {
+    async fn get_decided_leaf(&self) -> Leaf {
+        self.handle.get_decided_leaf().await
+    }
+
     async fn get_decided_state(&self) -> Arc<ValidatedState> {
         self.handle.get_decided_state().await
     }
@@ -69,6 +113,29 @@ impl<N: network::Type, Ver: StaticVersionType> StateDataSource for State<N, Ver>
     async fn get_undecided_state(&self, view: ViewNumber) -> Option<Arc<ValidatedState>> {
         self.handle.get_state(view).await
     }
+
+    async fn remember_accounts(
+        &self,
+        view: ViewNumber,
+        accounts: &[AccountQueryData],
+    ) -> anyhow::Result<()> {
+        let consensus = self.handle.get_consensus();
+        let mut consensus = consensus.write().await;
+        let Some(View {
+            view_inner: ViewInner::Leaf { state, .. },
+        }) = consensus.validated_state_map.get_mut(&view)
+        else {
+            bail!("state not available for view {view:?}");
+        };
+
+        // Other tasks may be holding the current state, so we replace it with an updated copy.
+        let mut new_state = (**state).clone();
+        for account in accounts {
+            account.proof.remember(&mut new_state.fee_merkle_tree)?;
+        }
+        *state = Arc::new(new_state);
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -87,38 +154,57 @@ impl<N: network::Type, Ver: StaticVersionType> StateSignatureDataSource<N> for S
     }
 }
 
-#[cfg(test)]
-mod test_helpers {
+#[async_trait]
+impl<N: network::Type, D: Sync, Ver: StaticVersionType> HealthDataSource
+    for StorageState<N, D, Ver>
+{
+    async fn health(&self, max_l1_age: Duration) -> HealthReport {
+        self.as_ref().health(max_l1_age).await
+    }
+}
+
+#[async_trait]
+impl<N: network::Type, Ver: StaticVersionType> HealthDataSource for State<N, Ver> {
+    async fn health(&self, max_l1_age: Duration) -> HealthReport {
+        self.health.report(max_l1_age).await
+    }
+}
+
+impl<N: network::Type, D, Ver: StaticVersionType> AdminDataSource for StorageState<N, D, Ver> {
+    fn submit_switch(&self) -> &SubmitSwitch {
+        self.as_ref().submit_switch()
+    }
+
+    fn catchup(&self) -> &Arc<dyn StateCatchup> {
+        self.as_ref().catchup()
+    }
+}
+
+impl<N: network::Type, Ver: StaticVersionType> AdminDataSource for State<N, Ver> {
+    fn submit_switch(&self) -> &SubmitSwitch {
+        &self.submit_switch
+    }
+
+    fn catchup(&self) -> &Arc<dyn StateCatchup> {
+        &self.catchup
+    }
+}
+
+/// An in-process network of sequencer nodes, for testing code which talks to the sequencer API.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing {
     use super::*;
     use crate::{
-        api::endpoints::{AccountQueryData, BlocksFrontier},
-        catchup::{mock::MockStateCatchup, StateCatchup},
+        catchup::mock::MockStateCatchup,
         persistence::{no_storage::NoStorage, SequencerPersistence},
-        state::BlockMerkleTree,
-        testing::{wait_for_decide_on_handle, TestConfig},
-        Transaction,
+        testing::TestConfig,
     };
-    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
-    use async_std::task::sleep;
-    use commit::Committable;
     use es_version::{SequencerVersion, SEQUENCER_VERSION};
-    use ethers::prelude::Address;
-    use futures::{
-        future::{join_all, FutureExt},
-        stream::StreamExt,
-    };
-    use hotshot::types::{Event, EventType};
-    use hotshot_types::{
-        event::LeafInfo,
-        traits::{metrics::NoMetrics, node_implementation::ConsensusTime},
-    };
+    use futures::future::{join_all, FutureExt};
+    use hotshot_types::traits::metrics::NoMetrics;
     use itertools::izip;
-    use jf_primitives::merkle_tree::{MerkleCommitment, MerkleTreeScheme};
-    use portpicker::pick_unused_port;
-    use std::time::Duration;
-    use surf_disco::Client;
-    use tide_disco::error::ServerError;
 
+    /// A network of [`TestConfig::NUM_NODES`] nodes, of which the first serves the API.
     pub struct TestNetwork {
         pub server: SequencerContext<network::Memory, SequencerVersion>,
         pub peers: Vec<SequencerContext<network::Memory, SequencerVersion>>,
@@ -201,7 +287,55 @@ mod test_helpers {
                 ctx.consensus_mut().shut_down().await;
             }
         }
+
+        /// Shut down all nodes gracefully, as if they had all received SIGTERM at once.
+        pub async fn shut_down_gracefully(&mut self, timeout: Duration) {
+            join_all(
+                std::iter::once(&mut self.server)
+                    .chain(&mut self.peers)
+                    .map(|ctx| ctx.shut_down_gracefully(timeout)),
+            )
+            .await;
+        }
     }
+}
+
+#[cfg(test)]
+mod test_helpers {
+    pub use super::testing::TestNetwork;
+    use super::*;
+    use crate::{
+        api::endpoints::{
+            AccountQueryData, BatchSubmissionResult, BlocksFrontier, CatchupRequest, StateSnapshot,
+            StateSummary, MAX_SUBMIT_BATCH_SIZE,
+        },
+        catchup::{mock::MockStateCatchup, StatePeers},
+        persistence::no_storage::NoStorage,
+        state::{BlockMerkleTree, FeeAccount, FeeAccountProof},
+        testing::{wait_for_decide_on_handle, TestConfig},
+        Transaction,
+    };
+    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use async_std::task::sleep;
+    use commit::{Commitment, Committable};
+    use data_source::testing::TestableSequencerDataSource;
+    use es_version::SequencerVersion;
+    use ethers::prelude::Address;
+    use futures::{future::join_all, stream::StreamExt};
+    use hotshot::types::{Event, EventType};
+    use hotshot_query_service::{
+        availability::{BlockQueryData, LeafQueryData},
+        types::HeightIndexed,
+    };
+    use hotshot_types::{
+        event::LeafInfo,
+        traits::{block_contents::BlockHeader, node_implementation::ConsensusTime, BlockPayload},
+    };
+    use jf_primitives::merkle_tree::{MerkleCommitment, MerkleTreeScheme};
+    use portpicker::pick_unused_port;
+    use std::collections::HashSet;
+    use surf_disco::Client;
+    use tide_disco::{error::ServerError, Error as _, StatusCode};
 
     /// Test the status API with custom options.
     ///
@@ -283,6 +417,71 @@ mod test_helpers {
         wait_for_decide_on_handle(&mut events, &txn).await;
     }
 
+    /// Test the batch submission endpoint of the submit API with custom options.
+    ///
+    /// The `opt` function can be used to modify the [`Options`] which are used to start the server,
+    /// with the same restrictions as [`submit_test_helper`].
+    pub async fn submit_batch_test_helper(opt: impl FnOnce(Options) -> Options) {
+        setup_logging();
+        setup_backtrace();
+
+        let txns = (0..3u8)
+            .map(|i| Transaction::new(Default::default(), vec![i; 4]))
+            .collect::<Vec<_>>();
+
+        let port = pick_unused_port().expect("No ports free");
+
+        let url = format!("http://localhost:{port}").parse().unwrap();
+        let client: Client<ServerError, SequencerVersion> = Client::new(url);
+
+        let options = opt(Options::from(options::Http { port }).submit(Default::default()));
+        let network = TestNetwork::new(options).await;
+        let mut events = network.server.get_event_stream();
+
+        client.connect(None).await;
+
+        let results: Vec<BatchSubmissionResult> = client
+            .post("submit/batch")
+            .body_binary(&txns)
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(results.len(), txns.len());
+        for (txn, res) in txns.iter().zip(&results) {
+            assert_eq!(res.hash, txn.commit());
+            assert_eq!(res.error, None);
+        }
+
+        // A batch which is too large is rejected as a whole.
+        let too_many = vec![txns[0].clone(); MAX_SUBMIT_BATCH_SIZE + 1];
+        let err = client
+            .post::<Vec<BatchSubmissionResult>>("submit/batch")
+            .body_binary(&too_many)
+            .unwrap()
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::BadRequest);
+
+        // Wait for Decide events containing all of the transactions we sent.
+        let mut pending = txns.iter().map(|txn| txn.commit()).collect::<HashSet<_>>();
+        while !pending.is_empty() {
+            let event = events.next().await.unwrap();
+            let EventType::Decide { leaf_chain, .. } = event.event else {
+                continue;
+            };
+            for LeafInfo { leaf, .. } in leaf_chain.iter() {
+                let Some(payload) = leaf.get_block_payload() else {
+                    continue;
+                };
+                for hash in payload.transaction_commitments(leaf.get_block_header().metadata()) {
+                    pending.remove(&hash);
+                }
+            }
+        }
+    }
+
     /// Test the state signature API.
     pub async fn state_signature_test_helper(opt: impl FnOnce(Options) -> Options) {
         setup_logging();
@@ -319,6 +518,242 @@ mod test_helpers {
             .is_ok());
     }
 
+    /// Test the health API with custom options.
+    ///
+    /// The `opt` function can be used to modify the [`Options`] which are used to start the server,
+    /// with the same restrictions as [`status_test_helper`].
+    pub async fn health_test_helper(opt: impl FnOnce(Options) -> Options) {
+        setup_logging();
+        setup_backtrace();
+
+        let port = pick_unused_port().expect("No ports free");
+        let url = format!("http://localhost:{port}").parse().unwrap();
+        let client: Client<ServerError, SequencerVersion> = Client::new(url);
+
+        let health = options::Health {
+            max_decide_age: Duration::from_secs(10),
+            ..Default::default()
+        };
+        let options = opt(Options::from(options::Http { port }).health(health));
+        let mut network = TestNetwork::new(options).await;
+        client.connect(None).await;
+
+        // Wait for the node to become ready, which requires it to decide a block.
+        let report = loop {
+            match client.get::<HealthReport>("health/ready").send().await {
+                Ok(report) => break report,
+                Err(err) => {
+                    tracing::info!("waiting for node to become ready: {err}");
+                    assert_eq!(err.status(), StatusCode::ServiceUnavailable);
+                    sleep(Duration::from_secs(1)).await;
+                }
+            }
+        };
+        assert!(report.last_decided_view.is_some());
+        assert!(report.last_decided_height.is_some());
+        assert!(report.l1_head.is_some());
+        assert!(!report.catchup_in_progress);
+        assert_eq!(report.persistence_write_failures, 0);
+        client
+            .get::<HealthReport>("health/live")
+            .send()
+            .await
+            .unwrap();
+
+        // Stop consensus. Once the last decide is old enough, the node should no longer be live,
+        // but it should still be able to report on its health.
+        network.stop_consensus().await;
+        let err = loop {
+            match client.get::<HealthReport>("health/live").send().await {
+                Ok(_) => sleep(Duration::from_secs(1)).await,
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err.status(), StatusCode::ServiceUnavailable);
+        let report = client
+            .get::<HealthReport>("health/status")
+            .send()
+            .await
+            .unwrap();
+        assert!(report.time_since_last_decide.unwrap() > health.max_decide_age);
+    }
+
+    /// Test the admin API with custom options.
+    ///
+    /// The `opt` function can be used to modify the [`Options`] which are used to start the server,
+    /// with the same restrictions as [`status_test_helper`].
+    pub async fn admin_test_helper(opt: impl FnOnce(Options) -> Options) {
+        setup_logging();
+        setup_backtrace();
+
+        let port = pick_unused_port().expect("No ports free");
+        let url = format!("http://localhost:{port}").parse().unwrap();
+        let client: Client<ServerError, SequencerVersion> = Client::new(url);
+        let token = "admin-secret";
+        let auth = format!("Bearer {token}");
+
+        let options = opt(Options::from(options::Http { port })
+            .submit(Default::default())
+            .catchup(Default::default())
+            .admin(options::Admin::new(token)));
+        let genesis = Arc::new(ValidatedState::default());
+        let network = TestNetwork::with_state(
+            options,
+            Default::default(),
+            [NoStorage; TestConfig::NUM_NODES],
+            std::array::from_fn(|i| {
+                let catchup: Box<dyn StateCatchup> = if i == 0 {
+                    // The server catches up from its own catchup API, so that admin-triggered
+                    // catchup can fetch accounts for any view this node has in memory.
+                    Box::new(StatePeers::<SequencerVersion>::from_urls(vec![format!(
+                        "http://localhost:{port}"
+                    )
+                    .parse()
+                    .unwrap()]))
+                } else {
+                    Box::new(MockStateCatchup::from_iter([(
+                        ViewNumber::genesis(),
+                        genesis.clone(),
+                    )]))
+                };
+                catchup
+            }),
+        )
+        .await;
+        let mut events = network.server.get_event_stream();
+        client.connect(None).await;
+
+        // Requests without the right token are rejected.
+        let err = client.get::<bool>("admin/submit").send().await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::Unauthorized);
+        let err = client
+            .get::<bool>("admin/submit")
+            .header("Authorization", "Bearer wrong-token")
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::Unauthorized);
+
+        // Test logging is not reloadable, so the log filter can't be changed.
+        let err = client
+            .get::<String>("admin/log-filter")
+            .header("Authorization", auth.as_str())
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::NotImplemented);
+
+        // Disable and re-enable submissions.
+        let txn = Transaction::new(Default::default(), vec![1, 2, 3, 4]);
+        assert!(client
+            .get::<bool>("admin/submit")
+            .header("Authorization", auth.as_str())
+            .send()
+            .await
+            .unwrap());
+        client
+            .post::<()>("admin/submit/disable")
+            .header("Authorization", auth.as_str())
+            .send()
+            .await
+            .unwrap();
+        assert!(!network.server.submit_switch().is_enabled());
+        let err = client
+            .post::<Commitment<Transaction>>("submit/submit")
+            .body_json(&txn)
+            .unwrap()
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::ServiceUnavailable);
+        client
+            .post::<()>("admin/submit/enable")
+            .header("Authorization", auth.as_str())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            txn.commit(),
+            client
+                .post::<Commitment<Transaction>>("submit/submit")
+                .body_json(&txn)
+                .unwrap()
+                .send()
+                .await
+                .unwrap()
+        );
+
+        // Once a block has been decided, the state summary reflects it.
+        wait_for_decide_on_handle(&mut events, &txn).await;
+        let summary = client
+            .get::<StateSummary>("admin/state")
+            .header("Authorization", auth.as_str())
+            .send()
+            .await
+            .unwrap();
+        assert!(summary.block_merkle_tree.num_leaves > 0);
+        assert!(summary.block_merkle_tree.memory_bytes > 0);
+        let err = client
+            .get::<StateSummary>(&format!("admin/state/{}", u32::MAX))
+            .header("Authorization", auth.as_str())
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::NotFound);
+
+        // Catchup requires this node to have the state for the requested view.
+        let account = FeeAccount::default();
+        let err = client
+            .post::<Vec<AccountQueryData>>(&format!("admin/catchup/{}", u32::MAX))
+            .header("Authorization", auth.as_str())
+            .body_json(&CatchupRequest {
+                accounts: vec![account],
+                fee_merkle_tree_root: None,
+            })
+            .unwrap()
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::NotFound);
+
+        // Trigger a catchup for an account at the latest decided view. Consensus may garbage collect
+        // the state for that view before our request arrives, in which case we try the next one.
+        let (view, accounts) = loop {
+            let view = network
+                .server
+                .consensus()
+                .get_decided_leaf()
+                .await
+                .get_view_number();
+            let res = client
+                .post::<Vec<AccountQueryData>>(&format!("admin/catchup/{}", view.get_u64()))
+                .header("Authorization", auth.as_str())
+                .body_json(&CatchupRequest {
+                    accounts: vec![account],
+                    fee_merkle_tree_root: None,
+                })
+                .unwrap()
+                .send()
+                .await;
+            match res {
+                Ok(accounts) => break (view, accounts),
+                Err(err) if err.status() == StatusCode::NotFound => {
+                    tracing::info!(?view, "state garbage collected, retrying");
+                }
+                Err(err) => panic!("catchup failed: {err}"),
+            }
+        };
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].balance, 0.into());
+
+        // The fetched account is part of this node's state for the view.
+        if let Some(state) = network.server.consensus().get_state(view).await {
+            let (_, balance) = FeeAccountProof::prove(&state.fee_merkle_tree, account.into())
+                .expect("fetched account is in memory");
+            assert_eq!(balance, 0.into());
+        }
+    }
+
     /// Test the state API with custom options.
     ///
     /// The `opt` function can be used to modify the [`Options`] which are used to start the server.
@@ -443,6 +878,187 @@ mod test_helpers {
         BlockMerkleTree::verify(root.digest(), root.size() - 1, res)
             .unwrap()
             .unwrap();
+
+        // Decided state snapshot.
+        let snapshot = client
+            .get::<StateSnapshot>("catchup/snapshot")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(snapshot.leaf, leaf);
+        assert_eq!(
+            snapshot.state,
+            *network.server.consensus().get_decided_state().await
+        );
+        snapshot.verify(&leaf).unwrap();
+
+        // A snapshot which does not match the trusted leaf is rejected.
+        let bad_snapshot = StateSnapshot {
+            state: Default::default(),
+            ..snapshot
+        };
+        bad_snapshot.verify(&leaf).unwrap_err();
+    }
+
+    /// How [`StoppedNetwork::run`] stops the network before it is restarted.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Shutdown {
+        /// Stop consensus on every node at once, as if they had all crashed.
+        Crash,
+        /// Shut down every node gracefully, as if they had all received SIGTERM at once.
+        Graceful,
+    }
+
+    /// A network which decided some blocks and was then stopped, ready to restart from storage.
+    pub struct StoppedNetwork<D: TestableSequencerDataSource> {
+        pub storage: Vec<D::Storage>,
+        /// The stopped network.
+        ///
+        /// After a crash, the query API of the server node is still running. After a graceful
+        /// shutdown it is not.
+        pub network: TestNetwork,
+        pub client: Client<ServerError, SequencerVersion>,
+        /// The last leaf decided before the network stopped.
+        pub leaf: Leaf,
+        /// The decided state corresponding to `leaf`.
+        pub state: Arc<ValidatedState>,
+    }
+
+    impl<D: TestableSequencerDataSource> StoppedNetwork<D> {
+        /// Start a network with persistent storage, wait for it to decide some blocks, and stop it.
+        pub async fn run(shutdown: Shutdown) -> Self {
+            setup_logging();
+            setup_backtrace();
+
+            // Initialize nodes.
+            let storage = join_all((0..TestConfig::NUM_NODES).map(|_| D::create_storage())).await;
+            let persistence = join_all(storage.iter().map(D::connect))
+                .await
+                .try_into()
+                .unwrap();
+            let port = pick_unused_port().unwrap();
+            let mut network = TestNetwork::with_state(
+                D::options(&storage[0], options::Http { port }.into())
+                    .status(Default::default())
+                    .submit(Default::default()),
+                Default::default(),
+                persistence,
+                std::array::from_fn(|_| MockStateCatchup::default()),
+            )
+            .await;
+
+            // Connect client.
+            let client: Client<ServerError, SequencerVersion> =
+                Client::new(format!("http://localhost:{port}").parse().unwrap());
+            client.connect(None).await;
+
+            // Wait until some blocks have been decided.
+            client
+                .socket("availability/stream/blocks/0")
+                .subscribe::<BlockQueryData<SeqTypes>>()
+                .await
+                .unwrap()
+                .take(3)
+                .collect::<Vec<_>>()
+                .await;
+
+            tracing::info!(?shutdown, "shutting down nodes");
+            match shutdown {
+                Shutdown::Crash => network.stop_consensus().await,
+                Shutdown::Graceful => {
+                    network.shut_down_gracefully(Duration::from_secs(10)).await;
+
+                    // The nodes should no longer accept transactions.
+                    let tx = Transaction::new(Default::default(), vec![1, 2, 3]);
+                    network.server.submit_transaction(tx).await.unwrap_err();
+                    assert!(!network.server.submit_switch().is_enabled());
+                }
+            }
+
+            let leaf = network.server.consensus().get_decided_leaf().await;
+            let state = network.server.consensus().get_decided_state().await;
+            tracing::info!(height = leaf.get_height(), "stopped network");
+            Self {
+                storage,
+                network,
+                client,
+                leaf,
+                state,
+            }
+        }
+
+        /// State catchup which gives every restarted node a copy of the last decided state.
+        pub fn state_catchup(&self) -> impl Fn(usize, u16) -> Box<dyn StateCatchup> {
+            let view = self.leaf.get_view_number();
+            let state = self.state.clone();
+            move |_, _| Box::new(MockStateCatchup::from_iter([(view, state.clone())]))
+        }
+
+        /// Restart every node from storage.
+        ///
+        /// `catchup` creates the state catchup provider for each node, given the index of the node
+        /// and the port of the restarted query API.
+        pub async fn restart(
+            self,
+            catchup: impl Fn(usize, u16) -> Box<dyn StateCatchup>,
+        ) -> RestartedNetwork<D> {
+            // Fully shut down the API servers.
+            drop(self.network);
+
+            let port = pick_unused_port().unwrap();
+            let persistence = join_all(self.storage.iter().map(D::connect))
+                .await
+                .try_into()
+                .unwrap();
+            let network = TestNetwork::with_state(
+                D::options(&self.storage[0], options::Http { port }.into()),
+                Default::default(),
+                persistence,
+                std::array::from_fn(|i| catchup(i, port)),
+            )
+            .await;
+            let client: Client<ServerError, SequencerVersion> =
+                Client::new(format!("http://localhost:{port}").parse().unwrap());
+            client.connect(None).await;
+
+            RestartedNetwork {
+                _storage: self.storage,
+                _network: network,
+                client,
+            }
+        }
+    }
+
+    /// A network restarted by [`StoppedNetwork::restart`].
+    pub struct RestartedNetwork<D: TestableSequencerDataSource> {
+        // Kept alive for as long as the restarted nodes are using it.
+        _storage: Vec<D::Storage>,
+        _network: TestNetwork,
+        pub client: Client<ServerError, SequencerVersion>,
+    }
+
+    impl<D: TestableSequencerDataSource> RestartedNetwork<D> {
+        /// Wait for the restarted network to decide a new block, and check that it extends `leaf`.
+        pub async fn wait_for_next_leaf(&self, leaf: &Leaf) -> LeafQueryData<SeqTypes> {
+            let height = leaf.get_height() + 1;
+            tracing::info!("waiting for decide, height {height}");
+            let new_leaf: LeafQueryData<SeqTypes> = async_std::future::timeout(
+                Duration::from_secs(60),
+                self.client
+                    .socket(&format!("availability/stream/leaves/{height}"))
+                    .subscribe()
+                    .await
+                    .unwrap()
+                    .next(),
+            )
+            .await
+            .expect("consensus stalled after restart")
+            .unwrap()
+            .unwrap();
+            assert_eq!(new_leaf.height(), height);
+            assert_eq!(new_leaf.leaf().get_parent_commitment(), leaf.commit());
+            new_leaf
+        }
     }
 }
 
@@ -453,28 +1069,26 @@ mod api_tests {
 
     use super::*;
     use crate::{
-        catchup::{mock::MockStateCatchup, StateCatchup, StatePeers},
-        testing::{wait_for_decide_on_handle, TestConfig},
-        Header, Transaction,
+        block::payload::NamespaceProof,
+        catchup::{mock::MockStateCatchup, StatePeers},
+        persistence::SequencerPersistence,
+        testing::wait_for_decide_on_handle,
+        Header, NamespaceId, Transaction,
     };
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
     use commit::Committable;
     use data_source::testing::TestableSequencerDataSource;
-    use endpoints::NamespaceProofQueryData;
+    use endpoints::{NamespaceProofQueryData, TransactionInclusionQueryData};
     use es_version::SequencerVersion;
-    use futures::{
-        future::join_all,
-        stream::{StreamExt, TryStreamExt},
-    };
-    use hotshot_query_service::{
-        availability::{BlockQueryData, LeafQueryData},
-        types::HeightIndexed,
-    };
+    use futures::stream::{StreamExt, TryStreamExt};
+    use hotshot_query_service::availability::{BlockQueryData, LeafQueryData, QueryablePayload};
     use hotshot_types::vid::vid_scheme;
     use portpicker::pick_unused_port;
+    use std::time::Duration;
     use surf_disco::Client;
     use test_helpers::{
-        state_signature_test_helper, state_test_helper, status_test_helper, submit_test_helper,
+        admin_test_helper, health_test_helper, state_signature_test_helper, state_test_helper,
+        status_test_helper, submit_batch_test_helper, submit_test_helper, Shutdown, StoppedNetwork,
         TestNetwork,
     };
     use tide_disco::error::ServerError;
@@ -485,6 +1099,12 @@ mod api_tests {
         submit_test_helper(|opt| D::options(&storage, opt)).await
     }
 
+    #[async_std::test]
+    pub(crate) async fn submit_batch_test_with_query_module<D: TestableSequencerDataSource>() {
+        let storage = D::create_storage().await;
+        submit_batch_test_helper(|opt| D::options(&storage, opt)).await
+    }
+
     #[async_std::test]
     pub(crate) async fn status_test_with_query_module<D: TestableSequencerDataSource>() {
         let storage = D::create_storage().await;
@@ -497,6 +1117,18 @@ mod api_tests {
         state_signature_test_helper(|opt| D::options(&storage, opt)).await
     }
 
+    #[async_std::test]
+    pub(crate) async fn health_test_with_query_module<D: TestableSequencerDataSource>() {
+        let storage = D::create_storage().await;
+        health_test_helper(|opt| D::options(&storage, opt)).await
+    }
+
+    #[async_std::test]
+    pub(crate) async fn admin_test_with_query_module<D: TestableSequencerDataSource>() {
+        let storage = D::create_storage().await;
+        admin_test_helper(|opt| D::options(&storage, opt)).await
+    }
+
     #[async_std::test]
     pub(crate) async fn test_namespace_query<D: TestableSequencerDataSource>() {
         setup_logging();
@@ -544,6 +1176,7 @@ mod api_tests {
         tracing::info!(block_height, "transaction sequenced");
         let mut found_txn = false;
         let mut found_empty_block = false;
+        let mut headers = vec![];
         for block_num in 0..=block_height {
             let header: Header = client
                 .get(&format!("availability/header/{block_num}"))
@@ -568,67 +1201,196 @@ mod api_tests {
                     found_txn = true;
                 }
             }
+            headers.push(header);
         }
         assert!(found_txn);
         assert!(found_empty_block);
+
+        // Get the same proofs all at once and check them against the headers.
+        let range_res: Vec<NamespaceProofQueryData> = client
+            .get(&format!(
+                "availability/block/0/{}/namespace/0",
+                block_height + 1
+            ))
+            .send()
+            .await
+            .unwrap();
+        let txns = NamespaceProof::verify_range(
+            &vid,
+            NamespaceId::from(0u64),
+            range_res.iter().map(|res| &res.proof),
+            &headers,
+        )
+        .unwrap();
+        assert_eq!(txns.len(), headers.len());
+        for (block_txns, res) in txns.iter().zip(&range_res) {
+            assert_eq!(*block_txns, res.transactions);
+        }
+        assert!(txns.iter().flatten().any(|txn| txn.commit() == hash));
+
+        // The number of proofs must match the number of headers.
+        assert!(NamespaceProof::verify_range(
+            &vid,
+            NamespaceId::from(0u64),
+            range_res.iter().map(|res| &res.proof),
+            &headers[1..],
+        )
+        .is_none());
     }
 
     #[async_std::test]
-    pub(crate) async fn state_test_with_query_module<D: TestableSequencerDataSource>() {
+    pub(crate) async fn test_namespace_stream<D: TestableSequencerDataSource>() {
+        setup_logging();
+        setup_backtrace();
+
+        let vid = vid_scheme(5);
+        let ns_id = NamespaceId::from(1u64);
+        let txn = Transaction::new(ns_id, vec![1, 2, 3, 4]);
+
+        // Start query service.
+        let port = pick_unused_port().expect("No ports free");
         let storage = D::create_storage().await;
-        state_test_helper(|opt| D::options(&storage, opt)).await
+        let _network = TestNetwork::new(
+            D::options(&storage, options::Http { port }.into()).submit(Default::default()),
+        )
+        .await;
+
+        // Connect client.
+        let client: Client<ServerError, SequencerVersion> =
+            Client::new(format!("http://localhost:{port}").parse().unwrap());
+        client.connect(None).await;
+
+        // Subscribe to the namespace from genesis, then submit a transaction to it.
+        let mut ns_stream = client
+            .socket(&format!("availability/stream/namespace/{ns_id}/from/0"))
+            .subscribe::<NamespaceProofQueryData>()
+            .await
+            .unwrap();
+        let hash = client
+            .post("submit/submit")
+            .body_json(&txn)
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(txn.commit(), hash);
+
+        // We should get one item per block, in order, until the block containing our transaction.
+        let mut found_empty_block = false;
+        for block_num in 0.. {
+            let ns_query_res = ns_stream.next().await.unwrap().unwrap();
+            let header: Header = client
+                .get(&format!("availability/header/{block_num}"))
+                .send()
+                .await
+                .unwrap();
+            let (txns, proof_ns_id) = ns_query_res
+                .proof
+                .verify(&vid, &header.payload_commitment, &header.ns_table)
+                .unwrap();
+            assert_eq!(proof_ns_id, ns_id);
+            assert_eq!(txns, ns_query_res.transactions);
+
+            if ns_query_res.transactions.is_empty() {
+                found_empty_block = true;
+            } else {
+                assert_eq!(ns_query_res.transactions, vec![txn]);
+                break;
+            }
+        }
+        assert!(found_empty_block);
     }
 
-    #[ignore]
     #[async_std::test]
-    pub(crate) async fn test_restart<D: TestableSequencerDataSource>() {
+    pub(crate) async fn test_transaction_inclusion<D: TestableSequencerDataSource>() {
         setup_logging();
         setup_backtrace();
 
-        // Initialize nodes.
-        let storage = join_all((0..TestConfig::NUM_NODES).map(|_| D::create_storage())).await;
-        let persistence = join_all(storage.iter().map(D::connect))
-            .await
-            .try_into()
-            .unwrap();
-        let port = pick_unused_port().unwrap();
-        let mut network = TestNetwork::with_state(
-            D::options(&storage[0], options::Http { port }.into()).status(Default::default()),
-            Default::default(),
-            persistence,
-            std::array::from_fn(|_| MockStateCatchup::default()),
+        let txn = Transaction::new(NamespaceId::from(2u64), vec![1, 2, 3, 4]);
+
+        // Start query service.
+        let port = pick_unused_port().expect("No ports free");
+        let storage = D::create_storage().await;
+        let network = TestNetwork::new(
+            D::options(&storage, options::Http { port }.into()).submit(Default::default()),
         )
         .await;
+        let mut events = network.server.get_event_stream();
 
         // Connect client.
         let client: Client<ServerError, SequencerVersion> =
             Client::new(format!("http://localhost:{port}").parse().unwrap());
         client.connect(None).await;
 
-        // Wait until some blocks have been decided.
+        let hash = client
+            .post("submit/submit")
+            .body_json(&txn)
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(txn.commit(), hash);
+        let block_height = wait_for_decide_on_handle(&mut events, &txn).await;
+
+        // Look up the transaction by hash. The query service may not have processed the block yet,
+        // but the request will wait for it to become available.
+        let res: TransactionInclusionQueryData = client
+            .get(&format!("availability/transaction/hash/{hash}/inclusion"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.block_height, block_height);
+        assert_eq!(res.namespace, txn.namespace());
+        assert_eq!(res.transaction, txn);
+
+        // The index should locate the same transaction in the block.
+        let block: BlockQueryData<SeqTypes> = client
+            .get(&format!("availability/block/{block_height}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            block
+                .payload()
+                .transaction(block.payload().get_ns_table(), &res.index)
+                .unwrap(),
+            txn
+        );
+
+        // Looking up an unknown transaction fails.
+        let unknown = Transaction::new(NamespaceId::from(2u64), vec![5, 6, 7, 8]);
         client
-            .socket("availability/stream/blocks/0")
-            .subscribe::<BlockQueryData<SeqTypes>>()
+            .get::<TransactionInclusionQueryData>(&format!(
+                "availability/transaction/hash/{}/inclusion",
+                unknown.commit()
+            ))
+            .send()
             .await
-            .unwrap()
-            .take(3)
-            .collect::<Vec<_>>()
-            .await;
+            .unwrap_err();
+    }
 
-        // Shut down the consensus nodes.
-        tracing::info!("shutting down nodes");
-        network.stop_consensus().await;
+    #[async_std::test]
+    pub(crate) async fn state_test_with_query_module<D: TestableSequencerDataSource>() {
+        let storage = D::create_storage().await;
+        state_test_helper(|opt| D::options(&storage, opt)).await
+    }
 
-        // Get the block height we reached.
-        let height = client
+    #[ignore]
+    #[async_std::test]
+    pub(crate) async fn test_restart<D: TestableSequencerDataSource>() {
+        let stopped = StoppedNetwork::<D>::run(Shutdown::Crash).await;
+        let leaf = stopped.leaf.clone();
+
+        // Get the decided chain, so we can check consistency after the restart.
+        let height = stopped
+            .client
             .get::<usize>("status/block-height")
             .send()
             .await
             .unwrap();
         tracing::info!("decided {height} blocks before shutting down");
-
-        // Get the decided chain, so we can check consistency after the restart.
-        let chain: Vec<LeafQueryData<SeqTypes>> = client
+        let chain: Vec<LeafQueryData<SeqTypes>> = stopped
+            .client
             .socket("availability/stream/leaves/0")
             .subscribe()
             .await
@@ -637,30 +1399,16 @@ mod api_tests {
             .try_collect()
             .await
             .unwrap();
-        let decided_view = chain.last().unwrap().leaf().get_view_number();
-
-        // Get the most recent state, for catchup.
-        let state = network.server.consensus().get_decided_state().await;
-
-        // Fully shut down the API servers.
-        drop(network);
 
         // Start up again, resuming from the last decided leaf.
-        let port = pick_unused_port().expect("No ports free");
-        let persistence = join_all(storage.iter().map(D::connect))
-            .await
-            .try_into()
-            .unwrap();
-        let _network = TestNetwork::with_state(
-            D::options(&storage[0], options::Http { port }.into()),
-            Default::default(),
-            persistence,
-            std::array::from_fn(|i| {
-                let catchup: Box<dyn StateCatchup> = if i == 0 {
+        let (view, state) = (leaf.get_view_number(), stopped.state.clone());
+        let restarted = stopped
+            .restart(|i, port| {
+                if i == 0 {
                     // Give the server node a copy of the full state to use for catchup. This
                     // simulates a node with archival state storage, which is then able to seed the
                     // rest of the network after a restart.
-                    Box::new(MockStateCatchup::from_iter([(decided_view, state.clone())]))
+                    Box::new(MockStateCatchup::from_iter([(view, state.clone())]))
                 } else {
                     // The remaining nodes should use this archival node as a peer for catchup.
                     Box::new(StatePeers::<SequencerVersion>::from_urls(vec![format!(
@@ -668,34 +1416,16 @@ mod api_tests {
                     )
                     .parse()
                     .unwrap()]))
-                };
-                catchup
-            }),
-        )
-        .await;
-        let client: Client<ServerError, SequencerVersion> =
-            Client::new(format!("http://localhost:{port}").parse().unwrap());
-        client.connect(None).await;
+                }
+            })
+            .await;
 
         // Make sure we can decide new blocks after the restart.
-        tracing::info!("waiting for decide, height {}", height + 1);
-        let new_leaf: LeafQueryData<SeqTypes> = client
-            .socket(&format!("availability/stream/leaves/{}", height + 1))
-            .subscribe()
-            .await
-            .unwrap()
-            .next()
-            .await
-            .unwrap()
-            .unwrap();
-        assert_eq!(new_leaf.height(), height as u64 + 1);
-        assert_eq!(
-            new_leaf.leaf().get_parent_commitment(),
-            chain[height - 1].hash()
-        );
+        restarted.wait_for_next_leaf(&leaf).await;
 
         // Ensure the new chain is consistent with the old chain.
-        let new_chain: Vec<LeafQueryData<SeqTypes>> = client
+        let new_chain: Vec<LeafQueryData<SeqTypes>> = restarted
+            .client
             .socket("availability/stream/leaves/0")
             .subscribe()
             .await
@@ -706,6 +1436,54 @@ mod api_tests {
             .unwrap();
         assert_eq!(chain, new_chain);
     }
+
+    #[async_std::test]
+    pub(crate) async fn test_restart_from_high_qc<D: TestableSequencerDataSource>() {
+        let stopped = StoppedNetwork::<D>::run(Shutdown::Crash).await;
+        let leaf = stopped.leaf.clone();
+
+        // Every node should have saved a high QC at least as recent as the last decided leaf.
+        for (i, storage) in stopped.storage.iter().enumerate() {
+            let high_qc = D::connect(storage)
+                .await
+                .load_high_qc()
+                .await
+                .unwrap()
+                .unwrap();
+            tracing::info!(i, view = ?high_qc.view_number, "loaded high QC");
+            assert!(high_qc.view_number >= leaf.get_view_number());
+        }
+
+        // Consensus should resume from the saved high QC and extend the old chain.
+        let catchup = stopped.state_catchup();
+        let restarted = stopped.restart(catchup).await;
+        restarted.wait_for_next_leaf(&leaf).await;
+    }
+
+    #[async_std::test]
+    pub(crate) async fn test_graceful_shutdown_and_restart<D: TestableSequencerDataSource>() {
+        let stopped = StoppedNetwork::<D>::run(Shutdown::Graceful).await;
+        let leaf = stopped.leaf.clone();
+        let catchup = stopped.state_catchup();
+        let restarted = stopped.restart(catchup).await;
+
+        // The last leaf decided before shutdown should have been flushed to the query service.
+        let old_leaf: LeafQueryData<SeqTypes> = async_std::future::timeout(
+            Duration::from_secs(10),
+            restarted
+                .client
+                .get(&format!("availability/leaf/{}", leaf.get_height()))
+                .send(),
+        )
+        .await
+        .expect("last decided leaf missing after restart")
+        .unwrap();
+        assert_eq!(old_leaf.leaf().commit(), leaf.commit());
+
+        // Consensus should resume and extend the old chain.
+        restarted.wait_for_next_leaf(&leaf).await;
+    }
+
     #[async_std::test]
     pub(crate) async fn test_hotshot_event_streaming<D: TestableSequencerDataSource>() {
         use hotshot_events_service::events_source::BuilderEvent;
@@ -766,24 +1544,31 @@ mod api_tests {
 mod test {
     use super::*;
     use crate::{
-        catchup::StatePeers, persistence::no_storage::NoStorage, testing::TestConfig, Header,
-        NodeState,
+        catchup::StatePeers,
+        persistence::{self, no_storage::NoStorage},
+        testing::TestConfig,
+        Header, NodeState,
     };
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
     use commit::Committable;
     use es_version::SequencerVersion;
     use ethers::prelude::Signer;
-    use futures::stream::StreamExt;
-    use hotshot::types::EventType;
-    use hotshot_types::{event::LeafInfo, traits::block_contents::BlockHeader};
+    use futures::stream::{Stream, StreamExt};
+    use hotshot::types::{Event, EventType};
+    use hotshot_types::{
+        event::LeafInfo,
+        traits::{block_contents::BlockHeader, metrics::NoMetrics},
+    };
     use jf_primitives::merkle_tree::AppendableMerkleTreeScheme;
     use portpicker::pick_unused_port;
     use surf_disco::Client;
+    use tempfile::TempDir;
     use test_helpers::{
-        state_signature_test_helper, state_test_helper, status_test_helper, submit_test_helper,
-        TestNetwork,
+        admin_test_helper, health_test_helper, state_signature_test_helper, state_test_helper,
+        status_test_helper, submit_batch_test_helper, submit_test_helper, TestNetwork,
     };
     use tide_disco::{app::AppHealth, error::ServerError, healthcheck::HealthStatus};
+    use url::Url;
 
     #[async_std::test]
     async fn test_healthcheck() {
@@ -811,6 +1596,11 @@ mod test {
         submit_test_helper(|opt| opt).await
     }
 
+    #[async_std::test]
+    async fn submit_batch_test_without_query_module() {
+        submit_batch_test_helper(|opt| opt).await
+    }
+
     #[async_std::test]
     async fn state_signature_test_without_query_module() {
         state_signature_test_helper(|opt| opt).await
@@ -821,6 +1611,16 @@ mod test {
         state_test_helper(|opt| opt).await
     }
 
+    #[async_std::test]
+    async fn health_test_without_query_module() {
+        health_test_helper(|opt| opt).await
+    }
+
+    #[async_std::test]
+    async fn admin_test_without_query_module() {
+        admin_test_helper(|opt| opt).await
+    }
+
     #[async_std::test]
     async fn test_catchup() {
         setup_logging();
@@ -884,4 +1684,91 @@ mod test {
             }
         }
     }
+
+    #[async_std::test]
+    async fn test_bootstrap_from_snapshot() {
+        setup_logging();
+        setup_backtrace();
+
+        // Start a sequencer network with a query service, which also serves snapshots for catchup.
+        let port = pick_unused_port().expect("No ports free");
+        let url: Url = format!("http://localhost:{port}").parse().unwrap();
+        let storage = TempDir::new().unwrap();
+        let mut network = TestNetwork::new(
+            Options::from(options::Http { port })
+                .query_fs(
+                    Default::default(),
+                    persistence::fs::Options {
+                        path: storage.path().into(),
+                    },
+                )
+                .catchup(Default::default()),
+        )
+        .await;
+        let mut events = network.server.get_event_stream();
+
+        // Wait for a few blocks, then stop one of the nodes and forget everything about it.
+        wait_for_decide_height(&mut events, 3).await;
+        let i = TestConfig::NUM_NODES - 1;
+        let mut lost = network.peers.pop().unwrap();
+        lost.consensus_mut().shut_down().await;
+        drop(lost);
+
+        // Bootstrap a replacement node from a snapshot, verified against the query service.
+        let peers = StatePeers::<SequencerVersion>::from_urls(vec![url.clone()]);
+        let snapshot = peers.fetch_snapshot(url).await;
+        let qc = snapshot.qc.clone().unwrap();
+        assert_eq!(qc.data.leaf_commit, snapshot.leaf.commit());
+        let height = snapshot.leaf.get_height();
+        tracing::info!(height, "bootstrapping from snapshot");
+        let node = network
+            .cfg
+            .init_node_from_snapshot(
+                i,
+                snapshot,
+                NoStorage,
+                peers,
+                &NoMetrics,
+                SequencerVersion::instance(),
+            )
+            .await;
+        node.start_consensus().await;
+
+        // Wait for a block proposed by the new node after the snapshot, which shows that it leads
+        // views on top of the QC from the snapshot.
+        let builder = TestConfig::builder_wallet(i).address().into();
+        'outer: loop {
+            let event = events.next().await.unwrap();
+            let EventType::Decide { leaf_chain, .. } = event.event else {
+                continue;
+            };
+            for LeafInfo { leaf, .. } in leaf_chain.iter().rev() {
+                let header = leaf.get_block_header();
+                tracing::info!(
+                    "waiting for block from {builder}, block {} is from {}",
+                    header.height,
+                    header.fee_info.account()
+                );
+                if header.height > height && header.fee_info.account() == builder {
+                    break 'outer;
+                }
+            }
+        }
+    }
+
+    /// Wait until `events` reports a decide at height at least `height`.
+    async fn wait_for_decide_height(
+        events: &mut (impl Stream<Item = Event<SeqTypes>> + Unpin),
+        height: u64,
+    ) {
+        loop {
+            let event = events.next().await.unwrap();
+            let EventType::Decide { leaf_chain, .. } = event.event else {
+                continue;
+            };
+            if leaf_chain[0].leaf.get_height() >= height {
+                return;
+            }
+        }
+    }
 }
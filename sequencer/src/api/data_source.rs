@@ -1,9 +1,13 @@
 use super::{
+    endpoints::AccountQueryData,
     fs,
     options::{Options, Query},
     sql,
 };
 use crate::{
+    catchup::StateCatchup,
+    context::SubmitSwitch,
+    health::HealthReport,
     network, persistence,
     state::{BlockMerkleTree, Delta, FeeAccount, FeeMerkleTree, ValidatedState},
     Node, SeqTypes,
@@ -25,6 +29,7 @@ use hotshot_types::{data::ViewNumber, light_client::StateSignatureRequestBody};
 use jf_primitives::merkle_tree::{
     prelude::MerklePath, MerkleTreeScheme, ToTraversalPath, UniversalMerkleTreeScheme,
 };
+use std::time::Duration;
 use tide_disco::Url;
 use versioned_binary_serialization::version::StaticVersionType;
 
@@ -61,12 +66,18 @@ pub trait SequencerDataSource:
     + StatusDataSource
     + UpdateDataSource<SeqTypes>
     + VersionedDataSource
+    + MerklizedStateStorage
     + Sized
 {
     type Options: DataSourceOptions<DataSource = Self>;
 
     /// Instantiate a data source from command line options.
     async fn create(opt: Self::Options, provider: Provider, reset: bool) -> anyhow::Result<Self>;
+}
+
+/// Storage for the parts of merklized state which change in each block.
+#[async_trait]
+pub trait MerklizedStateStorage: Send {
     /// Wrapper function to store merkle nodes
     async fn store_state<S: MerklizedState<SeqTypes>>(
         &mut self,
@@ -94,6 +105,9 @@ pub fn provider<Ver: StaticVersionType + 'static>(
 
 pub(crate) trait SubmitDataSource<N: network::Type> {
     fn consensus(&self) -> &SystemContextHandle<SeqTypes, Node<N>>;
+
+    /// Whether the node is currently accepting new transactions.
+    fn submissions_enabled(&self) -> bool;
 }
 
 #[async_trait]
@@ -101,10 +115,33 @@ pub(crate) trait StateSignatureDataSource<N: network::Type> {
     async fn get_state_signature(&self, height: u64) -> Option<StateSignatureRequestBody>;
 }
 
+pub(crate) trait AdminDataSource {
+    /// The switch controlling whether the node accepts new transactions.
+    fn submit_switch(&self) -> &SubmitSwitch;
+
+    /// The source of state which is missing from this node.
+    fn catchup(&self) -> &Arc<dyn StateCatchup>;
+}
+
+#[async_trait]
+pub(crate) trait HealthDataSource {
+    async fn health(&self, max_l1_age: Duration) -> HealthReport;
+}
+
 #[trait_variant::make(StateDataSource: Send)]
 pub(crate) trait LocalStateDataSource {
+    async fn get_decided_leaf(&self) -> Leaf<SeqTypes>;
     async fn get_decided_state(&self) -> Arc<ValidatedState>;
     async fn get_undecided_state(&self, view: ViewNumber) -> Option<Arc<ValidatedState>>;
+
+    /// Add `accounts`, fetched from catchup peers, to this node's state for `view`.
+    ///
+    /// Fails if the state for `view` is not in memory, or if the account proofs do not match it.
+    async fn remember_accounts(
+        &self,
+        view: ViewNumber,
+        accounts: &[AccountQueryData],
+    ) -> anyhow::Result<()>;
 }
 
 #[async_trait]
@@ -115,49 +152,57 @@ impl<D: SequencerDataSource + Send + Sync> UpdateStateStorage<SeqTypes, D> for V
         leaf: &Leaf<SeqTypes>,
         delta: Arc<Delta>,
     ) -> anyhow::Result<()> {
-        let block_number = leaf.get_height();
-        let ValidatedState {
-            fee_merkle_tree,
-            block_merkle_tree,
-        } = self;
-
-        let Delta { fees_delta } = delta.as_ref();
+        store_merklized_state(self, storage, leaf.get_height(), &delta).await
+    }
+}
 
-        // Insert block merkle tree nodes
-        let (_, proof) = block_merkle_tree
-            .lookup(block_number - 1)
+/// Store the paths in `state` which changed in block `block_number`, according to `delta`.
+pub(crate) async fn store_merklized_state(
+    state: &ValidatedState,
+    storage: &mut impl MerklizedStateStorage,
+    block_number: u64,
+    delta: &Delta,
+) -> anyhow::Result<()> {
+    let ValidatedState {
+        fee_merkle_tree,
+        block_merkle_tree,
+    } = state;
+
+    let Delta { fees_delta } = delta;
+
+    // Insert block merkle tree nodes
+    let (_, proof) = block_merkle_tree
+        .lookup(block_number - 1)
+        .expect_ok()
+        .context("Index not found in block merkle tree")?;
+    let path = <u64 as ToTraversalPath<typenum::U3>>::to_traversal_path(
+        &(block_number - 1),
+        block_merkle_tree.height(),
+    );
+
+    storage
+        .store_state::<BlockMerkleTree>(proof.proof, path, block_number)
+        .await
+        .context("failed to insert merkle nodes for block merkle tree")?;
+
+    // Insert fee merkle tree nodes
+    for delta in fees_delta {
+        let (_, proof) = fee_merkle_tree
+            .universal_lookup(delta)
             .expect_ok()
-            .context("Index not found in block merkle tree")?;
-        let path = <u64 as ToTraversalPath<typenum::U3>>::to_traversal_path(
-            &(block_number - 1),
-            block_merkle_tree.height(),
+            .context("Index not found in fee merkle tree")?;
+        let path: Vec<usize> = <FeeAccount as ToTraversalPath<typenum::U256>>::to_traversal_path(
+            delta,
+            fee_merkle_tree.height(),
         );
 
         storage
-            .store_state::<BlockMerkleTree>(proof.proof, path, block_number)
+            .store_state::<FeeMerkleTree>(proof.proof, path, block_number)
             .await
             .context("failed to insert merkle nodes for block merkle tree")?;
-
-        // Insert fee merkle tree nodes
-        for delta in fees_delta {
-            let (_, proof) = fee_merkle_tree
-                .universal_lookup(delta)
-                .expect_ok()
-                .context("Index not found in fee merkle tree")?;
-            let path: Vec<usize> =
-                <FeeAccount as ToTraversalPath<typenum::U256>>::to_traversal_path(
-                    delta,
-                    fee_merkle_tree.height(),
-                );
-
-            storage
-                .store_state::<FeeMerkleTree>(proof.proof, path, block_number)
-                .await
-                .context("failed to insert merkle nodes for block merkle tree")?;
-        }
-
-        Ok(())
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -2,33 +2,53 @@
 
 use super::{
     data_source::{
-        SequencerDataSource, StateDataSource, StateSignatureDataSource, SubmitDataSource,
+        AdminDataSource, HealthDataSource, SequencerDataSource, StateDataSource,
+        StateSignatureDataSource, SubmitDataSource,
     },
+    fs::MerkleStore,
     StorageState,
 };
 use crate::{
-    block::payload::{parse_ns_payload, NamespaceProof},
-    network,
-    state::{BlockMerkleTree, FeeAccountProof, ValidatedState},
-    NamespaceId, SeqTypes, Transaction,
+    block::{payload::NamespaceProof, queryable::TxInclusionProof, tx_iterator::TxIndex},
+    health::{HealthReport, HealthThresholds},
+    logging, network,
+    state::{
+        BlockMerkleCommitment, BlockMerkleTree, FeeAccount, FeeAccountProof, FeeMerkleCommitment,
+        ValidatedState,
+    },
+    Leaf, NamespaceId, SeqTypes, Transaction,
 };
+use anyhow::ensure;
 use async_std::sync::{Arc, RwLock};
-use commit::Committable;
+use commit::{Commitment, Committable};
 use ethers::prelude::U256;
-use futures::{try_join, FutureExt};
+use futures::{
+    future::try_join_all,
+    stream::{self, StreamExt, TryFutureExt},
+    try_join, FutureExt,
+};
 use hotshot_query_service::{
-    availability::{self, AvailabilityDataSource, CustomSnafu, FetchBlockSnafu},
+    availability::{
+        self, AvailabilityDataSource, BlockQueryData, CustomSnafu, FetchBlockSnafu,
+        FetchTransactionSnafu, LeafQueryData, QueryablePayload, VidCommonQueryData,
+    },
     merklized_state::{self, MerklizedState, MerklizedStateDataSource},
-    node, Error,
+    node,
+    types::HeightIndexed,
+    Error,
+};
+use hotshot_types::{
+    data::ViewNumber, simple_certificate::QuorumCertificate,
+    traits::node_implementation::ConsensusTime,
 };
-use hotshot_types::{data::ViewNumber, traits::node_implementation::ConsensusTime};
 use jf_primitives::merkle_tree::MerkleTreeScheme;
 use serde::{Deserialize, Serialize};
 use snafu::OptionExt;
+use std::time::Duration;
 use tagged_base64::TaggedBase64;
 use tide_disco::{
     method::{ReadState, WriteState},
-    Api, Error as _, StatusCode,
+    Api, Error as _, RequestParams, StatusCode,
 };
 
 use versioned_binary_serialization::version::StaticVersionType;
@@ -39,6 +59,15 @@ pub struct NamespaceProofQueryData {
     pub transactions: Vec<Transaction>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionInclusionQueryData {
+    pub block_height: u64,
+    pub namespace: NamespaceId,
+    pub index: TxIndex,
+    pub transaction: Transaction,
+    pub proof: TxInclusionProof,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AccountQueryData {
     pub balance: U256,
@@ -51,6 +80,138 @@ impl From<(FeeAccountProof, U256)> for AccountQueryData {
     }
 }
 
+/// A snapshot of the decided state, used to bootstrap new nodes.
+///
+/// The block Merkle tree is the frontier of the tree: only the path to its most recent leaf is
+/// included. The fee Merkle tree includes every account known to the serving node.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    /// The decided leaf which the state follows.
+    pub leaf: Leaf,
+    pub state: ValidatedState,
+    /// The QC for `leaf`.
+    ///
+    /// Peers do not serve this. It is taken from the trusted query service when the snapshot is
+    /// checked with [`verify_trusted`](Self::verify_trusted), and becomes the high QC of a node
+    /// bootstrapping from the snapshot.
+    #[serde(skip)]
+    pub qc: Option<QuorumCertificate<SeqTypes>>,
+}
+
+impl StateSnapshot {
+    /// Check that this snapshot is the state after the trusted leaf `trusted`, and attach its QC.
+    pub fn verify_trusted(mut self, trusted: &LeafQueryData<SeqTypes>) -> anyhow::Result<Self> {
+        self.verify(trusted.leaf())?;
+        ensure!(
+            trusted.qc().data.leaf_commit == self.leaf.commit(),
+            "trusted QC does not sign snapshot leaf {}",
+            self.leaf.get_height()
+        );
+        self.qc = Some(trusted.qc().clone());
+        Ok(self)
+    }
+
+    /// Check that this snapshot is the state after `trusted`.
+    pub fn verify(&self, trusted: &Leaf) -> anyhow::Result<()> {
+        ensure!(
+            self.leaf.commit() == trusted.commit(),
+            "snapshot leaf {} does not match trusted leaf",
+            self.leaf.get_height()
+        );
+        let header = trusted.get_block_header();
+        ensure!(
+            self.state.block_merkle_tree.commitment() == header.block_merkle_tree_root,
+            "snapshot block Merkle tree does not match header {}",
+            header.height
+        );
+        ensure!(
+            self.state.fee_merkle_tree.commitment() == header.fee_merkle_tree_root,
+            "snapshot fee Merkle tree does not match header {}",
+            header.height
+        );
+        Ok(())
+    }
+}
+
+/// The maximum number of transactions which can be submitted in a single batch.
+pub const MAX_SUBMIT_BATCH_SIZE: usize = 1000;
+
+/// The maximum number of transactions from a single batch which are submitted concurrently.
+const SUBMIT_BATCH_CONCURRENCY: usize = 16;
+
+/// The result of submitting a single transaction as part of a batch.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchSubmissionResult {
+    /// Commitment to the submitted transaction.
+    pub hash: Commitment<Transaction>,
+    /// The reason the transaction could not be submitted, if it failed.
+    pub error: Option<String>,
+}
+
+/// A summary of one of the Merkle trees making up the state.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MerkleTreeSummary<C> {
+    /// The root commitment of the tree.
+    pub commitment: C,
+    /// The number of leaves in the tree.
+    pub num_leaves: u64,
+    /// Approximate memory used by the parts of the tree held by this node, in bytes.
+    ///
+    /// This is estimated from the serialized size of the tree, which is expensive to compute for
+    /// a large tree, so the admin API computes it at most once for each state.
+    pub memory_bytes: u64,
+}
+
+impl<C> MerkleTreeSummary<C> {
+    fn new<T: MerkleTreeScheme<Commitment = C> + Serialize>(tree: &T) -> Self {
+        Self {
+            commitment: tree.commitment(),
+            num_leaves: tree.num_leaves(),
+            memory_bytes: bincode::serialized_size(tree).unwrap_or_default(),
+        }
+    }
+}
+
+/// A summary of a [`ValidatedState`], as reported by the admin API.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateSummary {
+    pub block_merkle_tree: MerkleTreeSummary<BlockMerkleCommitment>,
+    pub fee_merkle_tree: MerkleTreeSummary<FeeMerkleCommitment>,
+}
+
+impl From<&ValidatedState> for StateSummary {
+    fn from(state: &ValidatedState) -> Self {
+        Self {
+            block_merkle_tree: MerkleTreeSummary::new(&state.block_merkle_tree),
+            fee_merkle_tree: MerkleTreeSummary::new(&state.fee_merkle_tree),
+        }
+    }
+}
+
+impl StateSummary {
+    /// Whether this is a summary of `state`.
+    fn summarizes(&self, state: &ValidatedState) -> bool {
+        self.block_merkle_tree.commitment == state.block_merkle_tree.commitment()
+            && self.fee_merkle_tree.commitment == state.fee_merkle_tree.commitment()
+            && self.block_merkle_tree.num_leaves == state.block_merkle_tree.num_leaves()
+            && self.fee_merkle_tree.num_leaves == state.fee_merkle_tree.num_leaves()
+    }
+}
+
+/// A request to fetch fee accounts from catchup peers through the admin API.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CatchupRequest {
+    /// The accounts to fetch.
+    pub accounts: Vec<FeeAccount>,
+    /// The root of the fee Merkle tree expected at the requested view.
+    ///
+    /// If provided, this must match this node's state for the view.
+    pub fee_merkle_tree_root: Option<FeeMerkleCommitment>,
+}
+
+/// The maximum number of blocks for which namespace proofs can be requested at once.
+pub const MAX_NAMESPACE_PROOF_RANGE: usize = 100;
+
 pub type BlocksFrontier = <BlockMerkleTree as MerkleTreeScheme>::MembershipProof;
 
 pub(super) type AvailState<N, D, Ver> = Arc<RwLock<StorageState<N, D, Ver>>>;
@@ -74,63 +235,155 @@ where
         async move {
             let height: usize = req.integer_param("height")?;
             let ns_id: u64 = req.integer_param("namespace")?;
+            fetch_namespace_proof(state, height, ns_id.into(), timeout).await
+        }
+        .boxed()
+    })?
+    .get("getnamespaceproofrange", move |req, state| {
+        async move {
+            let from: usize = req.integer_param("from")?;
+            let until: usize = req.integer_param("until")?;
+            let ns_id: u64 = req.integer_param("namespace")?;
             let ns_id = NamespaceId::from(ns_id);
-            let (block, common) = try_join!(
-                async move {
-                    state
-                        .get_block(height)
-                        .await
-                        .with_timeout(timeout)
-                        .await
-                        .context(FetchBlockSnafu {
-                            resource: height.to_string(),
-                        })
-                },
-                async move {
-                    state
-                        .get_vid_common(height)
-                        .await
-                        .with_timeout(timeout)
-                        .await
-                        .context(FetchBlockSnafu {
-                            resource: height.to_string(),
-                        })
-                }
-            )?;
-
-            let proof = block
+            if until.saturating_sub(from) > MAX_NAMESPACE_PROOF_RANGE {
+                return Err(availability::Error::Custom {
+                    message: format!(
+                        "requested range {from}..{until} is too large, the maximum is \
+                        {MAX_NAMESPACE_PROOF_RANGE} blocks"
+                    ),
+                    status: StatusCode::BadRequest,
+                });
+            }
+            try_join_all(
+                (from..until).map(|height| fetch_namespace_proof(state, height, ns_id, timeout)),
+            )
+            .await
+        }
+        .boxed()
+    })?
+    .get("gettransactioninclusion", move |req, state| {
+        async move {
+            let hash: Commitment<Transaction> = req.blob_param("hash")?;
+            // The data source maintains an index from transaction hashes to their positions in
+            // the chain, so we can find the block without scanning.
+            let (block, index) = state
+                .get_block_with_transaction(hash)
+                .await
+                .with_timeout(timeout)
+                .await
+                .context(FetchTransactionSnafu {
+                    resource: hash.to_string(),
+                })?;
+            let (transaction, proof) = block
                 .payload()
-                .namespace_with_proof(
-                    block.payload().get_ns_table(),
-                    ns_id,
-                    common.common().clone(),
-                )
+                .transaction_with_proof(block.payload().get_ns_table(), &index)
                 .context(CustomSnafu {
-                    message: format!("failed to make proof for namespace {ns_id}"),
+                    message: format!("failed to make inclusion proof for transaction {hash}"),
                     status: StatusCode::NotFound,
                 })?;
-
-            let transactions = if let NamespaceProof::Existence {
-                ref ns_payload_flat,
-                ..
-            } = proof
-            {
-                parse_ns_payload(ns_payload_flat, ns_id)
-            } else {
-                Vec::new()
-            };
-
-            Ok(NamespaceProofQueryData {
-                transactions,
+            Ok(TransactionInclusionQueryData {
+                block_height: block.height(),
+                namespace: transaction.namespace(),
+                index,
+                transaction,
                 proof,
             })
         }
         .boxed()
+    })?
+    .stream("stream_namespace", move |req, state| {
+        async move {
+            let height = req.integer_param("height")?;
+            let ns_id: u64 = req.integer_param("namespace")?;
+            let ns_id = NamespaceId::from(ns_id);
+            state
+                .read(|state| {
+                    async move {
+                        // Both streams start at the same height and yield one item per block, so
+                        // zipping them pairs each block with its own VID common data.
+                        let blocks = state.subscribe_blocks(height).await;
+                        let common = state.subscribe_vid_common(height).await;
+                        Ok(blocks
+                            .zip(common)
+                            .map(move |(block, common)| namespace_proof(&block, &common, ns_id)))
+                    }
+                    .boxed()
+                })
+                .await
+        }
+        .try_flatten_stream()
+        .boxed()
     })?;
 
     Ok(api)
 }
 
+/// Fetch the transactions in namespace `ns_id` from the block at `height`, along with a proof.
+async fn fetch_namespace_proof<D>(
+    state: &D,
+    height: usize,
+    ns_id: NamespaceId,
+    timeout: Duration,
+) -> Result<NamespaceProofQueryData, availability::Error>
+where
+    D: AvailabilityDataSource<SeqTypes> + Sync,
+{
+    let (block, common) = try_join!(
+        async move {
+            state
+                .get_block(height)
+                .await
+                .with_timeout(timeout)
+                .await
+                .context(FetchBlockSnafu {
+                    resource: height.to_string(),
+                })
+        },
+        async move {
+            state
+                .get_vid_common(height)
+                .await
+                .with_timeout(timeout)
+                .await
+                .context(FetchBlockSnafu {
+                    resource: height.to_string(),
+                })
+        }
+    )?;
+
+    namespace_proof(&block, &common, ns_id)
+}
+
+/// Extract the transactions in namespace `ns_id` from a block, along with a proof.
+fn namespace_proof(
+    block: &BlockQueryData<SeqTypes>,
+    common: &VidCommonQueryData<SeqTypes>,
+    ns_id: NamespaceId,
+) -> Result<NamespaceProofQueryData, availability::Error> {
+    let proof = block
+        .payload()
+        .namespace_with_proof(
+            block.payload().get_ns_table(),
+            ns_id,
+            common.common().clone(),
+        )
+        .context(CustomSnafu {
+            message: format!("failed to make proof for namespace {ns_id}"),
+            status: StatusCode::NotFound,
+        })?;
+
+    let transactions = if let NamespaceProof::Existence { .. } = proof {
+        block.payload().namespace(ns_id).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    Ok(NamespaceProofQueryData {
+        transactions,
+        proof,
+    })
+}
+
 pub(super) fn node<N, D, Ver: StaticVersionType + 'static>(
     bind_version: Ver,
 ) -> anyhow::Result<Api<AvailState<N, D, Ver>, node::Error, Ver>>
@@ -155,6 +408,7 @@ where
 
     api.post("submit", |req, state| {
         async move {
+            check_submissions_enabled(state)?;
             let tx = req
                 .body_auto::<Transaction, Ver>(Ver::instance())
                 .map_err(Error::from_request_error)?;
@@ -167,11 +421,59 @@ where
             Ok(hash)
         }
         .boxed()
+    })?
+    .post("submit_batch", |req, state| {
+        async move {
+            check_submissions_enabled(state)?;
+            let txs = req
+                .body_auto::<Vec<Transaction>, Ver>(Ver::instance())
+                .map_err(Error::from_request_error)?;
+            if txs.len() > MAX_SUBMIT_BATCH_SIZE {
+                return Err(Error::catch_all(
+                    StatusCode::BadRequest,
+                    format!(
+                        "batch of {} transactions exceeds the maximum batch size of \
+                         {MAX_SUBMIT_BATCH_SIZE}",
+                        txs.len()
+                    ),
+                ));
+            }
+            let consensus = state.consensus();
+            // A failure to submit one transaction does not prevent the rest of the batch from
+            // being submitted, so we report errors individually rather than failing the request.
+            Ok(stream::iter(txs)
+                .map(|tx| async move {
+                    let hash = tx.commit();
+                    let error = consensus
+                        .submit_transaction(tx)
+                        .await
+                        .err()
+                        .map(|err| err.to_string());
+                    BatchSubmissionResult { hash, error }
+                })
+                .buffered(SUBMIT_BATCH_CONCURRENCY)
+                .collect::<Vec<_>>()
+                .await)
+        }
+        .boxed()
     })?;
 
     Ok(api)
 }
 
+fn check_submissions_enabled<N: network::Type>(
+    state: &impl SubmitDataSource<N>,
+) -> Result<(), Error> {
+    if state.submissions_enabled() {
+        Ok(())
+    } else {
+        Err(Error::catch_all(
+            StatusCode::ServiceUnavailable,
+            "this node is not accepting transactions".into(),
+        ))
+    }
+}
+
 pub(super) fn state_signature<N, S, Ver: StaticVersionType + 'static>(
     _: Ver,
 ) -> anyhow::Result<Api<S, Error, Ver>>
@@ -202,6 +504,26 @@ where
     Ok(api)
 }
 
+/// Get the state at the view given by the optional `view` parameter, or the decided state.
+async fn get_state<S: StateDataSource>(
+    req: &RequestParams,
+    state: &S,
+) -> Result<Arc<ValidatedState>, Error> {
+    match req
+        .opt_integer_param("view")
+        .map_err(Error::from_request_error)?
+    {
+        Some(view) => state
+            .get_undecided_state(ViewNumber::new(view))
+            .await
+            .ok_or(Error::catch_all(
+                StatusCode::NotFound,
+                format!("state not available for view {view}"),
+            )),
+        None => Ok(state.get_decided_state().await),
+    }
+}
+
 pub(super) fn catchup<S, Ver: StaticVersionType + 'static>(
     _: Ver,
 ) -> anyhow::Result<Api<S, Error, Ver>>
@@ -212,25 +534,6 @@ where
     let toml = toml::from_str::<toml::Value>(include_str!("../../api/catchup.toml"))?;
     let mut api = Api::<S, Error, Ver>::new(toml)?;
 
-    async fn get_state<S: StateDataSource>(
-        req: &tide_disco::RequestParams,
-        state: &S,
-    ) -> Result<Arc<ValidatedState>, Error> {
-        match req
-            .opt_integer_param("view")
-            .map_err(Error::from_request_error)?
-        {
-            Some(view) => state
-                .get_undecided_state(ViewNumber::new(view))
-                .await
-                .ok_or(Error::catch_all(
-                    StatusCode::NotFound,
-                    format!("state not available for view {view}"),
-                )),
-            None => Ok(state.get_decided_state().await),
-        }
-    }
-
     api.get("account", |req, state| {
         async move {
             let state = get_state(&req, state).await?;
@@ -272,6 +575,264 @@ where
             Ok(frontier)
         }
         .boxed()
+    })?
+    .get("snapshot", |_, state| {
+        async move {
+            // The decided leaf and state are read separately, so a decide in between can make them
+            // inconsistent. In that case the client should just try again.
+            let leaf = state.get_decided_leaf().await;
+            let snapshot = StateSnapshot {
+                state: (*state.get_decided_state().await).clone(),
+                leaf,
+                qc: None,
+            };
+            snapshot.verify(&snapshot.leaf).map_err(|err| {
+                Error::catch_all(
+                    StatusCode::ServiceUnavailable,
+                    format!("decided state changed while taking snapshot: {err:#}"),
+                )
+            })?;
+            Ok(snapshot)
+        }
+        .boxed()
+    })?;
+
+    Ok(api)
+}
+
+pub(super) fn health<S, Ver: StaticVersionType + 'static>(
+    thresholds: HealthThresholds,
+    _: Ver,
+) -> anyhow::Result<Api<S, Error, Ver>>
+where
+    S: 'static + Send + Sync + ReadState,
+    S::State: Send + Sync + HealthDataSource,
+{
+    let toml = toml::from_str::<toml::Value>(include_str!("../../api/health.toml"))?;
+    let mut api = Api::<S, Error, Ver>::new(toml)?;
+
+    fn check(report: HealthReport, problems: Vec<String>) -> Result<HealthReport, Error> {
+        if problems.is_empty() {
+            Ok(report)
+        } else {
+            Err(Error::catch_all(
+                StatusCode::ServiceUnavailable,
+                format!("node is unhealthy: {}", problems.join("; ")),
+            ))
+        }
+    }
+
+    api.get("status", move |_, state| {
+        async move { Ok(state.health(thresholds.max_l1_age).await) }.boxed()
+    })?
+    .get("live", move |_, state| {
+        async move {
+            let report = state.health(thresholds.max_l1_age).await;
+            let problems = report.liveness_problems(&thresholds);
+            check(report, problems)
+        }
+        .boxed()
+    })?
+    .get("ready", move |_, state| {
+        async move {
+            let report = state.health(thresholds.max_l1_age).await;
+            let problems = report.readiness_problems(&thresholds);
+            check(report, problems)
+        }
+        .boxed()
+    })?;
+
+    Ok(api)
+}
+
+pub(super) fn admin<S, Ver: StaticVersionType + 'static>(
+    token: String,
+    catchup_timeout: Duration,
+    _: Ver,
+) -> anyhow::Result<Api<S, Error, Ver>>
+where
+    S: 'static + Send + Sync + ReadState + WriteState,
+    S::State: Send + Sync + AdminDataSource + StateDataSource,
+{
+    let toml = toml::from_str::<toml::Value>(include_str!("../../api/admin.toml"))?;
+    let mut api = Api::<S, Error, Ver>::new(toml)?;
+    let token: Arc<str> = token.into();
+
+    // The most recently requested state summary. Operators tend to poll the same state repeatedly,
+    // and summarizing it means serializing every tree, so we only do that when the state changes.
+    let last_summary = Arc::new(std::sync::Mutex::new(None::<StateSummary>));
+
+    fn authorize(req: &RequestParams, token: &str) -> Result<(), Error> {
+        let authorized = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.last().as_str().strip_prefix("Bearer "))
+            .is_some_and(|provided| constant_time_eq(provided.as_bytes(), token.as_bytes()));
+        if authorized {
+            Ok(())
+        } else {
+            Err(Error::catch_all(
+                StatusCode::Unauthorized,
+                "missing or invalid admin token".into(),
+            ))
+        }
+    }
+
+    // Compare tokens in time that does not depend on where they first differ.
+    fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+
+    fn set_submit<S: AdminDataSource>(state: &S, enabled: bool) {
+        tracing::warn!(enabled, "admin API changed transaction submission");
+        state.submit_switch().set_enabled(enabled);
+    }
+
+    api.get("get_log_filter", {
+        let token = token.clone();
+        move |req, _| {
+            let auth = authorize(&req, &token);
+            async move {
+                auth?;
+                logging::filter().ok_or(Error::catch_all(
+                    StatusCode::NotImplemented,
+                    "log filter cannot be changed at runtime".into(),
+                ))
+            }
+            .boxed()
+        }
+    })?
+    .post("set_log_filter", {
+        let token = token.clone();
+        move |req, _| {
+            let auth = authorize(&req, &token);
+            async move {
+                auth?;
+                let directives = req
+                    .body_auto::<String, Ver>(Ver::instance())
+                    .map_err(Error::from_request_error)?;
+                if logging::filter().is_none() {
+                    return Err(Error::catch_all(
+                        StatusCode::NotImplemented,
+                        "log filter cannot be changed at runtime".into(),
+                    ));
+                }
+                logging::set_filter(&directives)
+                    .map_err(|err| Error::catch_all(StatusCode::BadRequest, format!("{err:#}")))
+            }
+            .boxed()
+        }
+    })?
+    .get("state", {
+        let token = token.clone();
+        move |req, state| {
+            let auth = authorize(&req, &token);
+            let last_summary = last_summary.clone();
+            async move {
+                auth?;
+                let state = get_state(&req, state).await?;
+                let cached = last_summary.lock().unwrap().clone();
+                if let Some(summary) = cached.filter(|summary| summary.summarizes(&state)) {
+                    return Ok(summary);
+                }
+                let summary = StateSummary::from(&*state);
+                *last_summary.lock().unwrap() = Some(summary.clone());
+                Ok(summary)
+            }
+            .boxed()
+        }
+    })?
+    .post("catchup", {
+        let token = token.clone();
+        move |req, state| {
+            let auth = authorize(&req, &token);
+            async move {
+                auth?;
+                let view = req
+                    .integer_param("view")
+                    .map_err(Error::from_request_error)?;
+                let body = req
+                    .body_auto::<CatchupRequest, Ver>(Ver::instance())
+                    .map_err(Error::from_request_error)?;
+                let root = state
+                    .get_undecided_state(ViewNumber::new(view))
+                    .await
+                    .ok_or(Error::catch_all(
+                        StatusCode::NotFound,
+                        format!("state not available for view {view}"),
+                    ))?
+                    .fee_merkle_tree
+                    .commitment();
+                if let Some(expected) = body.fee_merkle_tree_root {
+                    if expected != root {
+                        return Err(Error::catch_all(
+                            StatusCode::BadRequest,
+                            format!(
+                                "fee Merkle tree root does not match this node's state for \
+                                 view {view}"
+                            ),
+                        ));
+                    }
+                }
+                tracing::warn!(view, accounts = ?body.accounts, "admin API triggered catchup");
+                let accounts = async_std::future::timeout(
+                    catchup_timeout,
+                    state
+                        .catchup()
+                        .fetch_accounts(ViewNumber::new(view), root, body.accounts),
+                )
+                .await
+                .map_err(|_| {
+                    Error::catch_all(
+                        StatusCode::GatewayTimeout,
+                        format!("catchup for view {view} timed out"),
+                    )
+                })?;
+                state
+                    .remember_accounts(ViewNumber::new(view), &accounts)
+                    .await
+                    .map_err(|err| {
+                        Error::catch_all(
+                            StatusCode::InternalServerError,
+                            format!("failed to apply fetched accounts: {err:#}"),
+                        )
+                    })?;
+                Ok(accounts)
+            }
+            .boxed()
+        }
+    })?
+    .get("submit_status", {
+        let token = token.clone();
+        move |req, state| {
+            let auth = authorize(&req, &token);
+            async move {
+                auth?;
+                Ok(state.submit_switch().is_enabled())
+            }
+            .boxed()
+        }
+    })?
+    .post("enable_submit", {
+        let token = token.clone();
+        move |req, state| {
+            let auth = authorize(&req, &token);
+            async move {
+                auth?;
+                set_submit(state, true);
+                Ok(())
+            }
+            .boxed()
+        }
+    })?
+    .post("disable_submit", move |req, state| {
+        let auth = authorize(&req, &token);
+        async move {
+            auth?;
+            set_submit(state, false);
+            Ok(())
+        }
+        .boxed()
     })?;
 
     Ok(api)
@@ -291,3 +852,42 @@ where
     )?;
     Ok(api)
 }
+
+/// The merklized state API for the tree `T`, served from a file system [`MerkleStore`].
+pub(super) fn fs_merklized_state<S, T, Ver: StaticVersionType + 'static>(
+    store: MerkleStore,
+    _: Ver,
+) -> anyhow::Result<Api<S, Error, Ver>>
+where
+    S: 'static + Send + Sync + ReadState,
+    T: MerklizedState<SeqTypes>,
+{
+    let toml = toml::from_str::<toml::Value>(include_str!("../../api/fs_state.toml"))?;
+    let mut api = Api::<S, Error, Ver>::new(toml)?;
+
+    api.get("get_path", move |req, _| {
+        let store = store.clone();
+        async move {
+            let height = req
+                .integer_param("height")
+                .map_err(Error::from_request_error)?;
+            let key = req.string_param("key").map_err(Error::from_request_error)?;
+            let key = key.parse::<T::Key>().map_err(|_| {
+                Error::catch_all(StatusCode::BadRequest, format!("malformed key {key}"))
+            })?;
+            store
+                .get_path::<T>(height, key)
+                .await
+                .map_err(|err| {
+                    Error::catch_all(StatusCode::InternalServerError, format!("{err:#}"))
+                })?
+                .ok_or(Error::catch_all(
+                    StatusCode::NotFound,
+                    format!("state not available for block {height}"),
+                ))
+        }
+        .boxed()
+    })?;
+
+    Ok(api)
+}
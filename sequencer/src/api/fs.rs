@@ -1,9 +1,13 @@
-use super::data_source::{Provider, SequencerDataSource};
+use super::data_source::{MerklizedStateStorage, Provider, SequencerDataSource};
 use crate::{persistence::fs::Options, SeqTypes};
 use async_trait::async_trait;
 use hotshot_query_service::{data_source::FileSystemDataSource, merklized_state::MerklizedState};
 use jf_primitives::merkle_tree::prelude::MerklePath;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+mod merkle_store;
+
+pub use merkle_store::{MerkleStore, StateBatch};
 
 pub type DataSource = FileSystemDataSource<SeqTypes, Provider>;
 
@@ -23,7 +27,14 @@ impl SequencerDataSource for DataSource {
 
         Ok(data_source)
     }
+}
 
+#[async_trait]
+impl MerklizedStateStorage for DataSource {
+    /// The file system data source has no storage for merklized state of its own.
+    ///
+    /// Merklized state is kept in a separate [`MerkleStore`] (see [`merkle_store_path`]), which is
+    /// updated from consensus events when the state API is enabled.
     async fn store_state<S: MerklizedState<SeqTypes>>(
         &mut self,
         _path: MerklePath<S::Entry, S::Key, S::T>,
@@ -34,6 +45,11 @@ impl SequencerDataSource for DataSource {
     }
 }
 
+/// The location of the [`MerkleStore`] for a file system data source.
+pub fn merkle_store_path(opt: &Options) -> PathBuf {
+    opt.path.join("merklized_state")
+}
+
 #[cfg(test)]
 mod impl_testable_data_source {
     use super::*;
@@ -0,0 +1,968 @@
+//! File system storage for merklized state.
+
+use super::super::data_source::MerklizedStateStorage;
+use crate::SeqTypes;
+use anyhow::{anyhow, ensure, Context};
+use async_std::{sync::Arc, task::spawn_blocking};
+use async_trait::async_trait;
+use hotshot_query_service::merklized_state::MerklizedState;
+use jf_primitives::merkle_tree::{
+    prelude::{MerkleNode, MerklePath},
+    ToTraversalPath,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+/// Name of the log file within the store directory.
+const LOG_FILE: &str = "state.log";
+
+/// Name of the index checkpoint file within the store directory.
+const CHECKPOINT_FILE: &str = "index";
+
+/// Number of blocks between checkpoints of the index.
+///
+/// When the store is opened, only the part of the log written since the last checkpoint is read.
+const CHECKPOINT_INTERVAL: u64 = 1000;
+
+/// File system storage for snapshots of merklized state.
+///
+/// Like the Postgres tables for merklized state, this keeps a version of each Merkle tree node for
+/// every block at which the node changed, so that the path to any key can be read as of any block
+/// the store covers. The state at a block is made up of the latest version of each node created at
+/// or before that block.
+///
+/// The store covers a contiguous range of blocks, starting from the first block stored after the
+/// store was created, and ending at the latest block stored. Blocks must be stored in order. If a
+/// block is skipped, the nodes which changed in that block are missing, so the state at every later
+/// block would be wrong. Instead, the store is cleared and covers only blocks from the next one
+/// stored. Nodes which have not changed since the start of the range are not in the store at all,
+/// so paths through them are not available even for blocks in the range.
+///
+/// Only the last `retention` blocks are kept: when the store covers twice that many blocks, node
+/// versions which are no longer needed for the state at any of the last `retention` blocks are
+/// deleted and the log is compacted. This bounds both the log and the in-memory index.
+///
+/// All the nodes which changed in a block are written to an append-only log as a single entry, and
+/// synced to disk, so that a block which is interrupted part way through is discarded as a whole.
+/// An index of node versions is kept in memory, and checkpointed to disk periodically, while the
+/// nodes themselves are read from the log as needed. All file system access runs on the blocking
+/// thread pool.
+#[derive(Clone, Debug)]
+pub struct MerkleStore {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl MerkleStore {
+    /// Open the store in `dir`, creating it if it does not exist.
+    pub async fn open(dir: impl Into<PathBuf>, retention: u64) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        let inner = spawn_blocking(move || Inner::open(dir, retention)).await?;
+        Ok(Self {
+            inner: Arc::new(RwLock::new(inner)),
+        })
+    }
+
+    /// The range of blocks for which this store has state, if any.
+    pub fn range(&self) -> anyhow::Result<Option<(u64, u64)>> {
+        let inner = self.inner.read().map_err(|_| poisoned())?;
+        Ok(inner.first.zip(inner.last))
+    }
+
+    /// Store all the nodes which changed in a block.
+    ///
+    /// Blocks which are already covered by the store are ignored. If `batch` does not follow the
+    /// latest block in the store, the store is cleared first.
+    pub async fn append(&self, batch: StateBatch) -> anyhow::Result<()> {
+        let inner = self.inner.clone();
+        spawn_blocking(move || inner.write().map_err(|_| poisoned())?.append(batch)).await
+    }
+
+    /// Get the path to `key` in the tree `S` as of block `height`.
+    ///
+    /// Returns [`None`] if the store does not have the state needed to answer, either because
+    /// `height` is outside the range of blocks covered by the store or because the path goes
+    /// through a part of the tree which has not changed since the start of that range.
+    pub async fn get_path<S: MerklizedState<SeqTypes>>(
+        &self,
+        height: u64,
+        key: S::Key,
+    ) -> anyhow::Result<Option<MerklePath<S::Entry, S::Key, S::T>>> {
+        let tree_height = S::tree_height();
+        let branches = root_first(<S::Key as ToTraversalPath<S::Arity>>::to_traversal_path(
+            &key,
+            tree_height,
+        ));
+
+        // Read the versions of the nodes along the path to `key`, starting from the root, then
+        // decode them off the blocking thread pool.
+        let inner = self.inner.clone();
+        let tree = S::state_type();
+        let positions = branches.clone();
+        let Some(nodes) = spawn_blocking(move || {
+            inner
+                .read()
+                .map_err(|_| poisoned())?
+                .read_path(tree, &positions, height)
+        })
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        // Walk down from the root, towards the leaf for `key`.
+        let mut path = vec![];
+        for depth in 0..=tree_height {
+            let Some(bytes) = nodes.get(depth) else {
+                // We have never stored this node, or not since the start of the range covered by
+                // the store.
+                return Ok(None);
+            };
+            let node: MerkleNode<S::Entry, S::Key, S::T> = bincode::deserialize(bytes)?;
+            let (is_branch, next_is_empty) = match &node {
+                MerkleNode::Branch { children, .. } => (
+                    true,
+                    depth < tree_height && matches!(*children[branches[depth]], MerkleNode::Empty),
+                ),
+                _ => (false, false),
+            };
+            path.push(node);
+            if next_is_empty {
+                // The key is not in the tree, and this is as far down as the tree goes.
+                path.push(MerkleNode::Empty);
+                break;
+            }
+            if !is_branch {
+                break;
+            }
+        }
+
+        // Merkle paths start at the bottom of the tree.
+        path.reverse();
+        Ok(Some(path))
+    }
+}
+
+/// The nodes which changed in a single block, to be written to a [`MerkleStore`] all at once.
+#[derive(Debug)]
+pub struct StateBatch {
+    created: u64,
+    /// For each tree, each node which changed, serialized, by position.
+    trees: BTreeMap<String, BTreeMap<Vec<usize>, Vec<u8>>>,
+}
+
+impl StateBatch {
+    /// An empty batch of changes in block `created`.
+    pub fn new(created: u64) -> Self {
+        Self {
+            created,
+            trees: Default::default(),
+        }
+    }
+
+    /// Add the nodes of `path`, the path to a key in the tree `S`.
+    ///
+    /// `traversal_path` gives the branch taken at each level of the tree to reach the key, starting
+    /// from the leaf level.
+    pub fn insert<S: MerklizedState<SeqTypes>>(
+        &mut self,
+        path: MerklePath<S::Entry, S::Key, S::T>,
+        traversal_path: Vec<usize>,
+    ) -> anyhow::Result<()> {
+        let tree_height = S::tree_height();
+        ensure!(
+            traversal_path.len() == tree_height,
+            "traversal path has length {}, but tree height is {tree_height}",
+            traversal_path.len()
+        );
+        ensure!(
+            !path.is_empty() && path.len() <= tree_height + 1,
+            "Merkle path has invalid length {}",
+            path.len()
+        );
+
+        // `path` starts at the bottom of the tree and ends at the root. We index nodes by their
+        // position, which is the sequence of branches taken to reach them from the root.
+        let branches = root_first(traversal_path);
+        let depth = path.len() - 1;
+        let nodes = self.trees.entry(S::state_type().to_string()).or_default();
+        for (i, node) in path.iter().enumerate() {
+            if matches!(node, MerkleNode::Empty) {
+                continue;
+            }
+            let pos = branches[..depth - i].to_vec();
+            nodes.insert(pos, bincode::serialize(node)?);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MerklizedStateStorage for StateBatch {
+    async fn store_state<S: MerklizedState<SeqTypes>>(
+        &mut self,
+        path: MerklePath<S::Entry, S::Key, S::T>,
+        traversal_path: Vec<usize>,
+        block_number: u64,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            block_number == self.created,
+            "cannot add state for block {block_number} to batch for block {}",
+            self.created
+        );
+        self.insert::<S>(path, traversal_path)
+    }
+}
+
+/// An entry in the log.
+#[derive(Debug, Serialize, Deserialize)]
+enum Record {
+    /// All the nodes which changed in one block.
+    Block(Batch),
+    /// The log has been compacted, and only covers blocks starting from `first`.
+    ///
+    /// This is always the first entry in a compacted log.
+    Pruned { first: u64 },
+}
+
+/// All the nodes which changed in one block, for each tree.
+#[derive(Debug, Serialize, Deserialize)]
+struct Batch {
+    created: u64,
+    /// For each tree, each node, serialized, with its position.
+    trees: Vec<(String, Vec<(Vec<usize>, Vec<u8>)>)>,
+}
+
+impl From<StateBatch> for Batch {
+    fn from(batch: StateBatch) -> Self {
+        Self {
+            created: batch.created,
+            trees: batch
+                .trees
+                .into_iter()
+                .map(|(tree, nodes)| (tree, nodes.into_iter().collect()))
+                .collect(),
+        }
+    }
+}
+
+/// Location of a node in the log.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct NodeLocation {
+    /// Offset of the entry containing the node.
+    offset: u64,
+    /// Length of the entry containing the node.
+    len: u32,
+    /// Index of the tree within its batch.
+    tree: usize,
+    /// Index of the node within its tree.
+    index: usize,
+}
+
+/// For each tree, for each node position, the location of each version of that node, by block.
+type Index = HashMap<String, HashMap<Vec<usize>, BTreeMap<u64, NodeLocation>>>;
+
+/// A snapshot of the index, covering the log up to `log_len`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    log_len: u64,
+    first: Option<u64>,
+    last: Option<u64>,
+    index: Index,
+}
+
+#[derive(Debug)]
+struct Inner {
+    dir: PathBuf,
+    log: File,
+    log_len: u64,
+    index: Index,
+    /// The first block covered by the store.
+    first: Option<u64>,
+    /// The latest block for which state has been stored.
+    last: Option<u64>,
+    /// The number of recent blocks for which to keep state.
+    retention: u64,
+    /// The number of blocks stored since the last checkpoint.
+    since_checkpoint: u64,
+}
+
+impl Inner {
+    /// Open the store in `dir`, creating it if it does not exist.
+    ///
+    /// If the log ends with an incomplete entry, because the process stopped while writing it, the
+    /// incomplete entry is discarded.
+    fn open(dir: PathBuf, retention: u64) -> anyhow::Result<Self> {
+        ensure!(retention > 0, "merklized state retention must be positive");
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create merklized state dir {}", dir.display()))?;
+        let log = open_log(&dir.join(LOG_FILE))?;
+        let file_len = log.metadata()?.len();
+
+        let mut inner = Self {
+            dir,
+            log,
+            log_len: 0,
+            index: Default::default(),
+            first: None,
+            last: None,
+            retention,
+            since_checkpoint: 0,
+        };
+
+        // Start from the last checkpoint, if there is a usable one, so that we only have to read
+        // the end of the log.
+        match inner.load_checkpoint() {
+            Ok(Some(checkpoint)) if checkpoint.log_len <= file_len => {
+                inner.log_len = checkpoint.log_len;
+                inner.index = checkpoint.index;
+                inner.first = checkpoint.first;
+                inner.last = checkpoint.last;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                tracing::warn!("ignoring merklized state index checkpoint: {err:#}");
+            }
+        }
+
+        let mut reader = BufReader::new(inner.log.try_clone()?);
+        reader.seek(SeekFrom::Start(inner.log_len))?;
+        while let Some((record, len)) = read_record(&mut reader)? {
+            let offset = inner.log_len;
+            inner.log_len += 4 + len as u64;
+            inner.index_record(&record, offset, len);
+        }
+
+        // Drop anything after the last complete entry.
+        if file_len > inner.log_len {
+            tracing::warn!(
+                dir = %inner.dir.display(),
+                offset = inner.log_len,
+                file_len,
+                "discarding incomplete entry at end of merklized state log"
+            );
+            inner.log.set_len(inner.log_len)?;
+            inner.log.sync_all()?;
+        }
+
+        Ok(inner)
+    }
+
+    fn append(&mut self, batch: StateBatch) -> anyhow::Result<()> {
+        let height = batch.created;
+        if let Some(last) = self.last {
+            if height <= last {
+                tracing::debug!(height, last, "merklized state already stored");
+                return Ok(());
+            }
+            if height > last + 1 {
+                tracing::warn!(
+                    height,
+                    last,
+                    "merklized state is missing blocks, clearing the store"
+                );
+                self.clear()?;
+            }
+        }
+
+        let record = Record::Block(batch.into());
+        let offset = self.log_len;
+        let len = match write_record(&mut self.log, &record) {
+            Ok(len) => len,
+            Err(err) => {
+                // Try not to leave a partial entry behind, which would be mistaken for the start of
+                // the next one. If this fails as well, the partial entry is discarded on the next
+                // open.
+                self.log.set_len(offset).ok();
+                return Err(err)
+                    .with_context(|| format!("failed to write to {}", self.log_path().display()));
+            }
+        };
+        self.log.sync_data()?;
+        self.log_len += 4 + len as u64;
+        self.index_record(&record, offset, len);
+
+        let (first, last) = (self.first.unwrap_or(height), height);
+        if last + 1 - first >= 2 * self.retention {
+            self.prune(last + 1 - self.retention)?;
+        } else if self.since_checkpoint >= CHECKPOINT_INTERVAL {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    fn index_record(&mut self, record: &Record, offset: u64, len: u32) {
+        match record {
+            Record::Block(batch) => {
+                index_batch(&mut self.index, batch, offset, len);
+                self.first = self.first.or(Some(batch.created));
+                self.last = self.last.max(Some(batch.created));
+                self.since_checkpoint += 1;
+            }
+            Record::Pruned { first } => self.first = Some(*first),
+        }
+    }
+
+    /// Read the nodes at each of the positions along `branches`, starting from the root, as of
+    /// block `height`.
+    ///
+    /// Stops at the first node which is not in the store. Returns [`None`] if `height` is not
+    /// covered by the store.
+    fn read_path(
+        &self,
+        tree: &str,
+        branches: &[usize],
+        height: u64,
+    ) -> anyhow::Result<Option<Vec<Vec<u8>>>> {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) if first <= height && height <= last => {}
+            _ => return Ok(None),
+        }
+        let Some(versions) = self.index.get(tree) else {
+            return Ok(Some(vec![]));
+        };
+
+        let mut file = File::open(self.log_path())
+            .with_context(|| format!("failed to open {}", self.log_path().display()))?;
+        let mut nodes = vec![];
+        for depth in 0..=branches.len() {
+            let Some((_, loc)) = versions
+                .get(&branches[..depth])
+                .and_then(|versions| versions.range(..=height).next_back())
+            else {
+                break;
+            };
+            file.seek(SeekFrom::Start(loc.offset + 4))?;
+            let mut bytes = vec![0; loc.len as usize];
+            file.read_exact(&mut bytes)?;
+            let record: Record = bincode::deserialize(&bytes)?;
+            let Record::Block(mut batch) = record else {
+                return Err(anyhow!("expected a block at offset {}", loc.offset));
+            };
+            let (_, mut tree_nodes) = batch.trees.swap_remove(loc.tree);
+            let (_, node) = tree_nodes.swap_remove(loc.index);
+            nodes.push(node);
+        }
+        Ok(Some(nodes))
+    }
+
+    /// Delete everything in the store.
+    fn clear(&mut self) -> anyhow::Result<()> {
+        self.remove_checkpoint()?;
+        self.log.set_len(0)?;
+        self.log.sync_all()?;
+        self.log_len = 0;
+        self.index.clear();
+        self.first = None;
+        self.last = None;
+        self.since_checkpoint = 0;
+        Ok(())
+    }
+
+    /// Delete node versions which are not needed for the state at `first` or any later block, and
+    /// compact the log.
+    fn prune(&mut self, first: u64) -> anyhow::Result<()> {
+        tracing::info!(first, "pruning merklized state");
+
+        // For each node, keep the version current at `first` and every later version.
+        for tree in self.index.values_mut() {
+            tree.retain(|_, versions| {
+                let keep = versions.range(..=first).next_back().map(|(&keep, _)| keep);
+                if let Some(keep) = keep {
+                    *versions = versions.split_off(&keep);
+                }
+                !versions.is_empty()
+            });
+        }
+
+        // Copy the versions we are keeping to a new log. Remove the checkpoint first, since it
+        // refers to offsets in the old log.
+        self.remove_checkpoint()?;
+        let tmp_path = self.dir.join(format!("{LOG_FILE}.tmp"));
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        let mut index = Index::default();
+        let mut log_len = 4 + write_record(&mut writer, &Record::Pruned { first })? as u64;
+
+        let mut reader = BufReader::new(File::open(self.log_path())?);
+        while let Some((record, _)) = read_record(&mut reader)? {
+            let Record::Block(mut batch) = record else {
+                continue;
+            };
+            let created = batch.created;
+            for (name, nodes) in &mut batch.trees {
+                let tree = self.index.get(name);
+                nodes.retain(|(pos, _)| {
+                    tree.and_then(|tree| tree.get(pos))
+                        .map_or(false, |versions| versions.contains_key(&created))
+                });
+            }
+            batch.trees.retain(|(_, nodes)| !nodes.is_empty());
+            if batch.trees.is_empty() {
+                continue;
+            }
+
+            let record = Record::Block(batch);
+            let len = write_record(&mut writer, &record)?;
+            if let Record::Block(batch) = &record {
+                index_batch(&mut index, batch, log_len, len);
+            }
+            log_len += 4 + len as u64;
+        }
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+
+        fs::rename(&tmp_path, self.log_path())?;
+        File::open(&self.dir)?.sync_all()?;
+        self.log = open_log(&self.log_path())?;
+        self.log_len = log_len;
+        self.index = index;
+        self.first = Some(first);
+        self.checkpoint()
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.dir.join(LOG_FILE)
+    }
+
+    fn checkpoint_path(&self) -> PathBuf {
+        self.dir.join(CHECKPOINT_FILE)
+    }
+
+    fn load_checkpoint(&self) -> anyhow::Result<Option<Checkpoint>> {
+        let file = match File::open(self.checkpoint_path()) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some(bincode::deserialize_from(BufReader::new(file))?))
+    }
+
+    /// Save the index, so that the log does not have to be read from the start on the next open.
+    fn checkpoint(&mut self) -> anyhow::Result<()> {
+        let checkpoint = CheckpointRef {
+            log_len: self.log_len,
+            first: self.first,
+            last: self.last,
+            index: &self.index,
+        };
+        let tmp_path = self.dir.join(format!("{CHECKPOINT_FILE}.tmp"));
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        bincode::serialize_into(&mut writer, &checkpoint)?;
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp_path, self.checkpoint_path())?;
+        self.since_checkpoint = 0;
+        Ok(())
+    }
+
+    fn remove_checkpoint(&self) -> anyhow::Result<()> {
+        match fs::remove_file(self.checkpoint_path()) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Borrowed form of [`Checkpoint`], for writing without cloning the index.
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+    log_len: u64,
+    first: Option<u64>,
+    last: Option<u64>,
+    index: &'a Index,
+}
+
+fn index_batch(index: &mut Index, batch: &Batch, offset: u64, len: u32) {
+    for (tree, (name, nodes)) in batch.trees.iter().enumerate() {
+        let versions = index.entry(name.clone()).or_default();
+        for (i, (pos, _)) in nodes.iter().enumerate() {
+            versions.entry(pos.clone()).or_default().insert(
+                batch.created,
+                NodeLocation {
+                    offset,
+                    len,
+                    tree,
+                    index: i,
+                },
+            );
+        }
+    }
+}
+
+fn open_log(path: &Path) -> anyhow::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))
+}
+
+/// Read the next entry in a log, with its length.
+///
+/// Returns [`None`] at the end of the log, including if the last entry is incomplete.
+fn read_record(reader: &mut impl Read) -> anyhow::Result<Option<(Record, u32)>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = u32::from_le_bytes(len);
+    let mut bytes = vec![0; len as usize];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let record = bincode::deserialize(&bytes).context("corrupt merklized state log entry")?;
+    Ok(Some((record, len)))
+}
+
+/// Write an entry to a log, returning its length.
+///
+/// The whole entry is written at once, so that a failed write leaves at most one incomplete entry
+/// at the end of the log.
+fn write_record(writer: &mut impl Write, record: &Record) -> anyhow::Result<u32> {
+    let bytes = bincode::serialize(record)?;
+    let len = u32::try_from(bytes.len()).context("log entry too large")?;
+    let mut buf = Vec::with_capacity(4 + bytes.len());
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(&bytes);
+    writer.write_all(&buf)?;
+    Ok(len)
+}
+
+fn poisoned() -> anyhow::Error {
+    anyhow!("merklized state store lock poisoned")
+}
+
+/// Reverse a traversal path, so that it starts from the root.
+fn root_first(mut traversal_path: Vec<usize>) -> Vec<usize> {
+    traversal_path.reverse();
+    traversal_path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        api::{self, data_source::store_merklized_state, options, testing::TestNetwork},
+        persistence,
+        state::{BlockMerkleTree, Delta, FeeAccount, FeeAmount, FeeMerkleTree},
+        Header, Leaf, NodeState, ValidatedState,
+    };
+    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use async_std::task::sleep;
+    use commit::Committable;
+    use es_version::SequencerVersion;
+    use ethers::types::Address;
+    use jf_primitives::merkle_tree::{
+        AppendableMerkleTreeScheme, LookupResult, MerkleCommitment, MerkleTreeScheme,
+        UniversalMerkleTreeScheme,
+    };
+    use portpicker::pick_unused_port;
+    use std::{collections::HashSet, time::Duration};
+    use surf_disco::Client;
+    use tempfile::TempDir;
+    use tide_disco::{error::ServerError, Error as _, StatusCode};
+
+    type BlockPath = MerklePath<
+        <BlockMerkleTree as MerklizedState<SeqTypes>>::Entry,
+        <BlockMerkleTree as MerklizedState<SeqTypes>>::Key,
+        <BlockMerkleTree as MerklizedState<SeqTypes>>::T,
+    >;
+
+    fn accounts() -> [FeeAccount; 3] {
+        [1, 2, 3].map(|i| FeeAccount::from(Address::from_low_u64_be(i)))
+    }
+
+    /// Apply block `height` to `state`, updating `accounts`, and return the changes.
+    async fn apply_block(
+        state: &mut ValidatedState,
+        height: u64,
+        accounts: &[FeeAccount],
+    ) -> StateBatch {
+        let header = Leaf::genesis(&NodeState::mock())
+            .get_block_header()
+            .commit();
+        for account in accounts {
+            state
+                .fee_merkle_tree
+                .update(*account, FeeAmount::from(height))
+                .unwrap();
+        }
+        state.block_merkle_tree.push(header).unwrap();
+
+        let delta = Delta {
+            fees_delta: accounts.iter().copied().collect::<HashSet<_>>(),
+        };
+        let mut batch = StateBatch::new(height);
+        store_merklized_state(state, &mut batch, height, &delta)
+            .await
+            .unwrap();
+        batch
+    }
+
+    /// Check that `store` has the same state at `height` as `snapshot`.
+    async fn check_state(store: &MerkleStore, height: u64, snapshot: &ValidatedState) {
+        for account in accounts() {
+            let path = store
+                .get_path::<FeeMerkleTree>(height, account)
+                .await
+                .unwrap()
+                .unwrap();
+            let expected = match snapshot.fee_merkle_tree.universal_lookup(account) {
+                LookupResult::Ok(_, proof) => proof.proof,
+                LookupResult::NotFound(proof) => proof.proof,
+                LookupResult::NotInMemory => panic!("account {account} not in memory"),
+            };
+            assert_eq!(path, expected, "account {account} at height {height}");
+        }
+
+        // The block tree only keeps the path to the latest block in memory.
+        let path = store
+            .get_path::<BlockMerkleTree>(height, height - 1)
+            .await
+            .unwrap()
+            .unwrap();
+        let (_, proof) = snapshot
+            .block_merkle_tree
+            .lookup(height - 1)
+            .expect_ok()
+            .unwrap();
+        assert_eq!(path, proof.proof, "block tree at height {height}");
+    }
+
+    #[async_std::test]
+    async fn test_merkle_store() {
+        setup_logging();
+        setup_backtrace();
+
+        let dir = TempDir::new().unwrap();
+        let store = MerkleStore::open(dir.path(), 100).await.unwrap();
+
+        // Record the state after each block, updating one of the first two accounts in each block.
+        // The third account is never set.
+        let accounts = accounts();
+        let mut state = ValidatedState::default();
+        let mut snapshots = vec![];
+        for height in 1..=5u64 {
+            let batch = apply_block(&mut state, height, &[accounts[height as usize % 2]]).await;
+            store.append(batch).await.unwrap();
+            snapshots.push(state.clone());
+        }
+        assert_eq!(store.range().unwrap(), Some((1, 5)));
+
+        // Storing a block again does nothing.
+        let batch = apply_block(&mut state.clone(), 5, &[accounts[2]]).await;
+        store.append(batch).await.unwrap();
+
+        // Reopen the store, with a partially written entry at the end of the log, which should be
+        // ignored.
+        drop(store);
+        OpenOptions::new()
+            .append(true)
+            .open(dir.path().join(LOG_FILE))
+            .unwrap()
+            .write_all(&[100, 0, 0, 0, 1, 2, 3])
+            .unwrap();
+        let store = MerkleStore::open(dir.path(), 100).await.unwrap();
+        assert_eq!(store.range().unwrap(), Some((1, 5)));
+
+        for (height, snapshot) in (1..).zip(&snapshots) {
+            check_state(&store, height, snapshot).await;
+        }
+
+        // There is no state after the last block.
+        assert_eq!(store.get_path::<BlockMerkleTree>(6, 0).await.unwrap(), None);
+    }
+
+    #[async_std::test]
+    async fn test_merkle_store_gap() {
+        setup_logging();
+        setup_backtrace();
+
+        let dir = TempDir::new().unwrap();
+        let store = MerkleStore::open(dir.path(), 100).await.unwrap();
+        let accounts = accounts();
+
+        let mut state = ValidatedState::default();
+        for height in 1..=3 {
+            let batch = apply_block(&mut state, height, &[accounts[0]]).await;
+            store.append(batch).await.unwrap();
+        }
+
+        // Skip block 4, which changes one of the accounts.
+        apply_block(&mut state, 4, &[accounts[1]]).await;
+        let batch = apply_block(&mut state, 5, &[accounts[0]]).await;
+        store.append(batch).await.unwrap();
+
+        // The store starts over from block 5, since it cannot give correct state at later blocks
+        // without block 4.
+        assert_eq!(store.range().unwrap(), Some((5, 5)));
+        assert_eq!(
+            store
+                .get_path::<FeeMerkleTree>(3, accounts[0])
+                .await
+                .unwrap(),
+            None
+        );
+
+        // The paths which changed in block 5 are available.
+        let path = store
+            .get_path::<FeeMerkleTree>(5, accounts[0])
+            .await
+            .unwrap()
+            .unwrap();
+        let (_, proof) = state
+            .fee_merkle_tree
+            .universal_lookup(accounts[0])
+            .expect_ok()
+            .unwrap();
+        assert_eq!(path, proof.proof);
+        let path = store
+            .get_path::<BlockMerkleTree>(5, 4)
+            .await
+            .unwrap()
+            .unwrap();
+        let (_, proof) = state.block_merkle_tree.lookup(4).expect_ok().unwrap();
+        assert_eq!(path, proof.proof);
+
+        // Paths through parts of the tree which have not changed since the store started over are
+        // not available.
+        assert_eq!(store.get_path::<BlockMerkleTree>(5, 0).await.unwrap(), None);
+    }
+
+    #[async_std::test]
+    async fn test_merkle_store_prune() {
+        setup_logging();
+        setup_backtrace();
+
+        let dir = TempDir::new().unwrap();
+        let store = MerkleStore::open(dir.path(), 2).await.unwrap();
+        let accounts = accounts();
+
+        // The third account only changes in the first block, so the version from that block must
+        // survive pruning.
+        let mut state = ValidatedState::default();
+        let mut snapshots = vec![];
+        for height in 1..=6u64 {
+            let mut updates = vec![accounts[height as usize % 2]];
+            if height == 1 {
+                updates.push(accounts[2]);
+            }
+            let batch = apply_block(&mut state, height, &updates).await;
+            store.append(batch).await.unwrap();
+            snapshots.push(state.clone());
+        }
+
+        // Once the store covers twice the retention, it is pruned to the retention.
+        assert_eq!(store.range().unwrap(), Some((5, 6)));
+        assert_eq!(store.get_path::<BlockMerkleTree>(4, 3).await.unwrap(), None);
+        for height in 5..=6 {
+            check_state(&store, height, &snapshots[height as usize - 1]).await;
+        }
+
+        // Pruning survives a restart, both from the checkpoint and from the log alone.
+        drop(store);
+        let store = MerkleStore::open(dir.path(), 2).await.unwrap();
+        assert_eq!(store.range().unwrap(), Some((5, 6)));
+        for height in 5..=6 {
+            check_state(&store, height, &snapshots[height as usize - 1]).await;
+        }
+        drop(store);
+        fs::remove_file(dir.path().join(CHECKPOINT_FILE)).unwrap();
+        let store = MerkleStore::open(dir.path(), 2).await.unwrap();
+        assert_eq!(store.range().unwrap(), Some((5, 6)));
+        for height in 5..=6 {
+            check_state(&store, height, &snapshots[height as usize - 1]).await;
+        }
+
+        // The store keeps working after pruning.
+        let batch = apply_block(&mut state, 7, &[accounts[1]]).await;
+        store.append(batch).await.unwrap();
+        check_state(&store, 7, &state).await;
+    }
+
+    #[async_std::test]
+    async fn test_fs_state_api() {
+        setup_logging();
+        setup_backtrace();
+
+        // Start a sequencer network with the state API backed by the file system.
+        let port = pick_unused_port().expect("No ports free");
+        let storage = TempDir::new().unwrap();
+        let _network = TestNetwork::new(
+            api::Options::from(options::Http { port })
+                .query_fs(
+                    Default::default(),
+                    persistence::fs::Options {
+                        path: storage.path().into(),
+                    },
+                )
+                .state(Default::default()),
+        )
+        .await;
+        let client: Client<ServerError, SequencerVersion> =
+            Client::new(format!("http://localhost:{port}").parse().unwrap());
+        client.connect(None).await;
+
+        // Wait for a few blocks, so that the update loop has stored some state.
+        let height = loop {
+            let height = client
+                .get::<u64>("status/block-height")
+                .send()
+                .await
+                .unwrap();
+            if height > 3 {
+                break height - 1;
+            }
+            sleep(Duration::from_millis(100)).await;
+        };
+        let header: Header = client
+            .get(&format!("availability/header/{height}"))
+            .send()
+            .await
+            .unwrap();
+
+        // The path to the latest block leads to the root in the header.
+        let path = loop {
+            match client
+                .get::<BlockPath>(&format!("state/blocks/{height}/{}", height - 1))
+                .send()
+                .await
+            {
+                Ok(path) => break path,
+                Err(err) if err.status() == StatusCode::NotFound => {
+                    tracing::info!(height, "waiting for state");
+                    sleep(Duration::from_millis(100)).await;
+                }
+                Err(err) => panic!("unexpected error: {err:#}"),
+            }
+        };
+        let root = match path.last() {
+            Some(MerkleNode::Branch { value, .. }) => *value,
+            node => panic!("path does not end at a branch: {node:?}"),
+        };
+        assert_eq!(root, header.block_merkle_tree_root.digest());
+
+        // State which is not in the store is reported as not found.
+        let err = client
+            .get::<BlockPath>(&format!("state/blocks/{}/0", height + 1000))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::NotFound);
+    }
+}
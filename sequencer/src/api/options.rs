@@ -2,29 +2,39 @@
 
 use super::{
     data_source::{
-        provider, SequencerDataSource, StateDataSource, StateSignatureDataSource, SubmitDataSource,
+        provider, AdminDataSource, HealthDataSource, SequencerDataSource, StateDataSource,
+        StateSignatureDataSource, SubmitDataSource,
     },
     endpoints, fs, sql,
-    update::update_loop,
+    update::{merkle_store_update_loop, update_loop},
     StorageState,
 };
 use crate::{
     context::SequencerContext,
-    network, persistence,
+    health::HealthThresholds,
+    network,
+    options::parse_duration,
+    persistence::{
+        self,
+        sql::{MerklePruner, MerklePrunerCfg},
+        PersistenceOptions,
+    },
     state::{BlockMerkleTree, FeeMerkleTree},
     SeqTypes,
 };
 use anyhow::bail;
 use async_std::sync::{Arc, RwLock};
-use clap::Parser;
-use futures::future::BoxFuture;
+use clap::{builder::NonEmptyStringValueParser, Parser};
+use derivative::Derivative;
+use futures::future::{BoxFuture, FutureExt};
 use hotshot_query_service::{
-    data_source::{ExtensibleDataSource, MetricsDataSource},
+    data_source::{ExtensibleDataSource, MetricsDataSource, VersionedDataSource},
     merklized_state::MerklizedStateDataSource,
     status::{self, UpdateStatusData},
     Error,
 };
 use hotshot_types::traits::metrics::{Metrics, NoMetrics};
+use std::time::Duration;
 use tide_disco::{
     method::{ReadState, WriteState},
     App, Url,
@@ -41,6 +51,8 @@ pub struct Options {
     pub status: Option<Status>,
     pub catchup: Option<Catchup>,
     pub state: Option<State>,
+    pub health: Option<Health>,
+    pub admin: Option<Admin>,
     pub hotshot_events: Option<HotshotEvents>,
     pub storage_fs: Option<persistence::fs::Options>,
     pub storage_sql: Option<persistence::sql::Options>,
@@ -55,6 +67,8 @@ impl From<Http> for Options {
             status: None,
             catchup: None,
             state: None,
+            health: None,
+            admin: None,
             hotshot_events: None,
             storage_fs: None,
             storage_sql: None,
@@ -101,6 +115,18 @@ impl Options {
         self
     }
 
+    /// Add a health API module.
+    pub fn health(mut self, opt: Health) -> Self {
+        self.health = Some(opt);
+        self
+    }
+
+    /// Add an admin API module.
+    pub fn admin(mut self, opt: Admin) -> Self {
+        self.admin = Some(opt);
+        self
+    }
+
     /// Add a Hotshot events streaming API module.
     pub fn hotshot_events(mut self, opt: HotshotEvents) -> Self {
         self.hotshot_events = Some(opt);
@@ -126,21 +152,47 @@ impl Options {
         N: network::Type,
         F: FnOnce(Box<dyn Metrics>) -> BoxFuture<'static, SequencerContext<N, Ver>>,
     {
+        if self.query.is_none() {
+            if let Some(cfg) = self
+                .storage_sql
+                .as_ref()
+                .and_then(|opt| opt.merkle_pruner_cfg())
+            {
+                tracing::warn!(
+                    ?cfg,
+                    "Merkle state pruning requires the query module, the pruner will not run"
+                );
+            }
+        }
+
         // The server state type depends on whether we are running a query or status API or not, so
         // we handle the two cases differently.
         if let Some(query_opt) = self.query.take() {
             if let Some(opt) = self.storage_sql.take() {
+                let merkle_pruner = match opt.merkle_pruner_cfg() {
+                    Some(cfg) => Some((opt.clone().create().await?, cfg)),
+                    None => None,
+                };
                 self.init_with_query_module_sql::<N, sql::DataSource, Ver>(
                     query_opt,
                     opt,
+                    merkle_pruner,
                     init_context,
                     bind_version,
                 )
                 .await
             } else if let Some(opt) = self.storage_fs.take() {
+                let merkle_store = match self.state {
+                    Some(state) => Some(
+                        fs::MerkleStore::open(fs::merkle_store_path(&opt), state.fs_retention)
+                            .await?,
+                    ),
+                    None => None,
+                };
                 self.init_with_query_module_fs::<N, fs::DataSource, Ver>(
                     query_opt,
                     opt,
+                    merkle_store,
                     init_context,
                     bind_version,
                 )
@@ -153,9 +205,11 @@ impl Options {
             // which allows us to run the status API with no persistent storage.
             let ds = MetricsDataSource::default();
             let mut context = init_context(ds.populate_metrics()).await;
-            let mut app = App::<_, Error, Ver>::with_state(Arc::new(RwLock::new(
-                ExtensibleDataSource::new(ds, super::State::from(&context)),
+            let state = Arc::new(RwLock::new(ExtensibleDataSource::new(
+                ds,
+                super::State::from(&context),
             )));
+            let mut app = App::<_, Error, Ver>::with_state(state.clone());
 
             // Initialize status API.
             let status_api = status::define_api(&Default::default(), bind_version)?;
@@ -167,11 +221,7 @@ impl Options {
                 self.init_and_spawn_hotshot_event_streaming_module(&mut context, bind_version)?;
             }
 
-            context.spawn(
-                "API server",
-                app.serve(format!("0.0.0.0:{}", self.http.port), bind_version),
-            );
-
+            spawn_server(&mut context, app, state, self.http.port, bind_version);
             Ok(context)
         } else {
             // If no status or availability API is requested, we don't need metrics or a query
@@ -181,8 +231,8 @@ impl Options {
             // If we have no availability API, we cannot load a saved leaf from local storage, so we
             // better have been provided the leaf ahead of time if we want it at all.
             let mut context = init_context(Box::new(NoMetrics)).await;
-            let mut app =
-                App::<_, Error, Ver>::with_state(RwLock::new(super::State::from(&context)));
+            let state = Arc::new(RwLock::new(super::State::from(&context)));
+            let mut app = App::<_, Error, Ver>::with_state(state.clone());
 
             self.init_hotshot_modules(&mut app)?;
 
@@ -190,10 +240,7 @@ impl Options {
                 self.init_and_spawn_hotshot_event_streaming_module(&mut context, bind_version)?;
             }
 
-            context.spawn(
-                "API server",
-                app.serve(format!("0.0.0.0:{}", self.http.port), bind_version),
-            );
+            spawn_server(&mut context, app, state, self.http.port, bind_version);
 
             Ok(context)
         }
@@ -207,6 +254,7 @@ impl Options {
     ) -> anyhow::Result<(
         SequencerContext<N, Ver>,
         App<Arc<RwLock<StorageState<N, D, Ver>>>, Error, Ver>,
+        Arc<RwLock<StorageState<N, D, Ver>>>,
     )>
     where
         N: network::Type,
@@ -244,15 +292,26 @@ impl Options {
 
         self.init_hotshot_modules(&mut app)?;
 
-        context.spawn("query storage updater", update_loop(state, events));
+        // The update loop is a background task, so it is cancelled before this flush runs during a
+        // graceful shutdown, and the commit includes every update it applied.
+        context.spawn("query storage updater", update_loop(state.clone(), events));
+        let flush_state = state.clone();
+        context.on_shutdown("query data source", move || {
+            async move {
+                flush_state.write().await.commit().await?;
+                Ok(())
+            }
+            .boxed()
+        });
 
-        Ok((context, app))
+        Ok((context, app, state))
     }
 
     async fn init_with_query_module_fs<N, D, Ver: StaticVersionType + 'static>(
         &self,
         query_opt: Query,
         mod_opt: D::Options,
+        merkle_store: Option<fs::MerkleStore>,
         init_context: impl FnOnce(Box<dyn Metrics>) -> BoxFuture<'static, SequencerContext<N, Ver>>,
         bind_version: Ver,
     ) -> anyhow::Result<SequencerContext<N, Ver>>
@@ -262,18 +321,35 @@ impl Options {
     {
         let ds = D::create(mod_opt, provider(query_opt.peers, bind_version), false).await?;
 
-        let (mut context, app) = self
+        let (mut context, mut app, state) = self
             .init_app_modules(ds, init_context, bind_version)
             .await?;
 
+        if let Some(store) = merkle_store {
+            // The file system data source has no storage for merklized state, so we keep it in a
+            // separate store, updated from consensus events, and serve it with our own API.
+            context.spawn(
+                "merklized state updater",
+                merkle_store_update_loop(store.clone(), context.get_event_stream()),
+            );
+            app.register_module(
+                "state/blocks",
+                endpoints::fs_merklized_state::<_, BlockMerkleTree, _>(
+                    store.clone(),
+                    bind_version,
+                )?,
+            )?;
+            app.register_module(
+                "state/fees",
+                endpoints::fs_merklized_state::<_, FeeMerkleTree, _>(store, bind_version)?,
+            )?;
+        }
+
         if self.hotshot_events.is_some() {
             self.init_and_spawn_hotshot_event_streaming_module(&mut context, bind_version)?;
         }
 
-        context.spawn(
-            "API server",
-            app.serve(format!("0.0.0.0:{}", self.http.port), Ver::instance()),
-        );
+        spawn_server(&mut context, app, state, self.http.port, bind_version);
         Ok(context)
     }
 
@@ -281,6 +357,7 @@ impl Options {
         self,
         query_opt: Query,
         mod_opt: D::Options,
+        merkle_pruner: Option<(persistence::sql::Persistence, MerklePrunerCfg)>,
         init_context: impl FnOnce(Box<dyn Metrics>) -> BoxFuture<'static, SequencerContext<N, Ver>>,
         bind_version: Ver,
     ) -> anyhow::Result<SequencerContext<N, Ver>>
@@ -294,10 +371,16 @@ impl Options {
             + 'static,
     {
         let ds = D::create(mod_opt, provider(query_opt.peers, bind_version), false).await?;
-        let (mut context, mut app) = self
+        let merkle_pruner = merkle_pruner
+            .map(|(storage, cfg)| MerklePruner::new(storage, cfg, &*ds.populate_metrics()));
+        let (mut context, mut app, state) = self
             .init_app_modules(ds, init_context, bind_version)
             .await?;
 
+        if let Some(pruner) = merkle_pruner {
+            context.spawn("Merkle state pruner", pruner.run());
+        }
+
         if self.state.is_some() {
             // Initialize merklized state module for block merkle tree
             app.register_module(
@@ -315,17 +398,14 @@ impl Options {
             self.init_and_spawn_hotshot_event_streaming_module(&mut context, bind_version)?;
         }
 
-        context.spawn(
-            "API server",
-            app.serve(format!("0.0.0.0:{}", self.http.port), Ver::instance()),
-        );
+        spawn_server(&mut context, app, state, self.http.port, bind_version);
         Ok(context)
     }
 
     /// Initialize the modules for interacting with HotShot.
     ///
-    /// This function adds the `submit`, `state`, and `state_signature` API modules to the given
-    /// app. These modules only require a HotShot handle as state, and thus they work with any data
+    /// This function adds the `submit`, `state`, `health`, `admin`, and `state_signature` API modules to the
+    /// given app. These modules only require a HotShot handle as state, and thus they work with any data
     /// source, so initialization is the same no matter what mode the service is running in.
     fn init_hotshot_modules<N, S, Ver: StaticVersionType + 'static>(
         &self,
@@ -333,7 +413,13 @@ impl Options {
     ) -> anyhow::Result<()>
     where
         S: 'static + Send + Sync + ReadState + WriteState,
-        S::State: Send + Sync + SubmitDataSource<N> + StateSignatureDataSource<N> + StateDataSource,
+        S::State: Send
+            + Sync
+            + SubmitDataSource<N>
+            + StateSignatureDataSource<N>
+            + StateDataSource
+            + HealthDataSource
+            + AdminDataSource,
         N: network::Type,
     {
         let bind_version = Ver::instance();
@@ -350,6 +436,18 @@ impl Options {
             app.register_module("catchup", catchup_api)?;
         }
 
+        // Initialize health API.
+        if let Some(opt) = &self.health {
+            let health_api = endpoints::health(opt.thresholds(), bind_version)?;
+            app.register_module("health", health_api)?;
+        }
+
+        // Initialize admin API.
+        if let Some(opt) = &self.admin {
+            let admin_api = endpoints::admin(opt.token.clone(), opt.catchup_timeout, bind_version)?;
+            app.register_module("admin", admin_api)?;
+        }
+
         let state_signature_api = endpoints::state_signature(bind_version)?;
         app.register_module("state-signature", state_signature_api)?;
 
@@ -396,6 +494,35 @@ impl Options {
     }
 }
 
+/// Serve `app` as a background task of `context`, draining requests on a graceful shutdown.
+///
+/// Each request holds a read lock on the app `state` while it is being handled. During a graceful
+/// shutdown, the server stops accepting connections when the background tasks are cancelled, and
+/// then taking the write lock waits for the requests which are still in flight.
+fn spawn_server<N, S, Ver>(
+    context: &mut SequencerContext<N, Ver>,
+    app: App<Arc<RwLock<S>>, Error, Ver>,
+    state: Arc<RwLock<S>>,
+    port: u16,
+    bind_version: Ver,
+) where
+    N: network::Type,
+    S: Send + Sync + 'static,
+    Ver: StaticVersionType + 'static,
+{
+    context.spawn(
+        "API server",
+        app.serve(format!("0.0.0.0:{port}"), bind_version),
+    );
+    context.on_shutdown("API requests", move || {
+        async move {
+            drop(state.write().await);
+            Ok(())
+        }
+        .boxed()
+    });
+}
+
 /// The minimal HTTP API.
 ///
 /// The API automatically includes health and version endpoints. Additional API modules can be
@@ -428,8 +555,107 @@ pub struct Query {
 }
 
 /// Options for the state API module.
-#[derive(Parser, Clone, Copy, Debug, Default)]
-pub struct State;
+#[derive(Parser, Clone, Copy, Debug)]
+pub struct State {
+    /// Number of recent blocks for which to keep merklized state, with file system storage.
+    ///
+    /// With file system storage, the state API serves state from a separate store, whose index is
+    /// kept in memory. Only the state at the most recent blocks is kept, to bound its size. This has
+    /// no effect with Postgres storage (see `ESPRESSO_SEQUENCER_PRUNER_STATE_RETENTION`).
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_STATE_FS_RETENTION",
+        default_value = "10000",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub fs_retention: u64,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::parse_from(std::iter::empty::<String>())
+    }
+}
+
+/// Options for the health API module.
+#[derive(Parser, Clone, Copy, Debug)]
+pub struct Health {
+    /// The longest a node may go without seeing a decide before it is considered unhealthy.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_HEALTH_MAX_DECIDE_AGE",
+        default_value = "5m",
+        value_parser = parse_duration
+    )]
+    pub max_decide_age: Duration,
+
+    /// The longest a node may go without hearing from the L1 before it is considered unhealthy.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_HEALTH_MAX_L1_AGE",
+        default_value = "2m",
+        value_parser = parse_duration
+    )]
+    pub max_l1_age: Duration,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        let HealthThresholds {
+            max_decide_age,
+            max_l1_age,
+        } = HealthThresholds::default();
+        Self {
+            max_decide_age,
+            max_l1_age,
+        }
+    }
+}
+
+impl Health {
+    fn thresholds(&self) -> HealthThresholds {
+        HealthThresholds {
+            max_decide_age: self.max_decide_age,
+            max_l1_age: self.max_l1_age,
+        }
+    }
+}
+
+/// Options for the admin API module.
+#[derive(Parser, Clone, Derivative)]
+#[derivative(Debug)]
+pub struct Admin {
+    /// Secret token which clients must present to use the admin API.
+    ///
+    /// Clients send the token in an `Authorization: Bearer <token>` header.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_ADMIN_TOKEN",
+        hide_env_values = true,
+        value_parser = NonEmptyStringValueParser::new()
+    )]
+    #[derivative(Debug = "ignore")]
+    pub token: String,
+
+    /// The longest to wait for peers when catching up through the admin API.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_ADMIN_CATCHUP_TIMEOUT",
+        default_value = "30s",
+        value_parser = parse_duration
+    )]
+    pub catchup_timeout: Duration,
+}
+
+impl Admin {
+    /// Admin options with the given token and default settings.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            catchup_timeout: Duration::from_secs(30),
+        }
+    }
+}
 
 /// Options for the Hotshot events streaming API module.
 #[derive(Parser, Clone, Copy, Debug, Default)]
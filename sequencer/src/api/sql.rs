@@ -1,4 +1,4 @@
-use super::data_source::{Provider, SequencerDataSource};
+use super::data_source::{MerklizedStateStorage, Provider, SequencerDataSource};
 use crate::{persistence::sql::Options, SeqTypes};
 use anyhow::Context;
 use async_trait::async_trait;
@@ -22,7 +22,10 @@ impl SequencerDataSource for DataSource {
 
         Ok(cfg.connect(provider).await?)
     }
+}
 
+#[async_trait]
+impl MerklizedStateStorage for DataSource {
     async fn store_state<S: MerklizedState<SeqTypes>>(
         &mut self,
         path: MerklePath<S::Entry, S::Key, S::T>,
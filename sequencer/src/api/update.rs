@@ -1,16 +1,21 @@
 //! Update loop for query API state.
 
-use super::{data_source::SequencerDataSource, StorageState};
+use super::{
+    data_source::{store_merklized_state, SequencerDataSource},
+    fs::{MerkleStore, StateBatch},
+    StorageState,
+};
 use crate::{network, state::Delta, SeqTypes, ValidatedState};
 use async_std::sync::{Arc, RwLock};
 use async_trait::async_trait;
 use futures::stream::{Stream, StreamExt};
-use hotshot::types::Event;
+use hotshot::types::{Event, EventType};
 use hotshot_query_service::{
     data_source::{UpdateDataSource, VersionedDataSource},
     merklized_state::UpdateStateStorage,
     Leaf,
 };
+use hotshot_types::event::LeafInfo;
 use versioned_binary_serialization::version::StaticVersionType;
 
 pub(super) async fn update_loop<N, D, Ver: StaticVersionType>(
@@ -39,6 +44,46 @@ pub(super) async fn update_loop<N, D, Ver: StaticVersionType>(
     tracing::warn!("end of HotShot event stream, updater task will exit");
 }
 
+/// Keep a file system [`MerkleStore`] up to date with the state decided by consensus.
+///
+/// The state which changed in each decided block is written to the store as a single batch. If the
+/// state delta for a block is not available, the store cannot cover that block, and it starts over
+/// from the next block (see [`MerkleStore`]).
+pub(super) async fn merkle_store_update_loop(
+    store: MerkleStore,
+    mut events: impl Stream<Item = Event<SeqTypes>> + Unpin,
+) {
+    while let Some(event) = events.next().await {
+        let EventType::Decide { leaf_chain, .. } = &event.event else {
+            continue;
+        };
+        // The leaf chain is ordered from newest to oldest.
+        for LeafInfo {
+            leaf, state, delta, ..
+        } in leaf_chain.iter().rev()
+        {
+            let height = leaf.get_height();
+            let Some(delta) = delta else {
+                tracing::warn!(
+                    height,
+                    "no state delta for decided leaf, state will be missing"
+                );
+                continue;
+            };
+            let mut batch = StateBatch::new(height);
+            let res = async {
+                store_merklized_state(state, &mut batch, height, delta).await?;
+                store.append(batch).await
+            }
+            .await;
+            if let Err(err) = res {
+                tracing::error!(height, "failed to store merklized state: {err:#}");
+            }
+        }
+    }
+    tracing::warn!("end of HotShot event stream, merklized state updater task will exit");
+}
+
 async fn update_state<N, D, Ver: StaticVersionType>(
     state: &mut StorageState<N, D, Ver>,
     event: &Event<SeqTypes>,
@@ -0,0 +1,933 @@
+//! Portable archives of chain history.
+//!
+//! An archive holds the leaves, payloads and VID common data for a range of blocks, and optionally
+//! the parts of the merklized state which changed in each block. It can be exported from any query
+//! service and imported into a fresh file system or SQL data source, which makes it possible to
+//! bootstrap new query nodes and to preserve the history of a chain independently of the storage
+//! of any particular node.
+//!
+//! Since each block only carries the state which changed in it, an archive with state must start at
+//! genesis for the imported state to be complete.
+//!
+//! # Format
+//!
+//! An archive is a file starting with the magic bytes [`MAGIC`], followed by a sequence of records.
+//! Each record is encoded with `bincode` and prefixed with its length as a little-endian `u64`. The
+//! first record is an [`ArchiveHeader`], giving the format version and the range of blocks in the
+//! archive. It is followed by one record for each block, in order, and a trailer.
+//!
+//! # Integrity
+//!
+//! [`ArchiveReader`] checks that the blocks in an archive are consistent with each other: each QC
+//! is for the leaf it is archived with, payloads and VID common data match the commitments in their
+//! headers, each leaf links to the previous one, and each Merkle path is a valid proof against the
+//! corresponding state root in the header. The signatures on the QCs are _not_ checked, so this
+//! does not show that the blocks were decided by consensus. An archive from an untrusted source
+//! should be checked against the live chain, for example by comparing the last leaf with one
+//! fetched from a trusted query service.
+//!
+//! The trailer holds a running SHA-256 digest of every preceding record. It has no key, so it only
+//! detects accidental corruption, such as truncation or flipped bits, not deliberate tampering.
+
+use crate::{
+    api::{
+        data_source::MerklizedStateStorage,
+        fs::{MerkleStore, StateBatch},
+    },
+    l1_client::L1Client,
+    state::{BlockMerkleTree, FeeAccount, FeeMerkleTree},
+    Header, SeqTypes,
+};
+use anyhow::{bail, ensure, Context};
+use hotshot_query_service::{
+    availability::{BlockQueryData, LeafQueryData, UpdateAvailabilityData, VidCommonQueryData},
+    data_source::VersionedDataSource,
+    merklized_state::MerklizedState,
+};
+use hotshot_types::{
+    traits::{block_contents::vid_commitment, BlockPayload},
+    vid::VidSchemeType,
+};
+use jf_primitives::{
+    merkle_tree::{
+        prelude::{MerklePath, MerkleProof},
+        MerkleCommitment, MerkleTreeScheme, ToTraversalPath,
+    },
+    vid::VidScheme,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{ErrorKind, Read, Write};
+use versioned_binary_serialization::version::StaticVersionType;
+
+/// The version of the archive format written by this version of the software.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Bytes identifying a file as an archive.
+pub const MAGIC: &[u8; 16] = b"ESPRESSO-ARCHIVE";
+
+/// Upper bound on the size of a single record, so that a corrupt length prefix cannot cause us to
+/// allocate huge amounts of memory.
+///
+/// A block record holds a payload of at most [`MAX_BLOCK_SIZE`](crate::block::MAX_BLOCK_SIZE)
+/// bytes, plus its leaf and VID common data, so this leaves plenty of headroom.
+const MAX_RECORD_LEN: u64 = 1 << 26;
+
+/// Number of blocks to import between commits to the data source.
+const IMPORT_BATCH_SIZE: u64 = 100;
+
+/// Metadata describing an archive.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ArchiveHeader {
+    /// The format version of the archive.
+    pub version: u32,
+    /// The first block in the archive.
+    pub from: u64,
+    /// The end (exclusive) of the range of blocks in the archive.
+    pub to: u64,
+    /// Whether the archive includes merklized state.
+    pub state: bool,
+}
+
+/// Everything needed to import a single block.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ArchivedBlock {
+    pub leaf: LeafQueryData<SeqTypes>,
+    pub block: BlockQueryData<SeqTypes>,
+    pub vid_common: VidCommonQueryData<SeqTypes>,
+    /// Merkle paths for the state which changed in this block.
+    pub state: Vec<StatePath>,
+}
+
+type Path<S> = MerklePath<
+    <S as MerklizedState<SeqTypes>>::Entry,
+    <S as MerklizedState<SeqTypes>>::Key,
+    <S as MerklizedState<SeqTypes>>::T,
+>;
+
+/// A path in one of the merklized state trees, as of the block it is archived with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum StatePath {
+    /// The path to a block in the block Merkle tree.
+    Block {
+        index: u64,
+        path: Path<BlockMerkleTree>,
+    },
+    /// The path to an account in the fee Merkle tree.
+    Fee {
+        account: FeeAccount,
+        path: Path<FeeMerkleTree>,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+enum Record {
+    Header(ArchiveHeader),
+    Block(Box<ArchivedBlock>),
+    Trailer { num_blocks: u64, digest: [u8; 32] },
+}
+
+impl StatePath {
+    /// Check that this path is a membership proof against the corresponding state root in `header`.
+    fn verify(&self, header: &Header) -> anyhow::Result<()> {
+        let valid = match self {
+            Self::Block { index, path } => BlockMerkleTree::verify(
+                header.block_merkle_tree_root.digest(),
+                index,
+                MerkleProof::new(*index, path.clone()),
+            )?
+            .is_ok(),
+            Self::Fee { account, path } => FeeMerkleTree::verify(
+                header.fee_merkle_tree_root.digest(),
+                account,
+                MerkleProof::new(*account, path.clone()),
+            )?
+            .is_ok(),
+        };
+        ensure!(valid, "{} is not a valid proof", self.key());
+        Ok(())
+    }
+
+    fn key(&self) -> String {
+        match self {
+            Self::Block { index, .. } => format!("path to block {index}"),
+            Self::Fee { account, .. } => format!("path to fee account {account}"),
+        }
+    }
+
+    async fn store(
+        self,
+        storage: &mut impl MerklizedStateStorage,
+        height: u64,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Block { index, path } => {
+                storage
+                    .store_state::<BlockMerkleTree>(
+                        path,
+                        traversal_path::<BlockMerkleTree>(index),
+                        height,
+                    )
+                    .await
+            }
+            Self::Fee { account, path } => {
+                storage
+                    .store_state::<FeeMerkleTree>(
+                        path,
+                        traversal_path::<FeeMerkleTree>(account),
+                        height,
+                    )
+                    .await
+            }
+        }
+    }
+}
+
+fn traversal_path<S: MerklizedState<SeqTypes>>(key: S::Key) -> Vec<usize> {
+    <S::Key as ToTraversalPath<S::Arity>>::to_traversal_path(&key, S::tree_height())
+}
+
+impl ArchivedBlock {
+    pub fn height(&self) -> u64 {
+        self.leaf.height()
+    }
+
+    /// Check that this block is internally consistent and follows `parent`.
+    ///
+    /// This does not check the signature on the QC.
+    fn verify(&self, parent: Option<&LeafQueryData<SeqTypes>>) -> anyhow::Result<()> {
+        let height = self.height();
+        let header = self.leaf.header();
+        ensure!(
+            self.leaf.qc().data.leaf_commit == self.leaf.hash(),
+            "QC for leaf {height} does not sign the leaf"
+        );
+        ensure!(
+            self.block.height() == height && self.vid_common.height() == height,
+            "block {} or VID common {} does not match leaf {height}",
+            self.block.height(),
+            self.vid_common.height()
+        );
+        ensure!(
+            self.block.hash() == self.leaf.block_hash() && self.block.header() == header,
+            "block {height} does not match leaf"
+        );
+        ensure!(
+            self.vid_common.block_hash() == self.leaf.block_hash(),
+            "VID common {height} does not match leaf"
+        );
+
+        let payload = self.block.payload().encode()?.collect::<Vec<_>>();
+        let num_storage_nodes = VidSchemeType::get_num_storage_nodes(self.vid_common.common());
+        ensure!(
+            vid_commitment(&payload, num_storage_nodes) == header.payload_commitment,
+            "payload {height} does not match payload commitment"
+        );
+
+        if let Some(parent) = parent {
+            ensure!(
+                self.leaf.leaf().get_parent_commitment() == parent.hash(),
+                "leaf {height} does not extend leaf {}",
+                parent.height()
+            );
+        }
+
+        for path in &self.state {
+            path.verify(header)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes blocks to an archive.
+#[derive(Debug)]
+pub struct ArchiveWriter<W: Write> {
+    inner: W,
+    header: ArchiveHeader,
+    digest: [u8; 32],
+    next: u64,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Start writing an archive of the blocks in `[from, to)`.
+    ///
+    /// An archive with `state` must start at genesis, since each block only carries the state which
+    /// changed in it.
+    pub fn new(mut inner: W, from: u64, to: u64, state: bool) -> anyhow::Result<Self> {
+        ensure!(from <= to, "invalid block range [{from}, {to})");
+        ensure!(
+            !state || from == 0,
+            "an archive with state must start at genesis, not block {from}"
+        );
+        let header = ArchiveHeader {
+            version: ARCHIVE_VERSION,
+            from,
+            to,
+            state,
+        };
+        inner.write_all(MAGIC)?;
+        let mut writer = Self {
+            inner,
+            header: header.clone(),
+            digest: Default::default(),
+            next: from,
+        };
+        writer.write(&Record::Header(header))?;
+        Ok(writer)
+    }
+
+    /// Append the next block to the archive.
+    pub fn append(&mut self, block: ArchivedBlock) -> anyhow::Result<()> {
+        ensure!(
+            block.height() == self.next,
+            "expected block {}, got {}",
+            self.next,
+            block.height()
+        );
+        ensure!(
+            self.next < self.header.to,
+            "block {} is out of range",
+            self.next
+        );
+        ensure!(
+            self.header.state || block.state.is_empty(),
+            "archive does not include state"
+        );
+        self.write(&Record::Block(Box::new(block)))?;
+        self.next += 1;
+        Ok(())
+    }
+
+    /// Write the trailer, completing the archive.
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        ensure!(
+            self.next == self.header.to,
+            "archive is missing blocks [{}, {})",
+            self.next,
+            self.header.to
+        );
+        let trailer = Record::Trailer {
+            num_blocks: self.header.to - self.header.from,
+            digest: self.digest,
+        };
+        self.write(&trailer)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write(&mut self, record: &Record) -> anyhow::Result<()> {
+        let bytes = bincode::serialize(record)?;
+        self.inner.write_all(&(bytes.len() as u64).to_le_bytes())?;
+        self.inner.write_all(&bytes)?;
+        self.digest = chain_digest(&self.digest, &bytes);
+        Ok(())
+    }
+}
+
+/// Reads and verifies blocks from an archive.
+#[derive(Debug)]
+pub struct ArchiveReader<R: Read> {
+    inner: R,
+    header: ArchiveHeader,
+    digest: [u8; 32],
+    parent: Option<LeafQueryData<SeqTypes>>,
+    next: u64,
+    done: bool,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    /// Open an archive, reading its header.
+    pub fn new(mut inner: R) -> anyhow::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        inner
+            .read_exact(&mut magic)
+            .context("reading archive magic")?;
+        ensure!(&magic == MAGIC, "not an archive");
+
+        let mut digest = Default::default();
+        let Some(Record::Header(header)) = read_record(&mut inner, &mut digest)? else {
+            bail!("archive does not start with a header");
+        };
+        ensure!(
+            header.version == ARCHIVE_VERSION,
+            "unsupported archive version {} (expected {ARCHIVE_VERSION})",
+            header.version
+        );
+        ensure!(
+            !header.state || header.from == 0,
+            "archive with state starts at block {}, not genesis",
+            header.from
+        );
+        Ok(Self {
+            inner,
+            next: header.from,
+            header,
+            digest,
+            parent: None,
+            done: false,
+        })
+    }
+
+    pub fn header(&self) -> &ArchiveHeader {
+        &self.header
+    }
+
+    /// Read and verify the next block.
+    ///
+    /// Returns [`None`] once all blocks have been read and the trailer has been verified.
+    pub fn next_block(&mut self) -> anyhow::Result<Option<ArchivedBlock>> {
+        if self.done {
+            return Ok(None);
+        }
+        let expected_digest = self.digest;
+        match read_record(&mut self.inner, &mut self.digest)? {
+            Some(Record::Block(block)) => {
+                ensure!(
+                    block.height() == self.next && self.next < self.header.to,
+                    "unexpected block {} in archive of [{}, {})",
+                    block.height(),
+                    self.header.from,
+                    self.header.to
+                );
+                ensure!(
+                    self.header.state || block.state.is_empty(),
+                    "archive includes state but its header says it does not"
+                );
+                block
+                    .verify(self.parent.as_ref())
+                    .context(format!("invalid block {}", self.next))?;
+                self.parent = Some(block.leaf.clone());
+                self.next += 1;
+                Ok(Some(*block))
+            }
+            Some(Record::Trailer { num_blocks, digest }) => {
+                ensure!(
+                    self.next == self.header.to && num_blocks == self.header.to - self.header.from,
+                    "archive ended after {} blocks, expected {}",
+                    self.next - self.header.from,
+                    self.header.to - self.header.from
+                );
+                ensure!(digest == expected_digest, "archive digest does not match");
+                self.done = true;
+                Ok(None)
+            }
+            Some(Record::Header(_)) => bail!("unexpected header in archive"),
+            None => bail!("archive is truncated after block {}", self.next),
+        }
+    }
+}
+
+/// Read a record, updating the running `digest`. Returns [`None`] at the end of the input.
+fn read_record(r: &mut impl Read, digest: &mut [u8; 32]) -> anyhow::Result<Option<Record>> {
+    let mut len = [0; 8];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = u64::from_le_bytes(len);
+    ensure!(len <= MAX_RECORD_LEN, "record length {len} is too large");
+    // Read incrementally rather than allocating `len` bytes up front, so that a truncated archive
+    // does not cost us more memory than it actually contains.
+    let mut bytes = vec![];
+    r.by_ref()
+        .take(len)
+        .read_to_end(&mut bytes)
+        .context("reading record")?;
+    ensure!(
+        bytes.len() as u64 == len,
+        "record is truncated: expected {len} bytes, got {}",
+        bytes.len()
+    );
+    *digest = chain_digest(digest, &bytes);
+    Ok(Some(
+        bincode::deserialize(&bytes).context("decoding record")?,
+    ))
+}
+
+fn chain_digest(prev: &[u8; 32], record: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(prev);
+    hasher.update(Sha256::digest(record));
+    hasher.finalize().into()
+}
+
+/// A query service client for exporting blocks.
+pub type Client<Ver> = surf_disco::Client<hotshot_query_service::Error, Ver>;
+
+/// Export the blocks in `[from, to)` from a query service.
+///
+/// If `state` is set, the Merkle paths for the state which changed in each block are exported too.
+/// These are the path to the new block in the block Merkle tree, the path to the builder account
+/// charged in the block and the paths to the accounts credited by L1 deposits in the block. Finding
+/// the deposits requires `l1`, so it is an error to export state without it, and `from` must be 0.
+pub async fn export<W: Write, Ver: StaticVersionType>(
+    client: &Client<Ver>,
+    from: u64,
+    to: u64,
+    state: bool,
+    l1: Option<&L1Client>,
+    writer: W,
+) -> anyhow::Result<W> {
+    ensure!(
+        !state || l1.is_some(),
+        "an L1 client is required to find the accounts credited by deposits"
+    );
+    let mut writer = ArchiveWriter::new(writer, from, to, state)?;
+    let mut parent: Option<Header> = None;
+    for height in from..to {
+        let leaf: LeafQueryData<SeqTypes> = client
+            .get(&format!("availability/leaf/{height}"))
+            .send()
+            .await
+            .context(format!("fetching leaf {height}"))?;
+        let block: BlockQueryData<SeqTypes> = client
+            .get(&format!("availability/block/{height}"))
+            .send()
+            .await
+            .context(format!("fetching block {height}"))?;
+        let vid_common: VidCommonQueryData<SeqTypes> = client
+            .get(&format!("availability/vid/common/{height}"))
+            .send()
+            .await
+            .context(format!("fetching VID common {height}"))?;
+
+        let header = leaf.header().clone();
+        let mut paths = vec![];
+        if state && height > 0 {
+            let index = height - 1;
+            paths.push(StatePath::Block {
+                index,
+                path: client
+                    .get(&format!("state/blocks/{height}/{index}"))
+                    .send()
+                    .await
+                    .context(format!("fetching block state for {height}"))?,
+            });
+
+            let mut accounts = vec![header.fee_info.account()];
+            if let (Some(l1), Some(l1_finalized)) = (l1, header.l1_finalized) {
+                let parent = match &parent {
+                    Some(parent) => parent.clone(),
+                    None => client
+                        .get(&format!("availability/header/{}", height - 1))
+                        .send()
+                        .await
+                        .context(format!("fetching header {}", height - 1))?,
+                };
+                let deposits = l1
+                    .get_finalized_deposits(
+                        parent.l1_finalized.map(|block| block.number),
+                        l1_finalized.number,
+                    )
+                    .await;
+                accounts.extend(deposits.iter().map(|deposit| deposit.account()));
+            }
+            accounts.sort();
+            accounts.dedup();
+            for account in accounts {
+                paths.push(StatePath::Fee {
+                    account,
+                    path: client
+                        .get(&format!("state/fees/{height}/{}", account.address()))
+                        .send()
+                        .await
+                        .context(format!("fetching fee state for {account} at {height}"))?,
+                });
+            }
+        }
+
+        tracing::debug!(height, "exporting block");
+        writer.append(ArchivedBlock {
+            leaf,
+            block,
+            vid_common,
+            state: paths,
+        })?;
+        parent = Some(header);
+    }
+    writer.finish()
+}
+
+/// Import all the blocks from an archive into a data source.
+///
+/// Merklized state is stored in `merkle_store` if one is given, or in `ds` itself otherwise.
+/// Returns the number of blocks imported.
+pub async fn import<R, D>(
+    mut reader: ArchiveReader<R>,
+    ds: &mut D,
+    merkle_store: Option<&MerkleStore>,
+) -> anyhow::Result<u64>
+where
+    R: Read,
+    D: UpdateAvailabilityData<SeqTypes> + VersionedDataSource + MerklizedStateStorage + Send,
+{
+    let mut imported = 0;
+    while let Some(block) = reader.next_block()? {
+        let height = block.height();
+        tracing::debug!(height, "importing block");
+        ds.insert_leaf(block.leaf)
+            .await
+            .context(format!("inserting leaf {height}"))?;
+        ds.insert_block(block.block)
+            .await
+            .context(format!("inserting block {height}"))?;
+        ds.insert_vid(block.vid_common, None)
+            .await
+            .context(format!("inserting VID common {height}"))?;
+        match merkle_store {
+            Some(store) => {
+                // The file system store takes all the state for a block at once.
+                let mut batch = StateBatch::new(height);
+                for path in block.state {
+                    path.store(&mut batch, height)
+                        .await
+                        .context(format!("inserting state for block {height}"))?;
+                }
+                store
+                    .append(batch)
+                    .await
+                    .context(format!("inserting state for block {height}"))?;
+            }
+            None => {
+                for path in block.state {
+                    path.store(ds, height)
+                        .await
+                        .context(format!("inserting state for block {height}"))?;
+                }
+            }
+        }
+
+        imported += 1;
+        if imported % IMPORT_BATCH_SIZE == 0 {
+            ds.commit().await?;
+            tracing::info!(height, "imported {imported} blocks");
+        }
+    }
+    ds.commit().await?;
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        api::{self, data_source::SequencerDataSource, options, testing::TestNetwork},
+        persistence,
+    };
+    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use async_std::task::sleep;
+    use es_version::SequencerVersion;
+    use ethers::types::Address;
+    use hotshot_query_service::{
+        availability::AvailabilityDataSource,
+        data_source::storage::sql::testing::TmpDb,
+        merklized_state::{MerklizedStateDataSource, Snapshot},
+    };
+    use portpicker::pick_unused_port;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    /// Start a sequencer network with a query service and the state API, and wait until it has
+    /// produced `to` blocks and stored the state for all of them.
+    async fn start_network(
+        port: u16,
+        storage: &TempDir,
+        to: u64,
+    ) -> (TestNetwork, Client<SequencerVersion>) {
+        let network = TestNetwork::new(
+            api::Options::from(options::Http { port })
+                .query_fs(
+                    Default::default(),
+                    persistence::fs::Options {
+                        path: storage.path().into(),
+                    },
+                )
+                .state(Default::default()),
+        )
+        .await;
+        let client =
+            Client::<SequencerVersion>::new(format!("http://localhost:{port}").parse().unwrap());
+        client.connect(None).await;
+
+        while client
+            .get::<Path<BlockMerkleTree>>(&format!("state/blocks/{}/{}", to - 1, to - 2))
+            .send()
+            .await
+            .is_err()
+        {
+            sleep(Duration::from_millis(100)).await;
+        }
+        (network, client)
+    }
+
+    /// Check that `ds` has the same leaves and blocks in `[0, to)` as the query service.
+    async fn check_blocks(
+        client: &Client<SequencerVersion>,
+        ds: &impl AvailabilityDataSource<SeqTypes>,
+        to: u64,
+    ) {
+        for height in 0..to {
+            let leaf: LeafQueryData<SeqTypes> = client
+                .get(&format!("availability/leaf/{height}"))
+                .send()
+                .await
+                .unwrap();
+            let imported = ds
+                .get_leaf(height as usize)
+                .await
+                .with_timeout(Duration::from_secs(1))
+                .await
+                .unwrap();
+            assert_eq!(leaf, imported);
+
+            let block: BlockQueryData<SeqTypes> = client
+                .get(&format!("availability/block/{height}"))
+                .send()
+                .await
+                .unwrap();
+            let imported = ds
+                .get_block(height as usize)
+                .await
+                .with_timeout(Duration::from_secs(1))
+                .await
+                .unwrap();
+            assert_eq!(block, imported);
+        }
+    }
+
+    /// Export the first `to` blocks, with state, from a new network.
+    async fn export_with_state(to: u64) -> (TestNetwork, Client<SequencerVersion>, Vec<u8>) {
+        let port = pick_unused_port().expect("No ports free");
+        let storage = TempDir::new().unwrap();
+        let (network, client) = start_network(port, &storage, to).await;
+        let l1 = L1Client::new(network.cfg.l1_url(), Address::default());
+
+        // State can only be exported from genesis, and only with an L1 client.
+        export(&client, 1, to, true, Some(&l1), vec![])
+            .await
+            .unwrap_err();
+        export(&client, 0, to, true, None, vec![])
+            .await
+            .unwrap_err();
+
+        let archive = export(&client, 0, to, true, Some(&l1), vec![])
+            .await
+            .unwrap();
+        (network, client, archive)
+    }
+
+    /// The state paths in `archive`, with the height of the block they are archived with.
+    fn archived_state(archive: &[u8]) -> Vec<(u64, StatePath)> {
+        let mut reader = ArchiveReader::new(archive).unwrap();
+        let mut paths = vec![];
+        while let Some(block) = reader.next_block().unwrap() {
+            let height = block.height();
+            paths.extend(block.state.into_iter().map(|path| (height, path)));
+        }
+        paths
+    }
+
+    #[test]
+    fn test_read_record_length() {
+        let trailer = bincode::serialize(&Record::Trailer {
+            num_blocks: 0,
+            digest: [0; 32],
+        })
+        .unwrap();
+        let record = |len: u64, bytes: &[u8]| {
+            let mut record = len.to_le_bytes().to_vec();
+            record.extend_from_slice(bytes);
+            record
+        };
+
+        let mut digest = [0; 32];
+        let bytes = record(trailer.len() as u64, &trailer);
+        assert!(matches!(
+            read_record(&mut bytes.as_slice(), &mut digest).unwrap(),
+            Some(Record::Trailer { num_blocks: 0, .. })
+        ));
+
+        // A length prefix past the limit is rejected without reading the record.
+        let bytes = record(MAX_RECORD_LEN + 1, &trailer);
+        read_record(&mut bytes.as_slice(), &mut digest).unwrap_err();
+
+        // So is a record shorter than its length prefix.
+        let bytes = record(trailer.len() as u64 + 1, &trailer);
+        read_record(&mut bytes.as_slice(), &mut digest).unwrap_err();
+    }
+
+    #[async_std::test]
+    async fn test_archive_round_trip() {
+        setup_logging();
+        setup_backtrace();
+
+        let to = 5;
+        let port = pick_unused_port().expect("No ports free");
+        let storage = TempDir::new().unwrap();
+        let (_network, client) = start_network(port, &storage, to).await;
+
+        // Export some blocks.
+        let archive = export(&client, 0, to, false, None, vec![]).await.unwrap();
+
+        // Import them into a fresh data source.
+        let import_storage = TempDir::new().unwrap();
+        let mut ds = api::fs::DataSource::create(
+            persistence::fs::Options {
+                path: import_storage.path().into(),
+            },
+            Default::default(),
+            true,
+        )
+        .await
+        .unwrap();
+        let reader = ArchiveReader::new(archive.as_slice()).unwrap();
+        assert_eq!(
+            *reader.header(),
+            ArchiveHeader {
+                version: ARCHIVE_VERSION,
+                from: 0,
+                to,
+                state: false,
+            }
+        );
+        let imported = import(reader, &mut ds, None).await.unwrap();
+        assert_eq!(imported, to);
+
+        // The imported data source has the same leaves and blocks as the original.
+        check_blocks(&client, &ds, to).await;
+
+        // A truncated archive is rejected.
+        let mut reader = ArchiveReader::new(&archive[..archive.len() - 1]).unwrap();
+        let err = loop {
+            match reader.next_block() {
+                Ok(Some(_)) => continue,
+                Ok(None) => panic!("truncated archive was accepted"),
+                Err(err) => break err,
+            }
+        };
+        tracing::info!("truncated archive rejected: {err:#}");
+
+        // So is a modified one.
+        let mut modified = archive.clone();
+        let last = modified.len() - 1;
+        modified[last] ^= 1;
+        let mut reader = ArchiveReader::new(modified.as_slice()).unwrap();
+        while let Ok(Some(_)) = reader.next_block() {}
+        reader.next_block().unwrap_err();
+
+        // Blocks must be appended in order.
+        let mut reader = ArchiveReader::new(archive.as_slice()).unwrap();
+        let genesis = reader.next_block().unwrap().unwrap();
+        let mut writer = ArchiveWriter::new(vec![], 1, 2, false).unwrap();
+        writer.append(genesis).unwrap_err();
+
+        // An archive with state must start at genesis.
+        ArchiveWriter::new(vec![], 1, 2, true).unwrap_err();
+    }
+
+    #[async_std::test]
+    async fn test_archive_state_round_trip() {
+        setup_logging();
+        setup_backtrace();
+
+        let to = 5;
+        let (_network, client, archive) = export_with_state(to).await;
+
+        // Import into file system storage, with a separate Merkle store.
+        let import_storage = TempDir::new().unwrap();
+        let opt = persistence::fs::Options {
+            path: import_storage.path().into(),
+        };
+        let mut ds = api::fs::DataSource::create(opt.clone(), Default::default(), true)
+            .await
+            .unwrap();
+        let store = api::fs::MerkleStore::open(api::fs::merkle_store_path(&opt), 100)
+            .await
+            .unwrap();
+        let reader = ArchiveReader::new(archive.as_slice()).unwrap();
+        assert!(reader.header().state);
+        let imported = import(reader, &mut ds, Some(&store)).await.unwrap();
+        assert_eq!(imported, to);
+        check_blocks(&client, &ds, to).await;
+
+        // Every archived path can be read back from the store.
+        let paths = archived_state(&archive);
+        assert!(!paths.is_empty());
+        for (height, path) in paths {
+            match path {
+                StatePath::Block { index, path } => {
+                    let imported = store
+                        .get_path::<BlockMerkleTree>(height, index)
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    assert_eq!(imported, path, "block {index} at height {height}");
+                }
+                StatePath::Fee { account, path } => {
+                    let imported = store
+                        .get_path::<FeeMerkleTree>(height, account)
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    assert_eq!(imported, path, "account {account} at height {height}");
+                }
+            }
+        }
+    }
+
+    #[async_std::test]
+    async fn test_archive_import_sql() {
+        setup_logging();
+        setup_backtrace();
+
+        let to = 5;
+        let (_network, client, archive) = export_with_state(to).await;
+
+        // Import into SQL storage, which keeps the merklized state itself.
+        let db = TmpDb::init().await;
+        let mut ds = api::sql::DataSource::create(
+            persistence::sql::Options {
+                port: Some(db.port()),
+                host: Some(db.host()),
+                user: Some("postgres".into()),
+                password: Some("password".into()),
+                ..Default::default()
+            },
+            Default::default(),
+            true,
+        )
+        .await
+        .unwrap();
+        let reader = ArchiveReader::new(archive.as_slice()).unwrap();
+        let imported = import(reader, &mut ds, None).await.unwrap();
+        assert_eq!(imported, to);
+        check_blocks(&client, &ds, to).await;
+
+        // Every archived path can be read back from the database.
+        for (height, path) in archived_state(&archive) {
+            match path {
+                StatePath::Block { index, path } => {
+                    let imported = MerklizedStateDataSource::<SeqTypes, BlockMerkleTree>::get_path(
+                        &ds,
+                        Snapshot::Index(height),
+                        index,
+                    )
+                    .await
+                    .unwrap();
+                    assert_eq!(imported, path, "block {index} at height {height}");
+                }
+                StatePath::Fee { account, path } => {
+                    let imported = MerklizedStateDataSource::<SeqTypes, FeeMerkleTree>::get_path(
+                        &ds,
+                        Snapshot::Index(height),
+                        account,
+                    )
+                    .await
+                    .unwrap();
+                    assert_eq!(imported, path, "account {account} at height {height}");
+                }
+            }
+        }
+    }
+}
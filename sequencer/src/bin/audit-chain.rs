@@ -0,0 +1,601 @@
+//! Utility program to audit the full history of an Espresso Sequencer chain.
+//!
+//! The auditor replays the chain from genesis, checking every block against the rules a sequencer
+//! node applies when it validates a proposal:
+//! * header heights are sequential, and timestamps and L1 references never decrease
+//! * each leaf extends the leaf before it
+//! * each header's `block_merkle_tree_root` and `fee_merkle_tree_root` match the state obtained by
+//!   applying the header, and the L1 deposits it references, to the state after its parent
+//! * each header is signed by the builder account that paid its fee
+//! * each namespace table is well formed and matches the block payload
+//! * each payload commitment matches the block payload
+//!
+//! The report also counts the transactions in the audited blocks, in total and unique. Any
+//! divergence is recorded in a JSON report, which is written to stdout or to a file. The program
+//! exits with an error if any divergence was found, or if the chain could not be fetched.
+//!
+//! An audit can start from any block with `--from`. The state cannot be replayed without starting
+//! from genesis, so state roots are then not checked, but every other property is, including the
+//! invariants between the first audited block and its parent. Checks can be disabled by kind with
+//! `--skip`.
+
+use anyhow::bail;
+use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+use async_std::task::sleep;
+use clap::{Parser, ValueEnum};
+use commit::{Commitment, Committable};
+use es_version::SequencerVersion;
+use ethers::prelude::*;
+use futures::stream::{self, StreamExt};
+use hotshot_query_service::availability::{
+    LeafQueryData, PayloadQueryData, QueryablePayload, VidCommonQueryData,
+};
+use hotshot_types::{
+    traits::{block_contents::vid_commitment, BlockPayload},
+    vid::VidSchemeType,
+};
+use jf_primitives::{merkle_tree::MerkleTreeScheme, vid::VidScheme};
+use sequencer::{
+    l1_client::L1Client,
+    state::{charge_builder_fee, validate_and_apply_proposal, verify_builder_signature, Delta},
+    Header, L1BlockInfo, Leaf, SeqTypes, Transaction, ValidatedState,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashSet, fmt::Display, path::PathBuf, process::exit, time::Duration};
+use surf_disco::Url;
+
+/// Utility program to audit the full history of an Espresso Sequencer chain.
+#[derive(Clone, Debug, Parser)]
+struct Options {
+    /// Start auditing from block FROM.
+    ///
+    /// If this is not 0, state roots are not checked.
+    #[clap(long, name = "FROM", default_value = "0")]
+    from: u64,
+
+    /// Stop auditing at block TO.
+    ///
+    /// If not specified, the auditor checks every block up to the current block height. It is an
+    /// error to specify a block past the current block height.
+    #[clap(long, name = "TO")]
+    to: Option<u64>,
+
+    /// Kinds of divergence not to check for, as a comma-separated list.
+    #[clap(long, value_enum, value_delimiter = ',')]
+    skip: Vec<DivergenceKind>,
+
+    /// Number of times to retry a failed request to the query service before giving up.
+    #[clap(long, default_value = "10")]
+    max_retries: usize,
+
+    /// L1 RPC URL.
+    ///
+    /// This is used to fetch the L1 deposits credited by each block, and to verify the hashes and
+    /// timestamps of L1 finalized references. Without it, the auditor assumes there are no
+    /// deposits, so blocks which credit deposits will be reported as diverging.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_L1_PROVIDER")]
+    l1_provider_url: Option<Url>,
+
+    /// Address of the fee contract proxy on the L1.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_FEE_CONTRACT_PROXY_ADDRESS",
+        default_value = "0x0000000000000000000000000000000000000000"
+    )]
+    fee_contract_address: Address,
+
+    /// Builder accounts prefunded in the genesis state.
+    ///
+    /// This must match the configuration of the sequencer nodes, or the fee state of the genesis
+    /// block will diverge.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_PREFUNDED_BUILDER_ACCOUNTS",
+        value_delimiter = ','
+    )]
+    prefunded_builder_accounts: Vec<Address>,
+
+    /// Number of blocks to fetch in parallel.
+    ///
+    /// Blocks are always audited in order, but fetching ahead hides the latency of the query
+    /// service.
+    #[clap(short, long, default_value = "1")]
+    jobs: usize,
+
+    /// Write the report to this file instead of stdout.
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+
+    /// URL of the HotShot query service.
+    url: Url,
+}
+
+type SequencerClient = surf_disco::Client<hotshot_query_service::Error, SequencerVersion>;
+
+/// The result of an audit.
+#[derive(Clone, Debug, Default, Serialize)]
+struct Report {
+    /// Number of blocks audited.
+    blocks: u64,
+    /// Number of transactions in the audited blocks.
+    transactions: u64,
+    /// Number of distinct transactions in the audited blocks.
+    unique_transactions: u64,
+    /// Whether L1 deposits and finalized references were checked against the L1.
+    l1_verified: bool,
+    /// The last block whose state roots were verified.
+    ///
+    /// The state cannot be replayed past a block which fails to apply, so roots after such a
+    /// block are not checked.
+    state_verified_through: Option<u64>,
+    divergences: Vec<Divergence>,
+    /// Set if the audit stopped before TO because the chain could not be fetched.
+    error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct Divergence {
+    height: u64,
+    kind: DivergenceKind,
+    message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+enum DivergenceKind {
+    Height,
+    Parent,
+    Timestamp,
+    L1Head,
+    L1Finalized,
+    State,
+    BuilderSignature,
+    NamespaceTable,
+    PayloadCommitment,
+}
+
+/// Everything we need from the query service to audit one block.
+struct BlockData {
+    leaf: LeafQueryData<SeqTypes>,
+    payload: PayloadQueryData<SeqTypes>,
+    vid_common: VidCommonQueryData<SeqTypes>,
+}
+
+impl BlockData {
+    async fn fetch(client: &SequencerClient, height: u64, retries: usize) -> anyhow::Result<Self> {
+        Ok(Self {
+            leaf: fetch(client, &format!("availability/leaf/{height}"), retries).await?,
+            payload: fetch(client, &format!("availability/payload/{height}"), retries).await?,
+            vid_common: fetch(
+                client,
+                &format!("availability/vid/common/{height}"),
+                retries,
+            )
+            .await?,
+        })
+    }
+}
+
+/// Fetch `path` from the query service, retrying up to `retries` times.
+///
+/// The caller must only request blocks below the block height, so persistent failures mean the
+/// query service is unable to serve the chain, not that the data is not available yet.
+async fn fetch<T: DeserializeOwned>(
+    client: &SequencerClient,
+    path: &str,
+    retries: usize,
+) -> anyhow::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match client.get(path).send().await {
+            Ok(obj) => break Ok(obj),
+            Err(err) if attempt < retries => {
+                tracing::warn!("error fetching {path} (attempt {attempt}): {err}");
+                attempt += 1;
+
+                // Back off a bit and then retry.
+                sleep(Duration::from_millis(100) * attempt as u32).await;
+            }
+            Err(err) => bail!("error fetching {path}: {err}"),
+        }
+    }
+}
+
+struct L1 {
+    client: L1Client,
+    provider: Provider<Http>,
+}
+
+impl L1 {
+    async fn get_block(&self, height: u64) -> L1BlockInfo {
+        loop {
+            let block = match self.provider.get_block(height).await {
+                Ok(Some(block)) => block,
+                Ok(None) => {
+                    tracing::warn!("L1 block {height} not yet available");
+                    sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+                Err(err) => {
+                    tracing::warn!("error fetching L1 block {height}: {err}");
+                    sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let Some(hash) = block.hash else {
+                tracing::warn!("L1 block {height} has no hash, might not be finalized yet");
+                sleep(Duration::from_secs(1)).await;
+                continue;
+            };
+
+            return L1BlockInfo {
+                number: height,
+                hash,
+                timestamp: block.timestamp,
+            };
+        }
+    }
+}
+
+struct Auditor {
+    l1: Option<L1>,
+    skip: Vec<DivergenceKind>,
+    /// Hashes of the transactions seen so far, to count unique transactions.
+    transactions: HashSet<Commitment<Transaction>>,
+    /// The state after the last audited block, if it could be replayed.
+    state: Option<ValidatedState>,
+    parent: Option<Leaf>,
+    report: Report,
+}
+
+impl Auditor {
+    /// An auditor starting from genesis, or from a block whose parent is `parent`.
+    fn new(opt: &Options, parent: Option<Leaf>) -> Self {
+        let l1 = opt.l1_provider_url.as_ref().map(|url| L1 {
+            client: L1Client::new(url.clone(), opt.fee_contract_address),
+            provider: Provider::try_from(url.to_string()).unwrap(),
+        });
+        // We only know the state if we are starting from genesis.
+        let state = parent
+            .is_none()
+            .then(|| sequencer::genesis_state(opt.prefunded_builder_accounts.iter().copied()));
+        Self {
+            report: Report {
+                l1_verified: l1.is_some(),
+                ..Default::default()
+            },
+            l1,
+            skip: opt.skip.clone(),
+            transactions: Default::default(),
+            state,
+            parent,
+        }
+    }
+
+    fn diverge(&mut self, height: u64, kind: DivergenceKind, message: impl Display) {
+        if self.skip.contains(&kind) {
+            tracing::debug!("block {height} diverges ({kind:?}), skipping: {message}");
+            return;
+        }
+        tracing::error!("block {height} diverges ({kind:?}): {message}");
+        self.report.divergences.push(Divergence {
+            height,
+            kind,
+            message: message.to_string(),
+        });
+    }
+
+    async fn audit(&mut self, height: u64, block: BlockData) {
+        tracing::debug!("auditing block {height}");
+        let leaf = block.leaf.leaf().clone();
+        let header = leaf.get_block_header().clone();
+        if header.height != height {
+            self.diverge(
+                height,
+                DivergenceKind::Height,
+                format!("header has height {}", header.height),
+            );
+        }
+
+        self.audit_payload(&header, &block);
+        match self.parent.take() {
+            Some(parent) => {
+                if leaf.get_parent_commitment() != parent.commit() {
+                    self.diverge(
+                        height,
+                        DivergenceKind::Parent,
+                        format!(
+                            "leaf extends {}, not the leaf at height {}",
+                            leaf.get_parent_commitment(),
+                            parent.get_height()
+                        ),
+                    );
+                }
+                self.audit_header(&parent, &header).await;
+                self.audit_state(&parent, &header).await;
+            }
+            None => self.audit_genesis(&header),
+        }
+
+        self.report.blocks += 1;
+        self.parent = Some(leaf);
+    }
+
+    fn audit_genesis(&mut self, header: &Header) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let block_merkle_tree_root = state.block_merkle_tree.commitment();
+        let fee_merkle_tree_root = state.fee_merkle_tree.commitment();
+        if header.block_merkle_tree_root != block_merkle_tree_root {
+            self.diverge(
+                header.height,
+                DivergenceKind::State,
+                format!(
+                    "genesis block root {} does not match genesis state {block_merkle_tree_root}",
+                    header.block_merkle_tree_root
+                ),
+            );
+        } else if header.fee_merkle_tree_root != fee_merkle_tree_root {
+            self.diverge(
+                header.height,
+                DivergenceKind::State,
+                format!(
+                    "genesis fee root {} does not match genesis state {fee_merkle_tree_root}",
+                    header.fee_merkle_tree_root
+                ),
+            );
+        } else {
+            self.report.state_verified_through = Some(header.height);
+            return;
+        }
+        self.state = None;
+    }
+
+    async fn audit_header(&mut self, parent: &Leaf, header: &Header) {
+        let parent = parent.get_block_header();
+        let height = header.height;
+
+        if header.timestamp < parent.timestamp {
+            self.diverge(
+                height,
+                DivergenceKind::Timestamp,
+                format!(
+                    "decreasing timestamp: {} -> {}",
+                    parent.timestamp, header.timestamp
+                ),
+            );
+        }
+        if header.l1_head < parent.l1_head {
+            self.diverge(
+                height,
+                DivergenceKind::L1Head,
+                format!(
+                    "decreasing L1 head: {} -> {}",
+                    parent.l1_head, header.l1_head
+                ),
+            );
+        }
+        if header.l1_finalized < parent.l1_finalized {
+            self.diverge(
+                height,
+                DivergenceKind::L1Finalized,
+                format!(
+                    "decreasing L1 finalized: {:?} -> {:?}",
+                    parent.l1_finalized, header.l1_finalized
+                ),
+            );
+        }
+
+        // Only check the L1 finalized block when it changes, so we don't fetch the same L1 block
+        // for every header that references it.
+        let l1_finalized = match (&self.l1, header.l1_finalized) {
+            (Some(l1), Some(l1_finalized)) if header.l1_finalized != parent.l1_finalized => {
+                Some((l1_finalized, l1.get_block(l1_finalized.number).await))
+            }
+            _ => None,
+        };
+        if let Some((l1_finalized, l1_block)) = l1_finalized {
+            if l1_finalized != l1_block {
+                self.diverge(
+                    height,
+                    DivergenceKind::L1Finalized,
+                    format!("wrong L1 finalized info: {l1_finalized:?}, expected {l1_block:?}"),
+                );
+            }
+        }
+
+        if let Err(err) = verify_builder_signature(header) {
+            self.diverge(height, DivergenceKind::BuilderSignature, format!("{err:#}"));
+        }
+    }
+
+    async fn audit_state(&mut self, parent: &Leaf, header: &Header) {
+        let Some(state) = &mut self.state else {
+            return;
+        };
+
+        // Fetch the new L1 deposits between parent and current finalized L1 block, exactly as a
+        // sequencer node does when validating the header.
+        let l1_deposits = match (&self.l1, header.l1_finalized) {
+            (Some(l1), Some(block_info)) => {
+                l1.client
+                    .get_finalized_deposits(
+                        parent
+                            .get_block_header()
+                            .l1_finalized
+                            .map(|block_info| block_info.number),
+                        block_info.number,
+                    )
+                    .await
+            }
+            _ => vec![],
+        };
+
+        // This is `validate_and_apply_header`, except that the builder signature is not checked.
+        // It is checked by `audit_header`, and reporting it again here would only duplicate that
+        // divergence. The fee is charged either way, since the block was applied regardless.
+        let mut delta = Delta::default();
+        let res = validate_and_apply_proposal(state, &mut delta, parent, header, l1_deposits)
+            .and_then(|()| charge_builder_fee(&mut state.fee_merkle_tree, &mut delta, header));
+        if let Err(err) = res {
+            // The state may have been partially updated, so we can't continue replaying it.
+            self.state = None;
+            self.diverge(
+                header.height,
+                DivergenceKind::State,
+                format!("{err:#}; not checking state roots of later blocks"),
+            );
+        } else {
+            self.report.state_verified_through = Some(header.height);
+        }
+    }
+
+    fn audit_payload(&mut self, header: &Header, block: &BlockData) {
+        let height = header.height;
+        let payload = block.payload.data();
+        let bytes = match payload.encode() {
+            Ok(bytes) => bytes.collect::<Vec<_>>(),
+            Err(err) => {
+                self.diverge(
+                    height,
+                    DivergenceKind::PayloadCommitment,
+                    format!("unable to encode payload: {err}"),
+                );
+                return;
+            }
+        };
+
+        if let Err(err) = header.ns_table.validate(bytes.len()) {
+            self.diverge(height, DivergenceKind::NamespaceTable, format!("{err:#}"));
+        }
+        if *payload.get_ns_table() != header.ns_table {
+            self.diverge(
+                height,
+                DivergenceKind::NamespaceTable,
+                "header namespace table does not match payload",
+            );
+        }
+
+        let num_storage_nodes = VidSchemeType::get_num_storage_nodes(block.vid_common.common());
+        let commit = vid_commitment(&bytes, num_storage_nodes);
+        if commit != header.payload_commitment {
+            self.diverge(
+                height,
+                DivergenceKind::PayloadCommitment,
+                format!(
+                    "payload commitment {} does not match payload {commit}",
+                    header.payload_commitment
+                ),
+            );
+        }
+
+        for (_, txn) in payload.enumerate(&header.ns_table) {
+            self.report.transactions += 1;
+            if self.transactions.insert(txn.commit()) {
+                self.report.unique_transactions += 1;
+            }
+        }
+    }
+}
+
+#[async_std::main]
+async fn main() {
+    setup_logging();
+    setup_backtrace();
+
+    let opt = Options::parse();
+    let client = SequencerClient::new(opt.url.clone());
+    client.connect(None).await;
+
+    let block_height: u64 = match fetch(&client, "status/block-height", opt.max_retries).await {
+        Ok(height) => height,
+        Err(err) => {
+            tracing::error!("{err:#}");
+            exit(1);
+        }
+    };
+    let to = opt.to.unwrap_or(block_height);
+    if to > block_height {
+        tracing::error!("cannot audit up to block {to}, the chain only has {block_height} blocks");
+        exit(1);
+    }
+    let from = opt.from.min(to);
+    tracing::info!("auditing blocks {from}-{to}");
+    if opt.l1_provider_url.is_none() {
+        tracing::warn!("no L1 provider given, assuming blocks credit no L1 deposits");
+    }
+
+    let parent = if from > 0 {
+        tracing::warn!("not starting from genesis, state roots will not be checked");
+        let parent: LeafQueryData<SeqTypes> = match fetch(
+            &client,
+            &format!("availability/leaf/{}", from - 1),
+            opt.max_retries,
+        )
+        .await
+        {
+            Ok(leaf) => leaf,
+            Err(err) => {
+                tracing::error!("{err:#}");
+                exit(1);
+            }
+        };
+        Some(parent.leaf().clone())
+    } else {
+        None
+    };
+
+    let mut auditor = Auditor::new(&opt, parent);
+    let mut blocks = stream::iter(from..to)
+        .map(|height| {
+            let client = &client;
+            let retries = opt.max_retries;
+            async move { (height, BlockData::fetch(client, height, retries).await) }
+        })
+        .buffered(opt.jobs.max(1));
+    while let Some((height, block)) = blocks.next().await {
+        let block = match block {
+            Ok(block) => block,
+            Err(err) => {
+                tracing::error!("stopping audit at block {height}: {err:#}");
+                auditor.report.error = Some(format!("block {height}: {err:#}"));
+                break;
+            }
+        };
+        auditor.audit(height, block).await;
+        if (height + 1) % 1000 == 0 {
+            tracing::info!("audited {}/{to} blocks", height + 1);
+        }
+    }
+    drop(blocks);
+
+    let report = auditor.report;
+    let json = serde_json::to_string_pretty(&report).unwrap();
+    match &opt.output {
+        Some(path) => {
+            if let Err(err) = std::fs::write(path, json) {
+                tracing::error!("error writing report to {}: {err}", path.display());
+                exit(1);
+            }
+        }
+        None => println!("{json}"),
+    }
+
+    if !report.divergences.is_empty() {
+        tracing::error!("found {} divergences", report.divergences.len());
+        exit(1);
+    }
+    if report.error.is_some() {
+        tracing::error!("audit stopped after {} blocks", report.blocks);
+        exit(1);
+    }
+    tracing::info!(
+        "all {} blocks ok ({} transactions, {} unique)",
+        report.blocks,
+        report.transactions,
+        report.unique_transactions
+    );
+}
@@ -0,0 +1,205 @@
+//! Utility program to export and import Espresso Sequencer chain history.
+//!
+//! `export` downloads a range of blocks from a query service into a portable archive (see
+//! [`sequencer::archive`]). `import` verifies an archive and loads it into the query service storage
+//! of a node, which can then serve the archived history without fetching it from its peers. Do not
+//! import into the storage of a node while it is running.
+
+use anyhow::ensure;
+use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+use clap::{Parser, Subcommand};
+use es_version::SequencerVersion;
+use ethers::types::Address;
+use hotshot_query_service::availability::UpdateAvailabilityData;
+use sequencer::{
+    api::{
+        data_source::{DataSourceOptions, SequencerDataSource},
+        fs,
+    },
+    archive::{self, ArchiveReader},
+    l1_client::L1Client,
+    persistence, SeqTypes,
+};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+use surf_disco::Url;
+
+/// Utility program to export and import Espresso Sequencer chain history.
+#[derive(Clone, Debug, Parser)]
+struct Options {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum Command {
+    /// Export a range of blocks from a query service into an archive.
+    Export(ExportOptions),
+    /// Import an archive into the query service storage of a node.
+    #[clap(subcommand)]
+    Import(ImportOptions),
+}
+
+#[derive(Clone, Debug, Parser)]
+struct ExportOptions {
+    /// Start exporting at block FROM.
+    #[clap(long, name = "FROM", default_value = "0")]
+    from: u64,
+
+    /// Stop exporting at block TO.
+    ///
+    /// If not specified, every block up to the current block height is exported.
+    #[clap(long, name = "TO")]
+    to: Option<u64>,
+
+    /// Do not export merklized state.
+    ///
+    /// By default, the paths in the block and fee Merkle trees which changed in each block are
+    /// exported, which requires the state API to be enabled on the query service and FROM to be 0.
+    #[clap(long)]
+    no_state: bool,
+
+    /// L1 RPC URL.
+    ///
+    /// This is used to find the accounts credited by L1 deposits in each block, so that their fee
+    /// state can be exported. It is required unless `--no-state` is given.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_L1_PROVIDER",
+        required_unless_present = "no_state"
+    )]
+    l1_provider_url: Option<Url>,
+
+    /// Address of the fee contract proxy on the L1.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_FEE_CONTRACT_PROXY_ADDRESS",
+        default_value = "0x0000000000000000000000000000000000000000"
+    )]
+    fee_contract_address: Address,
+
+    /// File to write the archive to.
+    #[clap(short, long)]
+    output: PathBuf,
+
+    /// URL of the query service to export from.
+    url: Url,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum ImportOptions {
+    /// Import into file system storage.
+    Fs {
+        #[clap(flatten)]
+        archive: ArchiveOptions,
+        #[clap(flatten)]
+        storage: persistence::fs::Options,
+    },
+    /// Import into SQL storage.
+    Sql {
+        #[clap(flatten)]
+        archive: ArchiveOptions,
+        #[clap(flatten)]
+        storage: persistence::sql::Options,
+    },
+}
+
+#[derive(Clone, Debug, Parser)]
+struct ArchiveOptions {
+    /// Reset the storage before importing.
+    ///
+    /// Without this option, the archive is imported alongside whatever data the storage already
+    /// contains.
+    #[clap(long)]
+    reset: bool,
+
+    /// The archive to import.
+    #[clap(long)]
+    archive: PathBuf,
+
+    /// Number of recent blocks for which to keep merklized state, with file system storage.
+    ///
+    /// This should match the `ESPRESSO_SEQUENCER_STATE_FS_RETENTION` of the node which will serve
+    /// the imported data.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_STATE_FS_RETENTION",
+        default_value = "10000",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    state_retention: u64,
+}
+
+#[async_std::main]
+async fn main() -> anyhow::Result<()> {
+    setup_logging();
+    setup_backtrace();
+
+    let opt = Options::parse();
+    match opt.command {
+        Command::Export(opt) => export(opt).await,
+        Command::Import(ImportOptions::Fs { archive, storage }) => {
+            // The file system data source keeps merklized state in a separate store.
+            import(archive, storage, |opt| Some(fs::merkle_store_path(opt))).await
+        }
+        Command::Import(ImportOptions::Sql { archive, storage }) => {
+            import(archive, storage, |_| None).await
+        }
+    }
+}
+
+async fn export(opt: ExportOptions) -> anyhow::Result<()> {
+    ensure!(
+        opt.no_state || opt.from == 0,
+        "state can only be exported from genesis, use --no-state to export from block {}",
+        opt.from
+    );
+
+    let client = archive::Client::<SequencerVersion>::new(opt.url);
+    client.connect(None).await;
+
+    let to = match opt.to {
+        Some(to) => to,
+        None => client.get("status/block-height").send().await?,
+    };
+    let l1 = opt
+        .l1_provider_url
+        .map(|url| L1Client::new(url, opt.fee_contract_address));
+    tracing::info!(from = opt.from, to, "exporting to {}", opt.output.display());
+
+    let file = BufWriter::new(File::create(&opt.output)?);
+    archive::export(&client, opt.from, to, !opt.no_state, l1.as_ref(), file).await?;
+    tracing::info!("exported {} blocks", to - opt.from);
+    Ok(())
+}
+
+async fn import<O: DataSourceOptions>(
+    opt: ArchiveOptions,
+    storage: O,
+    merkle_store_path: impl FnOnce(&O) -> Option<PathBuf>,
+) -> anyhow::Result<()>
+where
+    O::DataSource: UpdateAvailabilityData<SeqTypes>,
+{
+    let reader = ArchiveReader::new(BufReader::new(File::open(&opt.archive)?))?;
+    let header = reader.header();
+    tracing::info!(
+        from = header.from,
+        to = header.to,
+        state = header.state,
+        "importing {}",
+        opt.archive.display()
+    );
+
+    let mut ds = O::DataSource::create(storage.clone(), Default::default(), opt.reset).await?;
+    let merkle_store = match merkle_store_path(&storage) {
+        Some(path) => Some(fs::MerkleStore::open(path, opt.state_retention).await?),
+        None => None,
+    };
+    let imported = archive::import(reader, &mut ds, merkle_store.as_ref()).await?;
+    tracing::info!("imported {imported} blocks");
+    Ok(())
+}
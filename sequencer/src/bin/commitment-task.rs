@@ -1,13 +1,26 @@
+use anyhow::{ensure, Context};
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
-use async_std::task::spawn;
+use async_std::{
+    sync::RwLock,
+    task::{sleep, spawn},
+};
 use clap::Parser;
 use es_version::SEQUENCER_VERSION;
 use ethers::prelude::*;
 use futures::FutureExt;
-use sequencer::hotshot_commitment::{run_hotshot_commitment_task, CommitmentTaskOptions};
+use hotshot::traits::election::static_committee::StaticElectionConfig;
+use hotshot_orchestrator::{config::NetworkConfig, OrchestratorVersion};
+use hotshot_query_service::metrics::PrometheusMetrics;
+use hotshot_types::PeerConfig;
+use sequencer::hotshot_commitment::{
+    genesis_leaf_commitment, run_hotshot_commitment_task, CommitmentTaskOptions, FeeOptions,
+    TurnOptions,
+};
 use sequencer::options::parse_duration;
+use sequencer::PubKey;
 use std::io;
-use std::time::Duration;
+use std::{borrow::Cow, time::Duration};
+use surf_disco::Client;
 use tide_disco::error::ServerError;
 use tide_disco::Api;
 use url::Url;
@@ -57,18 +70,132 @@ pub struct Options {
     /// If specified, sequencing attempts will be delayed by duration sampled from an exponential distribution with mean DELAY.
     #[clap(long, name = "DELAY", value_parser = parse_duration, env = "ESPRESSO_COMMITMENT_TASK_DELAY")]
     pub delay: Option<Duration>,
+
+    /// URL of the HotShot orchestrator.
+    ///
+    /// The stake table is fetched from the orchestrator and used to check the signature on each QC
+    /// before it is sent to the HotShot contract.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_ORCHESTRATOR_URL")]
+    pub orchestrator_url: Url,
+
+    /// Accounts prefunded in the genesis state of the HotShot chain.
+    ///
+    /// This must match the prefunded builder accounts of the sequencer nodes. It determines the
+    /// genesis leaf, which is the only leaf sent to the HotShot contract without a signed QC.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_PREFUNDED_BUILDER_ACCOUNTS",
+        value_delimiter = ','
+    )]
+    pub prefunded_builder_accounts: Vec<Address>,
+
+    /// Maximum gas to spend on a single transaction.
+    ///
+    /// Batches of blocks are cut short so that their estimated gas stays within this budget.
+    #[clap(long, env = "ESPRESSO_COMMITMENT_TASK_MAX_GAS")]
+    pub max_gas: Option<u64>,
+
+    /// Maximum fee per gas, in wei.
+    ///
+    /// If not specified, the fee estimated by the L1 provider is used without a cap.
+    #[clap(long, env = "ESPRESSO_COMMITMENT_TASK_MAX_FEE_PER_GAS")]
+    pub max_fee_per_gas: Option<u64>,
+
+    /// Priority fee per gas, in wei.
+    ///
+    /// If not specified, the priority fee estimated by the L1 provider is used.
+    #[clap(long, env = "ESPRESSO_COMMITMENT_TASK_MAX_PRIORITY_FEE_PER_GAS")]
+    pub max_priority_fee_per_gas: Option<u64>,
+
+    /// How long to wait for a transaction to be mined before replacing it with a higher fee.
+    #[clap(long, env = "ESPRESSO_COMMITMENT_TASK_REPLACEMENT_TIMEOUT", value_parser = parse_duration, default_value = "1m")]
+    pub replacement_timeout: Duration,
+
+    /// Percentage by which fees are raised when replacing a stuck transaction.
+    ///
+    /// Must be at least 10, since most nodes reject replacements with a smaller bump.
+    #[clap(
+        long,
+        env = "ESPRESSO_COMMITMENT_TASK_FEE_BUMP_PERCENT",
+        default_value = "20",
+        value_parser = clap::value_parser!(u64).range(10..)
+    )]
+    pub fee_bump_percent: u64,
+
+    /// Maximum number of times to replace a stuck transaction before giving up on it.
+    #[clap(
+        long,
+        env = "ESPRESSO_COMMITMENT_TASK_MAX_REPLACEMENTS",
+        default_value = "5"
+    )]
+    pub max_replacements: usize,
+
+    /// Number of commitment task instances taking turns submitting blocks.
+    ///
+    /// Running several instances with the same HotShot contract provides redundancy. When this is
+    /// greater than 1, each instance only submits blocks in its own time slots, so that instances do
+    /// not waste gas on duplicate submissions.
+    #[clap(
+        long,
+        env = "ESPRESSO_COMMITMENT_TASK_NUM_INSTANCES",
+        default_value = "1"
+    )]
+    pub num_instances: usize,
+
+    /// Index of this instance, in `0..num-instances`.
+    #[clap(
+        long,
+        env = "ESPRESSO_COMMITMENT_TASK_INSTANCE_INDEX",
+        default_value = "0"
+    )]
+    pub instance_index: usize,
+
+    /// Length of each instance's turn when several instances are coordinating.
+    #[clap(long, env = "ESPRESSO_COMMITMENT_TASK_SLOT_DURATION", value_parser = parse_duration, default_value = "30s")]
+    pub slot_duration: Duration,
 }
 #[async_std::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
     setup_logging();
     setup_backtrace();
 
     let opt = Options::parse();
+    let metrics = PrometheusMetrics::default();
+
+    ensure!(opt.num_instances > 0, "there must be at least one instance");
+    ensure!(
+        opt.instance_index < opt.num_instances,
+        "instance index {} out of range for {} instances",
+        opt.instance_index,
+        opt.num_instances
+    );
+    let turns = if opt.num_instances > 1 {
+        Some(TurnOptions {
+            instance_index: opt.instance_index,
+            num_instances: opt.num_instances,
+            slot_duration: opt.slot_duration,
+        })
+    } else {
+        None
+    };
 
     if let Some(port) = opt.port {
-        start_http_server(port, opt.hotshot_address, SEQUENCER_VERSION).unwrap();
+        start_http_server(
+            port,
+            opt.hotshot_address,
+            metrics.clone(),
+            SEQUENCER_VERSION,
+        )
+        .context("failed to start HTTP server")?;
     }
 
+    let stake_table = fetch_stake_table(&opt.orchestrator_url).await;
+
+    let genesis_leaf = genesis_leaf_commitment(
+        opt.l1_provider.clone(),
+        sequencer::genesis_state(opt.prefunded_builder_accounts),
+    );
+
     let hotshot_contract_options = CommitmentTaskOptions {
         hotshot_address: opt.hotshot_address,
         l1_chain_id: None,
@@ -78,26 +205,73 @@ async fn main() {
         sequencer_account_index: opt.hotshot_account_index,
         request_timeout: opt.request_timeout,
         query_service_url: Some(opt.sequencer_url),
+        stake_table,
+        genesis_leaf,
+        max_gas: opt.max_gas,
+        fees: FeeOptions {
+            max_fee_per_gas: opt.max_fee_per_gas.map(U256::from),
+            max_priority_fee_per_gas: opt.max_priority_fee_per_gas.map(U256::from),
+            replacement_timeout: opt.replacement_timeout,
+            fee_bump_percent: opt.fee_bump_percent,
+            max_replacements: opt.max_replacements,
+        },
+        turns,
     };
     tracing::info!("Launching HotShot commitment task..");
-    run_hotshot_commitment_task::<es_version::SequencerVersion>(&hotshot_contract_options).await;
+    run_hotshot_commitment_task::<es_version::SequencerVersion>(
+        &hotshot_contract_options,
+        &metrics,
+    )
+    .await;
+    Ok(())
+}
+
+async fn fetch_stake_table(orchestrator_url: &Url) -> Vec<PeerConfig<PubKey>> {
+    tracing::info!("fetching stake table from orchestrator");
+    let client = Client::<ServerError, OrchestratorVersion>::new(orchestrator_url.clone());
+    loop {
+        match client.get::<bool>("api/peer_pub_ready").send().await {
+            Ok(true) => {
+                match client
+                    .get::<NetworkConfig<PubKey, StaticElectionConfig>>(
+                        "api/get_config_after_peer_collected",
+                    )
+                    .send()
+                    .await
+                {
+                    Ok(config) => return config.config.known_nodes_with_stake,
+                    Err(err) => tracing::warn!("orchestrator error: {err}, retrying"),
+                }
+            }
+            Ok(false) => tracing::info!("peers' keys are not ready, retrying"),
+            Err(err) => tracing::warn!("orchestrator error: {err}, retrying"),
+        }
+        sleep(Duration::from_secs(2)).await;
+    }
 }
 
 fn start_http_server<Ver: StaticVersionType + 'static>(
     port: u16,
     hotshot_address: Address,
+    metrics: PrometheusMetrics,
     bind_version: Ver,
 ) -> io::Result<()> {
-    let mut app = tide_disco::App::<(), ServerError, Ver>::with_state(());
+    type State = RwLock<PrometheusMetrics>;
+
+    let mut app = tide_disco::App::<State, ServerError, Ver>::with_state(RwLock::new(metrics));
     let toml = toml::from_str::<toml::value::Value>(include_str!("../../api/commitment_task.toml"))
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
 
-    let mut api = Api::<(), ServerError, Ver>::new(toml)
+    let mut api = Api::<State, ServerError, Ver>::new(toml)
         .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
 
     api.get("gethotshotcontract", move |_, _| {
         async move { Ok(hotshot_address) }.boxed()
     })
+    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+    .metrics("metrics", |_, state| {
+        async move { Ok(Cow::Borrowed(state)) }.boxed()
+    })
     .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
 
     app.register_module("api", api)
@@ -116,6 +290,8 @@ mod test {
 
     use super::start_http_server;
     use super::Address;
+    use super::Options;
+    use super::PrometheusMetrics;
     use super::ServerError;
 
     #[async_std::test]
@@ -127,8 +303,13 @@ mod test {
         let expected_addr = "0xED15E1FE0789c524398137a066ceb2EF9884E5D8"
             .parse::<Address>()
             .unwrap();
-        start_http_server(port, expected_addr, SEQUENCER_VERSION)
-            .expect("Failed to start the server");
+        start_http_server(
+            port,
+            expected_addr,
+            PrometheusMetrics::default(),
+            SEQUENCER_VERSION,
+        )
+        .expect("Failed to start the server");
 
         let client: Client<ServerError, SequencerVersion> =
             Client::new(format!("http://localhost:{port}").parse().unwrap());
@@ -138,4 +319,28 @@ mod test {
 
         assert_eq!(addr, expected_addr);
     }
+
+    #[test]
+    fn test_fee_bump_percent() {
+        let parse = |bump: &str| {
+            <Options as clap::Parser>::try_parse_from([
+                "commitment-task",
+                "--sequencer-url",
+                "http://localhost:1234",
+                "--l1-provider",
+                "http://localhost:8545",
+                "--hotshot-address",
+                "0xED15E1FE0789c524398137a066ceb2EF9884E5D8",
+                "--eth-mnemonic",
+                "test test test test test test test test test test test junk",
+                "--orchestrator-url",
+                "http://localhost:40001",
+                "--fee-bump-percent",
+                bump,
+            ])
+        };
+        assert_eq!(parse("10").unwrap().fee_bump_percent, 10);
+        // Nodes would reject replacements with a smaller bump, so refuse to start.
+        parse("9").unwrap_err();
+    }
 }
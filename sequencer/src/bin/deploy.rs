@@ -1,28 +1,34 @@
-use anyhow::{ensure, Context};
+use anyhow::{bail, ensure, Context};
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
 use async_std::sync::Arc;
 use clap::{builder::OsStr, Parser};
 use contract_bindings::{
     erc1967_proxy::ERC1967Proxy,
+    fee_contract::{FeeContract, FEECONTRACT_ABI},
     hot_shot::HotShot,
     light_client::{LightClient, LIGHTCLIENT_ABI},
     light_client_state_update_vk::LightClientStateUpdateVK,
+    light_client_v2::LightClientV2,
+    ownable_upgradeable::OwnableUpgradeable,
     plonk_verifier::PlonkVerifier,
+    uups_upgradeable::UUPSUpgradeable,
 };
 use derive_more::Display;
 use ethers::{
+    abi::Abi,
     contract::Contract as ContractBindings,
     prelude::{coins_bip39::English, *},
     solc::artifacts::BytecodeObject,
 };
 use futures::future::{BoxFuture, FutureExt};
 use hotshot_state_prover::service::light_client_genesis;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     fs::File,
     io::{stdout, Write},
     ops::Deref,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 use url::Url;
 
@@ -42,6 +48,10 @@ use url::Url;
 /// addresses will be used in place of deploying a new contract wherever that contract is required
 /// in the deployment process. The generated .env file will include all the addresses passed in as
 /// well as those newly deployed.
+///
+/// What to deploy, and which existing proxies to upgrade, can be described in a deployment plan
+/// (see PLAN). A JSON manifest describing the results of the deployment can be written with
+/// MANIFEST.
 #[derive(Clone, Debug, Parser)]
 struct Options {
     /// A JSON-RPC endpoint for the L1 to deploy to.
@@ -90,6 +100,47 @@ struct Options {
     #[clap(short, long, name = "OUT", env = "ESPRESSO_DEPLOYER_OUT_PATH")]
     out: Option<PathBuf>,
 
+    /// TOML file describing the deployment.
+    ///
+    /// The plan lists the contracts to deploy, in order, and the proxies to upgrade afterwards:
+    ///
+    /// ```toml
+    /// # Owner of upgradable contracts. Defaults to the deployer account.
+    /// owner = "0x..."
+    ///
+    /// [[deploy]]
+    /// contract = "hot_shot"
+    ///
+    /// [[deploy]]
+    /// contract = "light_client"
+    /// # Deploy behind an ERC1967 proxy (the default), or deploy only the implementation.
+    /// proxy = true
+    /// blocks_per_epoch = 4294967295
+    ///
+    /// [[deploy]]
+    /// contract = "fee_contract"
+    ///
+    /// [[upgrade]]
+    /// contract = "light_client"
+    /// # "builtin" (the default) deploys the implementation built into this program. Otherwise
+    /// # use `{ address = "0x..." }` for an existing implementation or `{ artifact = "path" }` for
+    /// # the artifact written by `forge build`, whose bytecode is linked against the light client
+    /// # libraries it references.
+    /// implementation = { artifact = "out/LightClientV2.sol/LightClientV2.json" }
+    /// # Calldata for `upgradeToAndCall`. Defaults to empty.
+    /// call = "0x"
+    /// # Number of storage slots, from slot 0, which must not change in the upgrade.
+    /// check_slots = 16
+    /// ```
+    ///
+    /// If not provided, HotShot.sol and LightClient.sol (behind a proxy) are deployed.
+    #[clap(long, name = "PLAN", env = "ESPRESSO_DEPLOYER_PLAN_PATH")]
+    plan: Option<PathBuf>,
+
+    /// Write a JSON manifest of the deployment to MANIFEST.
+    #[clap(long, name = "MANIFEST", env = "ESPRESSO_DEPLOYER_MANIFEST_PATH")]
+    manifest: Option<PathBuf>,
+
     #[clap(flatten)]
     contracts: DeployedContracts,
 }
@@ -116,6 +167,14 @@ struct DeployedContracts {
     /// Use an already-deployed LightClient.sol proxy instead of deploying a new one.
     #[clap(long, env = Contract::LightClientProxy)]
     light_client_proxy: Option<Address>,
+
+    /// Use an already-deployed FeeContract.sol instead of deploying a new one.
+    #[clap(long, env = Contract::FeeContract)]
+    fee_contract: Option<Address>,
+
+    /// Use an already-deployed FeeContract.sol proxy instead of deploying a new one.
+    #[clap(long, env = Contract::FeeContractProxy)]
+    fee_contract_proxy: Option<Address>,
 }
 
 /// An identifier for a particular contract.
@@ -131,6 +190,10 @@ enum Contract {
     LightClient,
     #[display(fmt = "ESPRESSO_SEQUENCER_LIGHT_CLIENT_PROXY_ADDRESS")]
     LightClientProxy,
+    #[display(fmt = "ESPRESSO_SEQUENCER_FEE_CONTRACT_ADDRESS")]
+    FeeContract,
+    #[display(fmt = "ESPRESSO_SEQUENCER_FEE_CONTRACT_PROXY_ADDRESS")]
+    FeeContractProxy,
 }
 
 impl From<Contract> for OsStr {
@@ -160,6 +223,12 @@ impl From<DeployedContracts> for Contracts {
         if let Some(addr) = deployed.light_client_proxy {
             m.insert(Contract::LightClientProxy, addr);
         }
+        if let Some(addr) = deployed.fee_contract {
+            m.insert(Contract::FeeContract, addr);
+        }
+        if let Some(addr) = deployed.fee_contract_proxy {
+            m.insert(Contract::FeeContractProxy, addr);
+        }
         Self(m)
     }
 }
@@ -219,12 +288,252 @@ impl Contracts {
     }
 }
 
+/// A declarative description of a deployment.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Plan {
+    /// The owner of upgradable contracts. Defaults to the deployer account.
+    #[serde(default)]
+    owner: Option<Address>,
+    /// Contracts to deploy, in order.
+    #[serde(default, rename = "deploy")]
+    deployments: Vec<Deployment>,
+    /// Proxies to upgrade, in order, after all deployments.
+    #[serde(default, rename = "upgrade")]
+    upgrades: Vec<Upgrade>,
+}
+
+impl Default for Plan {
+    fn default() -> Self {
+        Self {
+            owner: None,
+            deployments: vec![
+                Deployment::HotShot,
+                Deployment::LightClient {
+                    proxy: true,
+                    blocks_per_epoch: u32::MAX,
+                },
+            ],
+            upgrades: vec![],
+        }
+    }
+}
+
+/// A contract to deploy.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "contract", rename_all = "snake_case")]
+enum Deployment {
+    HotShot,
+    LightClient {
+        /// Deploy behind an ERC1967 proxy, or deploy only the implementation.
+        #[serde(default = "default_proxy")]
+        proxy: bool,
+        #[serde(default = "default_blocks_per_epoch")]
+        blocks_per_epoch: u32,
+    },
+    FeeContract {
+        /// Deploy behind an ERC1967 proxy, or deploy only the implementation.
+        #[serde(default = "default_proxy")]
+        proxy: bool,
+    },
+}
+
+impl Deployment {
+    /// The proxy this deployment creates, if any.
+    fn proxy(&self) -> Option<Contract> {
+        match self {
+            Self::LightClient { proxy: true, .. } => Some(Contract::LightClientProxy),
+            Self::FeeContract { proxy: true } => Some(Contract::FeeContractProxy),
+            _ => None,
+        }
+    }
+}
+
+fn default_proxy() -> bool {
+    true
+}
+
+fn default_blocks_per_epoch() -> u32 {
+    u32::MAX
+}
+
+/// An upgrade of an existing proxy to a new implementation.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Upgrade {
+    contract: UpgradableContract,
+    #[serde(default)]
+    implementation: Implementation,
+    /// Calldata for a function to call on the new implementation as part of the upgrade.
+    #[serde(default)]
+    call: Bytes,
+    /// Number of storage slots of the proxy, starting from slot 0, which the upgrade must not
+    /// change.
+    #[serde(default = "default_check_slots")]
+    check_slots: u64,
+}
+
+fn default_check_slots() -> u64 {
+    16
+}
+
+/// A contract deployed behind a UUPS proxy.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum UpgradableContract {
+    #[display(fmt = "light_client")]
+    LightClient,
+    #[display(fmt = "fee_contract")]
+    FeeContract,
+}
+
+impl UpgradableContract {
+    fn proxy(self) -> Contract {
+        match self {
+            Self::LightClient => Contract::LightClientProxy,
+            Self::FeeContract => Contract::FeeContractProxy,
+        }
+    }
+
+    fn implementation(self) -> Contract {
+        match self {
+            Self::LightClient => Contract::LightClient,
+            Self::FeeContract => Contract::FeeContract,
+        }
+    }
+}
+
+/// The new implementation for an upgrade.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Implementation {
+    /// Deploy a new instance of the implementation built into this program.
+    #[default]
+    Builtin,
+    /// Use an implementation which is already deployed.
+    Address(Address),
+    /// Deploy the bytecode in a Foundry artifact, linked against the light client libraries it
+    /// references.
+    Artifact(PathBuf),
+}
+
+/// The results of a deployment.
+#[derive(Clone, Debug, Serialize)]
+struct Manifest {
+    chain_id: u64,
+    deployer: Address,
+    owner: Address,
+    /// Addresses of all known contracts, by the name of their environment variable.
+    contracts: BTreeMap<String, Address>,
+    /// The contracts which were deployed in this run.
+    deployed: Vec<String>,
+    upgrades: Vec<UpgradeRecord>,
+}
+
+/// The result of upgrading a proxy.
+#[derive(Clone, Debug, Serialize)]
+struct UpgradeRecord {
+    contract: UpgradableContract,
+    proxy: Address,
+    previous_implementation: Address,
+    implementation: Address,
+    previous_version: Option<String>,
+    version: Option<String>,
+    transaction: H256,
+    /// The storage slots which were checked to be unchanged by the upgrade.
+    checked_slots: Vec<H256>,
+}
+
+/// The ERC-1967 storage slot holding the address of a proxy's implementation.
+fn implementation_slot() -> H256 {
+    "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"
+        .parse()
+        .unwrap()
+}
+
+/// The ERC-7201 storage slot used by `OwnableUpgradeable`.
+fn ownable_slot() -> H256 {
+    "0x9016d09d72d40fdae2fd8ceac6b6234c7706214fd39c1cd1e609a0528c199300"
+        .parse()
+        .unwrap()
+}
+
+/// The ERC-7201 storage slot used by `Initializable`.
+fn initializable_slot() -> H256 {
+    "0xf0c57e16840df040f15088dc2f81fe391c3923bec73e23a9662efc9c229c6a00"
+        .parse()
+        .unwrap()
+}
+
+impl Plan {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let plan = std::fs::read_to_string(path)
+            .with_context(|| format!("reading plan {}", path.display()))?;
+        toml::from_str(&plan).with_context(|| format!("parsing plan {}", path.display()))
+    }
+
+    /// Execute the plan, deploying contracts with and upgrading proxies owned by `deployer`.
+    async fn run<M: Middleware + 'static>(
+        &self,
+        l1: Arc<M>,
+        deployer: Address,
+        contracts: &mut Contracts,
+        orchestrator_url: &Url,
+    ) -> anyhow::Result<Manifest> {
+        let predeployed = contracts.0.keys().copied().collect::<HashSet<_>>();
+        let owner = self.owner.unwrap_or(deployer);
+
+        // Check every upgrade before sending any transaction, so that a plan which cannot be
+        // carried out does not leave a partial deployment behind.
+        let proxies = self
+            .deployments
+            .iter()
+            .filter_map(Deployment::proxy)
+            .collect::<HashSet<_>>();
+        for upgrade in &self.upgrades {
+            check_upgrade(&l1, deployer, contracts, &proxies, owner, upgrade).await?;
+        }
+
+        for deployment in &self.deployments {
+            deploy(l1.clone(), contracts, deployment, owner, orchestrator_url).await?;
+        }
+        let mut upgrades = vec![];
+        for upgrade in &self.upgrades {
+            upgrades.push(upgrade_proxy(l1.clone(), deployer, contracts, upgrade).await?);
+        }
+
+        let mut deployed = contracts
+            .0
+            .keys()
+            .filter(|contract| !predeployed.contains(contract))
+            .map(|contract| contract.to_string())
+            .collect::<Vec<_>>();
+        deployed.sort();
+        Ok(Manifest {
+            chain_id: l1.get_chainid().await?.as_u64(),
+            deployer,
+            owner,
+            contracts: contracts
+                .0
+                .iter()
+                .map(|(contract, address)| (contract.to_string(), *address))
+                .collect(),
+            deployed,
+            upgrades,
+        })
+    }
+}
+
 #[async_std::main]
 async fn main() -> anyhow::Result<()> {
     setup_logging();
     setup_backtrace();
 
     let opt = Options::parse();
+    let plan = match &opt.plan {
+        Some(path) => Plan::load(path)?,
+        None => Plan::default(),
+    };
     let mut contracts = Contracts::from(opt.contracts);
 
     let provider = Provider::<Http>::try_from(opt.rpc_url.to_string())?;
@@ -234,37 +543,11 @@ async fn main() -> anyhow::Result<()> {
         .index(opt.account_index)?
         .build()?
         .with_chain_id(chain_id);
-    let owner = wallet.address();
+    let deployer = wallet.address();
     let l1 = Arc::new(SignerMiddleware::new(provider, wallet));
 
-    contracts
-        .deploy_tx(Contract::HotShot, HotShot::deploy(l1.clone(), ())?)
-        .await?;
-    contracts
-        .deploy_fn(Contract::LightClientProxy, |contracts| {
-            let l1 = l1.clone();
-            let orchestrator_url = opt.orchestrator_url.clone();
-            async move {
-                let light_client = LightClient::new(
-                    contracts
-                        .deploy_fn(Contract::LightClient, |contracts| {
-                            deploy_light_client_contract(l1.clone(), contracts).boxed()
-                        })
-                        .await?,
-                    l1.clone(),
-                );
-                let genesis = light_client_genesis(&orchestrator_url).await?;
-                let data = light_client
-                    .initialize(genesis.into(), u32::MAX, owner)
-                    .calldata()
-                    .context("calldata for initialize transaction not available")?;
-                let proxy = ERC1967Proxy::deploy(l1, (light_client.address(), data))?
-                    .send()
-                    .await?;
-                Ok(proxy.address())
-            }
-            .boxed()
-        })
+    let manifest = plan
+        .run(l1, deployer, &mut contracts, &opt.orchestrator_url)
         .await?;
 
     if let Some(out) = &opt.out {
@@ -273,62 +556,664 @@ async fn main() -> anyhow::Result<()> {
     } else {
         contracts.write(stdout())?;
     }
+    if let Some(path) = &opt.manifest {
+        serde_json::to_writer_pretty(File::create(path)?, &manifest)?;
+    }
 
     Ok(())
 }
 
-async fn deploy_light_client_contract<M: Middleware + 'static>(
+async fn deploy<M: Middleware + 'static>(
     l1: Arc<M>,
     contracts: &mut Contracts,
-) -> anyhow::Result<Address> {
-    // Deploy library contracts.
-    let plonk_verifier = contracts
-        .deploy_tx(
-            Contract::PlonkVerifier,
-            PlonkVerifier::deploy(l1.clone(), ())?,
-        )
-        .await?;
-    let vk = contracts
-        .deploy_tx(
-            Contract::StateUpdateVK,
-            LightClientStateUpdateVK::deploy(l1.clone(), ())?,
+    deployment: &Deployment,
+    owner: Address,
+    orchestrator_url: &Url,
+) -> anyhow::Result<()> {
+    match *deployment {
+        Deployment::HotShot => {
+            contracts
+                .deploy_tx(Contract::HotShot, HotShot::deploy(l1, ())?)
+                .await?;
+        }
+        Deployment::LightClient { proxy: false, .. } => {
+            contracts
+                .deploy_fn(Contract::LightClient, |contracts| {
+                    deploy_light_client_contract(l1, contracts).boxed()
+                })
+                .await?;
+        }
+        Deployment::LightClient {
+            proxy: true,
+            blocks_per_epoch,
+        } => {
+            contracts
+                .deploy_fn(Contract::LightClientProxy, |contracts| {
+                    let orchestrator_url = orchestrator_url.clone();
+                    async move {
+                        let light_client = LightClient::new(
+                            contracts
+                                .deploy_fn(Contract::LightClient, |contracts| {
+                                    deploy_light_client_contract(l1.clone(), contracts).boxed()
+                                })
+                                .await?,
+                            l1.clone(),
+                        );
+                        let genesis = light_client_genesis(&orchestrator_url).await?;
+                        let data = light_client
+                            .initialize(genesis.into(), blocks_per_epoch, owner)
+                            .calldata()
+                            .context("calldata for initialize transaction not available")?;
+                        let proxy = ERC1967Proxy::deploy(l1, (light_client.address(), data))?
+                            .send()
+                            .await?;
+                        Ok(proxy.address())
+                    }
+                    .boxed()
+                })
+                .await?;
+        }
+        Deployment::FeeContract { proxy: false } => {
+            contracts
+                .deploy_tx(Contract::FeeContract, FeeContract::deploy(l1, ())?)
+                .await?;
+        }
+        Deployment::FeeContract { proxy: true } => {
+            contracts
+                .deploy_fn(Contract::FeeContractProxy, |contracts| {
+                    async move {
+                        let fee_contract = FeeContract::new(
+                            contracts
+                                .deploy_tx(
+                                    Contract::FeeContract,
+                                    FeeContract::deploy(l1.clone(), ())?,
+                                )
+                                .await?,
+                            l1.clone(),
+                        );
+                        let data = fee_contract
+                            .initialize(owner)
+                            .calldata()
+                            .context("calldata for initialize transaction not available")?;
+                        let proxy = ERC1967Proxy::deploy(l1, (fee_contract.address(), data))?
+                            .send()
+                            .await?;
+                        Ok(proxy.address())
+                    }
+                    .boxed()
+                })
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that `upgrade` can be carried out by `deployer`, without sending any transaction.
+///
+/// The proxy must be owned by `deployer`. If it is not deployed yet, it must be one of `proxies`,
+/// which the plan deploys with `owner` as their owner. An existing implementation must be a UUPS
+/// implementation, and an artifact must have the UUPS interface.
+async fn check_upgrade<M: Middleware + 'static>(
+    l1: &Arc<M>,
+    deployer: Address,
+    contracts: &Contracts,
+    proxies: &HashSet<Contract>,
+    owner: Address,
+    upgrade: &Upgrade,
+) -> anyhow::Result<()> {
+    let contract = upgrade.contract;
+    match contracts.0.get(&contract.proxy()) {
+        Some(proxy) => check_owner(l1, contract, *proxy, deployer).await?,
+        None if proxies.contains(&contract.proxy()) => ensure!(
+            owner == deployer,
+            "{contract} proxy will be owned by {owner:#x}, cannot upgrade from {deployer:#x}"
+        ),
+        None => bail!(
+            "cannot upgrade {contract}: proxy address is not known, set {}",
+            contract.proxy()
+        ),
+    }
+
+    match &upgrade.implementation {
+        Implementation::Builtin => {}
+        Implementation::Address(implementation) => check_uups(l1, *implementation).await?,
+        Implementation::Artifact(path) => {
+            let (abi, _) = read_artifact(path)?;
+            ensure!(
+                abi.function("proxiableUUID").is_ok() && abi.function("upgradeToAndCall").is_ok(),
+                "artifact {} is not a UUPS implementation",
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Check that `proxy` is owned by `deployer`.
+async fn check_owner<M: Middleware + 'static>(
+    l1: &Arc<M>,
+    contract: UpgradableContract,
+    proxy: Address,
+    deployer: Address,
+) -> anyhow::Result<()> {
+    let owner = OwnableUpgradeable::new(proxy, l1.clone())
+        .owner()
+        .call()
+        .await
+        .with_context(|| format!("reading owner of {contract} proxy at {proxy:#x}"))?;
+    ensure!(
+        owner == deployer,
+        "{contract} proxy is owned by {owner:#x}, cannot upgrade from {deployer:#x}"
+    );
+    Ok(())
+}
+
+/// Check that a UUPS implementation compatible with ERC-1967 proxies is deployed at
+/// `implementation`.
+async fn check_uups<M: Middleware + 'static>(
+    l1: &Arc<M>,
+    implementation: Address,
+) -> anyhow::Result<()> {
+    ensure!(
+        !l1.get_code(implementation, None).await?.is_empty(),
+        "no contract deployed at {implementation:#x}"
+    );
+    let uuid = UUPSUpgradeable::new(implementation, l1.clone())
+        .proxiable_uuid()
+        .call()
+        .await
+        .with_context(|| format!("{implementation:#x} is not a UUPS implementation"))?;
+    ensure!(
+        H256(uuid) == implementation_slot(),
+        "{implementation:#x} is not compatible with ERC-1967 proxies"
+    );
+    Ok(())
+}
+
+/// Upgrade a UUPS proxy to a new implementation.
+///
+/// Before deploying the new implementation, this checks that `deployer` owns the proxy. Before
+/// upgrading, it checks that the new implementation is a UUPS implementation. Afterwards, it checks
+/// that the proxy points to the new implementation, that its owner is unchanged, and that the
+/// upgrade did not modify the first `check_slots` storage slots or the `OwnableUpgradeable`
+/// storage. If no call is made as part of the upgrade, the `Initializable` storage must not change
+/// either.
+async fn upgrade_proxy<M: Middleware + 'static>(
+    l1: Arc<M>,
+    deployer: Address,
+    contracts: &mut Contracts,
+    upgrade: &Upgrade,
+) -> anyhow::Result<UpgradeRecord> {
+    let contract = upgrade.contract;
+    let proxy = *contracts.0.get(&contract.proxy()).with_context(|| {
+        format!(
+            "cannot upgrade {contract}: proxy address is not known, set {}",
+            contract.proxy()
         )
+    })?;
+    tracing::info!("upgrading {contract} proxy at {proxy:#x}");
+    check_owner(&l1, contract, proxy, deployer).await?;
+
+    let previous_implementation = read_address(&l1, proxy, implementation_slot()).await?;
+    let implementation = match &upgrade.implementation {
+        Implementation::Builtin => match contract {
+            UpgradableContract::LightClient => {
+                deploy_light_client_contract(l1.clone(), contracts).await?
+            }
+            UpgradableContract::FeeContract => {
+                FeeContract::deploy(l1.clone(), ())?.send().await?.address()
+            }
+        },
+        Implementation::Address(address) => *address,
+        Implementation::Artifact(path) => {
+            let (abi, bytecode) = read_artifact(path)?;
+            deploy_linked_contract(l1.clone(), contracts, abi, bytecode).await?
+        }
+    };
+    tracing::info!("new {contract} implementation is {implementation:#x}");
+
+    // Pre-upgrade checks.
+    ensure!(
+        implementation != previous_implementation,
+        "{contract} proxy already points to {implementation:#x}"
+    );
+    check_uups(&l1, implementation).await?;
+
+    let mut checked_slots = (0..upgrade.check_slots)
+        .map(H256::from_low_u64_be)
+        .collect::<Vec<_>>();
+    checked_slots.push(ownable_slot());
+    if upgrade.call.is_empty() {
+        checked_slots.push(initializable_slot());
+    }
+    let mut storage = vec![];
+    for slot in &checked_slots {
+        storage.push(l1.get_storage_at(proxy, *slot, None).await?);
+    }
+    let previous_version = get_version(&l1, proxy).await;
+
+    // Upgrade.
+    let receipt = UUPSUpgradeable::new(proxy, l1.clone())
+        .upgrade_to_and_call(implementation, upgrade.call.clone())
+        .send()
+        .await?
+        .await?
+        .context("upgrade transaction dropped")?;
+    ensure!(
+        receipt.status == Some(1.into()),
+        "upgrade transaction {:#x} failed",
+        receipt.transaction_hash
+    );
+
+    // Post-upgrade checks.
+    let new_implementation = read_address(&l1, proxy, implementation_slot()).await?;
+    ensure!(
+        new_implementation == implementation,
+        "{contract} proxy points to {new_implementation:#x} after upgrade, expected \
+         {implementation:#x}"
+    );
+    for (slot, expected) in checked_slots.iter().zip(storage) {
+        let actual = l1.get_storage_at(proxy, *slot, None).await?;
+        ensure!(
+            actual == expected,
+            "upgrade changed storage slot {slot:#x} from {expected:#x} to {actual:#x}"
+        );
+    }
+    let new_owner = OwnableUpgradeable::new(proxy, l1.clone())
+        .owner()
+        .call()
         .await?;
+    ensure!(
+        new_owner == deployer,
+        "upgrade changed owner from {deployer:#x} to {new_owner:#x}"
+    );
+    let version = get_version(&l1, proxy).await;
+    tracing::info!(
+        ?previous_version,
+        ?version,
+        "upgraded {contract} proxy from {previous_implementation:#x} to {implementation:#x}"
+    );
+
+    contracts
+        .0
+        .insert(contract.implementation(), implementation);
+    Ok(UpgradeRecord {
+        contract,
+        proxy,
+        previous_implementation,
+        implementation,
+        previous_version,
+        version,
+        transaction: receipt.transaction_hash,
+        checked_slots,
+    })
+}
+
+/// Read an address from a storage slot.
+async fn read_address<M: Middleware + 'static>(
+    l1: &Arc<M>,
+    contract: Address,
+    slot: H256,
+) -> anyhow::Result<Address> {
+    let word = l1.get_storage_at(contract, slot, None).await?;
+    Ok(Address::from(word))
+}
 
+/// Get the version of a contract, if it reports one.
+async fn get_version<M: Middleware + 'static>(l1: &Arc<M>, contract: Address) -> Option<String> {
+    // All of our upgradable contracts share the `getVersion` interface of `LightClientV2`.
+    let (major, minor, patch) = LightClientV2::new(contract, l1.clone())
+        .get_version()
+        .call()
+        .await
+        .ok()?;
+    Some(format!("{major}.{minor}.{patch}"))
+}
+
+/// Read the ABI and bytecode from a Foundry artifact, the JSON file `forge build` writes for each
+/// contract.
+fn read_artifact(path: &Path) -> anyhow::Result<(Abi, BytecodeObject)> {
+    #[derive(Deserialize)]
+    struct Artifact {
+        abi: Abi,
+        bytecode: Bytecode,
+    }
+
+    #[derive(Deserialize)]
+    struct Bytecode {
+        object: BytecodeObject,
+    }
+
+    let artifact = std::fs::read_to_string(path)
+        .with_context(|| format!("reading artifact {}", path.display()))?;
+    let artifact: Artifact = serde_json::from_str(&artifact)
+        .with_context(|| format!("parsing artifact {}", path.display()))?;
+    Ok((artifact.abi, artifact.bytecode.object))
+}
+
+async fn deploy_light_client_contract<M: Middleware + 'static>(
+    l1: Arc<M>,
+    contracts: &mut Contracts,
+) -> anyhow::Result<Address> {
     // Link with LightClient's bytecode artifacts. We include the unlinked bytecode for the contract
     // in this binary so that the contract artifacts do not have to be distributed with the binary.
     // This should be fine because if the bindings we are importing are up to date, so should be the
     // contract artifacts: this is no different than foundry inlining bytecode objects in generated
     // bindings, except that foundry doesn't provide the bytecode for contracts that link with
     // libraries, so we have to do it ourselves.
-    let mut bytecode: BytecodeObject = serde_json::from_str(include_str!(
+    let bytecode: BytecodeObject = serde_json::from_str(include_str!(
         "../../../contract-bindings/artifacts/LightClient_bytecode.json"
     ))?;
-    bytecode
-        .link_fully_qualified(
-            "contracts/src/libraries/PlonkVerifier.sol:PlonkVerifier",
-            plonk_verifier,
-        )
-        .resolve()
-        .context("error linking PlonkVerifier lib")?;
-    bytecode
-        .link_fully_qualified(
-            "contracts/src/libraries/LightClientStateUpdateVK.sol:LightClientStateUpdateVK",
-            vk,
-        )
-        .resolve()
-        .context("error linking LightClientStateUpdateVK lib")?;
-    ensure!(!bytecode.is_unlinked(), "failed to link LightClient.sol");
+    deploy_linked_contract(l1, contracts, LIGHTCLIENT_ABI.clone(), bytecode).await
+}
+
+/// Fully qualified name of the `PlonkVerifier` library.
+const PLONK_VERIFIER_LIB: &str = "contracts/src/libraries/PlonkVerifier.sol:PlonkVerifier";
+
+/// Fully qualified name of the `LightClientStateUpdateVK` library.
+const STATE_UPDATE_VK_LIB: &str =
+    "contracts/src/libraries/LightClientStateUpdateVK.sol:LightClientStateUpdateVK";
+
+/// Deploy a contract which may link with the light client libraries.
+///
+/// Only the libraries the bytecode references are linked, and they are deployed first if
+/// necessary.
+async fn deploy_linked_contract<M: Middleware + 'static>(
+    l1: Arc<M>,
+    contracts: &mut Contracts,
+    abi: Abi,
+    mut bytecode: BytecodeObject,
+) -> anyhow::Result<Address> {
+    if bytecode.contains_fully_qualified_placeholder(PLONK_VERIFIER_LIB) {
+        let plonk_verifier = contracts
+            .deploy_tx(
+                Contract::PlonkVerifier,
+                PlonkVerifier::deploy(l1.clone(), ())?,
+            )
+            .await?;
+        bytecode
+            .link_fully_qualified(PLONK_VERIFIER_LIB, plonk_verifier)
+            .resolve()
+            .context("error linking PlonkVerifier lib")?;
+    }
+    if bytecode.contains_fully_qualified_placeholder(STATE_UPDATE_VK_LIB) {
+        let vk = contracts
+            .deploy_tx(
+                Contract::StateUpdateVK,
+                LightClientStateUpdateVK::deploy(l1.clone(), ())?,
+            )
+            .await?;
+        bytecode
+            .link_fully_qualified(STATE_UPDATE_VK_LIB, vk)
+            .resolve()
+            .context("error linking LightClientStateUpdateVK lib")?;
+    }
+    ensure!(
+        !bytecode.is_unlinked(),
+        "contract links with libraries other than the light client libraries"
+    );
 
-    // Deploy light client.
-    let light_client_factory = ContractFactory::new(
-        LIGHTCLIENT_ABI.clone(),
+    // Deploy the contract.
+    let factory = ContractFactory::new(
+        abi,
         bytecode
             .as_bytes()
-            .context("error parsing bytecode for linked LightClient contract")?
+            .context("error parsing bytecode for linked contract")?
             .clone(),
         l1,
     );
-    let contract = light_client_factory.deploy(())?.send().await?;
+    let contract = factory.deploy(())?.send().await?;
     Ok(contract.address())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sequencer_utils::{init_signer, AnvilOptions};
+    use tempfile::TempDir;
+
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    #[test]
+    fn test_parse_plan() {
+        let plan: Plan = toml::from_str(
+            r#"
+            owner = "0x0000000000000000000000000000000000000001"
+
+            [[deploy]]
+            contract = "hot_shot"
+
+            [[deploy]]
+            contract = "light_client"
+            blocks_per_epoch = 10
+
+            [[deploy]]
+            contract = "fee_contract"
+            proxy = false
+
+            [[upgrade]]
+            contract = "light_client"
+            implementation = { artifact = "out/LightClientV2.sol/LightClientV2.json" }
+            call = "0x1234"
+
+            [[upgrade]]
+            contract = "fee_contract"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(plan.owner, Some(Address::from_low_u64_be(1)));
+        assert_eq!(
+            plan.deployments,
+            [
+                Deployment::HotShot,
+                Deployment::LightClient {
+                    proxy: true,
+                    blocks_per_epoch: 10
+                },
+                Deployment::FeeContract { proxy: false },
+            ]
+        );
+        assert_eq!(
+            plan.upgrades,
+            [
+                Upgrade {
+                    contract: UpgradableContract::LightClient,
+                    implementation: Implementation::Artifact(
+                        "out/LightClientV2.sol/LightClientV2.json".into()
+                    ),
+                    call: vec![0x12, 0x34].into(),
+                    check_slots: 16,
+                },
+                Upgrade {
+                    contract: UpgradableContract::FeeContract,
+                    implementation: Implementation::Builtin,
+                    call: Default::default(),
+                    check_slots: 16,
+                },
+            ]
+        );
+
+        // Unknown fields are rejected, so that typos do not silently change the deployment.
+        toml::from_str::<Plan>("ownr = \"0x0000000000000000000000000000000000000001\"")
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_read_artifact() {
+        let dir = TempDir::new().unwrap();
+
+        // The bytecode is read from a Foundry artifact.
+        let path = dir.path().join("Contract.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "abi": [],
+                "bytecode": { "object": "0x6080", "sourceMap": "", "linkReferences": {} },
+                "deployedBytecode": { "object": "0x", "sourceMap": "", "linkReferences": {} }
+            }"#,
+        )
+        .unwrap();
+        let (abi, bytecode) = read_artifact(&path).unwrap();
+        assert_eq!(abi, Abi::default());
+        assert_eq!(bytecode.as_bytes(), Some(&Bytes::from(vec![0x60, 0x80])));
+
+        // A bare bytecode object is not an artifact.
+        let path = dir.path().join("Contract_bytecode.json");
+        std::fs::write(&path, r#""0x6080""#).unwrap();
+        read_artifact(&path).unwrap_err();
+    }
+
+    #[async_std::test]
+    async fn test_deploy_and_upgrade() {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+        let chain_id = anvil.provider().get_chainid().await.unwrap().as_u64();
+        let l1 = Arc::new(init_signer(&anvil.url(), TEST_MNEMONIC, 0).await.unwrap());
+        let deployer = l1.address();
+        let orchestrator_url = "http://localhost:40001".parse().unwrap();
+
+        // Deploy the fee contract behind a proxy, and upgrade it to a new implementation.
+        let plan: Plan = toml::from_str(
+            r#"
+            [[deploy]]
+            contract = "hot_shot"
+
+            [[deploy]]
+            contract = "fee_contract"
+
+            [[upgrade]]
+            contract = "fee_contract"
+            "#,
+        )
+        .unwrap();
+        let mut contracts = Contracts(Default::default());
+        let manifest = plan
+            .run(l1.clone(), deployer, &mut contracts, &orchestrator_url)
+            .await
+            .unwrap();
+        assert_eq!(manifest.chain_id, chain_id);
+        assert_eq!(manifest.owner, deployer);
+        assert_eq!(
+            manifest.deployed,
+            [
+                Contract::FeeContract.to_string(),
+                Contract::FeeContractProxy.to_string(),
+                Contract::HotShot.to_string(),
+            ]
+        );
+
+        let proxy = contracts.0[&Contract::FeeContractProxy];
+        let [upgrade] = &manifest.upgrades[..] else {
+            panic!("expected one upgrade, got {:?}", manifest.upgrades);
+        };
+        assert_eq!(upgrade.proxy, proxy);
+        assert_ne!(upgrade.implementation, upgrade.previous_implementation);
+        assert_eq!(upgrade.implementation, contracts.0[&Contract::FeeContract]);
+        assert_eq!(
+            read_address(&l1, proxy, implementation_slot())
+                .await
+                .unwrap(),
+            upgrade.implementation
+        );
+        assert_eq!(upgrade.version, upgrade.previous_version);
+
+        // Running the plan again with the existing contracts deploys nothing new and upgrades the
+        // proxy once more.
+        let manifest = plan
+            .run(l1.clone(), deployer, &mut contracts, &orchestrator_url)
+            .await
+            .unwrap();
+        assert_eq!(manifest.deployed, Vec::<String>::new());
+        assert_eq!(manifest.upgrades.len(), 1);
+
+        // An implementation from an artifact is linked only against the libraries it references,
+        // so upgrading the fee contract does not deploy the light client libraries.
+        let dir = TempDir::new().unwrap();
+        let artifact = dir.path().join("FeeContract.json");
+        std::fs::write(
+            &artifact,
+            serde_json::to_string(&serde_json::json!({
+                "abi": &*FEECONTRACT_ABI,
+                "bytecode": { "object": FEECONTRACT_BYTECODE.to_string() },
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+        let plan = Plan {
+            owner: None,
+            deployments: vec![],
+            upgrades: vec![Upgrade {
+                contract: UpgradableContract::FeeContract,
+                implementation: Implementation::Artifact(artifact),
+                call: Default::default(),
+                check_slots: default_check_slots(),
+            }],
+        };
+        let manifest = plan
+            .run(l1.clone(), deployer, &mut contracts, &orchestrator_url)
+            .await
+            .unwrap();
+        assert_eq!(manifest.deployed, Vec::<String>::new());
+        assert!(!contracts.0.contains_key(&Contract::PlonkVerifier));
+        assert!(!contracts.0.contains_key(&Contract::StateUpdateVK));
+
+        // Upgrading to a contract which is not a UUPS implementation fails.
+        let nonce = l1.get_transaction_count(deployer, None).await.unwrap();
+        let hotshot = contracts.0[&Contract::HotShot];
+        let plan = Plan {
+            owner: None,
+            deployments: vec![],
+            upgrades: vec![Upgrade {
+                contract: UpgradableContract::FeeContract,
+                implementation: Implementation::Address(hotshot),
+                call: Default::default(),
+                check_slots: default_check_slots(),
+            }],
+        };
+        plan.run(l1.clone(), deployer, &mut contracts, &orchestrator_url)
+            .await
+            .unwrap_err();
+
+        // Upgrading from an account which does not own the proxy fails. Both failures are caught
+        // before any transaction is sent, so nothing is deployed.
+        let other_l1 = Arc::new(init_signer(&anvil.url(), TEST_MNEMONIC, 1).await.unwrap());
+        let other_address = other_l1.address();
+        let other_nonce = l1.get_transaction_count(other_address, None).await.unwrap();
+        let plan = Plan {
+            owner: None,
+            deployments: vec![Deployment::HotShot],
+            upgrades: vec![Upgrade {
+                contract: UpgradableContract::FeeContract,
+                implementation: Implementation::Builtin,
+                call: Default::default(),
+                check_slots: default_check_slots(),
+            }],
+        };
+        let mut other_contracts = Contracts(
+            [(
+                Contract::FeeContractProxy,
+                contracts.0[&Contract::FeeContractProxy],
+            )]
+            .into(),
+        );
+        plan.run(
+            other_l1,
+            other_address,
+            &mut other_contracts,
+            &orchestrator_url,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            l1.get_transaction_count(deployer, None).await.unwrap(),
+            nonce
+        );
+        assert_eq!(
+            l1.get_transaction_count(other_address, None).await.unwrap(),
+            other_nonce
+        );
+        assert!(!other_contracts.0.contains_key(&Contract::HotShot));
+    }
+}
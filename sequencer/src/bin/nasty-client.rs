@@ -8,15 +8,59 @@
 //! one-off queries and maintenance of long-lived connections. The client may keep many connections
 //! open at one time, which has been a source of performance problems for the server in the past.
 //!
+//! Besides querying, the client can also submit malformed or oversized transactions, to check that
+//! the submit API rejects bad input gracefully rather than failing or hanging.
+//!
+//! Random actions are generated from a seeded RNG, so a run can be reproduced by passing the seed
+//! logged at startup to `--seed`. For reproducing a specific sequence of events, the client can
+//! instead run a scenario file (`--scenario`), which lists the actions to execute in order along
+//! with the expected outcome of each. A scenario is a TOML file like:
+//!
+//! ```toml
+//! # Optional seed for the payloads of submit actions; overridden by `--seed`.
+//! seed = 42
+//!
+//! [[step]]
+//! action = "open_stream"
+//! resource = "blocks"
+//! from = 0
+//!
+//! [[step]]
+//! action = "poll_stream"
+//! resource = "blocks"
+//! id = 0
+//! amount = 10
+//! # Run this step several times in a row.
+//! repeat = 3
+//!
+//! [[step]]
+//! action = "submit_malformed"
+//! kind = "garbage_binary"
+//! size = 1000
+//!
+//! [[step]]
+//! action = "query_namespace"
+//! block = 1
+//! namespace = 1000
+//! # Assert that the action fails, with an error message containing the given string.
+//! expect = "failure"
+//! error_contains = "namespace"
+//! ```
+//!
+//! Each step is an action, with the same fields as the randomly generated actions, and an optional
+//! assertion on its outcome (`expect`, which defaults to "success"). The scenario stops at the
+//! first failed assertion, and the program exits with an error.
+//!
 //! The program also runs a web server to provide some visibility into its state. The web server
 //! provides a healthcheck endpoint as well as a prometheus endpoint which provides metrics like the
 //! count of various types of actions performed and the number of open streams.
 
-use anyhow::{ensure, Context};
+use anyhow::{bail, ensure, Context};
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
 use async_std::{sync::RwLock, task::spawn};
+use bytesize::ByteSize;
 use clap::Parser;
-use commit::Committable;
+use commit::{Commitment, Committable};
 use derivative::Derivative;
 use es_version::{SequencerVersion, SEQUENCER_VERSION};
 use futures::{
@@ -33,20 +77,24 @@ use hotshot_types::{
     vid::{vid_scheme, VidSchemeType},
 };
 use jf_primitives::vid::VidScheme;
-use rand::{seq::SliceRandom, RngCore};
-use sequencer::{api::endpoints::NamespaceProofQueryData, Header, SeqTypes};
-use serde::de::DeserializeOwned;
+use rand::{seq::SliceRandom, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
+use sequencer::{api::endpoints::NamespaceProofQueryData, Header, SeqTypes, Transaction};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
 use std::time::Duration;
 use std::{
     borrow::Cow,
     collections::{BTreeMap, HashMap},
     fmt::Debug,
+    path::{Path, PathBuf},
     pin::Pin,
+    process::exit,
     sync::Arc,
 };
 use strum::{EnumDiscriminants, VariantArray};
 use surf_disco::{error::ClientError, socket, Url};
-use tide_disco::{error::ServerError, App};
+use tide_disco::{error::ServerError, App, StatusCode};
 use time::OffsetDateTime;
 use toml::toml;
 
@@ -81,6 +129,19 @@ struct Options {
     )]
     http_timeout: Duration,
 
+    /// Seed for the random number generator.
+    ///
+    /// If not specified, a random seed is used, and logged at startup so that the run can be
+    /// reproduced.
+    #[clap(long, env = "ESPRESSO_NASTY_CLIENT_SEED")]
+    seed: Option<u64>,
+
+    /// Run the scenario in this file instead of generating random actions.
+    ///
+    /// The program exits after running the scenario, with an error if any of its assertions fail.
+    #[clap(long, env = "ESPRESSO_NASTY_CLIENT_SCENARIO")]
+    scenario: Option<PathBuf>,
+
     #[clap(flatten)]
     distribution: ActionDistribution,
 
@@ -143,6 +204,39 @@ struct ActionDistribution {
         default_value = "3"
     )]
     weight_query_namespace: u8,
+
+    /// The weight of "submit malformed" actions in the random distribution.
+    ///
+    /// These actions require the server to run the submit module, so they are disabled by default.
+    #[clap(
+        long,
+        env = "ESPRESSO_NASTY_CLIENT_WEIGHT_SUBMIT_MALFORMED",
+        default_value = "0"
+    )]
+    weight_submit_malformed: u8,
+
+    /// The weight of "submit oversized" actions in the random distribution.
+    ///
+    /// These actions may add large transactions to the chain, so they are disabled by default.
+    #[clap(
+        long,
+        env = "ESPRESSO_NASTY_CLIENT_WEIGHT_SUBMIT_OVERSIZED",
+        default_value = "0"
+    )]
+    weight_submit_oversized: u8,
+
+    /// The size of transactions submitted by "submit oversized" actions.
+    #[clap(
+        long,
+        env = "ESPRESSO_NASTY_CLIENT_OVERSIZED_SIZE",
+        default_value = "5mb",
+        value_parser = parse_size
+    )]
+    oversized_size: u64,
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    Ok(s.parse::<ByteSize>()?.0)
 }
 
 impl ActionDistribution {
@@ -154,6 +248,8 @@ impl ActionDistribution {
             ActionDiscriminants::PollStream => self.weight_poll_stream,
             ActionDiscriminants::QueryWindow => self.weight_query_window,
             ActionDiscriminants::QueryNamespace => self.weight_query_namespace,
+            ActionDiscriminants::SubmitMalformed => self.weight_submit_malformed,
+            ActionDiscriminants::SubmitOversized => self.weight_submit_oversized,
         }
     }
 }
@@ -167,6 +263,8 @@ struct Metrics {
     poll_stream_actions: HashMap<Resource, Box<dyn Counter>>,
     query_window_actions: Box<dyn Counter>,
     query_namespace_actions: Box<dyn Counter>,
+    submit_malformed_actions: Box<dyn Counter>,
+    submit_oversized_actions: Box<dyn Counter>,
 }
 
 impl Metrics {
@@ -233,6 +331,10 @@ impl Metrics {
             query_window_actions: registry.create_counter("query_window_actions".into(), None),
             query_namespace_actions: registry
                 .create_counter("query_namespace_actions".into(), None),
+            submit_malformed_actions: registry
+                .create_counter("submit_malformed_actions".into(), None),
+            submit_oversized_actions: registry
+                .create_counter("submit_oversized_actions".into(), None),
         }
     }
 }
@@ -592,7 +694,110 @@ impl ResourceManager<BlockQueryData<SeqTypes>> {
     }
 }
 
-#[derive(Clone, Copy, Debug, VariantArray, Hash, PartialEq, Eq)]
+#[derive(Debug)]
+struct Submitter {
+    client: surf_disco::Client<ClientError, SequencerVersion>,
+    rng: ChaChaRng,
+    metrics: Arc<Metrics>,
+}
+
+impl Submitter {
+    fn new(opt: &Options, rng: ChaChaRng, metrics: Arc<Metrics>) -> Self {
+        Self {
+            client: surf_disco::Client::builder(opt.url.clone())
+                .set_timeout(Some(opt.http_timeout))
+                .build(),
+            rng,
+            metrics,
+        }
+    }
+
+    async fn submit_malformed(&mut self, kind: MalformedKind, size: u16) -> anyhow::Result<()> {
+        tracing::debug!("submitting malformed transaction ({kind:?}, size {size})");
+        let req = self.client.post::<Commitment<Transaction>>("submit/submit");
+        let req = match kind {
+            MalformedKind::EmptyObject => req.body_json(&json!({})),
+            MalformedKind::WrongTypes => req.body_json(&json!({
+                "namespace": "nasty",
+                "payload": size,
+            })),
+            MalformedKind::NegativeNamespace => req.body_json(&json!({
+                "namespace": -1,
+                "payload": "",
+            })),
+            MalformedKind::InvalidBase64 => req.body_json(&json!({
+                "namespace": 1,
+                "payload": "!".repeat(size.max(1) as usize),
+            })),
+            MalformedKind::GarbageBinary => {
+                let mut garbage = vec![0; size as usize];
+                self.rng.fill_bytes(&mut garbage);
+                req.body_binary(&garbage)
+            }
+        }
+        .context("building request")?;
+
+        self.metrics.submit_malformed_actions.add(1);
+        match req.send().await {
+            Ok(hash) => bail!("malformed transaction ({kind:?}) was accepted with hash {hash}"),
+            // Only a 400 shows that the server parsed and rejected the transaction. Other client
+            // errors, like a 404 from a server without the submit module, test nothing.
+            Err(err) if err.status() == StatusCode::BadRequest => {
+                tracing::debug!("malformed transaction ({kind:?}) rejected: {err}");
+                Ok(())
+            }
+            Err(err) => Err(err).context(format!(
+                "expected 400 Bad Request for malformed transaction ({kind:?})"
+            )),
+        }
+    }
+
+    async fn submit_oversized(&mut self, namespace: u64, size: u64) -> anyhow::Result<()> {
+        let mut payload = vec![0; size as usize];
+        self.rng.fill_bytes(&mut payload);
+        let tx = Transaction::new(namespace.into(), payload);
+        tracing::debug!(
+            "submitting oversized transaction {} ({})",
+            tx.commit(),
+            ByteSize(size)
+        );
+
+        // The server may either accept or reject a large transaction, but if it rejects it, it
+        // should do so with a client error, not by failing or timing out.
+        self.metrics.submit_oversized_actions.add(1);
+        match self
+            .client
+            .post::<Commitment<Transaction>>("submit/submit")
+            .body_binary(&tx)
+            .context("building request")?
+            .send()
+            .await
+        {
+            Ok(hash) => {
+                ensure!(
+                    hash == tx.commit(),
+                    format!(
+                        "oversized transaction {} was accepted with wrong hash {hash}",
+                        tx.commit()
+                    )
+                );
+                Ok(())
+            }
+            Err(err) if err.status().is_client_error() => {
+                tracing::debug!("oversized transaction {} rejected: {err}", tx.commit());
+                Ok(())
+            }
+            Err(err) => Err(err).context(format!(
+                "submitting oversized transaction {} ({})",
+                tx.commit(),
+                ByteSize(size)
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, VariantArray, Hash, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
 enum Resource {
     Blocks,
     Leaves,
@@ -624,8 +829,31 @@ impl Resource {
     }
 }
 
-#[derive(Clone, Copy, Debug, EnumDiscriminants)]
+/// Ways of malforming a submitted transaction.
+#[derive(Clone, Copy, Debug, VariantArray, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MalformedKind {
+    /// An empty JSON object.
+    EmptyObject,
+    /// A JSON object with the right fields but the wrong types.
+    WrongTypes,
+    /// A JSON transaction with a negative namespace ID.
+    NegativeNamespace,
+    /// A JSON transaction whose payload is not valid base64.
+    InvalidBase64,
+    /// Random bytes in a binary request body.
+    GarbageBinary,
+}
+
+impl MalformedKind {
+    fn random(rng: &mut impl RngCore) -> Self {
+        *Self::VARIANTS.choose(rng).unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug, EnumDiscriminants, PartialEq, Eq, Deserialize)]
 #[strum_discriminants(derive(VariantArray))]
+#[serde(tag = "action", rename_all = "snake_case")]
 enum Action {
     Query {
         resource: Resource,
@@ -652,6 +880,14 @@ enum Action {
         block: u64,
         namespace: usize,
     },
+    SubmitMalformed {
+        kind: MalformedKind,
+        size: u16,
+    },
+    SubmitOversized {
+        namespace: u64,
+        size: u64,
+    },
 }
 
 impl Action {
@@ -685,6 +921,80 @@ impl Action {
                 block: rng.next_u64(),
                 namespace: rng.next_u32() as usize,
             },
+            ActionDiscriminants::SubmitMalformed => Self::SubmitMalformed {
+                kind: MalformedKind::random(rng),
+                size: (rng.next_u32() % u16::MAX as u32) as u16,
+            },
+            ActionDiscriminants::SubmitOversized => Self::SubmitOversized {
+                namespace: rng.next_u64(),
+                size: distribution.oversized_size,
+            },
+        }
+    }
+}
+
+/// A scripted sequence of actions.
+#[derive(Clone, Debug, Deserialize)]
+struct Scenario {
+    /// Seed for the random number generator, used when `--seed` is not given.
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default, rename = "step")]
+    steps: Vec<Step>,
+}
+
+impl Scenario {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let scenario = std::fs::read_to_string(path)
+            .context(format!("reading scenario {}", path.display()))?;
+        toml::from_str(&scenario).context(format!("parsing scenario {}", path.display()))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct Step {
+    #[serde(flatten)]
+    action: Action,
+    /// The expected outcome of the action.
+    #[serde(default)]
+    expect: Expectation,
+    /// If the action is expected to fail, a string which the error message must contain.
+    #[serde(default)]
+    error_contains: Option<String>,
+    /// The number of times to run the action.
+    #[serde(default = "default_repeat")]
+    repeat: usize,
+}
+
+fn default_repeat() -> usize {
+    1
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Expectation {
+    #[default]
+    Success,
+    Failure,
+}
+
+impl Step {
+    /// Check the result of running this step against its assertions.
+    fn check(&self, res: anyhow::Result<()>) -> anyhow::Result<()> {
+        match (self.expect, res) {
+            (Expectation::Success, res) => res.context("expected action to succeed"),
+            (Expectation::Failure, Ok(())) => bail!("expected action to fail, but it succeeded"),
+            (Expectation::Failure, Err(err)) => {
+                let msg = format!("{err:#}");
+                tracing::info!("action failed as expected: {msg}");
+                if let Some(expected) = &self.error_contains {
+                    ensure!(
+                        msg.contains(expected),
+                        format!("expected error containing \"{expected}\", got: {msg}")
+                    );
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -695,16 +1005,18 @@ struct Client {
     leaves: ResourceManager<LeafQueryData<SeqTypes>>,
     headers: ResourceManager<Header>,
     payloads: ResourceManager<PayloadQueryData<SeqTypes>>,
+    submitter: Submitter,
 }
 
 impl Client {
-    fn new(opt: &Options, registry: &PrometheusMetrics) -> Self {
+    fn new(opt: &Options, registry: &PrometheusMetrics, rng: ChaChaRng) -> Self {
         let metrics = Arc::new(Metrics::new(registry));
         Self {
             blocks: ResourceManager::new(opt, metrics.clone()),
             leaves: ResourceManager::new(opt, metrics.clone()),
             headers: ResourceManager::new(opt, metrics.clone()),
             payloads: ResourceManager::new(opt, metrics.clone()),
+            submitter: Submitter::new(opt, rng, metrics),
         }
     }
 
@@ -749,7 +1061,32 @@ impl Client {
             Action::QueryNamespace { block, namespace } => {
                 self.blocks.query_namespace(block, namespace).await
             }
+            Action::SubmitMalformed { kind, size } => {
+                self.submitter.submit_malformed(kind, size).await
+            }
+            Action::SubmitOversized { namespace, size } => {
+                self.submitter.submit_oversized(namespace, size).await
+            }
+        }
+    }
+
+    async fn run_scenario(
+        &mut self,
+        scenario: &Scenario,
+        failed_actions: &dyn Counter,
+    ) -> anyhow::Result<()> {
+        for (i, step) in scenario.steps.iter().enumerate() {
+            for rep in 0..step.repeat {
+                tracing::info!(?step.action, "step {i}, repetition {rep}");
+                let res = self.run(step.action).await;
+                if let Err(err) = step.check(res) {
+                    failed_actions.add(1);
+                    return Err(err)
+                        .context(format!("step {i} ({:?}), repetition {rep}", step.action));
+                }
+            }
         }
+        Ok(())
     }
 }
 
@@ -780,13 +1117,42 @@ async fn main() {
     setup_backtrace();
 
     let opt = Options::parse();
+    let scenario = opt
+        .scenario
+        .as_ref()
+        .map(|path| match Scenario::load(path) {
+            Ok(scenario) => scenario,
+            Err(err) => {
+                tracing::error!("{err:#}");
+                exit(1);
+            }
+        });
+    let seed = opt
+        .seed
+        .or(scenario.as_ref().and_then(|scenario| scenario.seed))
+        .unwrap_or_else(|| rand::thread_rng().gen());
+    tracing::info!("PRNG seed: {seed}");
+    let mut rng = ChaChaRng::seed_from_u64(seed);
+
     let metrics = PrometheusMetrics::default();
     let failed_actions = metrics.create_counter("failed_actions".into(), None);
-    let mut client = Client::new(&opt, &metrics);
-    let mut rng = rand::thread_rng();
+    let mut client = Client::new(&opt, &metrics, ChaChaRng::from_rng(&mut rng).unwrap());
 
     spawn(serve(opt.port, metrics));
 
+    if let Some(scenario) = scenario {
+        match client.run_scenario(&scenario, &*failed_actions).await {
+            Ok(()) => {
+                tracing::info!("scenario completed successfully");
+                return;
+            }
+            Err(err) => {
+                tracing::error!("scenario failed: {err:#}");
+                exit(1);
+            }
+        }
+    }
+
     loop {
         if let Err(err) = client
             .run(Action::random(&mut rng, &opt.distribution))
@@ -797,3 +1163,93 @@ async fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_scenario() {
+        let scenario: Scenario = toml::from_str(
+            r#"
+            seed = 42
+
+            [[step]]
+            action = "open_stream"
+            resource = "blocks"
+            from = 0
+
+            [[step]]
+            action = "poll_stream"
+            resource = "leaves"
+            id = 0
+            amount = 10
+            repeat = 3
+
+            [[step]]
+            action = "submit_malformed"
+            kind = "garbage_binary"
+            size = 1000
+            expect = "failure"
+            error_contains = "accepted"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scenario.seed, Some(42));
+        assert_eq!(scenario.steps.len(), 3);
+
+        assert_eq!(
+            scenario.steps[0].action,
+            Action::OpenStream {
+                resource: Resource::Blocks,
+                from: 0
+            }
+        );
+        assert_eq!(scenario.steps[0].expect, Expectation::Success);
+        assert_eq!(scenario.steps[0].repeat, 1);
+
+        assert_eq!(
+            scenario.steps[1].action,
+            Action::PollStream {
+                resource: Resource::Leaves,
+                id: 0,
+                amount: 10
+            }
+        );
+        assert_eq!(scenario.steps[1].repeat, 3);
+
+        let step = &scenario.steps[2];
+        assert_eq!(
+            step.action,
+            Action::SubmitMalformed {
+                kind: MalformedKind::GarbageBinary,
+                size: 1000
+            }
+        );
+        assert_eq!(step.expect, Expectation::Failure);
+
+        // Check assertions.
+        step.check(Err(anyhow::anyhow!("transaction was accepted")))
+            .unwrap();
+        step.check(Err(anyhow::anyhow!("timed out"))).unwrap_err();
+        step.check(Ok(())).unwrap_err();
+        scenario.steps[0].check(Ok(())).unwrap();
+        scenario.steps[0]
+            .check(Err(anyhow::anyhow!("error")))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_random_actions_deterministic() {
+        let distribution =
+            ActionDistribution::parse_from(["nasty-client", "--weight-submit-oversized", "1"]);
+        let actions = |seed| {
+            let mut rng = ChaChaRng::seed_from_u64(seed);
+            (0..100)
+                .map(|_| Action::random(&mut rng, &distribution))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(actions(0), actions(0));
+        assert_ne!(actions(0), actions(1));
+    }
+}
@@ -1,7 +1,16 @@
 //! Utility program to submit random transactions to an Espresso Sequencer.
+//!
+//! Transactions are either generated randomly or replayed from a trace recorded by a previous run.
+//! The program tracks each transaction until it is included in a block, and reports inclusion
+//! latency and throughput, both as Prometheus metrics while running and as a summary report when
+//! it finishes.
 
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
-use async_std::task::{sleep, spawn};
+use async_std::{
+    future::timeout,
+    sync::{Mutex, RwLock},
+    task::{sleep, spawn},
+};
 use bytesize::ByteSize;
 use clap::Parser;
 use commit::{Commitment, Committable};
@@ -9,21 +18,39 @@ use derive_more::From;
 use es_version::{SequencerVersion, SEQUENCER_VERSION};
 use futures::{
     channel::mpsc::{self, Sender},
+    future::FutureExt,
     sink::SinkExt,
     stream::StreamExt,
 };
-use hotshot_query_service::{availability::BlockQueryData, types::HeightIndexed, Error};
-use rand::{Rng, RngCore, SeedableRng};
+use hotshot_query_service::{
+    availability::BlockQueryData, metrics::PrometheusMetrics, types::HeightIndexed, Error,
+};
+use hotshot_types::traits::metrics::{Counter, Gauge, Histogram, Metrics};
+use rand::{distributions::WeightedIndex, Rng, RngCore, SeedableRng};
 use rand_chacha::ChaChaRng;
 use rand_distr::Distribution;
-use sequencer::{options::parse_duration, SeqTypes, Transaction};
+use sequencer::{
+    api::endpoints::BatchSubmissionResult, options::parse_duration, SeqTypes, Transaction,
+};
+use serde::{Deserialize, Serialize};
 use snafu::Snafu;
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    iter::{Enumerate, Peekable},
+    path::{Path, PathBuf},
+    process::exit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 use surf_disco::{Client, Url};
 use tide_disco::{error::ServerError, App};
+use toml::toml;
 use versioned_binary_serialization::version::StaticVersionType;
 
 /// Submit random transactions to an Espresso Sequencer.
@@ -57,6 +84,14 @@ struct Options {
     )]
     max_namespace: u64,
 
+    /// Relative weights of the namespaces to submit to, as a list of NAMESPACE:WEIGHT.
+    ///
+    /// For example, `10000:3,10001:1` submits three times as many transactions to namespace 10000
+    /// as to namespace 10001. If specified, this replaces the uniform distribution between
+    /// MIN_NAMESPACE and MAX_NAMESPACE.
+    #[clap(long, value_parser = parse_namespace_weights, env = "ESPRESSO_SUBMIT_TRANSACTIONS_NAMESPACE_WEIGHTS")]
+    namespace_weights: Option<NamespaceWeights>,
+
     /// Mean delay between submitting transactions.
     ///
     /// The delay after each transaction will be sampled from an exponential distribution with mean
@@ -64,6 +99,17 @@ struct Options {
     #[clap(long, name = "DELAY", value_parser = parse_duration, default_value = "30s", env = "ESPRESSO_SUBMIT_TRANSACTIONS_DELAY")]
     delay: Duration,
 
+    /// Number of transactions to submit in each request.
+    ///
+    /// If this is greater than 1, transactions are submitted in batches using the batch submission
+    /// endpoint, and DELAY is the mean delay between batches.
+    #[clap(
+        long,
+        default_value = "1",
+        env = "ESPRESSO_SUBMIT_TRANSACTIONS_BATCH_SIZE"
+    )]
+    batch_size: usize,
+
     /// Maximum number of unprocessed transaction submissions.
     ///
     /// This can be used to apply backpressure so that the tasks submitting transactions do not get
@@ -100,7 +146,52 @@ struct Options {
     #[clap(long, value_parser = parse_duration, default_value = "30s", env = "ESPRESSO_SUBMIT_TRANSACTIONS_SLOW_TRANSACTION_WARNING_THRESHOLD")]
     slow_transaction_warning_threshold: Duration,
 
-    /// Enable an HTTP server with a healthcheck endpoint on this port.
+    /// Replay the transactions in a trace file instead of generating random transactions.
+    ///
+    /// The trace is a file of JSON lines, one per transaction, each giving the time at which to
+    /// submit the transaction, in milliseconds since the start of the run (`at_ms`), its namespace
+    /// and its size. Such a file can be recorded with `--record-trace`. The trace only records the
+    /// shape of each transaction, so payloads are generated from SEED and the position of each
+    /// transaction in the trace: replaying a trace twice with the same seed submits the same
+    /// transactions, though not the payloads of the run which recorded it. JOBS tasks replay the
+    /// trace concurrently, and transactions which are due at the same time are submitted in batches
+    /// of up to BATCH_SIZE. When the trace is exhausted, the program waits for pending transactions
+    /// and exits.
+    #[clap(
+        long,
+        env = "ESPRESSO_SUBMIT_TRANSACTIONS_TRACE",
+        conflicts_with = "record_trace"
+    )]
+    trace: Option<PathBuf>,
+
+    /// Record the submitted transactions to a trace file which can later be replayed with
+    /// `--trace`.
+    #[clap(long, env = "ESPRESSO_SUBMIT_TRANSACTIONS_RECORD_TRACE")]
+    record_trace: Option<PathBuf>,
+
+    /// Stop submitting transactions after this many have been submitted.
+    ///
+    /// The program then waits for pending transactions and exits with a summary report.
+    #[clap(long, env = "ESPRESSO_SUBMIT_TRANSACTIONS_NUM_TRANSACTIONS")]
+    num_transactions: Option<usize>,
+
+    /// Stop submitting transactions after this much time has passed.
+    ///
+    /// The program then waits for pending transactions and exits with a summary report.
+    #[clap(long, value_parser = parse_duration, env = "ESPRESSO_SUBMIT_TRANSACTIONS_DURATION")]
+    duration: Option<Duration>,
+
+    /// How long to wait for pending transactions once we have stopped submitting.
+    ///
+    /// If any transactions are still pending after this time, the program exits with an error.
+    #[clap(long, value_parser = parse_duration, default_value = "1m", env = "ESPRESSO_SUBMIT_TRANSACTIONS_DRAIN_TIMEOUT")]
+    drain_timeout: Duration,
+
+    /// Write the summary report as JSON to this file, in addition to logging it.
+    #[clap(long, env = "ESPRESSO_SUBMIT_TRANSACTIONS_REPORT")]
+    report: Option<PathBuf>,
+
+    /// Enable an HTTP server with healthcheck and Prometheus metrics endpoints on this port.
     #[clap(short, long, env = "ESPRESSO_SUBMIT_TRANSACTIONS_PORT")]
     port: Option<u16>,
 
@@ -118,6 +209,95 @@ fn parse_size(s: &str) -> Result<usize, ParseSizeError> {
     Ok(s.parse::<ByteSize>()?.0 as usize)
 }
 
+/// Relative weights for choosing the namespace of each transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct NamespaceWeights(Vec<(u64, u32)>);
+
+#[derive(Clone, Debug, Snafu)]
+#[snafu(display("invalid namespace weights: {msg}"))]
+struct ParseNamespaceWeightsError {
+    msg: String,
+}
+
+fn parse_namespace_weights(s: &str) -> Result<NamespaceWeights, ParseNamespaceWeightsError> {
+    let err = |msg: String| ParseNamespaceWeightsError { msg };
+    let weights = s
+        .split(',')
+        .map(|entry| {
+            let (ns, weight) = entry
+                .split_once(':')
+                .ok_or_else(|| err(format!("expected NAMESPACE:WEIGHT, got {entry}")))?;
+            let ns = ns
+                .trim()
+                .parse()
+                .map_err(|_| err(format!("invalid namespace {ns}")))?;
+            let weight = weight
+                .trim()
+                .parse()
+                .map_err(|_| err(format!("invalid weight {weight}")))?;
+            Ok((ns, weight))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if weights.iter().all(|(_, weight)| *weight == 0) {
+        return Err(err("at least one weight must be positive".into()));
+    }
+    Ok(NamespaceWeights(weights))
+}
+
+/// The distribution from which the namespace of each random transaction is sampled.
+enum NamespaceDistr {
+    Uniform {
+        min: u64,
+        max: u64,
+    },
+    Weighted {
+        namespaces: Vec<u64>,
+        index: WeightedIndex<u32>,
+    },
+}
+
+impl NamespaceDistr {
+    fn new(opt: &Options) -> Self {
+        match &opt.namespace_weights {
+            Some(NamespaceWeights(weights)) => Self::Weighted {
+                namespaces: weights.iter().map(|(ns, _)| *ns).collect(),
+                index: WeightedIndex::new(weights.iter().map(|(_, weight)| *weight)).unwrap(),
+            },
+            None => Self::Uniform {
+                min: opt.min_namespace,
+                max: opt.max_namespace,
+            },
+        }
+    }
+
+    fn sample(&self, rng: &mut ChaChaRng) -> u64 {
+        match self {
+            Self::Uniform { min, max } => rng.gen_range(*min..=*max),
+            Self::Weighted { namespaces, index } => namespaces[index.sample(rng)],
+        }
+    }
+}
+
+/// A transaction in a trace, which can be recorded and replayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+struct TraceEntry {
+    /// When the transaction is submitted, in milliseconds since the start of the run.
+    at_ms: u64,
+    namespace: u64,
+    size: usize,
+}
+
+fn read_trace(path: &Path) -> anyhow::Result<Vec<TraceEntry>> {
+    let file = BufReader::new(File::open(path)?);
+    let mut trace = file
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect::<anyhow::Result<Vec<TraceEntry>>>()?;
+    trace.sort_by_key(|entry| entry.at_ms);
+    Ok(trace)
+}
+
 #[async_std::main]
 async fn main() {
     setup_backtrace();
@@ -132,6 +312,15 @@ async fn main() {
     tracing::info!("PRNG seed: {seed}");
     let mut rng = ChaChaRng::seed_from_u64(seed);
 
+    let metrics = PrometheusMetrics::default();
+    let mut stats = Stats::new(&metrics);
+    let mut recorder =
+        opt.record_trace.as_ref().map(|path| {
+            BufWriter::new(File::create(path).unwrap_or_else(|err| {
+                panic!("unable to create trace file {}: {err}", path.display())
+            }))
+        });
+
     // Subscribe to block stream so we can check that our transactions are getting sequenced.
     let client = Client::<Error, SequencerVersion>::new(opt.url.clone());
     let block_height: usize = client.get("status/block-height").send().await.unwrap();
@@ -143,25 +332,66 @@ async fn main() {
     tracing::info!("listening for blocks starting at {block_height}");
 
     // Spawn tasks to submit transactions.
-    for _ in 0..opt.jobs {
-        spawn(submit_transactions(
-            opt.clone(),
-            sender.clone(),
-            ChaChaRng::from_rng(&mut rng).unwrap(),
-            SEQUENCER_VERSION,
-        ));
+    if let Some(path) = &opt.trace {
+        let trace = read_trace(path)
+            .unwrap_or_else(|err| panic!("unable to read trace {}: {err:#}", path.display()));
+        tracing::info!(
+            "replaying {} transactions from {}",
+            trace.len(),
+            path.display()
+        );
+        let trace = Arc::new(Mutex::new(trace.into_iter().enumerate().peekable()));
+        for _ in 0..opt.jobs {
+            spawn(replay_trace(
+                opt.clone(),
+                trace.clone(),
+                sender.clone(),
+                stats.start,
+                seed,
+                SEQUENCER_VERSION,
+            ));
+        }
+        drop(sender);
+    } else {
+        let submitted = Arc::new(AtomicUsize::new(0));
+        for _ in 0..opt.jobs {
+            spawn(submit_transactions(
+                opt.clone(),
+                sender.clone(),
+                submitted.clone(),
+                stats.start,
+                ChaChaRng::from_rng(&mut rng).unwrap(),
+                SEQUENCER_VERSION,
+            ));
+        }
+        drop(sender);
     }
 
     // Start healthcheck endpoint once tasks are running.
     if let Some(port) = opt.port {
-        spawn(server(port, SEQUENCER_VERSION));
+        spawn(server(port, metrics, SEQUENCER_VERSION));
     }
 
     // Keep track of the results.
     let mut pending = HashMap::new();
-    let mut total_latency = Duration::default();
-    let mut total_transactions = 0;
-    while let Some(block) = blocks.next().await {
+    // Once all the submission tasks have finished, the time by which all pending transactions
+    // must be sequenced.
+    let mut deadline = None;
+    loop {
+        let block = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match timeout(remaining, blocks.next()).await {
+                    Ok(block) => block,
+                    Err(_) => break,
+                }
+            }
+            None => blocks.next().await,
+        };
+        let Some(block) = block else {
+            tracing::warn!("block stream ended");
+            break;
+        };
         let block: BlockQueryData<SeqTypes> = match block {
             Ok(block) => block,
             Err(err) => {
@@ -173,24 +403,45 @@ async fn main() {
         tracing::debug!("got block {}", block.height());
 
         // Get all transactions which were submitted before this block.
-        while let Ok(Some(tx)) = receiver.try_next() {
-            pending.insert(tx.hash, tx.submitted_at);
+        loop {
+            match receiver.try_next() {
+                Ok(Some(tx)) => {
+                    if let Some(recorder) = &mut recorder {
+                        record(recorder, &tx, stats.start);
+                    }
+                    stats.submitted();
+                    pending.insert(tx.hash, tx);
+                }
+                // All the submission tasks have finished. Wait for the remaining transactions.
+                Ok(None) if deadline.is_none() => {
+                    tracing::info!(
+                        "finished submitting transactions, waiting for {} pending",
+                        pending.len()
+                    );
+                    deadline = Some(received_at + opt.drain_timeout);
+                    break;
+                }
+                Ok(None) | Err(_) => break,
+            }
         }
 
         // Clear pending transactions from the block.
         for (_, tx) in block.enumerate() {
-            if let Some(submitted_at) = pending.remove(&tx.commit()) {
-                let latency = received_at - submitted_at;
+            if let Some(submitted) = pending.remove(&tx.commit()) {
+                let latency = received_at - submitted.submitted_at;
                 tracing::info!(
                     "got transaction {} in block {}, latency {latency:?}",
                     tx.commit(),
                     block.height()
                 );
-                total_latency += latency;
-                total_transactions += 1;
-                tracing::info!("average latency: {:?}", total_latency / total_transactions);
+                stats.included(&submitted, latency);
             }
         }
+        stats.update_metrics(pending.len());
+
+        if deadline.is_some() && pending.is_empty() {
+            break;
+        }
 
         // If a lot of transactions are pending, it might indicate the sequencer is struggling to
         // finalize them. We should warn about this.
@@ -205,79 +456,484 @@ async fn main() {
             // Even if we are not accumulating transactions, it is still possible that some
             // individual transactions are not being finalized. Warn about any transaction which has
             // been pending for too long.
-            for (tx, submitted_at) in &pending {
-                let duration = received_at - *submitted_at;
+            for (tx, submitted) in &pending {
+                let duration = received_at - submitted.submitted_at;
                 if duration >= opt.slow_transaction_warning_threshold {
                     tracing::warn!("transaction {tx} has been pending for {duration:?}");
                 }
             }
         }
     }
-    tracing::info!(
-        "block stream ended with {} transactions still pending",
-        pending.len()
-    );
+    if let Some(recorder) = &mut recorder {
+        if let Err(err) = recorder.flush() {
+            tracing::error!("error writing trace: {err}");
+        }
+    }
+
+    let report = stats.report(pending.len());
+    report.log();
+    if let Some(path) = &opt.report {
+        if let Err(err) = std::fs::write(path, serde_json::to_vec_pretty(&report).unwrap()) {
+            tracing::error!("error writing report to {}: {err}", path.display());
+        }
+    }
+    if !pending.is_empty() {
+        tracing::error!("{} transactions still pending", pending.len());
+        exit(1);
+    }
 }
 
 struct SubmittedTransaction {
     hash: Commitment<Transaction>,
+    namespace: u64,
+    size: usize,
     submitted_at: Instant,
 }
 
 async fn submit_transactions<Ver: StaticVersionType>(
     opt: Options,
     mut sender: Sender<SubmittedTransaction>,
+    submitted: Arc<AtomicUsize>,
+    start: Instant,
     mut rng: ChaChaRng,
     _: Ver,
 ) {
     let client = Client::<Error, Ver>::new(opt.url.clone());
+    let namespaces = NamespaceDistr::new(&opt);
 
     // Create an exponential distribution for sampling delay times. The distribution should have
     // mean `opt.delay`, or parameter `\lambda = 1 / opt.delay`.
     let delay_distr = rand_distr::Exp::<f64>::new(1f64 / opt.delay.as_millis() as f64).unwrap();
 
     loop {
-        let tx = random_transaction(&opt, &mut rng);
-        let hash = tx.commit();
+        if opt
+            .duration
+            .is_some_and(|duration| start.elapsed() >= duration)
+        {
+            break;
+        }
+        let mut batch_size = opt.batch_size.max(1);
+        if let Some(limit) = opt.num_transactions {
+            let prev = submitted.fetch_add(batch_size, Ordering::SeqCst);
+            if prev >= limit {
+                break;
+            }
+            batch_size = batch_size.min(limit - prev);
+        }
+
+        let txs = (0..batch_size)
+            .map(|_| {
+                let namespace = namespaces.sample(&mut rng);
+                let len = rng.gen_range(opt.min_size..=opt.max_size);
+                random_transaction(namespace, len, &mut rng)
+            })
+            .collect::<Vec<_>>();
+        submit_and_track(&client, &txs, &mut sender).await;
+
+        let delay = Duration::from_millis(delay_distr.sample(&mut rng) as u64);
+        tracing::info!("sleeping for {delay:?}");
+        sleep(delay).await;
+    }
+}
+
+/// The entries of a trace which have yet to be replayed, with their positions in the trace.
+type TraceQueue = Arc<Mutex<Peekable<Enumerate<std::vec::IntoIter<TraceEntry>>>>>;
+
+async fn replay_trace<Ver: StaticVersionType>(
+    opt: Options,
+    trace: TraceQueue,
+    mut sender: Sender<SubmittedTransaction>,
+    start: Instant,
+    seed: u64,
+    _: Ver,
+) {
+    let client = Client::<Error, Ver>::new(opt.url.clone());
+    let due = |entry: &TraceEntry| start + Duration::from_millis(entry.at_ms);
+    loop {
+        // Take the next entry once it is due, along with any others which are also due, up to a
+        // full batch. We hold the lock while waiting so that the jobs take entries in order, but
+        // release it before submitting, so that a slow submission does not hold up the others.
+        let batch = {
+            let mut trace = trace.lock().await;
+            let Some((_, next)) = trace.peek() else {
+                break;
+            };
+            sleep(due(next).saturating_duration_since(Instant::now())).await;
+            let mut batch = vec![trace.next().unwrap()];
+            while batch.len() < opt.batch_size.max(1) {
+                match trace.next_if(|(_, entry)| due(entry) <= Instant::now()) {
+                    Some(entry) => batch.push(entry),
+                    None => break,
+                }
+            }
+            batch
+        };
+        let txs = batch
+            .iter()
+            .map(|(index, entry)| trace_transaction(seed, *index, entry))
+            .collect::<Vec<_>>();
+        submit_and_track(&client, &txs, &mut sender).await;
+    }
+}
+
+/// The transaction replayed for the `index`th entry of a trace.
+///
+/// The payload depends only on the seed and the position of the entry, not on the order in which
+/// the jobs replaying the trace get to it.
+fn trace_transaction(seed: u64, index: usize, entry: &TraceEntry) -> Transaction {
+    let mut rng = ChaChaRng::seed_from_u64(seed);
+    rng.set_stream(index as u64);
+    random_transaction(entry.namespace, entry.size, &mut rng)
+}
+
+/// Submit a batch of transactions and send them to the main task to be tracked.
+async fn submit_and_track<Ver: StaticVersionType>(
+    client: &Client<Error, Ver>,
+    txs: &[Transaction],
+    sender: &mut Sender<SubmittedTransaction>,
+) {
+    for tx in txs {
         tracing::info!(
-            "submitting transaction {hash} for namespace {} of size {}",
+            "submitting transaction {} for namespace {} of size {}",
+            tx.commit(),
             tx.namespace(),
             tx.payload().len()
         );
-        if let Err(err) = client
-            .post::<()>("submit/submit")
-            .body_binary(&tx)
-            .unwrap()
-            .send()
-            .await
-        {
-            tracing::error!("failed to submit transaction: {err}");
+    }
+    // Measure latency from the start of the request, so it includes the submission itself.
+    let submitted_at = Instant::now();
+    let hashes = match submit(client, txs).await {
+        Ok(hashes) => hashes,
+        Err(err) => {
+            tracing::error!("failed to submit transactions: {err}");
+            // We don't know which transactions made it, so track all of them.
+            txs.iter().map(|tx| tx.commit()).collect()
+        }
+    };
+    for tx in txs {
+        let hash = tx.commit();
+        if !hashes.contains(&hash) {
+            continue;
         }
-        let submitted_at = Instant::now();
         sender
-            .send(SubmittedTransaction { hash, submitted_at })
+            .send(SubmittedTransaction {
+                hash,
+                namespace: tx.namespace().into(),
+                size: tx.payload().len(),
+                submitted_at,
+            })
             .await
             .ok();
-
-        let delay = Duration::from_millis(delay_distr.sample(&mut rng) as u64);
-        tracing::info!("sleeping for {delay:?}");
-        sleep(delay).await;
     }
 }
 
-async fn server<Ver: StaticVersionType + 'static>(port: u16, bind_version: Ver) {
-    if let Err(err) = App::<(), ServerError, Ver>::with_state(())
-        .serve(format!("0.0.0.0:{port}"), bind_version)
-        .await
+fn record(recorder: &mut impl Write, tx: &SubmittedTransaction, start: Instant) {
+    let entry = TraceEntry {
+        at_ms: tx.submitted_at.saturating_duration_since(start).as_millis() as u64,
+        namespace: tx.namespace,
+        size: tx.size,
+    };
+    if let Err(err) = serde_json::to_writer(&mut *recorder, &entry)
+        .map_err(std::io::Error::from)
+        .and_then(|()| writeln!(recorder))
     {
-        tracing::error!("web server exited: {err}");
+        tracing::error!("error writing trace: {err}");
+    }
+}
+
+/// Gauges tracking inclusion latency percentiles, in milliseconds.
+#[derive(Debug)]
+struct LatencyGauges {
+    p50: Box<dyn Gauge>,
+    p95: Box<dyn Gauge>,
+    p99: Box<dyn Gauge>,
+}
+
+impl LatencyGauges {
+    fn new(registry: &dyn Metrics) -> Self {
+        let ms = || Some("ms".to_string());
+        Self {
+            p50: registry.create_gauge("inclusion_latency_p50".into(), ms()),
+            p95: registry.create_gauge("inclusion_latency_p95".into(), ms()),
+            p99: registry.create_gauge("inclusion_latency_p99".into(), ms()),
+        }
+    }
+
+    /// Update the gauges from a sorted, non-empty list of latencies.
+    fn set(&self, sorted: &[Duration]) {
+        let ms = |p| percentile(sorted, p).as_millis() as usize;
+        self.p50.set(ms(50.));
+        self.p95.set(ms(95.));
+        self.p99.set(ms(99.));
+    }
+}
+
+/// Throughput and latency metrics, either for all transactions or for a single namespace.
+#[derive(Debug)]
+struct InclusionMetrics {
+    included: Box<dyn Counter>,
+    bytes_included: Box<dyn Counter>,
+    latency: LatencyGauges,
+}
+
+impl InclusionMetrics {
+    fn new(registry: &dyn Metrics) -> Self {
+        Self {
+            included: registry.create_counter("transactions_included".into(), None),
+            bytes_included: registry.create_counter("bytes_included".into(), None),
+            latency: LatencyGauges::new(registry),
+        }
+    }
+}
+
+/// Latencies and total size of included transactions, with the corresponding metrics.
+#[derive(Debug)]
+struct Inclusions {
+    /// Latencies of included transactions, kept sorted so percentiles can be read off directly.
+    latencies: Vec<Duration>,
+    bytes: usize,
+    metrics: InclusionMetrics,
+}
+
+impl Inclusions {
+    fn new(registry: &dyn Metrics) -> Self {
+        Self {
+            latencies: vec![],
+            bytes: 0,
+            metrics: InclusionMetrics::new(registry),
+        }
+    }
+
+    fn add(&mut self, size: usize, latency: Duration) {
+        let index = self.latencies.partition_point(|l| *l <= latency);
+        self.latencies.insert(index, latency);
+        self.bytes += size;
+        self.metrics.included.add(1);
+        self.metrics.bytes_included.add(size);
+        self.metrics.latency.set(&self.latencies);
+    }
+
+    fn summarize(&self, elapsed: f64) -> Summary {
+        Summary {
+            transactions: self.latencies.len(),
+            bytes: self.bytes,
+            transactions_per_sec: self.latencies.len() as f64 / elapsed,
+            bytes_per_sec: self.bytes as f64 / elapsed,
+            latency: LatencyReport::new(&self.latencies),
+        }
+    }
+}
+
+/// Statistics about the transactions submitted during this run.
+struct Stats {
+    start: Instant,
+    submitted: usize,
+    registry: PrometheusMetrics,
+    submitted_metric: Box<dyn Counter>,
+    pending: Box<dyn Gauge>,
+    latency: Box<dyn Histogram>,
+    /// All included transactions.
+    included: Inclusions,
+    /// Included transactions by namespace, with metrics in a subgroup for each namespace.
+    namespaces: BTreeMap<u64, Inclusions>,
+}
+
+impl Stats {
+    fn new(registry: &PrometheusMetrics) -> Self {
+        Self {
+            start: Instant::now(),
+            submitted: 0,
+            registry: registry.clone(),
+            submitted_metric: registry.create_counter("transactions_submitted".into(), None),
+            pending: registry.create_gauge("transactions_pending".into(), None),
+            latency: registry.create_histogram("inclusion_latency".into(), Some("s".into())),
+            included: Inclusions::new(registry),
+            namespaces: Default::default(),
+        }
+    }
+
+    fn submitted(&mut self) {
+        self.submitted += 1;
+        self.submitted_metric.add(1);
+    }
+
+    fn included(&mut self, tx: &SubmittedTransaction, latency: Duration) {
+        self.latency.add_point(latency.as_secs_f64());
+        self.included.add(tx.size, latency);
+        let registry = &self.registry;
+        self.namespaces
+            .entry(tx.namespace)
+            .or_insert_with(|| {
+                Inclusions::new(&*registry.subgroup(format!("namespace_{}", tx.namespace)))
+            })
+            .add(tx.size, latency);
+    }
+
+    fn update_metrics(&self, pending: usize) {
+        self.pending.set(pending);
+    }
+
+    fn report(&self, pending: usize) -> Report {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        Report {
+            elapsed_secs: elapsed,
+            submitted: self.submitted,
+            pending,
+            included: self.included.summarize(elapsed),
+            namespaces: self
+                .namespaces
+                .iter()
+                .map(|(ns, inclusions)| (*ns, inclusions.summarize(elapsed)))
+                .collect(),
+        }
+    }
+}
+
+/// Summary report of a run.
+#[derive(Debug, Serialize)]
+struct Report {
+    elapsed_secs: f64,
+    submitted: usize,
+    pending: usize,
+    included: Summary,
+    namespaces: BTreeMap<u64, Summary>,
+}
+
+/// Throughput and latency of included transactions.
+#[derive(Debug, Serialize)]
+struct Summary {
+    transactions: usize,
+    bytes: usize,
+    transactions_per_sec: f64,
+    bytes_per_sec: f64,
+    latency: Option<LatencyReport>,
+}
+
+/// Inclusion latency percentiles, in milliseconds.
+#[derive(Debug, Serialize)]
+struct LatencyReport {
+    p50_ms: u128,
+    p95_ms: u128,
+    p99_ms: u128,
+    max_ms: u128,
+}
+
+impl LatencyReport {
+    /// Summarize a sorted list of latencies.
+    fn new(sorted: &[Duration]) -> Option<Self> {
+        let max = *sorted.last()?;
+        Some(Self {
+            p50_ms: percentile(sorted, 50.).as_millis(),
+            p95_ms: percentile(sorted, 95.).as_millis(),
+            p99_ms: percentile(sorted, 99.).as_millis(),
+            max_ms: max.as_millis(),
+        })
+    }
+}
+
+impl Report {
+    fn log(&self) {
+        tracing::warn!(
+            "submitted {} transactions in {:.1}s, {} included, {} pending",
+            self.submitted,
+            self.elapsed_secs,
+            self.included.transactions,
+            self.pending
+        );
+        self.included.log("all namespaces");
+        for (ns, summary) in &self.namespaces {
+            summary.log(&format!("namespace {ns}"));
+        }
+    }
+}
+
+impl Summary {
+    fn log(&self, label: &str) {
+        let latency = match &self.latency {
+            Some(l) => format!(
+                "latency p50 {}ms, p95 {}ms, p99 {}ms, max {}ms",
+                l.p50_ms, l.p95_ms, l.p99_ms, l.max_ms
+            ),
+            None => "no latency data".into(),
+        };
+        tracing::warn!(
+            "{label}: {} transactions ({}), {:.2} tx/s, {}/s, {latency}",
+            self.transactions,
+            ByteSize(self.bytes as u64),
+            self.transactions_per_sec,
+            ByteSize(self.bytes_per_sec as u64),
+        );
+    }
+}
+
+/// The `p`th percentile of `sorted`, using the nearest-rank method.
+///
+/// `sorted` must be sorted and non-empty.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = ((p / 100.) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Submit a batch of transactions.
+///
+/// Returns the commitments of the transactions which were successfully submitted.
+async fn submit<Ver: StaticVersionType>(
+    client: &Client<Error, Ver>,
+    txs: &[Transaction],
+) -> Result<Vec<Commitment<Transaction>>, Error> {
+    if let [tx] = txs {
+        // Use the single transaction endpoint when we are not batching, so that we can still
+        // submit to servers which do not support batches.
+        client
+            .post::<()>("submit/submit")
+            .body_binary(tx)
+            .unwrap()
+            .send()
+            .await?;
+        return Ok(vec![tx.commit()]);
     }
+
+    let results: Vec<BatchSubmissionResult> = client
+        .post("submit/batch")
+        .body_binary(&txs)
+        .unwrap()
+        .send()
+        .await?;
+    Ok(results
+        .into_iter()
+        .filter_map(|res| match res.error {
+            Some(err) => {
+                tracing::error!("failed to submit transaction {}: {err}", res.hash);
+                None
+            }
+            None => Some(res.hash),
+        })
+        .collect())
 }
 
-fn random_transaction(opt: &Options, rng: &mut ChaChaRng) -> Transaction {
-    let namespace = rng.gen_range(opt.min_namespace..=opt.max_namespace);
+async fn server<Ver: StaticVersionType + 'static>(
+    port: u16,
+    metrics: PrometheusMetrics,
+    bind_version: Ver,
+) {
+    let api = toml! {
+        [route.metrics]
+        PATH = ["/metrics"]
+        METHOD = "METRICS"
+    };
+    let mut app = App::<_, ServerError, Ver>::with_state(RwLock::new(metrics));
+    app.module::<ServerError>("status", api)
+        .unwrap()
+        .metrics("metrics", |_req, state| {
+            async move { Ok(Cow::Borrowed(state)) }.boxed()
+        })
+        .unwrap();
+    if let Err(err) = app.serve(format!("0.0.0.0:{port}"), bind_version).await {
+        tracing::error!("web server exited: {err}");
+    }
+}
 
-    let len = rng.gen_range(opt.min_size..=opt.max_size);
+fn random_transaction(namespace: u64, len: usize, rng: &mut ChaChaRng) -> Transaction {
     let mut payload = vec![0; len];
     rng.fill_bytes(&mut payload);
 
@@ -287,3 +943,133 @@ fn random_transaction(opt: &Options, rng: &mut ChaChaRng) -> Transaction {
 fn random_seed() -> u64 {
     ChaChaRng::from_entropy().next_u64()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let latencies = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&latencies, 50.), Duration::from_millis(50));
+        assert_eq!(percentile(&latencies, 95.), Duration::from_millis(95));
+        assert_eq!(percentile(&latencies, 99.), Duration::from_millis(99));
+        assert_eq!(percentile(&latencies, 100.), Duration::from_millis(100));
+
+        let single = [Duration::from_secs(1)];
+        assert_eq!(percentile(&single, 50.), single[0]);
+        assert_eq!(percentile(&single, 99.), single[0]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let registry = PrometheusMetrics::default();
+        let mut stats = Stats::new(&registry);
+        let start = stats.start;
+        let tx = |namespace, size| SubmittedTransaction {
+            hash: random_transaction(namespace, size, &mut ChaChaRng::seed_from_u64(0)).commit(),
+            namespace,
+            size,
+            submitted_at: start,
+        };
+        // Latencies arrive out of order.
+        for ms in [30, 10, 20] {
+            stats.included(&tx(10000, 2), Duration::from_millis(ms));
+        }
+        stats.included(&tx(10001, 5), Duration::from_millis(100));
+
+        let gauge = |registry: &PrometheusMetrics, name| registry.get_gauge(name).unwrap().get();
+        assert_eq!(gauge(&registry, "inclusion_latency_p50"), 20);
+        assert_eq!(gauge(&registry, "inclusion_latency_p99"), 100);
+
+        // Each namespace has its own metrics.
+        let ns = registry.get_subgroup(["namespace_10000"]).unwrap();
+        assert_eq!(ns.get_counter("transactions_included").unwrap().get(), 3);
+        assert_eq!(ns.get_counter("bytes_included").unwrap().get(), 6);
+        assert_eq!(gauge(&ns, "inclusion_latency_p50"), 20);
+        assert_eq!(gauge(&ns, "inclusion_latency_p99"), 30);
+        let ns = registry.get_subgroup(["namespace_10001"]).unwrap();
+        assert_eq!(gauge(&ns, "inclusion_latency_p50"), 100);
+
+        let report = stats.report(0);
+        assert_eq!(report.included.transactions, 4);
+        assert_eq!(report.included.bytes, 11);
+        assert_eq!(
+            report.namespaces[&10000].latency.as_ref().unwrap().max_ms,
+            30
+        );
+        assert_eq!(report.namespaces[&10001].transactions, 1);
+    }
+
+    #[test]
+    fn test_trace_transaction() {
+        let entry = TraceEntry {
+            at_ms: 0,
+            namespace: 10000,
+            size: 32,
+        };
+        // The same seed and position always give the same transaction.
+        let tx = trace_transaction(0, 1, &entry);
+        assert_eq!(tx, trace_transaction(0, 1, &entry));
+        assert_eq!(u64::from(tx.namespace()), 10000);
+        assert_eq!(tx.payload().len(), 32);
+        // Different positions or seeds give different payloads.
+        assert_ne!(tx, trace_transaction(0, 2, &entry));
+        assert_ne!(tx, trace_transaction(1, 1, &entry));
+    }
+
+    #[test]
+    fn test_parse_namespace_weights() {
+        assert_eq!(
+            parse_namespace_weights("10000:3, 10001:1").unwrap(),
+            NamespaceWeights(vec![(10000, 3), (10001, 1)])
+        );
+        parse_namespace_weights("10000").unwrap_err();
+        parse_namespace_weights("10000:x").unwrap_err();
+        parse_namespace_weights("10000:0").unwrap_err();
+    }
+
+    #[test]
+    fn test_trace_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.jsonl");
+        let start = Instant::now();
+
+        let txs = [(10000, 5, 0), (10001, 100, 1500), (10000, 1, 20)].map(|(ns, size, ms)| {
+            SubmittedTransaction {
+                hash: random_transaction(ns, size, &mut ChaChaRng::seed_from_u64(0)).commit(),
+                namespace: ns,
+                size,
+                submitted_at: start + Duration::from_millis(ms),
+            }
+        });
+        let mut file = BufWriter::new(File::create(&path).unwrap());
+        for tx in &txs {
+            record(&mut file, tx, start);
+        }
+        file.flush().unwrap();
+        drop(file);
+
+        // Entries are replayed in order of submission time.
+        assert_eq!(
+            read_trace(&path).unwrap(),
+            [
+                TraceEntry {
+                    at_ms: 0,
+                    namespace: 10000,
+                    size: 5
+                },
+                TraceEntry {
+                    at_ms: 20,
+                    namespace: 10000,
+                    size: 1
+                },
+                TraceEntry {
+                    at_ms: 1500,
+                    namespace: 10001,
+                    size: 100
+                },
+            ]
+        );
+    }
+}
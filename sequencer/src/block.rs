@@ -15,10 +15,13 @@ pub mod tx_iterator;
 
 use entry::TxTableEntryWord;
 use payload::Payload;
-use tables::NameSpaceTable;
+use tables::{NameSpaceTable, NsCompression};
 
 pub type NsTable = NameSpaceTable<TxTableEntryWord>;
 
+/// Upper bound on the size of a block payload, before its namespaces are compressed.
+pub const MAX_BLOCK_SIZE: usize = 1 << 24;
+
 impl BlockPayload for Payload<TxTableEntryWord> {
     type Error = crate::Error;
     type Transaction = Transaction;
@@ -42,6 +45,12 @@ impl BlockPayload for Payload<TxTableEntryWord> {
     /// Edge case: for j=1 the jth namespace start index is implicitly 0.
     ///
     /// Word type is `TxTableEntry`.
+    ///
+    /// This describes the original, unversioned table, which is the default. With the
+    /// `compressed-namespaces` feature, blocks built here use a versioned table instead, which also
+    /// records how each namespace is encoded, and each namespace is compressed with zstd if that
+    /// makes it smaller; see [`Payload::from_txs_with_compression`] and [`tables::NsTableVersion`]
+    /// for details.
     /// TODO(746) don't use `TxTableEntry`; make a different type for type safety.
     ///
     /// TODO final entry should be implicit:
@@ -51,7 +60,11 @@ impl BlockPayload for Payload<TxTableEntryWord> {
     fn from_transactions(
         txs: impl IntoIterator<Item = Self::Transaction>,
     ) -> Result<(Self, Self::Metadata), Self::Error> {
-        let payload = Payload::from_txs(txs)?;
+        let payload = if cfg!(feature = "compressed-namespaces") {
+            Payload::from_txs_with_compression(txs, NsCompression::Zstd)?
+        } else {
+            Payload::from_txs(txs)?
+        };
         let ns_table = payload.get_ns_table().clone(); // TODO don't clone ns_table
         Some((payload, ns_table)).context(BlockBuildingSnafu)
     }
@@ -63,11 +76,16 @@ impl BlockPayload for Payload<TxTableEntryWord> {
         Self {
             raw_payload: encoded_transactions.into_iter().collect(),
             ns_table: metadata.clone(), // TODO don't clone ns_table
+            decoded_namespaces: Default::default(),
         }
     }
 
     fn genesis() -> (Self, Self::Metadata) {
-        Self::from_transactions([]).unwrap()
+        // The genesis payload always uses the unversioned format, so that the genesis header does
+        // not depend on the `compressed-namespaces` feature.
+        let payload = Payload::from_txs([]).unwrap();
+        let ns_table = payload.get_ns_table().clone();
+        (payload, ns_table)
     }
 
     fn encode(&self) -> Result<Self::Encode<'_>, Self::Error> {
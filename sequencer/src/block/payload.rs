@@ -1,6 +1,6 @@
 use crate::block::entry::{TxTableEntry, TxTableEntryWord};
 use crate::block::payload;
-use crate::{BlockBuildingSnafu, Error, NamespaceId, Transaction};
+use crate::{BlockBuildingSnafu, Error, Header, NamespaceId, Transaction};
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use commit::Committable;
 use derivative::Derivative;
@@ -16,9 +16,14 @@ use num_traits::PrimInt;
 use serde::{Deserialize, Serialize};
 use snafu::OptionExt;
 use std::default::Default;
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
 
-use crate::block::tables::NameSpaceTable;
+use crate::block::tables::{NameSpaceTable, NsCompression};
 use trait_set::trait_set;
 
 use crate::block::tables::TxTable;
@@ -73,6 +78,12 @@ pub struct Payload<TableWord: TableWordTraits> {
 
     // Sequence of bytes representing the namespace table
     pub(super) ns_table: NameSpaceTable<TableWord>,
+
+    // Decoded bytes of compressed namespaces, populated on first access.
+    #[derivative(Hash = "ignore")]
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip)]
+    pub(super) decoded_namespaces: DecodedNamespaces,
     // TODO(X) Revisit caching of frequently used items
     //
     // TODO type should be `OnceLock<SmallRangeProofType>` instead of `OnceLock<Option<SmallRangeProofType>>`.
@@ -83,6 +94,42 @@ pub struct Payload<TableWord: TableWordTraits> {
     // pub tx_table_len_proof: OnceLock<Option<SmallRangeProofType>>,
 }
 
+/// Cache of the decoded bytes of the compressed namespaces in a payload, by namespace index.
+///
+/// Decoding a namespace can be expensive, and the [`QueryablePayload`](hotshot_query_service::availability::QueryablePayload)
+/// interface looks up transactions one at a time, so without the cache enumerating a compressed
+/// namespace would decode it once per transaction. The cache is derived entirely from the payload
+/// bytes, so all caches compare equal.
+#[derive(Clone, Debug, Default)]
+pub(super) struct DecodedNamespaces(Arc<Mutex<HashMap<usize, Arc<[u8]>>>>);
+
+impl PartialEq for DecodedNamespaces {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for DecodedNamespaces {}
+
+/// The decoded bytes of a namespace: a tx table followed by the transaction payloads.
+pub(super) enum NsPayload<'a> {
+    /// An uncompressed namespace, borrowed from the block payload.
+    Raw(&'a [u8]),
+    /// A compressed namespace, decoded.
+    Decoded(Arc<[u8]>),
+}
+
+impl<'a> Deref for NsPayload<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Raw(bytes) => bytes,
+            Self::Decoded(bytes) => bytes,
+        }
+    }
+}
+
 impl<TableWord: TableWordTraits> Payload<TableWord> {
     // TODO dead code even with `pub` because this module is private in lib.rs
     #[allow(dead_code)]
@@ -99,20 +146,73 @@ impl<TableWord: TableWordTraits> Payload<TableWord> {
     /// Returns the list of txs for namespace `ns_id`.
     pub fn namespace(&self, ns_id: NamespaceId) -> Option<Vec<Transaction>> {
         let ns_index = self.ns_table.lookup(ns_id)?;
-        let ns_payload_range = self
-            .ns_table
+        let (ns_id, ns_bytes) = self.ns_payload(&self.ns_table, ns_index);
+        Some(parse_ns_payload(&ns_bytes, ns_id))
+    }
+
+    /// The number of transactions in the namespace at `ns_index` in `ns_table`.
+    ///
+    /// Unlike [`ns_payload`](Self::ns_payload), this neither keeps nor caches the decoded bytes of
+    /// compressed namespaces.
+    pub(super) fn ns_tx_table_len(
+        &self,
+        ns_table: &NameSpaceTable<TableWord>,
+        ns_index: usize,
+    ) -> usize {
+        let ns_range = ns_table
             .get_payload_range(ns_index, self.raw_payload.len())
             .1;
-        Some(parse_ns_payload(
-            self.raw_payload.get(ns_payload_range)?,
-            ns_id,
-        ))
+        ns_table.get_tx_table_len(ns_index, &self.raw_payload[ns_range])
+    }
+
+    /// Get the decoded bytes of the namespace at `ns_index` in `ns_table`, along with its ID.
+    ///
+    /// If the namespace bytes cannot be decoded, the namespace is treated as empty. Compressed
+    /// namespaces are decoded at most once per payload, as long as `ns_table` is this payload's own
+    /// namespace table. The total size of the cache is bounded by
+    /// [`NsCompression::MAX_DECOMPRESSED_PAYLOAD_LEN`].
+    pub(super) fn ns_payload(
+        &self,
+        ns_table: &NameSpaceTable<TableWord>,
+        ns_index: usize,
+    ) -> (NamespaceId, NsPayload<'_>) {
+        let (ns_id, ns_range) = ns_table.get_payload_range(ns_index, self.raw_payload.len());
+        let ns_bytes = &self.raw_payload[ns_range];
+        let compression = ns_table.get_compression(ns_index);
+        if compression == NsCompression::None {
+            return (ns_id, NsPayload::Raw(ns_bytes));
+        }
+
+        let decode = || -> Arc<[u8]> {
+            Arc::from(
+                ns_table
+                    .decode_ns(ns_index, ns_bytes)
+                    .unwrap_or_default()
+                    .into_owned(),
+            )
+        };
+        if ns_table != &self.ns_table {
+            // The cache is indexed by our own namespace table, so it cannot be used with another.
+            return (ns_id, NsPayload::Decoded(decode()));
+        }
+        let decoded = self
+            .decoded_namespaces
+            .0
+            .lock()
+            .unwrap()
+            .entry(ns_index)
+            .or_insert_with(decode)
+            .clone();
+        (ns_id, NsPayload::Decoded(decoded))
     }
 
     // TODO dead code even with `pub` because this module is private in lib.rs
     #[allow(dead_code)]
     /// Returns the flat bytes for namespace `ns_id`, along with a proof of correctness for those bytes.
     ///
+    /// The bytes are exactly as they appear in the block payload, so if the namespace is compressed
+    /// they are compressed as well. [`NamespaceProof::verify`] decodes them.
+    ///
     /// RPC-friendly proof contains:
     /// - the namespace bytes
     /// - `vid_common` needed to verify the proof. This data is not accessible to the verifier because it's not part of the block header.
@@ -153,19 +253,47 @@ impl<TableWord: TableWordTraits> Payload<TableWord> {
         &self.ns_table
     }
 
+    /// Build a payload using the original, unversioned format.
+    ///
+    /// This is the format of the genesis payload. Blocks built with
+    /// [`BlockPayload::from_transactions`] use the versioned format instead; see
+    /// [`from_txs_with_compression`](Self::from_txs_with_compression).
     pub fn from_txs(
         txs: impl IntoIterator<Item = <payload::Payload<TxTableEntryWord> as BlockPayload>::Transaction>,
+    ) -> Result<Self, Error> {
+        Self::build(txs, None)
+    }
+
+    /// Build a versioned payload in which each namespace may be compressed.
+    ///
+    /// Each namespace is encoded with `compression` only if doing so makes it smaller, otherwise it
+    /// is stored as is. Either way, the encoding is recorded in the namespace table. Namespaces are
+    /// not compressed once their total size would exceed
+    /// [`NsCompression::MAX_DECOMPRESSED_PAYLOAD_LEN`], since readers would refuse to decode them.
+    ///
+    /// Versioned payloads can only be read with the `compressed-namespaces` feature enabled.
+    pub fn from_txs_with_compression(
+        txs: impl IntoIterator<Item = <payload::Payload<TxTableEntryWord> as BlockPayload>::Transaction>,
+        compression: NsCompression,
+    ) -> Result<Self, Error> {
+        Self::build(txs, Some(compression))
+    }
+
+    fn build(
+        txs: impl IntoIterator<Item = <payload::Payload<TxTableEntryWord> as BlockPayload>::Transaction>,
+        compression: Option<NsCompression>,
     ) -> Result<Self, Error> {
         let mut namespaces: HashMap<NamespaceId, NamespaceInfo> = Default::default();
         let mut structured_payload = Self {
             raw_payload: vec![],
             ns_table: NameSpaceTable::default(),
+            decoded_namespaces: Default::default(),
         };
         for tx in txs.into_iter() {
             Payload::<TableWord>::update_namespace_with_tx(&mut namespaces, tx);
         }
 
-        structured_payload.generate_raw_payload(namespaces)?;
+        structured_payload.generate_raw_payload(namespaces, compression)?;
         Ok(structured_payload)
     }
 
@@ -195,9 +323,12 @@ impl<TableWord: TableWordTraits> Payload<TableWord> {
             .unwrap(); // TODO (Philippe) error handling
     }
 
+    // If `compression` is `None`, generate an unversioned namespace table and store every namespace
+    // uncompressed.
     fn generate_raw_payload(
         &mut self,
         namespaces: HashMap<NamespaceId, NamespaceInfo>,
+        compression: Option<NsCompression>,
     ) -> Result<(), Error> {
         // fill payload and namespace table
         let mut payload = vec![];
@@ -209,14 +340,43 @@ impl<TableWord: TableWordTraits> Payload<TableWord> {
                 .to_bytes(),
         ));
 
-        let mut namespaces_offsets = vec![];
+        let mut namespaces_entries = vec![];
+        let mut decompressed_len = 0;
         for (id, namespace) in namespaces {
-            payload.extend(namespace.tx_table_len.to_bytes());
-            payload.extend(namespace.tx_table);
-            payload.extend(namespace.tx_bodies);
-            namespaces_offsets.push((id, payload.len()));
+            let mut ns_bytes = namespace.tx_table_len.to_bytes().to_vec();
+            ns_bytes.extend(namespace.tx_table);
+            ns_bytes.extend(namespace.tx_bodies);
+            let decoded_len = ns_bytes.len();
+
+            let ns_compression = match compression {
+                Some(compression)
+                    if decompressed_len + decoded_len
+                        <= NsCompression::MAX_DECOMPRESSED_PAYLOAD_LEN =>
+                {
+                    match compression.encode(ns_bytes.clone()) {
+                        Some(encoded) if encoded.len() < ns_bytes.len() => {
+                            ns_bytes = encoded;
+                            decompressed_len += decoded_len;
+                            compression
+                        }
+                        _ => NsCompression::None,
+                    }
+                }
+                _ => NsCompression::None,
+            };
+            payload.extend(ns_bytes);
+            namespaces_entries.push((id, payload.len(), ns_compression, decoded_len));
         }
-        self.ns_table = NameSpaceTable::from_namespace_offsets(namespaces_offsets).unwrap();
+        self.ns_table = match compression {
+            Some(_) => NameSpaceTable::from_namespace_entries(namespaces_entries)?,
+            None => NameSpaceTable::from_namespace_offsets(
+                namespaces_entries
+                    .into_iter()
+                    .map(|(id, offset, _, _)| (id, offset))
+                    .collect(),
+            )
+            .unwrap(),
+        };
 
         self.raw_payload = payload;
         Ok(())
@@ -288,7 +448,10 @@ impl NamespaceProof {
 
                 // verification succeeded, return some data
                 // we know ns_id is correct because the corresponding ns_payload_range passed verification
-                Some((parse_ns_payload(ns_payload_flat, ns_id), ns_id))
+                let ns_bytes = ns_table
+                    .decode_ns(ns_index, ns_payload_flat)
+                    .unwrap_or_default();
+                Some((parse_ns_payload(&ns_bytes, ns_id), ns_id))
             }
             NamespaceProof::NonExistence { ns_id } => {
                 if ns_table.lookup(*ns_id).is_some() {
@@ -298,8 +461,39 @@ impl NamespaceProof {
             }
         }
     }
+
+    /// Verify a sequence of [`NamespaceProof`]s for consecutive blocks.
+    ///
+    /// `proofs` and `headers` must have the same length, and the `i`th proof is checked against the
+    /// `payload_commitment` and `ns_table` of the `i`th header. Every proof must be for namespace
+    /// `ns_id`. On success, returns the transactions in the namespace for each block, in order.
+    pub fn verify_range<'a>(
+        vid: &VidSchemeType,
+        ns_id: NamespaceId,
+        proofs: impl IntoIterator<Item = &'a NamespaceProof>,
+        headers: impl IntoIterator<Item = &'a Header>,
+    ) -> Option<Vec<Vec<Transaction>>> {
+        let mut headers = headers.into_iter();
+        let mut txs = vec![];
+        for proof in proofs {
+            let header = headers.next()?; // error: fewer headers than proofs
+            let (block_txs, block_ns_id) =
+                proof.verify(vid, &header.payload_commitment, &header.ns_table)?;
+            if block_ns_id != ns_id {
+                return None; // error: proof is for the wrong namespace
+            }
+            txs.push(block_txs);
+        }
+        if headers.next().is_some() {
+            return None; // error: fewer proofs than headers
+        }
+        Some(txs)
+    }
 }
 
+/// Parse the transactions in a namespace from its decoded bytes.
+///
+/// Compressed namespaces must be decoded first, with [`NameSpaceTable::decode_ns`].
 pub fn parse_ns_payload(ns_bytes: &[u8], ns_id: NamespaceId) -> Vec<Transaction> {
     let num_txs = TxTable::get_tx_table_len(ns_bytes);
     (0..TxTable::get_tx_table_len(ns_bytes))
@@ -325,6 +519,11 @@ impl hotshot_types::traits::block_contents::TestableBlock
 #[cfg(test)]
 mod test {
     use super::NamespaceProof;
+    #[cfg(feature = "compressed-namespaces")]
+    use crate::block::{
+        queryable::TxInclusionProof,
+        tables::{NsCompression, NsTableVersion},
+    };
     use crate::{
         block::{
             entry::{TxTableEntry, TxTableEntryWord},
@@ -465,7 +664,9 @@ mod test {
             let all_txs_iter = derived_nss
                 .iter()
                 .flat_map(|(_ns_id, ns)| ns.txs.iter().cloned());
-            let (block, actual_ns_table) = Payload::from_transactions(all_txs_iter).unwrap();
+            // This test checks the layout of the unversioned namespace table.
+            let block = Payload::<TxTableEntryWord>::from_txs(all_txs_iter).unwrap();
+            let actual_ns_table = block.get_ns_table().clone();
             let disperse_data = vid.disperse(&block.raw_payload).unwrap();
 
             // TEST ACTUAL STUFF AGAINST DERIVED STUFF
@@ -599,6 +800,7 @@ mod test {
                         .verify(
                             &tx_with_proof,
                             idx,
+                            &actual_ns_table,
                             &vid,
                             &disperse_data.commit,
                             &disperse_data.common,
@@ -762,6 +964,11 @@ mod test {
 
         // test: fake proof should get rejected
         // TODO should return Some(Err()) instead of None
+        let ns_table = NameSpaceTable::<TxTableEntryWord>::from_namespace_offsets(vec![(
+            Default::default(),
+            block.raw_payload.len(),
+        )])
+        .unwrap();
         assert!(proof
             .verify(
                 &tx,
@@ -769,6 +976,7 @@ mod test {
                     ns_idx: 0,
                     tx_idx: 0
                 },
+                &ns_table,
                 &vid,
                 &disperse_data.commit,
                 &disperse_data.common
@@ -1087,6 +1295,333 @@ mod test {
         }
     }
 
+    #[cfg(feature = "compressed-namespaces")]
+    #[test]
+    fn compressed_namespaces() {
+        setup_logging();
+        setup_backtrace();
+        let mut rng = jf_utils::test_rng();
+        let mut vid = vid_scheme(NUM_STORAGE_NODES);
+
+        // Namespace 0 is highly compressible, namespace 1 is random and should be stored as is.
+        let compressible_ns = NamespaceId::from(0);
+        let random_ns = NamespaceId::from(1);
+        let mut txs = vec![];
+        for i in 0..10 {
+            txs.push(Transaction::new(compressible_ns, vec![i; 500]));
+            txs.push(Transaction::new(random_ns, random_bytes(100, &mut rng)));
+        }
+
+        let legacy = Payload::<TxTableEntryWord>::from_txs(txs.clone()).unwrap();
+        let block = Payload::<TxTableEntryWord>::from_txs_with_compression(
+            txs.clone(),
+            NsCompression::Zstd,
+        )
+        .unwrap();
+        let ns_table = block.get_ns_table();
+        assert_eq!(legacy.get_ns_table().version(), Some(NsTableVersion::V0));
+        assert_eq!(ns_table.version(), Some(NsTableVersion::V1));
+        assert_eq!(ns_table.len(), 2);
+        assert!(block.raw_payload.len() < legacy.raw_payload.len());
+
+        let compressible_idx = ns_table.lookup(compressible_ns).unwrap();
+        let random_idx = ns_table.lookup(random_ns).unwrap();
+        assert_eq!(
+            ns_table.get_compression(compressible_idx),
+            NsCompression::Zstd
+        );
+        assert_eq!(ns_table.get_compression(random_idx), NsCompression::None);
+
+        // Queryable payload functions see the decoded transactions.
+        assert_eq!(block.len(ns_table), txs.len());
+        let mut block_txs = block
+            .enumerate(ns_table)
+            .map(|(_, tx)| tx)
+            .collect::<Vec<_>>();
+        let mut expected_txs = txs.clone();
+        block_txs.sort_by_key(|tx| (tx.namespace(), tx.payload().to_vec()));
+        expected_txs.sort_by_key(|tx| (tx.namespace(), tx.payload().to_vec()));
+        assert_eq!(block_txs, expected_txs);
+
+        // Transaction proofs are available for compressed and uncompressed namespaces.
+        let disperse_data = vid.disperse(&block.raw_payload).unwrap();
+        for idx in block.iter(ns_table) {
+            let tx = block.transaction(ns_table, &idx).unwrap();
+            let (tx_with_proof, proof) = block.transaction_with_proof(ns_table, &idx).unwrap();
+            assert_eq!(tx, tx_with_proof);
+            assert_eq!(
+                matches!(proof, TxInclusionProof::Compressed(_)),
+                idx.ns_idx == compressible_idx
+            );
+            proof
+                .verify(
+                    &tx,
+                    idx.clone(),
+                    ns_table,
+                    &vid,
+                    &disperse_data.commit,
+                    &disperse_data.common,
+                )
+                .unwrap()
+                .unwrap();
+
+            // The proof does not verify for a different transaction.
+            let mut wrong_payload = tx.payload().to_vec();
+            wrong_payload[0] ^= 1;
+            let wrong_tx = Transaction::new(tx.namespace(), wrong_payload);
+            proof
+                .verify(
+                    &wrong_tx,
+                    idx.clone(),
+                    ns_table,
+                    &vid,
+                    &disperse_data.commit,
+                    &disperse_data.common,
+                )
+                .unwrap()
+                .unwrap_err();
+
+            // The proof does not verify for a transaction claiming a different namespace, or
+            // against the entry of the other namespace, which has a different encoding.
+            let other_ns_idx = 1 - idx.ns_idx;
+            let other_ns = ns_table.get_table_entry(other_ns_idx).0;
+            let wrong_ns_tx = Transaction::new(other_ns, tx.payload().to_vec());
+            proof
+                .verify(
+                    &wrong_ns_tx,
+                    idx.clone(),
+                    ns_table,
+                    &vid,
+                    &disperse_data.commit,
+                    &disperse_data.common,
+                )
+                .unwrap()
+                .unwrap_err();
+            proof
+                .verify(
+                    &wrong_ns_tx,
+                    TxIndex {
+                        ns_idx: other_ns_idx,
+                        tx_idx: idx.tx_idx,
+                    },
+                    ns_table,
+                    &vid,
+                    &disperse_data.commit,
+                    &disperse_data.common,
+                )
+                .unwrap()
+                .unwrap_err();
+        }
+
+        // Decoded namespaces are cached, and the cache does not affect equality.
+        assert_eq!(block.decoded_namespaces.0.lock().unwrap().len(), 1);
+
+        // Counting transactions does not populate the cache.
+        let fresh = Payload::from_bytes(block.encode().unwrap(), ns_table);
+        assert_eq!(fresh.len(ns_table), txs.len());
+        assert!(fresh.decoded_namespaces.0.lock().unwrap().is_empty());
+        assert_eq!(
+            block,
+            Payload::from_bytes(block.encode().unwrap(), block.get_ns_table())
+        );
+
+        // Blocks built by the builder are compressed where it helps.
+        let (built, built_ns_table) =
+            <Payload<TxTableEntryWord> as BlockPayload>::from_transactions(txs.clone()).unwrap();
+        assert_eq!(built_ns_table.version(), Some(NsTableVersion::V1));
+        assert_eq!(built.raw_payload.len(), block.raw_payload.len());
+
+        // Namespace proofs are over the encoded bytes, but verify to the decoded transactions.
+        for ns_id in [compressible_ns, random_ns] {
+            let expected = txs
+                .iter()
+                .filter(|tx| tx.namespace() == ns_id)
+                .cloned()
+                .collect::<Vec<_>>();
+            assert_eq!(block.namespace(ns_id).unwrap(), expected);
+
+            let proof = block
+                .namespace_with_proof(ns_table, ns_id, disperse_data.common.clone())
+                .unwrap();
+            let (proof_txs, proof_ns_id) =
+                proof.verify(&vid, &disperse_data.commit, ns_table).unwrap();
+            assert_eq!(proof_ns_id, ns_id);
+            assert_eq!(proof_txs, expected);
+        }
+    }
+
+    #[cfg(feature = "compressed-namespaces")]
+    #[test]
+    fn malformed_compressed_namespace() {
+        setup_logging();
+        setup_backtrace();
+
+        // A namespace which claims to be compressed but is not valid zstd is treated as empty.
+        let ns_id = NamespaceId::from(0);
+        let ns_table = NameSpaceTable::<TxTableEntryWord>::from_namespace_entries(vec![(
+            ns_id,
+            100,
+            NsCompression::Zstd,
+            100,
+        )])
+        .unwrap();
+        let block = Payload::from_bytes(std::iter::repeat(1).take(100), &ns_table);
+        assert_eq!(block.len(&ns_table), 0);
+        assert!(block.namespace(ns_id).unwrap().is_empty());
+
+        // So is a namespace which does not decode to its declared length.
+        let ns_bytes = [TxTableEntry::from_usize(1).to_bytes(), [0; 1000].to_vec()].concat();
+        let encoded = NsCompression::Zstd.encode(ns_bytes.clone()).unwrap();
+        for decoded_len in [ns_bytes.len() - 1, ns_bytes.len() + 1] {
+            let ns_table = NameSpaceTable::<TxTableEntryWord>::from_namespace_entries(vec![(
+                ns_id,
+                encoded.len(),
+                NsCompression::Zstd,
+                decoded_len,
+            )])
+            .unwrap();
+            let block = Payload::from_bytes(encoded.iter().cloned(), &ns_table);
+            assert_eq!(block.len(&ns_table), 0);
+            assert!(block.namespace(ns_id).unwrap().is_empty());
+        }
+
+        // Compressed namespaces which together decode to more than the limit are not decoded at
+        // all, even if each one on its own is small enough.
+        let bomb = vec![0; NsCompression::MAX_DECOMPRESSED_PAYLOAD_LEN / 2 + 1];
+        let encoded = NsCompression::Zstd.encode(bomb.clone()).unwrap();
+        let ns_table = NameSpaceTable::<TxTableEntryWord>::from_namespace_entries(vec![
+            (ns_id, encoded.len(), NsCompression::Zstd, bomb.len()),
+            (
+                NamespaceId::from(1),
+                2 * encoded.len(),
+                NsCompression::Zstd,
+                bomb.len(),
+            ),
+        ])
+        .unwrap();
+        assert!(ns_table.decompressed_byte_len() > NsCompression::MAX_DECOMPRESSED_PAYLOAD_LEN);
+        let block = Payload::from_bytes(encoded.repeat(2).into_iter(), &ns_table);
+        assert_eq!(block.len(&ns_table), 0);
+        assert!(block.namespace(ns_id).unwrap().is_empty());
+        assert!(block.decoded_namespaces.0.lock().unwrap()[&0].is_empty());
+
+        // A namespace with an unknown encoding is also empty.
+        let ns_table = NameSpaceTable::<TxTableEntryWord>::from_namespace_entries(vec![(
+            ns_id,
+            100,
+            NsCompression::Unknown(7),
+            100,
+        )])
+        .unwrap();
+        let block = Payload::from_bytes(std::iter::repeat(0).take(100), &ns_table);
+        assert_eq!(block.len(&ns_table), 0);
+        assert!(block.namespace(ns_id).unwrap().is_empty());
+
+        // A namespace table with an unknown version is treated as empty.
+        let mut ns_table_bytes = ns_table.get_bytes().to_vec();
+        ns_table_bytes[TxTableEntry::byte_len() - 1] = 0xff;
+        let ns_table = NameSpaceTable::<TxTableEntryWord>::from_bytes(ns_table_bytes);
+        assert_eq!(ns_table.version(), None);
+        assert!(ns_table.is_empty());
+    }
+
+    #[test]
+    fn validate_ns_table() {
+        setup_logging();
+        setup_backtrace();
+        let mut rng = jf_utils::test_rng();
+
+        // Tables built from transactions are well formed.
+        let txs = (0..10)
+            .map(|i| Transaction::new(NamespaceId::from(i % 3), random_bytes(50, &mut rng)))
+            .collect::<Vec<_>>();
+        let block = Payload::<TxTableEntryWord>::from_txs(txs).unwrap();
+        block
+            .get_ns_table()
+            .validate(block.raw_payload.len())
+            .unwrap();
+        let (genesis, _) = <Payload<TxTableEntryWord> as BlockPayload>::genesis();
+        genesis.get_ns_table().validate(0).unwrap();
+
+        // The namespaces must cover the whole payload.
+        block
+            .get_ns_table()
+            .validate(block.raw_payload.len() + 1)
+            .unwrap_err();
+
+        // Duplicate namespaces.
+        let ns_id = NamespaceId::from(0);
+        NameSpaceTable::<TxTableEntryWord>::from_namespace_offsets(vec![(ns_id, 10), (ns_id, 20)])
+            .unwrap()
+            .validate(20)
+            .unwrap_err();
+
+        // Decreasing offsets.
+        NameSpaceTable::<TxTableEntryWord>::from_namespace_offsets(vec![
+            (NamespaceId::from(0), 20),
+            (NamespaceId::from(1), 10),
+        ])
+        .unwrap()
+        .validate(10)
+        .unwrap_err();
+
+        // Trailing bytes after the last entry.
+        let ns_table =
+            NameSpaceTable::<TxTableEntryWord>::from_namespace_offsets(vec![(ns_id, 10)]).unwrap();
+        ns_table.validate(10).unwrap();
+        let mut ns_table_bytes = ns_table.get_bytes().to_vec();
+        ns_table_bytes.push(0);
+        NameSpaceTable::<TxTableEntryWord>::from_bytes(ns_table_bytes)
+            .validate(10)
+            .unwrap_err();
+
+        // Unknown version, or without versioned tables, a length that does not match the entries.
+        let mut ns_table_bytes = ns_table.get_bytes().to_vec();
+        ns_table_bytes[TxTableEntry::byte_len() - 1] = 0xff;
+        NameSpaceTable::<TxTableEntryWord>::from_bytes(ns_table_bytes)
+            .validate(10)
+            .unwrap_err();
+    }
+
+    #[cfg(feature = "compressed-namespaces")]
+    #[test]
+    fn validate_versioned_ns_table() {
+        setup_logging();
+        setup_backtrace();
+        let mut rng = jf_utils::test_rng();
+
+        // Tables built with compression are well formed.
+        let txs = (0..10)
+            .map(|i| Transaction::new(NamespaceId::from(i % 3), vec![i as u8; 50]))
+            .chain([Transaction::new(
+                NamespaceId::from(3),
+                random_bytes(50, &mut rng),
+            )])
+            .collect::<Vec<_>>();
+        let block =
+            Payload::<TxTableEntryWord>::from_txs_with_compression(txs, NsCompression::Zstd)
+                .unwrap();
+        block
+            .get_ns_table()
+            .validate(block.raw_payload.len())
+            .unwrap();
+        block
+            .get_ns_table()
+            .validate(block.raw_payload.len() + 1)
+            .unwrap_err();
+
+        // Unknown encoding.
+        NameSpaceTable::<TxTableEntryWord>::from_namespace_entries(vec![(
+            NamespaceId::from(0),
+            100,
+            NsCompression::Unknown(7),
+            100,
+        )])
+        .unwrap()
+        .validate(100)
+        .unwrap_err();
+    }
+
     struct TestCase<TableWord: TableWordTraits> {
         payload: Vec<u8>,
         num_txs: usize,
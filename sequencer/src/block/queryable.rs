@@ -1,9 +1,14 @@
 use crate::block::entry::TxTableEntryWord;
 use crate::block::payload::Payload;
-use crate::block::tables::TxTable;
+use crate::block::tables::{NameSpaceTable, NsCompression, TxTable};
 use hotshot_query_service::availability::QueryablePayload;
-use hotshot_types::vid::{vid_scheme, SmallRangeProofType};
-use jf_primitives::vid::payload_prover::{PayloadProver, Statement};
+use hotshot_types::vid::{
+    vid_scheme, LargeRangeProofType, SmallRangeProofType, VidCommitment, VidCommon, VidSchemeType,
+};
+use jf_primitives::vid::{
+    payload_prover::{PayloadProver, Statement},
+    VidScheme,
+};
 use serde::{Deserialize, Serialize};
 use std::ops::Range;
 
@@ -22,8 +27,7 @@ impl QueryablePayload for Payload<TxTableEntryWord> {
 
     fn len(&self, ns_table: &Self::Metadata) -> usize {
         (0..ns_table.len())
-            .map(|ns_idx| ns_table.get_payload_range(ns_idx, self.raw_payload.len()).1)
-            .map(|ns_range| TxTable::get_tx_table_len(&self.raw_payload[ns_range]))
+            .map(|ns_idx| self.ns_tx_table_len(ns_table, ns_idx))
             .sum()
     }
 
@@ -40,24 +44,21 @@ impl QueryablePayload for Payload<TxTableEntryWord> {
         if ns_idx >= meta.len() {
             return None; // error: index out of bounds
         }
-        let (ns_id, ns_range) = meta.get_payload_range(ns_idx, self.raw_payload.len());
+        let (ns_id, ns_payload) = self.ns_payload(meta, ns_idx);
 
-        let tx_table_len = TxTable::get_tx_table_len(&self.raw_payload[ns_range.clone()]);
+        let tx_table_len = TxTable::get_tx_table_len(&ns_payload);
         if tx_idx >= tx_table_len {
             return None; // error: index out of bounds
         }
-        let ns_payload = &self.raw_payload[ns_range.clone()];
-
-        let tx_within_ns = TxTable::get_payload_range(ns_payload, tx_idx, tx_table_len);
-        let (start, end) = (tx_within_ns.start, tx_within_ns.end);
-        let ns_start = ns_range.start;
-        let tx_payload_range = start.saturating_add(ns_start)..end.saturating_add(ns_start);
 
-        let tx_payload = self.raw_payload.get(tx_payload_range)?.to_vec();
+        let tx_within_ns = TxTable::get_payload_range(&ns_payload, tx_idx, tx_table_len);
+        let tx_payload = ns_payload.get(tx_within_ns)?.to_vec();
 
         Some(Transaction::new(ns_id, tx_payload))
     }
 
+    /// For transactions in compressed namespaces the transaction bytes do not appear in the block
+    /// payload, so the proof covers the whole namespace instead.
     fn transaction_with_proof(
         &self,
         meta: &Self::Metadata,
@@ -70,6 +71,21 @@ impl QueryablePayload for Payload<TxTableEntryWord> {
         let (ns_id, ns_range) = meta.get_payload_range(ns_idx, self.raw_payload.len());
         let ns_start_offset = ns_range.start;
 
+        // TODO temporary VID construction. We need to get the number of storage nodes from the VID
+        // common data. May need the query service to pass common into this function along with
+        // metadata.
+        let vid = vid_scheme(10);
+
+        let compression = meta.get_compression(ns_idx);
+        if compression != NsCompression::None {
+            let tx = self.transaction(meta, index)?;
+            let proof = CompressedTxProof {
+                ns_payload_flat: self.raw_payload.get(ns_range.clone())?.to_vec(),
+                ns_proof: vid.payload_proof(&self.raw_payload, ns_range).ok()?,
+            };
+            return Some((tx, TxInclusionProof::Compressed(proof)));
+        }
+
         let tx_table_len = TxTable::get_tx_table_len(&self.raw_payload[ns_range.clone()]);
         if tx_idx >= tx_table_len {
             return None; // error: index out of bounds
@@ -80,11 +96,6 @@ impl QueryablePayload for Payload<TxTableEntryWord> {
             .checked_mul(TxTableEntry::byte_len())?
             .checked_add(ns_start_offset)?;
 
-        // TODO temporary VID construction. We need to get the number of storage nodes from the VID
-        // common data. May need the query service to pass common into this function along with
-        // metadata.
-        let vid = vid_scheme(10);
-
         // Read the tx payload range from the tx table into `tx_table_range_[start|end]` and compute a proof that this range is correct.
         //
         // This correctness proof requires a range of its own, which we read into `tx_table_range_proof_[start|end]`.
@@ -151,7 +162,7 @@ impl QueryablePayload for Payload<TxTableEntryWord> {
                 ns_id,
                 self.raw_payload.get(tx_payload_range.clone())?.to_vec(),
             ),
-            TxInclusionProof {
+            TxInclusionProof::Uncompressed(TxRangeProof {
                 ns_range: ns_range.clone(),
                 tx_table_len: TxTableEntry::from_usize(tx_table_len),
                 tx_table_len_proof: vid
@@ -165,13 +176,26 @@ impl QueryablePayload for Payload<TxTableEntryWord> {
                 } else {
                     vid.payload_proof(&self.raw_payload, tx_payload_range).ok()
                 },
-            },
+            }),
         ))
     }
 }
 
+/// A proof that a transaction is included in a block.
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
-pub struct TxInclusionProof {
+pub enum TxInclusionProof {
+    /// A transaction in an uncompressed namespace, proven by its entries in the tx table and its
+    /// bytes in the block payload.
+    Uncompressed(TxRangeProof),
+    /// A transaction in a compressed namespace.
+    ///
+    /// Individual transactions cannot be located within the compressed bytes, so the proof covers
+    /// the whole namespace, which the verifier decodes.
+    Compressed(CompressedTxProof),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TxRangeProof {
     ns_range: Range<usize>,
     tx_table_len: TxTableEntry,
     tx_table_len_proof: SmallRangeProofType,
@@ -183,15 +207,113 @@ pub struct TxInclusionProof {
     tx_payload_proof: Option<SmallRangeProofType>, // `None` if the tx has zero length
 }
 
+/// The location and encoding of the namespace are not part of the proof: the verifier reads them
+/// from the committed namespace table.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CompressedTxProof {
+    #[serde(with = "base64_bytes")]
+    ns_payload_flat: Vec<u8>,
+    ns_proof: LargeRangeProofType,
+}
+
 impl TxInclusionProof {
     // TODO currently broken, fix in https://github.com/EspressoSystems/espresso-sequencer/issues/1010
     //
     // - We need to decide where to store VID params.
     // - Returns `None` if an error occurred.
     // - Use of `Result<(),()>` pattern to enable use of `?` for concise abort-on-failure.
+    // - The namespace of the transaction, its range in the payload and its encoding are taken
+    //   from `ns_table`, which must be the committed namespace table of the block, never from the
+    //   proof itself.
     #[allow(dead_code)] // TODO temporary
     #[allow(clippy::too_many_arguments)]
-    pub fn verify<V>(
+    pub fn verify(
+        &self,
+        tx: &Transaction,
+        tx_index: TxIndex,
+        ns_table: &NameSpaceTable<TxTableEntryWord>,
+        vid: &VidSchemeType,
+        vid_commit: &VidCommitment,
+        vid_common: &VidCommon,
+    ) -> Option<Result<(), ()>> {
+        if tx_index.ns_idx >= ns_table.len() {
+            return None; // error: index out of bounds
+        }
+        let (ns_id, ns_range) = ns_table.get_payload_range(
+            tx_index.ns_idx,
+            VidSchemeType::get_payload_byte_len(vid_common),
+        );
+        if ns_id != tx.namespace() {
+            return Some(Err(())); // the transaction is not in this namespace
+        }
+        match (self, ns_table.get_compression(tx_index.ns_idx)) {
+            (Self::Uncompressed(proof), NsCompression::None) => {
+                if proof.ns_range != ns_range {
+                    return Some(Err(())); // the proof is for a different namespace
+                }
+                proof.verify(tx, tx_index, vid, vid_commit, vid_common)
+            }
+            (Self::Compressed(proof), compression) if compression != NsCompression::None => proof
+                .verify(
+                    tx, tx_index, ns_table, ns_range, vid, vid_commit, vid_common,
+                ),
+            // The proof does not match the encoding of the namespace.
+            _ => Some(Err(())),
+        }
+    }
+}
+
+impl CompressedTxProof {
+    #[allow(clippy::too_many_arguments)]
+    fn verify<V>(
+        &self,
+        tx: &Transaction,
+        tx_index: TxIndex,
+        ns_table: &NameSpaceTable<TxTableEntryWord>,
+        ns_range: Range<usize>,
+        vid: &V,
+        vid_commit: &V::Commit,
+        vid_common: &V::Common,
+    ) -> Option<Result<(), ()>>
+    where
+        V: PayloadProver<LargeRangeProofType>,
+    {
+        V::is_consistent(vid_commit, vid_common).ok()?;
+
+        // Verify proof for the namespace bytes.
+        if vid
+            .payload_verify(
+                Statement {
+                    payload_subslice: &self.ns_payload_flat,
+                    range: ns_range,
+                    commit: vid_commit,
+                    common: vid_common,
+                },
+                &self.ns_proof,
+            )
+            .ok()?
+            .is_err()
+        {
+            return Some(Err(()));
+        }
+
+        // Find the transaction in the decoded namespace.
+        let ns_bytes = ns_table.decode_ns(tx_index.ns_idx, &self.ns_payload_flat)?;
+        let tx_table_len = TxTable::get_tx_table_len(&ns_bytes);
+        if tx_index.tx_idx >= tx_table_len {
+            return None; // error: index out of bounds
+        }
+        let tx_range = TxTable::get_payload_range(&ns_bytes, tx_index.tx_idx, tx_table_len);
+        if ns_bytes.get(tx_range)? != tx.payload() {
+            return Some(Err(()));
+        }
+
+        Some(Ok(()))
+    }
+}
+
+impl TxRangeProof {
+    fn verify<V>(
         &self,
         tx: &Transaction,
         tx_index: TxIndex,
@@ -322,7 +444,7 @@ pub(crate) fn gen_tx_proof_for_testing(
     tx_table_len_proof: SmallRangeProofType,
     payload_proof: SmallRangeProofType,
 ) -> TxInclusionProof {
-    TxInclusionProof {
+    TxInclusionProof::Uncompressed(TxRangeProof {
         ns_range,
         tx_table_len,
         tx_table_len_proof,
@@ -330,5 +452,5 @@ pub(crate) fn gen_tx_proof_for_testing(
         tx_table_range_end: TxTableEntry::from_usize(1),
         tx_table_range_proof: payload_proof,
         tx_payload_proof: None,
-    }
+    })
 }
@@ -1,9 +1,13 @@
-use crate::block::entry::TxTableEntry;
+use crate::block::entry::{TxTableEntry, TxTableEntryWord};
 use crate::block::payload::TableWordTraits;
+use crate::block::MAX_BLOCK_SIZE;
 use crate::{BlockBuildingSnafu, Error, NamespaceId};
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 use snafu::OptionExt;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::io::Read;
 use std::marker::PhantomData;
 use std::mem::size_of;
 use std::ops::Range;
@@ -35,6 +39,158 @@ impl<TableWord: TableWordTraits> Table<TableWord> for NameSpaceTable<TableWord>
     }
 }
 
+/// Version of the namespace table format.
+///
+/// The version is stored in the most significant byte of the first word of the namespace table,
+/// alongside the number of entries in the remaining bytes. Tables created before the format was
+/// versioned always have a zero in this byte, so they are interpreted as [`V0`](Self::V0).
+///
+/// Versioned tables change how existing blocks are read, so they are only recognized when the
+/// `compressed-namespaces` feature is enabled. Otherwise every table is [`V0`](Self::V0) and the
+/// whole first word is the number of entries, as it always was.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NsTableVersion {
+    /// Each entry is `(namespace id, end offset)` and every namespace is stored uncompressed.
+    V0,
+    /// Each entry is `(namespace id, end offset, flags, decoded length)`, where `flags` is an
+    /// [`NsCompression`] and `decoded length` is the size of the namespace once decoded.
+    V1,
+}
+
+impl NsTableVersion {
+    const SHIFT: usize = 8 * (TxTableEntry::byte_len() - 1);
+    const LEN_MASK: TxTableEntryWord = (1 << Self::SHIFT) - 1;
+
+    /// Number of words in each namespace table entry.
+    fn entry_words(&self) -> usize {
+        match self {
+            Self::V0 => 2,
+            Self::V1 => 4,
+        }
+    }
+
+    fn from_header(header: TxTableEntryWord) -> Option<Self> {
+        if !cfg!(feature = "compressed-namespaces") {
+            return Some(Self::V0);
+        }
+        match header >> Self::SHIFT {
+            0 => Some(Self::V0),
+            1 => Some(Self::V1),
+            _ => None,
+        }
+    }
+
+    /// The number of entries recorded in the header of a namespace table.
+    fn header_len(header: TxTableEntryWord) -> TxTableEntryWord {
+        if cfg!(feature = "compressed-namespaces") {
+            header & Self::LEN_MASK
+        } else {
+            header
+        }
+    }
+
+    fn to_header(self, len: usize) -> Option<TxTableEntry> {
+        let len = TxTableEntryWord::try_from(len).ok()?;
+        if len > Self::LEN_MASK {
+            return None;
+        }
+        let version = match self {
+            Self::V0 => 0,
+            Self::V1 => 1,
+        };
+        Some(TxTableEntry::from_bytes_array(
+            ((version << Self::SHIFT) | len).to_le_bytes(),
+        ))
+    }
+}
+
+/// How the bytes of a namespace are encoded in the block payload.
+///
+/// The bytes of a namespace, once decoded, always have the same layout: a tx table followed by
+/// the concatenated transaction payloads. Compression only changes how those bytes are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NsCompression {
+    /// The namespace bytes are stored as is.
+    #[default]
+    None,
+    /// The namespace bytes are compressed with zstd.
+    Zstd,
+    /// An unrecognized encoding. Namespaces with unrecognized encodings contain no transactions.
+    Unknown(TxTableEntryWord),
+}
+
+impl NsCompression {
+    /// Upper bound on the total decoded size of the compressed namespaces in a payload.
+    ///
+    /// Builders only compress namespaces up to this limit, and readers refuse to decode any
+    /// namespace in a payload whose table declares more, so that a malicious block cannot cause us
+    /// to allocate huge amounts of memory.
+    pub const MAX_DECOMPRESSED_PAYLOAD_LEN: usize = MAX_BLOCK_SIZE;
+
+    fn from_word(word: TxTableEntryWord) -> Self {
+        match word {
+            0 => Self::None,
+            1 => Self::Zstd,
+            other => Self::Unknown(other),
+        }
+    }
+
+    fn to_word(self) -> TxTableEntryWord {
+        match self {
+            Self::None => 0,
+            Self::Zstd => 1,
+            Self::Unknown(other) => other,
+        }
+    }
+
+    /// Encode the bytes of a namespace.
+    ///
+    /// Returns `None` if this is not a known encoding.
+    pub fn encode(&self, ns_bytes: Vec<u8>) -> Option<Vec<u8>> {
+        match self {
+            Self::None => Some(ns_bytes),
+            Self::Zstd => zstd::bulk::compress(&ns_bytes, 0).ok(),
+            Self::Unknown(_) => None,
+        }
+    }
+
+    /// Decode the bytes of a namespace as stored in a block payload.
+    ///
+    /// Compressed namespaces must decode to exactly `decoded_len` bytes. Returns `None` if the
+    /// bytes are not a valid encoding.
+    pub fn decode<'a>(&self, bytes: &'a [u8], decoded_len: usize) -> Option<Cow<'a, [u8]>> {
+        match self {
+            Self::None => Some(Cow::Borrowed(bytes)),
+            _ => self
+                .decode_prefix(bytes, decoded_len, decoded_len)
+                .map(Cow::Owned),
+        }
+    }
+
+    /// Decode a compressed namespace, keeping only the first `keep` decoded bytes.
+    ///
+    /// The whole namespace is decoded to check that it has exactly `decoded_len` bytes, but no
+    /// more than `keep` bytes are held in memory at once.
+    fn decode_prefix(&self, bytes: &[u8], decoded_len: usize, keep: usize) -> Option<Vec<u8>> {
+        let Self::Zstd = self else {
+            return None;
+        };
+        let mut decoder = zstd::stream::read::Decoder::new(bytes)
+            .ok()?
+            .take(decoded_len as u64 + 1);
+        let mut prefix = vec![];
+        (&mut decoder)
+            .take(std::cmp::min(keep, decoded_len) as u64)
+            .read_to_end(&mut prefix)
+            .ok()?;
+        let rest = std::io::copy(&mut decoder, &mut std::io::sink()).ok()?;
+        if prefix.len() as u64 + rest != decoded_len as u64 {
+            return None; // error: namespace does not have the declared length
+        }
+        Some(prefix)
+    }
+}
+
 #[derive(Clone, Debug, Derivative, Deserialize, Eq, Serialize, Default)]
 #[derivative(Hash, PartialEq)]
 pub struct NameSpaceTable<TableWord: TableWordTraits> {
@@ -68,10 +224,41 @@ impl<TableWord: TableWordTraits> NameSpaceTable<TableWord> {
         Ok(ns_table)
     }
 
+    /// Create a [`V1`](NsTableVersion::V1) namespace table, which records the encoding and decoded
+    /// length of each namespace.
+    pub fn from_namespace_entries(
+        namespace_entries: Vec<(NamespaceId, usize, NsCompression, usize)>,
+    ) -> Result<Self, Error> {
+        let mut ns_table = NameSpaceTable::from_bytes(
+            NsTableVersion::V1
+                .to_header(namespace_entries.len())
+                .context(BlockBuildingSnafu)?
+                .to_bytes(),
+        );
+        for (id, offset, compression, decoded_len) in namespace_entries {
+            ns_table.add_new_entry_ns_id(id)?;
+            ns_table.add_new_entry_payload_len(offset)?;
+            ns_table.bytes.extend(
+                TxTableEntry::from_bytes_array(compression.to_word().to_le_bytes()).to_bytes(),
+            );
+            ns_table.add_new_entry_payload_len(decoded_len)?;
+        }
+        Ok(ns_table)
+    }
+
     pub fn get_bytes(&self) -> &[u8] {
         &self.bytes
     }
 
+    /// The format version of this namespace table.
+    ///
+    /// Tables with an unrecognized version are treated as empty.
+    pub fn version(&self) -> Option<NsTableVersion> {
+        NsTableVersion::from_header(TxTableEntryWord::from_le_bytes(
+            self.get_table_len(0).to_bytes(),
+        ))
+    }
+
     /// Find `ns_id` and return its index into this namespace table.
     ///
     /// TODO return Result or Option? Want to avoid catch-all Error type :(
@@ -102,9 +289,13 @@ impl<TableWord: TableWordTraits> NameSpaceTable<TableWord> {
     // Parse the table length from the beginning of the namespace table.
     // Returned value is guaranteed to be no larger than the number of ns table entries that could possibly fit into `ns_table_bytes`.
     pub fn len(&self) -> usize {
-        let left = self.get_table_len(0).try_into().unwrap_or(0);
+        let Some(version) = self.version() else {
+            return 0;
+        };
+        let header = TxTableEntryWord::from_le_bytes(self.get_table_len(0).to_bytes());
+        let left = NsTableVersion::header_len(header).try_into().unwrap_or(0);
         let right = self.bytes.len().saturating_sub(TxTableEntry::byte_len())
-            / (2 * TxTableEntry::byte_len());
+            / (version.entry_words() * TxTableEntry::byte_len());
         std::cmp::min(left, right)
     }
 
@@ -117,9 +308,10 @@ impl<TableWord: TableWordTraits> NameSpaceTable<TableWord> {
     pub fn get_table_entry(&self, ns_index: usize) -> (NamespaceId, usize) {
         // get the range for ns_id bytes in ns table
         // ensure `range` is within range for ns_table_bytes
+        let entry_words = self.version().unwrap_or(NsTableVersion::V0).entry_words();
         let start = std::cmp::min(
             ns_index
-                .saturating_mul(2)
+                .saturating_mul(entry_words)
                 .saturating_add(1)
                 .saturating_mul(TxTableEntry::byte_len()),
             self.bytes.len(),
@@ -162,6 +354,144 @@ impl<TableWord: TableWordTraits> NameSpaceTable<TableWord> {
         (ns_id, ns_offset)
     }
 
+    /// The encoding of the bytes of the namespace at `ns_index`.
+    ///
+    /// Namespaces in a [`V0`](NsTableVersion::V0) table are never compressed.
+    pub fn get_compression(&self, ns_index: usize) -> NsCompression {
+        if self.version() != Some(NsTableVersion::V1) {
+            return NsCompression::None;
+        }
+        // The flags are the third word of the entry.
+        let offset = ns_index
+            .saturating_mul(NsTableVersion::V1.entry_words())
+            .saturating_add(3)
+            .saturating_mul(TxTableEntry::byte_len());
+        NsCompression::from_word(TxTableEntryWord::from_le_bytes(
+            self.get_table_len(offset).to_bytes(),
+        ))
+    }
+
+    /// The decoded length of the namespace at `ns_index`, as declared in the table.
+    ///
+    /// This is only meaningful for compressed namespaces.
+    pub fn get_decoded_len(&self, ns_index: usize) -> usize {
+        if self.version() != Some(NsTableVersion::V1) {
+            return 0;
+        }
+        // The decoded length is the fourth word of the entry.
+        let offset = ns_index
+            .saturating_mul(NsTableVersion::V1.entry_words())
+            .saturating_add(4)
+            .saturating_mul(TxTableEntry::byte_len());
+        usize::try_from(self.get_table_len(offset)).unwrap_or(usize::MAX)
+    }
+
+    /// The total decoded length of the compressed namespaces in this table.
+    pub fn decompressed_byte_len(&self) -> usize {
+        (0..self.len())
+            .filter(|&ns_index| self.get_compression(ns_index) != NsCompression::None)
+            .map(|ns_index| self.get_decoded_len(ns_index))
+            .fold(0, usize::saturating_add)
+    }
+
+    /// Decode `ns_bytes`, the bytes of the namespace at `ns_index` as stored in the block payload.
+    ///
+    /// Returns `None` if the bytes are not a valid encoding, or if the compressed namespaces in this
+    /// table would decode to more than [`NsCompression::MAX_DECOMPRESSED_PAYLOAD_LEN`] bytes. The
+    /// limit is checked against the table before anything is decoded.
+    pub fn decode_ns<'a>(&self, ns_index: usize, ns_bytes: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        let compression = self.get_compression(ns_index);
+        if compression != NsCompression::None
+            && self.decompressed_byte_len() > NsCompression::MAX_DECOMPRESSED_PAYLOAD_LEN
+        {
+            return None; // error: payload decompresses to too many bytes
+        }
+        compression.decode(ns_bytes, self.get_decoded_len(ns_index))
+    }
+
+    /// The number of entries in the tx table of the namespace at `ns_index`.
+    ///
+    /// Like [`TxTable::get_tx_table_len`], but `ns_bytes` are the bytes of the namespace as stored
+    /// in the block payload. Compressed namespaces are checked, but only their tx table length is
+    /// kept, so counting transactions does not hold whole namespaces in memory.
+    pub fn get_tx_table_len(&self, ns_index: usize, ns_bytes: &[u8]) -> usize {
+        let compression = self.get_compression(ns_index);
+        if compression == NsCompression::None {
+            return TxTable::get_tx_table_len(ns_bytes);
+        }
+        if self.decompressed_byte_len() > NsCompression::MAX_DECOMPRESSED_PAYLOAD_LEN {
+            return 0; // error: payload decompresses to too many bytes
+        }
+        let decoded_len = self.get_decoded_len(ns_index);
+        let Some(prefix) =
+            compression.decode_prefix(ns_bytes, decoded_len, TxTableEntry::byte_len())
+        else {
+            return 0;
+        };
+        std::cmp::min(
+            TxTable::get_len(&prefix, 0).try_into().unwrap_or(0),
+            decoded_len.saturating_sub(TxTableEntry::byte_len()) / TxTableEntry::byte_len(),
+        )
+    }
+
+    /// Check that this namespace table is well formed for a block payload of the given length.
+    ///
+    /// Readers tolerate malformed tables, treating bad entries as empty namespaces, so this is not
+    /// required for a block to be valid. However, a well-behaved builder never produces a table
+    /// that fails this check:
+    /// * the table has a known version and its length matches the number of entries
+    /// * namespace IDs are unique
+    /// * namespace offsets are non-decreasing, and the last one is the end of the payload
+    /// * every namespace has a known encoding
+    pub fn validate(&self, block_payload_byte_len: usize) -> anyhow::Result<()> {
+        // A table with no bytes at all is treated as an empty table.
+        if self.bytes.is_empty() {
+            anyhow::ensure!(
+                block_payload_byte_len == 0,
+                "empty namespace table for non-empty payload of {block_payload_byte_len} bytes"
+            );
+            return Ok(());
+        }
+
+        let header = TxTableEntryWord::from_le_bytes(self.get_table_len(0).to_bytes());
+        let version = self.version().ok_or_else(|| {
+            anyhow::anyhow!("unknown namespace table version in header {header:#x}")
+        })?;
+        let expected_byte_len = (1 + self.len() * version.entry_words()) * TxTableEntry::byte_len();
+        anyhow::ensure!(
+            self.bytes.len() == expected_byte_len,
+            "namespace table has {} bytes, expected {expected_byte_len} for {} entries",
+            self.bytes.len(),
+            self.len()
+        );
+        anyhow::ensure!(
+            usize::try_from(NsTableVersion::header_len(header)).ok() == Some(self.len()),
+            "namespace table length does not match number of entries ({})",
+            self.len()
+        );
+
+        let mut ids = HashSet::new();
+        let mut prev_offset = 0;
+        for ns_index in 0..self.len() {
+            let (ns_id, offset) = self.get_table_entry(ns_index);
+            anyhow::ensure!(ids.insert(ns_id), "duplicate namespace {ns_id}");
+            anyhow::ensure!(
+                offset >= prev_offset,
+                "namespace {ns_id} has offset {offset} before previous offset {prev_offset}"
+            );
+            anyhow::ensure!(
+                !matches!(self.get_compression(ns_index), NsCompression::Unknown(_)),
+                "namespace {ns_id} has unknown encoding"
+            );
+            prev_offset = offset;
+        }
+        anyhow::ensure!(
+            prev_offset == block_payload_byte_len,
+            "namespaces end at {prev_offset}, but payload has {block_payload_byte_len} bytes"
+        );
+        Ok(())
+    }
+
     /// Like `tx_payload_range` except for namespaces.
     /// Returns the ns id and the ns byte range in the block payload bytes.
     ///
@@ -1,7 +1,7 @@
 use std::ops::Range;
 
 use crate::block::payload::{Payload, TableWordTraits};
-use crate::block::tables::{NameSpaceTable, TxTable};
+use crate::block::tables::NameSpaceTable;
 use serde::{Deserialize, Serialize};
 
 /// TODO do we really need `PartialOrd`, `Ord` here?
@@ -50,11 +50,9 @@ impl<'a, TableWord: TableWordTraits> Iterator for TxIterator<'a, TableWord> {
             })
         } else {
             // move to the next name space
-            let payload = &self.block_payload.raw_payload;
             for ns_idx in self.ns_iter.by_ref() {
                 self.ns_idx = ns_idx;
-                let ns_range = self.ns_table.get_payload_range(ns_idx, payload.len()).1;
-                let tx_table_len = TxTable::get_tx_table_len(&payload[ns_range]);
+                let tx_table_len = self.block_payload.ns_tx_table_len(self.ns_table, ns_idx);
                 self.tx_iter = 0..tx_table_len;
                 if let Some(tx_idx) = self.tx_iter.next() {
                     return Some(TxIndex { ns_idx, tx_idx });
@@ -1,17 +1,30 @@
 use crate::{
-    api::endpoints::{AccountQueryData, BlocksFrontier},
+    api::endpoints::{AccountQueryData, BlocksFrontier, StateSnapshot},
     state::{BlockMerkleTree, FeeAccount, FeeMerkleCommitment},
+    SeqTypes,
 };
 use async_trait::async_trait;
+use hotshot_query_service::availability::LeafQueryData;
 use hotshot_types::{data::ViewNumber, traits::node_implementation::ConsensusTime as _};
 use jf_primitives::merkle_tree::ForgetableMerkleTreeScheme;
 use serde::de::DeserializeOwned;
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use surf_disco::Request;
 use tide_disco::error::ServerError;
 use url::Url;
 use versioned_binary_serialization::version::StaticVersionType;
 
+/// How many blocks a state snapshot may be behind the block height of the trusted query service.
+///
+/// Without a bound, a peer could serve a snapshot of a very old state which still verifies.
+pub const MAX_SNAPSHOT_LAG: u64 = 50;
+
 // This newtype is probably not worth having. It's only used to be able to log
 // URLs before doing requests.
 #[derive(Debug, Clone)]
@@ -43,12 +56,34 @@ pub trait StateCatchup: Send + Sync + std::fmt::Debug {
     ) -> Vec<AccountQueryData>;
 
     async fn remember_blocks_merkle_tree(&self, view: ViewNumber, mt: &mut BlockMerkleTree);
+
+    /// Whether this node is currently waiting to fetch state from its peers.
+    fn in_progress(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct StatePeers<Ver: StaticVersionType> {
     clients: Vec<Client<ServerError, Ver>>,
     interval: Duration,
+    in_progress: Arc<AtomicUsize>,
+}
+
+/// Marks a catchup request as in progress until dropped.
+struct InProgress<'a>(&'a AtomicUsize);
+
+impl<'a> InProgress<'a> {
+    fn new(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl<'a> Drop for InProgress<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl<Ver: StaticVersionType> StatePeers<Ver> {
@@ -60,6 +95,7 @@ impl<Ver: StaticVersionType> StatePeers<Ver> {
         Self {
             clients: urls.into_iter().map(Client::new).collect(),
             interval: Duration::from_secs(1),
+            in_progress: Default::default(),
         }
     }
 
@@ -99,6 +135,66 @@ impl<Ver: StaticVersionType> StatePeers<Ver> {
             async_std::task::sleep(self.interval).await;
         }
     }
+
+    /// Fetch a snapshot of the decided state from a peer.
+    ///
+    /// The leaf of the snapshot is checked against the leaf at the same height from the query
+    /// service at `trusted`, and the state is checked against the roots in its header. The snapshot
+    /// must also be recent: at most [`MAX_SNAPSHOT_LAG`] blocks behind the block height of the
+    /// trusted query service. This retries until it finds a snapshot which verifies, and returns it
+    /// with the QC for its leaf attached.
+    pub async fn fetch_snapshot(&self, trusted: Url) -> StateSnapshot {
+        if self.clients.is_empty() {
+            panic!("No peers to fetch snapshot from");
+        }
+        let _guard = InProgress::new(&self.in_progress);
+        let trusted = Client::<ServerError, Ver>::new(trusted);
+        loop {
+            for client in self.clients.iter() {
+                tracing::info!("Fetching state snapshot from {}", client.url);
+                let snapshot = match client.get::<StateSnapshot>("catchup/snapshot").send().await {
+                    Ok(snapshot) => snapshot,
+                    Err(err) => {
+                        tracing::warn!("Error fetching snapshot from peer: {}", err);
+                        continue;
+                    }
+                };
+                let height = snapshot.leaf.get_height();
+                let block_height = match trusted.get::<u64>("status/block-height").send().await {
+                    Ok(block_height) => block_height,
+                    Err(err) => {
+                        tracing::warn!("Error fetching trusted block height: {}", err);
+                        continue;
+                    }
+                };
+                if height + MAX_SNAPSHOT_LAG < block_height {
+                    tracing::warn!(
+                        "Snapshot {height} from {} is too old (block height {block_height})",
+                        client.url
+                    );
+                    continue;
+                }
+                tracing::info!("Fetching trusted leaf {height} from {}", trusted.url);
+                let leaf = match trusted
+                    .get::<LeafQueryData<SeqTypes>>(&format!("availability/leaf/{height}"))
+                    .send()
+                    .await
+                {
+                    Ok(leaf) => leaf,
+                    Err(err) => {
+                        tracing::warn!("Error fetching trusted leaf {height}: {}", err);
+                        continue;
+                    }
+                };
+                match snapshot.verify_trusted(&leaf) {
+                    Ok(snapshot) => return snapshot,
+                    Err(err) => tracing::warn!("Error verifying snapshot: {err:#}"),
+                }
+            }
+            tracing::warn!("Could not fetch snapshot from any peer, retrying");
+            async_std::task::sleep(self.interval).await;
+        }
+    }
 }
 
 #[async_trait]
@@ -109,6 +205,7 @@ impl<Ver: StaticVersionType> StateCatchup for StatePeers<Ver> {
         fee_merkle_tree_root: FeeMerkleCommitment,
         accounts: Vec<FeeAccount>,
     ) -> Vec<AccountQueryData> {
+        let _guard = InProgress::new(&self.in_progress);
         let mut ret = vec![];
         for account in accounts {
             tracing::info!("Fetching account {account:?} for view {view:?}");
@@ -124,6 +221,7 @@ impl<Ver: StaticVersionType> StateCatchup for StatePeers<Ver> {
         if self.clients.is_empty() {
             panic!("No peers to fetch frontier from");
         }
+        let _guard = InProgress::new(&self.in_progress);
         loop {
             for client in self.clients.iter() {
                 tracing::info!("Fetching frontier for view {view:?} from {}", client.url);
@@ -151,6 +249,10 @@ impl<Ver: StaticVersionType> StateCatchup for StatePeers<Ver> {
             async_std::task::sleep(self.interval).await;
         }
     }
+
+    fn in_progress(&self) -> bool {
+        self.in_progress.load(Ordering::Relaxed) > 0
+    }
 }
 
 #[async_trait]
@@ -169,6 +271,10 @@ impl<T: StateCatchup + ?Sized> StateCatchup for Box<T> {
     async fn remember_blocks_merkle_tree(&self, view: ViewNumber, mt: &mut BlockMerkleTree) {
         (**self).remember_blocks_merkle_tree(view, mt).await
     }
+
+    fn in_progress(&self) -> bool {
+        (**self).in_progress()
+    }
 }
 
 #[async_trait]
@@ -187,6 +293,10 @@ impl<T: StateCatchup + ?Sized> StateCatchup for Arc<T> {
     async fn remember_blocks_merkle_tree(&self, view: ViewNumber, mt: &mut BlockMerkleTree) {
         (**self).remember_blocks_merkle_tree(view, mt).await
     }
+
+    fn in_progress(&self) -> bool {
+        (**self).in_progress()
+    }
 }
 
 #[cfg(any(test, feature = "testing"))]
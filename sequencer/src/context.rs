@@ -1,15 +1,17 @@
+use anyhow::bail;
+use async_broadcast::{broadcast, InactiveReceiver, Sender};
 use async_std::{
     sync::Arc,
     task::{spawn, JoinHandle},
 };
 use derivative::Derivative;
 use futures::{
-    future::{join_all, Future},
+    future::{join_all, select_all, BoxFuture, Future, FutureExt},
     stream::{Stream, StreamExt},
 };
 use hotshot::{
     traits::election::static_committee::GeneralStaticCommittee,
-    types::{Event, SystemContextHandle},
+    types::{Event, EventType, SystemContextHandle},
     Memberships, Networks, SystemContext,
 };
 use hotshot_orchestrator::client::OrchestratorClient;
@@ -20,12 +22,21 @@ use hotshot_types::{
     traits::{election::Membership, metrics::Metrics},
     HotShotConfig,
 };
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 use url::Url;
 use versioned_binary_serialization::version::StaticVersionType;
 
 use crate::{
-    network, persistence::SequencerPersistence, state_signature::StateSigner,
+    api::endpoints::StateSnapshot,
+    catchup::StateCatchup,
+    health::NodeHealth,
+    network,
+    persistence::{PersistentStorage, SequencerPersistence},
+    state_signature::StateSigner,
     static_stake_table_commitment, ElectionConfig, Node, NodeState, PubKey, SeqTypes, Transaction,
 };
 
@@ -34,6 +45,37 @@ use hotshot_events_service::events_source::{EventConsumer, EventsStreamer};
 /// The consensus handle
 pub type Consensus<N> = SystemContextHandle<SeqTypes, Node<N>>;
 
+/// The number of consensus events buffered for each consumer of [`SequencerContext::get_event_stream`].
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// An action to run when the node shuts down, to make sure no data is lost.
+type Flush = Box<dyn FnOnce() -> BoxFuture<'static, anyhow::Result<()>> + Send + Sync>;
+
+/// A switch controlling whether a node accepts new transactions.
+///
+/// Clones of a switch share the same state, so the switch can be handed out to components (such as
+/// the HTTP API) which need to check or change it.
+#[derive(Clone, Debug)]
+pub struct SubmitSwitch(Arc<AtomicBool>);
+
+impl Default for SubmitSwitch {
+    fn default() -> Self {
+        Self(Arc::new(AtomicBool::new(true)))
+    }
+}
+
+impl SubmitSwitch {
+    /// Whether new transactions are currently accepted.
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Start or stop accepting new transactions.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::SeqCst);
+    }
+}
+
 /// The sequencer context contains a consensus handle and other sequencer specific information.
 #[derive(Derivative)]
 #[derivative(Debug(bound = ""))]
@@ -49,16 +91,34 @@ pub struct SequencerContext<N: network::Type, Ver: StaticVersionType + 'static>
     /// Context for generating state signatures.
     state_signer: Arc<StateSigner<Ver>>,
 
+    /// Health indicators for this node.
+    health: Arc<NodeHealth<Ver>>,
+
+    /// Source of state which is missing from this node.
+    #[derivative(Debug = "ignore")]
+    catchup: Arc<dyn StateCatchup>,
+
     /// An orchestrator to wait for before starting consensus.
     #[derivative(Debug = "ignore")]
     wait_for_orchestrator: Option<Arc<OrchestratorClient>>,
 
+    /// Whether this node is accepting new transactions.
+    submit_switch: SubmitSwitch,
+
     /// Background tasks to shut down when the node is dropped.
     tasks: Vec<(String, JoinHandle<()>)>,
 
+    /// Actions to run during a graceful shutdown.
+    #[derivative(Debug = "ignore")]
+    flushes: Vec<(String, Flush)>,
+
     /// events streamer to stream hotshot events to external clients
     events_streamer: Arc<RwLock<EventsStreamer<SeqTypes>>>,
 
+    /// Consensus events, after they have been handled by the main event handler.
+    #[derivative(Debug = "ignore")]
+    events: InactiveReceiver<Event<SeqTypes>>,
+
     detached: bool,
 }
 
@@ -67,15 +127,27 @@ impl<N: network::Type, Ver: StaticVersionType + 'static> SequencerContext<N, Ver
     pub async fn init(
         config: HotShotConfig<PubKey, ElectionConfig>,
         instance_state: NodeState,
-        persistence: impl SequencerPersistence,
+        mut persistence: impl SequencerPersistence,
+        snapshot: Option<StateSnapshot>,
         networks: Networks<SeqTypes, Node<N>>,
         state_relay_server: Option<Url>,
         metrics: &dyn Metrics,
         node_id: u64,
         _: Ver,
     ) -> anyhow::Result<Self> {
-        // Load saved consensus state from storage.
-        let initializer = persistence.load_consensus_state(instance_state).await?;
+        // Keep handles to the components whose health we want to monitor.
+        let l1_client = instance_state.l1_client.clone();
+        let catchup = instance_state.peers.clone();
+
+        // Load saved consensus state from storage, or start from a snapshot if we were given one.
+        let initializer = match snapshot {
+            Some(snapshot) => {
+                persistence
+                    .bootstrap_consensus_state(instance_state, snapshot)
+                    .await?
+            }
+            None => persistence.load_consensus_state(instance_state).await?,
+        };
 
         let election_config = GeneralStaticCommittee::<SeqTypes, PubKey>::default_election_config(
             config.num_nodes_with_stake.get() as u64,
@@ -92,7 +164,8 @@ impl<N: network::Type, Ver: StaticVersionType + 'static> SequencerContext<N, Ver
             vid_membership: membership.clone(),
             view_sync_membership: membership,
         };
-        let da_storage = Default::default();
+        // HotShot and the main event handler share the same persistence.
+        let storage = PersistentStorage::new(persistence);
 
         let stake_table_commit =
             static_stake_table_commitment(&config.known_nodes_with_stake, STAKE_TABLE_CAPACITY);
@@ -112,7 +185,7 @@ impl<N: network::Type, Ver: StaticVersionType + 'static> SequencerContext<N, Ver
             networks,
             initializer,
             ConsensusMetricsValue::new(metrics),
-            da_storage,
+            storage.clone(),
         )
         .await?
         .0;
@@ -121,12 +194,21 @@ impl<N: network::Type, Ver: StaticVersionType + 'static> SequencerContext<N, Ver
         if let Some(url) = state_relay_server {
             state_signer = state_signer.with_relay_server(url);
         }
+        let state_signer = Arc::new(state_signer);
+        let health = Arc::new(NodeHealth::new(
+            storage.clone(),
+            l1_client,
+            catchup.clone(),
+            state_signer.clone(),
+        ));
 
         Ok(Self::new(
             handle,
-            persistence,
+            storage,
             node_id,
             state_signer,
+            health,
+            catchup,
             event_streamer,
         ))
     }
@@ -134,32 +216,51 @@ impl<N: network::Type, Ver: StaticVersionType + 'static> SequencerContext<N, Ver
     /// Constructor
     fn new(
         handle: Consensus<N>,
-        persistence: impl SequencerPersistence,
+        storage: PersistentStorage,
         node_index: u64,
-        state_signer: StateSigner<Ver>,
+        state_signer: Arc<StateSigner<Ver>>,
+        health: Arc<NodeHealth<Ver>>,
+        catchup: Arc<dyn StateCatchup>,
         event_streamer: Arc<RwLock<EventsStreamer<SeqTypes>>>,
     ) -> Self {
         let events = handle.get_event_stream();
+        let (mut sender, receiver) = broadcast(EVENT_CHANNEL_CAPACITY);
+        sender.set_await_active(false);
 
         let mut ctx = Self {
             handle,
             node_index,
-            state_signer: Arc::new(state_signer),
+            state_signer,
+            health,
+            catchup,
+            submit_switch: Default::default(),
             tasks: vec![],
+            flushes: vec![],
             detached: false,
             wait_for_orchestrator: None,
             events_streamer: event_streamer.clone(),
+            events: receiver.deactivate(),
         };
         ctx.spawn(
             "main event handler",
             handle_events(
                 events,
-                persistence,
+                sender,
+                storage.clone(),
                 ctx.state_signer.clone(),
+                ctx.health.clone(),
                 Some(event_streamer.clone()),
             ),
         );
 
+        ctx.on_shutdown("persistence", move || {
+            async move { storage.persistence().write().await.flush().await }.boxed()
+        });
+        let state_signer = ctx.state_signer.clone();
+        ctx.on_shutdown("state signatures", move || {
+            async move { state_signer.post_pending().await }.boxed()
+        });
+
         ctx
     }
 
@@ -174,12 +275,33 @@ impl<N: network::Type, Ver: StaticVersionType + 'static> SequencerContext<N, Ver
         self.state_signer.clone()
     }
 
+    /// Return a reference to the health indicators for this node.
+    pub fn health(&self) -> Arc<NodeHealth<Ver>> {
+        self.health.clone()
+    }
+
+    /// Return a reference to the source of missing state for this node.
+    pub fn catchup(&self) -> Arc<dyn StateCatchup> {
+        self.catchup.clone()
+    }
+
     /// Stream consensus events.
+    ///
+    /// Events are delivered after the node has persisted them, and decide events include any block
+    /// payloads and VID shares which were restored from storage after a restart.
     pub fn get_event_stream(&self) -> impl Stream<Item = Event<SeqTypes>> {
-        self.handle.get_event_stream()
+        self.events.activate_cloned()
+    }
+
+    /// Return a switch controlling whether this node accepts new transactions.
+    pub fn submit_switch(&self) -> SubmitSwitch {
+        self.submit_switch.clone()
     }
 
     pub async fn submit_transaction(&self, tx: Transaction) -> anyhow::Result<()> {
+        if !self.submit_switch.is_enabled() {
+            bail!("this node is not accepting transactions");
+        }
         self.handle.submit_transaction(tx).await?;
         Ok(())
     }
@@ -226,16 +348,86 @@ impl<N: network::Type, Ver: StaticVersionType + 'static> SequencerContext<N, Ver
         self.tasks.push((name, task));
     }
 
+    /// Register an action to run during a [graceful shutdown](Self::shut_down_gracefully).
+    ///
+    /// Actions run after consensus has stopped and background tasks have been cancelled, so that
+    /// nothing is still writing to the state being flushed. Like background tasks, they run in the
+    /// reverse order that they were registered: components registered later, such as the API, are
+    /// built on top of those registered earlier, such as storage, so they are drained first.
+    pub fn on_shutdown(
+        &mut self,
+        name: impl Display,
+        flush: impl FnOnce() -> BoxFuture<'static, anyhow::Result<()>> + Send + Sync + 'static,
+    ) {
+        self.flushes.push((name.to_string(), Box::new(flush)));
+    }
+
     /// Stop participating in consensus.
     pub async fn shut_down(&mut self) {
         tracing::info!("shutting down SequencerContext");
         self.handle.shut_down().await;
+        self.cancel_tasks().await;
+    }
+
+    /// Stop participating in consensus without losing any data.
+    ///
+    /// This stops accepting new transactions and gives consensus up to `timeout` to finish the
+    /// current view. Once consensus has stopped, background tasks are cancelled as in
+    /// [`shut_down`](Self::shut_down), and finally each action registered with
+    /// [`on_shutdown`](Self::on_shutdown) is given up to `timeout` to complete.
+    pub async fn shut_down_gracefully(&mut self, timeout: Duration) {
+        tracing::info!("shutting down SequencerContext gracefully");
+        self.submit_switch.set_enabled(false);
+
+        let mut events = self.handle.get_event_stream();
+        let view_finished = async move {
+            while let Some(event) = events.next().await {
+                if matches!(event.event, EventType::ViewFinished { .. }) {
+                    break;
+                }
+            }
+        };
+        if async_std::future::timeout(timeout, view_finished)
+            .await
+            .is_err()
+        {
+            tracing::warn!("timed out waiting for the current view to finish");
+        }
+        self.handle.shut_down().await;
+        self.cancel_tasks().await;
+
+        for (name, flush) in std::mem::take(&mut self.flushes).into_iter().rev() {
+            tracing::info!(name, "flushing");
+            match async_std::future::timeout(timeout, flush()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => tracing::error!(name, "flush failed: {err:#}"),
+                Err(_) => tracing::error!(name, "flush timed out"),
+            }
+        }
+    }
+
+    async fn cancel_tasks(&mut self) {
         for (name, task) in self.tasks.drain(..).rev() {
             tracing::info!(name, "cancelling background task");
             task.cancel().await;
         }
     }
 
+    /// Wait for one of the background tasks attached to this context to exit.
+    ///
+    /// Background tasks run until the context is shut down, so this only completes early if one of
+    /// them fails, in which case the node can no longer work properly. Returns the name of the task
+    /// which exited, or `None` if there are no background tasks.
+    pub async fn task_exited(&mut self) -> Option<String> {
+        if self.tasks.is_empty() {
+            return None;
+        }
+        let (_, index, _) = select_all(self.tasks.iter_mut().map(|(_, task)| task)).await;
+        // The task has already finished, so it must not be cancelled during shutdown.
+        let (name, _) = self.tasks.remove(index);
+        Some(name)
+    }
+
     /// Wait for consensus to complete.
     ///
     /// Under normal conditions, this function will block forever, which is a convenient way of
@@ -261,19 +453,30 @@ impl<N: network::Type, Ver: StaticVersionType + 'static> Drop for SequencerConte
 
 async fn handle_events<Ver: StaticVersionType>(
     mut events: impl Stream<Item = Event<SeqTypes>> + Unpin,
-    mut persistence: impl SequencerPersistence,
+    sender: Sender<Event<SeqTypes>>,
+    storage: PersistentStorage,
     state_signer: Arc<StateSigner<Ver>>,
+    health: Arc<NodeHealth<Ver>>,
     events_streamer: Option<Arc<RwLock<EventsStreamer<SeqTypes>>>>,
 ) {
-    while let Some(event) = events.next().await {
+    while let Some(mut event) = events.next().await {
         tracing::debug!(?event, "consensus event");
 
+        // Restore data lost by consensus in a restart, before the saved copy is garbage collected.
+        storage.restore_decided_data(&mut event).await;
+
         // Store latest consensus state.
-        persistence.handle_event(&event).await;
+        storage.handle_event(&event).await;
+
+        // Track the progress of consensus.
+        health.handle_event(&event).await;
 
         // Generate state signature.
         state_signer.handle_event(&event).await;
 
+        // Pass the event on to the rest of the node.
+        sender.broadcast(event.clone()).await.ok();
+
         // Send the event via the event streaming service
         if let Some(events_streamer) = events_streamer.as_ref() {
             events_streamer.write().await.handle_event(event).await;
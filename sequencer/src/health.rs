@@ -0,0 +1,290 @@
+//! Node health tracking.
+//!
+//! [`NodeHealth`] collects indicators of whether a node is keeping up with the network from the
+//! various components which make up the node: consensus decides, the L1 client, state catchup,
+//! persistent storage and the state relay server. A [`HealthReport`] is a snapshot of these
+//! indicators, which can be checked against [`HealthThresholds`] to decide whether the node is
+//! ready to serve traffic, or alive at all.
+
+use crate::{
+    catchup::StateCatchup, l1_client::L1Client, persistence::PersistentStorage,
+    state_signature::StateSigner, SeqTypes,
+};
+use async_std::{
+    future::timeout,
+    sync::{Arc, Mutex, RwLock},
+};
+use derivative::Derivative;
+use hotshot::types::{Event, EventType};
+use hotshot_types::{event::LeafInfo, traits::node_implementation::ConsensusTime};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use versioned_binary_serialization::version::StaticVersionType;
+
+/// How long a health check waits for the L1 to respond before reporting it unreachable.
+const L1_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The minimum time between L1 probes made on behalf of health checks.
+///
+/// Health checks may be polled frequently, by many clients at once; this keeps them from turning
+/// into a stream of requests to the L1 provider while the L1 client is otherwise idle.
+const L1_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Health indicators shared between the components of a running node.
+#[derive(Derivative)]
+#[derivative(Debug(bound = ""))]
+pub struct NodeHealth<Ver: StaticVersionType> {
+    last_decide: RwLock<Option<LastDecide>>,
+    storage: PersistentStorage,
+    l1_client: L1Client,
+    /// When a health check last probed the L1.
+    last_l1_probe: Mutex<Option<Instant>>,
+    #[derivative(Debug = "ignore")]
+    catchup: Arc<dyn StateCatchup>,
+    state_signer: Arc<StateSigner<Ver>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct LastDecide {
+    view: u64,
+    height: u64,
+    time: Instant,
+}
+
+impl<Ver: StaticVersionType> NodeHealth<Ver> {
+    pub(crate) fn new(
+        storage: PersistentStorage,
+        l1_client: L1Client,
+        catchup: Arc<dyn StateCatchup>,
+        state_signer: Arc<StateSigner<Ver>>,
+    ) -> Self {
+        Self {
+            last_decide: Default::default(),
+            storage,
+            l1_client,
+            last_l1_probe: Default::default(),
+            catchup,
+            state_signer,
+        }
+    }
+
+    pub(crate) async fn handle_event(&self, event: &Event<SeqTypes>) {
+        let EventType::Decide { leaf_chain, .. } = &event.event else {
+            return;
+        };
+        let Some(LeafInfo { leaf, .. }) = leaf_chain.first() else {
+            return;
+        };
+        *self.last_decide.write().await = Some(LastDecide {
+            view: leaf.get_view_number().get_u64(),
+            height: leaf.get_height(),
+            time: Instant::now(),
+        });
+    }
+
+    /// Take a snapshot of the health of the node.
+    ///
+    /// If the L1 client has not heard from the L1 within `max_l1_age` (which can happen simply
+    /// because this node has not needed any L1 data recently) the L1 is probed to get a fresh
+    /// reading. The probe is bounded by a timeout, and is made at most once per
+    /// [`L1_PROBE_INTERVAL`], no matter how many reports are requested.
+    pub async fn report(&self, max_l1_age: Duration) -> HealthReport {
+        let last_decide = *self.last_decide.read().await;
+
+        let mut l1 = self.l1_client.last_head().await;
+        if l1.map_or(true, |(_, time)| time.elapsed() > max_l1_age) {
+            self.probe_l1().await;
+            l1 = self.l1_client.last_head().await;
+        }
+
+        HealthReport {
+            last_decided_view: last_decide.map(|decide| decide.view),
+            last_decided_height: last_decide.map(|decide| decide.height),
+            time_since_last_decide: last_decide.map(|decide| decide.time.elapsed()),
+            l1_head: l1.map(|(head, _)| head),
+            time_since_l1_update: l1.map(|(_, time)| time.elapsed()),
+            catchup_in_progress: self.catchup.in_progress(),
+            persistence_write_failures: self.storage.write_failures(),
+            time_since_persistence_write_failure: self
+                .storage
+                .last_write_failure()
+                .map(|time| time.elapsed()),
+            relay_server_reachable: self.state_signer.relay_server_reachable().await,
+        }
+    }
+
+    async fn probe_l1(&self) {
+        // Hold the lock for the duration of the probe, so that concurrent reports wait for this
+        // probe instead of starting their own.
+        let mut last_probe = self.last_l1_probe.lock().await;
+        if last_probe.map_or(false, |time| time.elapsed() < L1_PROBE_INTERVAL) {
+            return;
+        }
+        *last_probe = Some(Instant::now());
+        match timeout(L1_PROBE_TIMEOUT, self.l1_client.probe()).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => tracing::warn!("health check failed to reach L1: {err}"),
+            Err(_) => tracing::warn!("health check timed out waiting for L1"),
+        }
+    }
+}
+
+/// A snapshot of the health of a node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// The view number of the most recently decided leaf, if any.
+    pub last_decided_view: Option<u64>,
+    /// The block height of the most recently decided leaf, if any.
+    pub last_decided_height: Option<u64>,
+    /// How long ago this node last saw a decide.
+    pub time_since_last_decide: Option<Duration>,
+    /// The most recent L1 block number seen by this node.
+    pub l1_head: Option<u64>,
+    /// How long ago this node last heard from the L1.
+    pub time_since_l1_update: Option<Duration>,
+    /// Whether this node is currently fetching missing state from its peers.
+    pub catchup_in_progress: bool,
+    /// The number of writes to persistent storage which have failed since this node started.
+    pub persistence_write_failures: u64,
+    /// How long ago a write to persistent storage last failed.
+    pub time_since_persistence_write_failure: Option<Duration>,
+    /// Whether the state relay server was reachable the last time this node posted to it.
+    ///
+    /// This is `None` if there is no relay server or this node has not yet posted to it.
+    pub relay_server_reachable: Option<bool>,
+}
+
+/// Limits beyond which a node is considered unhealthy.
+#[derive(Clone, Copy, Debug)]
+pub struct HealthThresholds {
+    /// The longest a node may go without seeing a decide.
+    pub max_decide_age: Duration,
+    /// The longest a node may go without hearing from the L1.
+    pub max_l1_age: Duration,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            max_decide_age: Duration::from_secs(300),
+            max_l1_age: Duration::from_secs(120),
+        }
+    }
+}
+
+impl HealthReport {
+    /// Reasons the node should not be considered alive.
+    ///
+    /// A node is alive unless it has stopped making progress: it has decided before, but not
+    /// within `max_decide_age`. A node which has not yet decided anything may just be waiting for
+    /// the rest of the network to start, so it is not considered dead.
+    pub fn liveness_problems(&self, thresholds: &HealthThresholds) -> Vec<String> {
+        match self.time_since_last_decide {
+            Some(age) if age > thresholds.max_decide_age => {
+                vec![format!("no decide in {age:?}")]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Reasons the node should not be considered ready.
+    ///
+    /// A node is ready when it is caught up with the network and all of its dependencies are
+    /// reachable.
+    pub fn readiness_problems(&self, thresholds: &HealthThresholds) -> Vec<String> {
+        let mut problems = vec![];
+        match self.time_since_last_decide {
+            None => problems.push("no decide yet".into()),
+            Some(age) if age > thresholds.max_decide_age => {
+                problems.push(format!("no decide in {age:?}"))
+            }
+            _ => {}
+        }
+        match self.time_since_l1_update {
+            None => problems.push("L1 unreachable".into()),
+            Some(age) if age > thresholds.max_l1_age => {
+                problems.push(format!("no L1 update in {age:?}"))
+            }
+            _ => {}
+        }
+        if self.catchup_in_progress {
+            problems.push("catchup in progress".into());
+        }
+        // Consider storage healthy again once the node has gone a full decide window without a
+        // failed write.
+        if let Some(age) = self.time_since_persistence_write_failure {
+            if age <= thresholds.max_decide_age {
+                problems.push(format!("persistence write failed {age:?} ago"));
+            }
+        }
+        if self.relay_server_reachable == Some(false) {
+            problems.push("state relay server unreachable".into());
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn healthy() -> HealthReport {
+        HealthReport {
+            last_decided_view: Some(10),
+            last_decided_height: Some(5),
+            time_since_last_decide: Some(Duration::from_secs(1)),
+            l1_head: Some(100),
+            time_since_l1_update: Some(Duration::from_secs(1)),
+            catchup_in_progress: false,
+            persistence_write_failures: 0,
+            time_since_persistence_write_failure: None,
+            relay_server_reachable: Some(true),
+        }
+    }
+
+    #[test]
+    fn test_health_thresholds() {
+        let thresholds = HealthThresholds::default();
+        let report = healthy();
+        assert!(report.liveness_problems(&thresholds).is_empty());
+        assert!(report.readiness_problems(&thresholds).is_empty());
+
+        // A node which has not decided yet is alive, but not ready.
+        let report = HealthReport {
+            last_decided_view: None,
+            last_decided_height: None,
+            time_since_last_decide: None,
+            ..healthy()
+        };
+        assert!(report.liveness_problems(&thresholds).is_empty());
+        assert_eq!(report.readiness_problems(&thresholds).len(), 1);
+
+        // A node which has stopped deciding is neither.
+        let report = HealthReport {
+            time_since_last_decide: Some(thresholds.max_decide_age * 2),
+            ..healthy()
+        };
+        assert_eq!(report.liveness_problems(&thresholds).len(), 1);
+        assert_eq!(report.readiness_problems(&thresholds).len(), 1);
+
+        // Problems with dependencies make the node unready, but not dead.
+        let report = HealthReport {
+            time_since_l1_update: Some(thresholds.max_l1_age * 2),
+            catchup_in_progress: true,
+            persistence_write_failures: 1,
+            time_since_persistence_write_failure: Some(Duration::from_secs(1)),
+            relay_server_reachable: Some(false),
+            ..healthy()
+        };
+        assert!(report.liveness_problems(&thresholds).is_empty());
+        assert_eq!(report.readiness_problems(&thresholds).len(), 4);
+
+        // An old persistence failure is no longer a problem.
+        let report = HealthReport {
+            persistence_write_failures: 1,
+            time_since_persistence_write_failure: Some(thresholds.max_decide_age * 2),
+            ..healthy()
+        };
+        assert!(report.readiness_problems(&thresholds).is_empty());
+    }
+}
@@ -1,23 +1,37 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, ensure};
 use async_std::{sync::Arc, task::sleep};
 use async_trait::async_trait;
+use commit::{Commitment, Committable};
 use contract_bindings::hot_shot::{HotShot, Qc};
+use es_version::SequencerVersion;
 use ethers::prelude::*;
 use futures::{
     future,
     stream::{self, StreamExt},
 };
+use hotshot::traits::election::static_committee::GeneralStaticCommittee;
 use hotshot_query_service::{availability::LeafQueryData, types::HeightIndexed};
+use hotshot_types::{
+    traits::{
+        election::Membership,
+        metrics::{Counter, Gauge, Histogram, Metrics},
+    },
+    vote::Certificate,
+    PeerConfig,
+};
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
 use rand_distr::Distribution;
-use sequencer_utils::{commitment_to_u256, contract_send, init_signer, Signer};
+use sequencer_utils::{commitment_to_u256, init_signer, Signer};
 use std::error::Error;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use surf_disco::Url;
 use versioned_binary_serialization::version::StaticVersionType;
 
-use crate::{Header, SeqTypes};
+use crate::{
+    catchup::StatePeers, l1_client::L1Client, Header, Leaf, NodeState, PubKey, SeqTypes,
+    ValidatedState,
+};
 
 const RETRY_DELAY: Duration = Duration::from_secs(1);
 
@@ -58,10 +72,177 @@ pub struct CommitmentTaskOptions {
 
     /// If specified, sequencing attempts will be delayed by duration sampled from an exponential distribution with mean DELAY.
     pub delay: Option<Duration>,
+
+    /// Stake table of the HotShot network.
+    ///
+    /// The aggregated signature on the QC justifying each leaf is checked against this stake table
+    /// before the leaf is sent to the contract. The contract does not check these signatures itself,
+    /// so this is the only thing preventing a malicious query service from sequencing forged leaves.
+    pub stake_table: Vec<PeerConfig<PubKey>>,
+
+    /// Commitment to the genesis leaf of the HotShot chain (see [`genesis_leaf_commitment`]).
+    ///
+    /// The genesis QC is not signed by anyone, so the leaf at height 0 is only sent to the contract
+    /// if it is exactly this leaf.
+    pub genesis_leaf: Commitment<Leaf>,
+
+    /// Maximum gas to spend on a single transaction.
+    ///
+    /// Batches are cut short so that their estimated gas stays within this budget.
+    pub max_gas: Option<u64>,
+
+    /// Fee settings for transactions sent to the HotShot contract.
+    pub fees: FeeOptions,
+
+    /// Turn-taking with other instances of the commitment task.
+    ///
+    /// If not specified, this instance assumes it is the only one, and submits blocks whenever they
+    /// are available.
+    pub turns: Option<TurnOptions>,
+}
+
+/// Deterministic turn-taking between redundant instances of the commitment task.
+///
+/// Time is divided into slots of `slot_duration`, counted from the Unix epoch, and the instances
+/// take turns in round-robin order: instance `i` of `n` may submit only in slots `s` with
+/// `s % n == i`. An instance only starts a submission in the first half of its slot, leaving the
+/// second half for the transaction to be mined before the next instance takes over, and stops
+/// replacing its transaction once its slot ends. Each turn starts by reading the block height of
+/// the contract on L1, so if an instance is down, the next one simply picks up where it left off in
+/// its own turn.
+///
+/// This relies on the instances having roughly synchronized clocks.
+#[derive(Clone, Copy, Debug)]
+pub struct TurnOptions {
+    /// The index of this instance, in `0..num_instances`.
+    pub instance_index: usize,
+    /// The total number of coordinating instances.
+    pub num_instances: usize,
+    /// The length of each instance's turn.
+    pub slot_duration: Duration,
+}
+
+impl TurnOptions {
+    /// How long to wait, from `now` (measured since the Unix epoch), until this instance may start
+    /// a submission.
+    fn time_until_turn(&self, now: Duration) -> Duration {
+        let slot_len = self.slot_duration.as_millis().max(1);
+        let n = self.num_instances.max(1) as u128;
+        let i = self.instance_index as u128;
+        let now = now.as_millis();
+        let slot = now / slot_len;
+
+        if slot % n == i && now % slot_len < slot_len / 2 {
+            return Duration::ZERO;
+        }
+        let next = slot + 1 + (i + n - (slot + 1) % n) % n;
+        Duration::from_millis((next * slot_len - now) as u64)
+    }
+
+    /// If this instance may start a submission at `now` (measured since the Unix epoch), how long
+    /// until its slot ends.
+    fn time_left_in_turn(&self, now: Duration) -> Option<Duration> {
+        if !self.time_until_turn(now).is_zero() {
+            return None;
+        }
+        let slot_len = self.slot_duration.as_millis().max(1);
+        Some(Duration::from_millis(
+            (slot_len - now.as_millis() % slot_len) as u64,
+        ))
+    }
+
+    /// The end of this instance's current slot, if it may still start a submission in it.
+    fn turn_deadline(&self) -> Option<Instant> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.time_left_in_turn(now)
+            .map(|left| Instant::now() + left)
+    }
+
+    async fn wait_for_turn(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let wait = self.time_until_turn(now);
+        if !wait.is_zero() {
+            tracing::debug!(
+                instance = self.instance_index,
+                "waiting {wait:?} for our turn"
+            );
+            sleep(wait).await;
+        }
+    }
+}
+
+/// EIP-1559 fee settings for the commitment task.
+#[derive(Clone, Debug)]
+pub struct FeeOptions {
+    /// Maximum fee per gas, in wei, we are willing to pay.
+    ///
+    /// If not specified, the fee estimated by the L1 provider is used without a cap.
+    pub max_fee_per_gas: Option<U256>,
+
+    /// Priority fee per gas, in wei.
+    ///
+    /// If not specified, the priority fee estimated by the L1 provider is used.
+    pub max_priority_fee_per_gas: Option<U256>,
+
+    /// How long to wait for a transaction to be mined before replacing it with a higher fee.
+    pub replacement_timeout: Duration,
+
+    /// Percentage by which both fees are raised when replacing a stuck transaction.
+    ///
+    /// Most nodes will not accept a replacement unless its fees are at least 10% higher, so this
+    /// should be at least 10.
+    pub fee_bump_percent: u64,
+
+    /// Maximum number of times to replace a stuck transaction before giving up on it.
+    pub max_replacements: usize,
+}
+
+impl Default for FeeOptions {
+    fn default() -> Self {
+        Self {
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            replacement_timeout: Duration::from_secs(60),
+            fee_bump_percent: 20,
+            max_replacements: 5,
+        }
+    }
+}
+
+/// Metrics for the commitment task.
+#[derive(Debug)]
+struct CommitmentTaskMetrics {
+    blocks_sequenced: Box<dyn Counter>,
+    gas_used: Box<dyn Counter>,
+    gas_per_block: Box<dyn Histogram>,
+    gas_price: Box<dyn Gauge>,
+    replaced_transactions: Box<dyn Counter>,
+    failed_transactions: Box<dyn Counter>,
+}
+
+impl CommitmentTaskMetrics {
+    fn new(metrics: &dyn Metrics) -> Self {
+        let metrics = metrics.subgroup("commitment_task".into());
+        Self {
+            blocks_sequenced: metrics.create_counter("blocks_sequenced".into(), None),
+            gas_used: metrics.create_counter("gas_used".into(), None),
+            gas_per_block: metrics.create_histogram("gas_per_block".into(), None),
+            gas_price: metrics.create_gauge("gas_price".into(), Some("gwei".into())),
+            replaced_transactions: metrics.create_counter("replaced_transactions".into(), None),
+            failed_transactions: metrics.create_counter("failed_transactions".into(), None),
+        }
+    }
 }
 
 /// main logic for the commitment task, which sync the latest blocks from HotShot to L1 contracts
-pub async fn run_hotshot_commitment_task<Ver: StaticVersionType>(opt: &CommitmentTaskOptions) {
+pub async fn run_hotshot_commitment_task<Ver: StaticVersionType>(
+    opt: &CommitmentTaskOptions,
+    metrics: &dyn Metrics,
+) {
     // init a client connecting to HotShot query service
     let hotshot = HotShotClient::<Ver>::builder(
         opt.query_service_url
@@ -82,13 +263,21 @@ pub async fn run_hotshot_commitment_task<Ver: StaticVersionType>(opt: &Commitmen
     .map(Arc::new)
     .unwrap();
     let contract = HotShot::new(opt.hotshot_address, signer.clone());
+    let ctx = SyncContext {
+        verifier: QcVerifier::new(opt.stake_table.clone(), opt.genesis_leaf),
+        max_gas: opt.max_gas,
+        fees: opt.fees.clone(),
+        turns: opt.turns,
+        metrics: CommitmentTaskMetrics::new(metrics),
+    };
 
-    sequence(hotshot, contract, opt.delay).await;
+    sequence(hotshot, contract, ctx, opt.delay).await;
 }
 
 async fn sequence<Ver: StaticVersionType>(
     hotshot: HotShotClient<Ver>,
     contract: HotShot<Signer>,
+    ctx: SyncContext,
     delay: Option<Duration>,
 ) {
     // Get the maximum number of blocks the contract will allow at a time.
@@ -107,7 +296,7 @@ async fn sequence<Ver: StaticVersionType>(
     let mut soft_block_limit = hard_block_limit;
     let mut rng = ChaChaRng::from_entropy();
     loop {
-        if let Err(sync_err) = sync_with_l1(soft_block_limit, &hotshot, &contract).await {
+        if let Err(sync_err) = sync_with_l1(soft_block_limit, &hotshot, &contract, &ctx).await {
             match sync_err {
                 SyncError::Other(err) => {
                     tracing::error!("error synchronizing with HotShot contract: {err}");
@@ -169,6 +358,110 @@ impl<Ver: StaticVersionType> HotShotDataSource for HotShotClient<Ver> {
     }
 }
 
+/// The commitment of the genesis leaf of a chain whose genesis state is `genesis_state`.
+///
+/// Only the genesis state affects the genesis leaf, so the L1 client, builder wallet and catchup
+/// peers of the node state used to build it are placeholders which are never used.
+pub fn genesis_leaf_commitment(
+    l1_provider: Url,
+    genesis_state: ValidatedState,
+) -> Commitment<Leaf> {
+    let node_state = NodeState::new(
+        L1Client::new(l1_provider, Address::default()),
+        LocalWallet::new(&mut rand::thread_rng()),
+        StatePeers::<SequencerVersion>::from_urls(vec![]),
+    )
+    .with_genesis(genesis_state);
+    Leaf::genesis(&node_state).commit()
+}
+
+/// Checks that leaves fetched from the query service were actually decided by consensus.
+#[derive(Clone)]
+struct QcVerifier {
+    membership: GeneralStaticCommittee<SeqTypes, PubKey>,
+    genesis: Commitment<Leaf>,
+}
+
+impl QcVerifier {
+    fn new(stake_table: Vec<PeerConfig<PubKey>>, genesis: Commitment<Leaf>) -> Self {
+        let election_config = GeneralStaticCommittee::<SeqTypes, PubKey>::default_election_config(
+            stake_table.len() as u64,
+            0,
+        );
+        Self {
+            membership: GeneralStaticCommittee::create_election(stake_table, election_config, 0),
+            genesis,
+        }
+    }
+
+    /// Check that `leaf` is justified by its QC.
+    ///
+    /// The QC must sign this exact leaf, in the same view. The leaf at height 0 must be the genesis
+    /// leaf, whose QC is not signed. For every other leaf, the QC must carry a valid aggregated
+    /// signature from a quorum of the stake table.
+    fn verify(&self, leaf: &LeafQueryData<SeqTypes>) -> anyhow::Result<()> {
+        let qc = leaf.qc();
+        ensure!(
+            qc.data.leaf_commit == leaf.hash(),
+            "QC signs leaf {}, not {}",
+            qc.data.leaf_commit,
+            leaf.hash()
+        );
+        ensure!(
+            qc.view_number == leaf.leaf().get_view_number(),
+            "QC is for view {:?}, but leaf is for view {:?}",
+            qc.view_number,
+            leaf.leaf().get_view_number()
+        );
+        if leaf.height() == 0 {
+            ensure!(
+                leaf.hash() == self.genesis,
+                "leaf at height 0 is {}, not the genesis leaf {}",
+                leaf.hash(),
+                self.genesis
+            );
+        } else {
+            // Only the signature is checked against the stake table, so make sure it is a signature
+            // on the data in this QC.
+            ensure!(
+                qc.vote_commitment == qc.data.commit(),
+                "QC signature is not for the data in the QC"
+            );
+            ensure!(qc.signatures.is_some(), "QC is not signed");
+            ensure!(
+                !qc.is_genesis && qc.is_valid_cert(&self.membership),
+                "QC has an invalid signature"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Settings and state used by each call to [`sync_with_l1`].
+struct SyncContext {
+    verifier: QcVerifier,
+    max_gas: Option<u64>,
+    fees: FeeOptions,
+    turns: Option<TurnOptions>,
+    metrics: CommitmentTaskMetrics,
+}
+
+#[cfg(test)]
+impl Default for SyncContext {
+    fn default() -> Self {
+        Self {
+            verifier: QcVerifier::new(
+                test::mock_stake_table(&test::mock_keys(0, test::MOCK_NODES)),
+                Leaf::genesis(&NodeState::mock()).commit(),
+            ),
+            max_gas: None,
+            fees: Default::default(),
+            turns: None,
+            metrics: CommitmentTaskMetrics::new(&hotshot_types::traits::metrics::NoMetrics),
+        }
+    }
+}
+
 /// Error type during synchronization between data sources (e.g. L1, query services)
 #[derive(Debug)]
 enum SyncError {
@@ -184,13 +477,15 @@ async fn sync_with_l1(
     max_blocks: usize,
     hotshot: &impl HotShotDataSource,
     contract: &HotShot<Signer>,
+    ctx: &SyncContext,
 ) -> Result<(), SyncError> {
-    let contract_block_height = contract
-        .block_height()
-        .call()
-        .await
-        .map_err(|e| SyncError::Other(e.into()))?
-        .as_u64();
+    // If we are coordinating with other instances, wait for our turn before doing any work, so that
+    // we start from the state the previous instance left the contract in.
+    if let Some(turns) = &ctx.turns {
+        turns.wait_for_turn().await;
+    }
+
+    let contract_block_height = contract_height(contract).await.map_err(SyncError::Other)?;
     let hotshot_block_height = loop {
         let height = hotshot
             .block_height()
@@ -216,15 +511,21 @@ async fn sync_with_l1(
     let leaves = stream::iter(contract_block_height..hotshot_block_height)
         .take(max_blocks)
         .then(|height| hotshot.get_leaf(height))
-        // It is possible that we failed to fetch some leaves. But as long as we successfully
-        // fetched a prefix of the desired list (since leaves must be sent to the contract in order)
-        // we can make some progress.
+        // It is possible that we failed to fetch some leaves, or that some leaves we fetched are not
+        // justified by their QCs. But as long as we successfully fetched a prefix of valid leaves
+        // (since leaves must be sent to the contract in order) we can make some progress.
         .scan(contract_block_height, |height, leaf| {
             future::ready(match leaf {
-                Ok(leaf) => {
-                    *height += 1;
-                    Some(leaf)
-                }
+                Ok(leaf) => match ctx.verifier.verify(&leaf) {
+                    Ok(()) => {
+                        *height += 1;
+                        Some(leaf)
+                    }
+                    Err(err) => {
+                        tracing::error!("leaf {height} failed verification: {err:#}");
+                        None
+                    }
+                },
                 Err(err) => {
                     tracing::error!("error fetching leaf {height}: {err}");
                     None
@@ -236,53 +537,333 @@ async fn sync_with_l1(
     if leaves.is_empty() {
         return Err(SyncError::Other(anyhow!("failed to fetch any leaves")));
     }
-    let num_leaves = leaves.len();
+
+    // Cut the batch short if it would cost more gas than we are willing to spend.
+    let (txn, gas) = match fit_gas_budget(contract, leaves, ctx.max_gas).await {
+        Ok(res) => res,
+        Err(err) => {
+            if lost_race(contract, contract_block_height).await {
+                tracing::info!(
+                    "contract advanced past {contract_block_height} while estimating gas: {err:?}"
+                );
+                return Ok(());
+            }
+            return Err(err);
+        }
+    };
+    let num_leaves = txn.num_leaves;
+
+    // Waiting for HotShot blocks or fetching leaves may have taken us past the point in our turn
+    // where we may start a submission. If so, leave it to the next instance.
+    let deadline = match &ctx.turns {
+        Some(turns) => match turns.turn_deadline() {
+            Some(deadline) => Some(deadline),
+            None => {
+                tracing::info!("our turn ended before the batch was ready, skipping");
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
     tracing::info!(
-        "sending {num_leaves} leaves to the contract ({}-{})",
-        leaves[0].height(),
-        leaves[num_leaves - 1].height()
+        "sending {num_leaves} leaves to the contract ({}-{}), estimated gas {gas}",
+        txn.first_height,
+        txn.first_height + num_leaves as u64 - 1,
     );
 
     // Send the leaves to the contract.
-    let txn = build_sequence_batches_txn(contract, leaves);
+    //
     // If the transaction fails for any reason -- not mined, reverted, etc. -- just return the
     // error. We will retry, and may end up changing the transaction we send if the contract state
     // has changed, which is one possible cause of the transaction failure. This can happen, for
-    // example, if there are multiple commitment tasks racing.
-    contract_send(&txn)
-        .await
-        .map_err(|e| SyncError::TransactionFailed { err: e, num_leaves })?;
+    // example, if there are multiple commitment tasks racing, in which case losing the race is not
+    // a failure of ours.
+    let receipt =
+        match send_with_replacement(contract, &txn.call, gas, &ctx.fees, deadline, &ctx.metrics)
+            .await
+        {
+            Ok(receipt) => receipt,
+            Err(err) => {
+                if lost_race(contract, contract_block_height).await {
+                    tracing::info!(
+                        "contract advanced past {contract_block_height} while sending: {err:#}"
+                    );
+                    return Ok(());
+                }
+                ctx.metrics.failed_transactions.add(1);
+                return Err(SyncError::TransactionFailed { err, num_leaves });
+            }
+        };
+
+    let gas_used = receipt.gas_used.unwrap_or_default().as_u64();
+    tracing::info!(
+        "sequenced {num_leaves} leaves in L1 transaction {:x}, gas used {gas_used}",
+        receipt.transaction_hash
+    );
+    ctx.metrics.blocks_sequenced.add(num_leaves);
+    ctx.metrics.gas_used.add(gas_used as usize);
+    ctx.metrics
+        .gas_per_block
+        .add_point(gas_used as f64 / num_leaves as f64);
+    if let Some(price) = receipt.effective_gas_price {
+        ctx.metrics
+            .gas_price
+            .set((price / U256::exp10(9)).as_usize());
+    }
 
     Ok(())
 }
 
+async fn contract_height(contract: &HotShot<Signer>) -> anyhow::Result<u64> {
+    Ok(contract.block_height().call().await?.as_u64())
+}
+
+/// Whether the contract has advanced past `height`, meaning someone else sequenced the blocks we
+/// were trying to send (or our own transaction was mined after we stopped waiting for it).
+async fn lost_race(contract: &HotShot<Signer>, height: u64) -> bool {
+    match contract_height(contract).await {
+        Ok(current) => current != height,
+        Err(err) => {
+            tracing::warn!("error reading contract block height: {err:#}");
+            false
+        }
+    }
+}
+
+/// A `newBlocks` call for a batch of consecutive leaves.
+struct BatchTxn {
+    call: ContractCall<Signer, ()>,
+    first_height: u64,
+    num_leaves: usize,
+}
+
+/// Build a transaction from a prefix of `leaves` whose estimated gas is within `max_gas`.
+///
+/// Returns the transaction and its gas estimate.
+async fn fit_gas_budget(
+    contract: &HotShot<Signer>,
+    mut leaves: Vec<LeafQueryData<SeqTypes>>,
+    max_gas: Option<u64>,
+) -> Result<(BatchTxn, U256), SyncError> {
+    let first_height = leaves[0].height();
+    loop {
+        let num_leaves = leaves.len();
+        let call = build_sequence_batches_txn(contract, leaves.iter().cloned());
+        // Estimation fails if the transaction would revert, most likely because it would exceed
+        // the L1 block gas limit. Report it as a transaction failure so the caller tries a smaller
+        // batch next time.
+        let gas = call
+            .estimate_gas()
+            .await
+            .map_err(|err| SyncError::TransactionFailed {
+                err: anyhow!("error estimating gas: {err}"),
+                num_leaves,
+            })?;
+        let max_gas = match max_gas {
+            Some(max_gas) if gas > max_gas.into() => max_gas,
+            _ => {
+                let txn = BatchTxn {
+                    call,
+                    first_height,
+                    num_leaves,
+                };
+                return Ok((txn, gas));
+            }
+        };
+        if num_leaves == 1 {
+            return Err(SyncError::Other(anyhow!(
+                "sequencing a single block takes {gas} gas, more than the budget of {max_gas}"
+            )));
+        }
+
+        // Gas grows roughly linearly with the number of leaves, so scale the batch down
+        // proportionally, making sure we always make progress towards a smaller batch.
+        let scaled = (U256::from(num_leaves) * U256::from(max_gas) / gas).as_usize();
+        leaves.truncate(scaled.clamp(1, num_leaves - 1));
+    }
+}
+
+/// Send a transaction using EIP-1559 fees, replacing it with higher fees if it gets stuck.
+///
+/// Returns the receipt of whichever version of the transaction is mined. If `deadline` passes
+/// before any version is mined, we stop replacing the transaction and give up on it, since the
+/// next instance will have started its own submission.
+///
+/// The transaction always uses the next nonce after our last mined transaction. If we gave up on
+/// a transaction earlier and it is still pending, the new transaction replaces it instead of
+/// queueing up behind it.
+async fn send_with_replacement(
+    contract: &HotShot<Signer>,
+    call: &ContractCall<Signer, ()>,
+    gas: U256,
+    opt: &FeeOptions,
+    deadline: Option<Instant>,
+    metrics: &CommitmentTaskMetrics,
+) -> anyhow::Result<TransactionReceipt> {
+    let client = contract.client();
+
+    // Leave some headroom over the estimate, in case the state changes slightly before the
+    // transaction is mined.
+    let mut tx = Eip1559TransactionRequest::new()
+        .from(client.address())
+        .to(contract.address())
+        .data(call.calldata().unwrap_or_default())
+        .gas(gas + gas / 5);
+
+    let (est_max_fee, est_priority_fee) = client
+        .estimate_eip1559_fees(None)
+        .await
+        .map_err(|err| anyhow!("error estimating fees: {err}"))?;
+    let mut priority_fee = opt.max_priority_fee_per_gas.unwrap_or(est_priority_fee);
+    let mut max_fee = est_max_fee.max(priority_fee);
+    if let Some(cap) = opt.max_fee_per_gas {
+        max_fee = max_fee.min(cap);
+        priority_fee = priority_fee.min(cap);
+    }
+
+    // Fix the nonce, so that each replacement replaces the previous transaction rather than
+    // queueing up behind it. Take the nonce from the latest block rather than the mempool, so that
+    // a transaction we abandoned earlier does not hold up this one.
+    let nonce = client
+        .get_transaction_count(client.address(), Some(BlockNumber::Latest.into()))
+        .await
+        .map_err(|err| anyhow!("error getting nonce: {err}"))?;
+    let pending_nonce = client
+        .get_transaction_count(client.address(), Some(BlockNumber::Pending.into()))
+        .await
+        .map_err(|err| anyhow!("error getting pending nonce: {err}"))?;
+    tx = tx.nonce(nonce);
+
+    // All the versions of this transaction we have sent. Any one of them may end up being mined.
+    let mut hashes = vec![];
+    let mut send = true;
+    for replacement in 0..=opt.max_replacements {
+        if send {
+            tx = tx
+                .max_fee_per_gas(max_fee)
+                .max_priority_fee_per_gas(priority_fee);
+            match client.send_transaction(tx.clone(), None).await {
+                Ok(pending) => {
+                    tracing::debug!(
+                        replacement,
+                        %max_fee,
+                        %priority_fee,
+                        "submitted transaction {:x}",
+                        pending.tx_hash()
+                    );
+                    hashes.push(pending.tx_hash());
+                }
+                // If a previous version of the transaction was just mined, the replacement will be
+                // rejected, but we will find the receipt below.
+                Err(err) if !hashes.is_empty() => {
+                    tracing::warn!("error sending replacement transaction: {err}");
+                }
+                // An abandoned transaction is still pending with our nonce, and our fees are too
+                // low to replace it. Bump them and try again without waiting.
+                Err(err) if nonce < pending_nonce => {
+                    tracing::warn!(%nonce, "error replacing abandoned transaction: {err}");
+                }
+                Err(err) => return Err(anyhow!("error sending transaction: {err}")),
+            }
+        }
+
+        let mut timeout = Instant::now() + opt.replacement_timeout;
+        if let Some(deadline) = deadline {
+            timeout = timeout.min(deadline);
+        }
+        while !hashes.is_empty() && Instant::now() < timeout {
+            for hash in &hashes {
+                match client.get_transaction_receipt(*hash).await {
+                    Ok(Some(receipt)) => {
+                        if receipt.status != Some(1.into()) {
+                            return Err(anyhow!("transaction {hash:x} reverted"));
+                        }
+                        return Ok(receipt);
+                    }
+                    Ok(None) => {}
+                    Err(err) => tracing::warn!("error getting receipt for {hash:x}: {err}"),
+                }
+            }
+            sleep(
+                client
+                    .provider()
+                    .get_interval()
+                    .min(opt.replacement_timeout),
+            )
+            .await;
+        }
+        if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            return Err(anyhow!("our turn ended before the transaction was mined"));
+        }
+
+        // The transaction is stuck. Bump the fees and replace it.
+        let bump = |fee: U256| fee + (fee * opt.fee_bump_percent / 100).max(U256::one());
+        let (new_max_fee, new_priority_fee) = (bump(max_fee), bump(priority_fee));
+        send = opt
+            .max_fee_per_gas
+            .map_or(true, |cap| new_max_fee <= cap && new_priority_fee <= cap);
+        if send {
+            tracing::warn!(
+                replacement,
+                "transaction not mined after {:?}, replacing with higher fees",
+                opt.replacement_timeout
+            );
+            (max_fee, priority_fee) = (new_max_fee, new_priority_fee);
+            metrics.replaced_transactions.add(1);
+        } else {
+            tracing::warn!(%max_fee, "transaction is stuck, but fees are already at the limit");
+        }
+    }
+
+    Err(anyhow!(
+        "transaction not mined after {} replacements",
+        opt.max_replacements
+    ))
+}
+
 /// prepare the transaction from new leaves (with QC) from HotShot
-fn build_sequence_batches_txn<M: ethers::prelude::Middleware>(
+fn build_sequence_batches_txn<M: Middleware>(
     contract: &HotShot<M>,
     leaves: impl IntoIterator<Item = LeafQueryData<SeqTypes>>,
 ) -> ContractCall<M, ()> {
-    let qcs = leaves
-        .into_iter()
-        .map(|leaf| Qc {
-            height: leaf.height().into(),
-            block_commitment: commitment_to_u256(leaf.block_hash()),
-            ..Default::default()
-        })
-        .collect();
+    let qcs = leaves.into_iter().map(|leaf| contract_qc(&leaf)).collect();
     contract.new_blocks(qcs)
 }
 
+/// Convert a leaf and its justifying QC to the format of the HotShot contract.
+///
+/// Besides the height and block commitment, which the contract stores, the contract reserves two
+/// words for the QC itself. We use the first for the commitment of the leaf which the QC signs, so
+/// that each block on L1 can be matched to the leaf it came from. The aggregated signature does not
+/// fit in the remaining word, so it is left empty; the signature is only checked off chain, before
+/// the leaf is sent (see [`QcVerifier`]).
+fn contract_qc(leaf: &LeafQueryData<SeqTypes>) -> Qc {
+    Qc {
+        height: leaf.height().into(),
+        block_commitment: commitment_to_u256(leaf.block_hash()),
+        pad_1: commitment_to_u256(leaf.qc().data.leaf_commit),
+        pad_2: U256::zero(),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{l1_client::L1Client, Leaf, NodeState};
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
     use async_std::task::spawn;
-    use commit::Committable;
+    use bitvec::bitvec;
     use contract_bindings::hot_shot::{NewBlocksCall, NewBlocksFilter};
     use ethers::{abi::AbiDecode, providers::Middleware};
     use futures::FutureExt;
-    use hotshot_types::simple_certificate::QuorumCertificate;
+    use hotshot::types::SignatureKey;
+    use hotshot_query_service::metrics::PrometheusMetrics;
+    use hotshot_types::{
+        data::ViewNumber,
+        light_client::StateKeyPair,
+        simple_certificate::{QuorumCertificate, QuorumData},
+        traits::node_implementation::ConsensusTime,
+    };
     use sequencer_utils::test_utils::TestL1System;
     use sequencer_utils::AnvilOptions;
     use surf_disco::{Error, StatusCode};
@@ -327,7 +908,22 @@ mod test {
         }
     }
 
+    /// Number of nodes in the stake table used by [`SyncContext::default`].
+    pub(super) const MOCK_NODES: u64 = 5;
+
+    /// A leaf at `height` which is accepted by [`SyncContext::default`].
+    ///
+    /// The leaf at height 0 is the genesis leaf. Later leaves are signed by the mock stake table.
     fn mock_leaf(height: u64, node_state: &NodeState) -> LeafQueryData<SeqTypes> {
+        if height == 0 {
+            unsigned_leaf(0, node_state)
+        } else {
+            signed_leaf(height, node_state, &mock_keys(0, MOCK_NODES))
+        }
+    }
+
+    /// A leaf justified by an unsigned genesis QC.
+    fn unsigned_leaf(height: u64, node_state: &NodeState) -> LeafQueryData<SeqTypes> {
         let mut leaf = Leaf::genesis(node_state);
         let mut qc = QuorumCertificate::genesis();
         leaf.get_block_header_mut().height = height;
@@ -335,6 +931,91 @@ mod test {
         LeafQueryData::new(leaf, qc).unwrap()
     }
 
+    /// A leaf paired with a QC for a different leaf at the same height.
+    ///
+    /// This cannot be constructed with [`LeafQueryData::new`], but nothing stops a malicious query
+    /// service from sending it to us.
+    fn forged_leaf(height: u64, node_state: &NodeState) -> LeafQueryData<SeqTypes> {
+        let honest = mock_leaf(height, node_state);
+        let mut leaf = honest.leaf().clone();
+        leaf.get_block_header_mut().timestamp += 1;
+        serde_json::from_value(serde_json::json!({
+            "leaf": leaf,
+            "qc": honest.qc(),
+        }))
+        .unwrap()
+    }
+
+    /// A leaf with a QC which claims to sign it, but whose signature is for a different leaf.
+    ///
+    /// Unlike [`forged_leaf`], this is internally consistent and can be constructed with
+    /// [`LeafQueryData::new`], so it can only be detected by checking the signature.
+    fn relabeled_leaf(height: u64, node_state: &NodeState) -> LeafQueryData<SeqTypes> {
+        let honest = mock_leaf(height, node_state);
+        let mut leaf = honest.leaf().clone();
+        leaf.get_block_header_mut().timestamp += 1;
+        let mut qc = honest.qc().clone();
+        qc.data.leaf_commit = leaf.commit();
+        qc.vote_commitment = qc.data.commit();
+        LeafQueryData::new(leaf, qc).unwrap()
+    }
+
+    /// Private keys of the nodes in the stake table of [`signed_leaf`].
+    pub(super) fn mock_keys(
+        seed: u8,
+        n: u64,
+    ) -> Vec<(PubKey, <PubKey as SignatureKey>::PrivateKey)> {
+        (0..n)
+            .map(|i| PubKey::generated_from_seed_indexed([seed; 32], i))
+            .collect()
+    }
+
+    pub(super) fn mock_stake_table(
+        keys: &[(PubKey, <PubKey as SignatureKey>::PrivateKey)],
+    ) -> Vec<PeerConfig<PubKey>> {
+        keys.iter()
+            .map(|(pub_key, _)| PeerConfig {
+                stake_table_entry: pub_key.get_stake_table_entry(1),
+                state_ver_key: StateKeyPair::generate().ver_key(),
+            })
+            .collect()
+    }
+
+    /// A leaf whose QC carries an aggregated signature from all of `keys`.
+    fn signed_leaf(
+        height: u64,
+        node_state: &NodeState,
+        keys: &[(PubKey, <PubKey as SignatureKey>::PrivateKey)],
+    ) -> LeafQueryData<SeqTypes> {
+        let leaf = unsigned_leaf(height, node_state).leaf().clone();
+        let data = QuorumData {
+            leaf_commit: leaf.commit(),
+        };
+        let vote_commitment = data.commit();
+        let signatures = keys
+            .iter()
+            .map(|(_, priv_key)| PubKey::sign(priv_key, vote_commitment.as_ref()).unwrap())
+            .collect::<Vec<_>>();
+        let stake_entries = keys
+            .iter()
+            .map(|(pub_key, _)| pub_key.get_stake_table_entry(1))
+            .collect();
+        let pp = PubKey::get_public_parameter(stake_entries, U256::from(keys.len()));
+        let qc = QuorumCertificate {
+            data,
+            vote_commitment,
+            view_number: leaf.get_view_number(),
+            signatures: Some(PubKey::assemble(
+                &pp,
+                bitvec![1; keys.len()].as_bitslice(),
+                &signatures,
+            )),
+            is_genesis: false,
+            _pd: Default::default(),
+        };
+        LeafQueryData::new(leaf, qc).unwrap()
+    }
+
     async fn wait_for_new_batches(
         l1: &TestL1System,
         from_block: u64,
@@ -399,7 +1080,9 @@ mod test {
         assert!(size < 131072);
 
         // Sequence them in the HotShot contract.
-        sync_with_l1(num_batches, &data, &hotshot).await.unwrap();
+        sync_with_l1(num_batches, &data, &hotshot, &SyncContext::default())
+            .await
+            .unwrap();
 
         // Check the NewBatches event.
         let (event, meta) = wait_for_new_batches(&l1, l1_initial_block.as_u64()).await;
@@ -423,7 +1106,8 @@ mod test {
                     block_commitment: U256::from_little_endian(&<[u8; 32]>::from(
                         leaf.block_hash()
                     )),
-                    ..Default::default()
+                    pad_1: U256::from_little_endian(&<[u8; 32]>::from(leaf.hash())),
+                    pad_2: U256::zero(),
                 })
                 .collect::<Vec<_>>()
         );
@@ -457,7 +1141,9 @@ mod test {
         let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer);
 
         // Sequence them in the HotShot contract.
-        sync_with_l1(1, &data, &hotshot).await.unwrap();
+        sync_with_l1(1, &data, &hotshot, &SyncContext::default())
+            .await
+            .unwrap();
 
         // Check the NewBatches event.
         let (event, meta) = wait_for_new_batches(&l1, from_block.as_u64()).await;
@@ -468,7 +1154,7 @@ mod test {
         let fut = {
             let data = data.clone();
             let hotshot = hotshot.clone();
-            spawn(async move { sync_with_l1(1, &data, &hotshot).await })
+            spawn(async move { sync_with_l1(1, &data, &hotshot, &SyncContext::default()).await })
         };
         // Sleep for a few seconds and make sure nothing happened.
         sleep(Duration::from_secs(3)).await;
@@ -477,7 +1163,9 @@ mod test {
 
         // Once a new batch is available, we can sequence it.
         data.leaves.push(Some(mock_leaf(1, &node_state)));
-        sync_with_l1(1, &data, &hotshot).await.unwrap();
+        sync_with_l1(1, &data, &hotshot, &SyncContext::default())
+            .await
+            .unwrap();
         let (event, _) = wait_for_new_batches(&l1, from_block.as_u64()).await;
         assert_eq!(event.first_block_number.as_u64(), 1);
 
@@ -525,17 +1213,583 @@ mod test {
         let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer);
 
         // If the first leaf is missing, we cannot make any progress, and sync should fail.
-        sync_with_l1(3, &data, &hotshot).await.unwrap_err();
+        sync_with_l1(3, &data, &hotshot, &SyncContext::default())
+            .await
+            .unwrap_err();
 
         // If the first leaf is present but subsequent leaves are missing, we should sequence the
         // leaves that are available.
 
         data.leaves[0] = Some(mock_leaf(0, &node_state));
-        sync_with_l1(3, &data, &hotshot).await.unwrap();
+        sync_with_l1(3, &data, &hotshot, &SyncContext::default())
+            .await
+            .unwrap();
 
         // Check the NewBatches event.
         let event = wait_for_new_batches(&l1, l1_initial_block.as_u64()).await.0;
         assert_eq!(event.first_block_number, 0.into());
         assert_eq!(event.num_blocks, 2.into());
     }
+
+    #[async_std::test]
+    async fn test_gas_budget() {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+
+        let l1 = TestL1System::deploy(anvil.provider()).await.unwrap();
+        let l1_initial_block = l1.provider.get_block_number().await.unwrap();
+        let adaptor_l1_signer = Arc::new(
+            init_signer(l1.provider.url(), TEST_MNEMONIC, l1.clients.funded[0].index)
+                .await
+                .unwrap(),
+        );
+
+        let node_state = NodeState::mock().with_l1(L1Client::new(
+            anvil.provider().url().clone(),
+            Address::default(),
+        ));
+        let mut data = MockDataSource::default();
+        for i in 0..5 {
+            data.leaves.push(Some(mock_leaf(i, &node_state)));
+        }
+
+        // Connect to the HotShot contract with the expected L1 client.
+        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer);
+
+        // Set the budget to exactly the cost of sequencing two blocks.
+        let max_gas = build_sequence_batches_txn(
+            &hotshot,
+            data.leaves[..2].iter().cloned().map(Option::unwrap),
+        )
+        .estimate_gas()
+        .await
+        .unwrap();
+        let ctx = SyncContext {
+            max_gas: Some(max_gas.as_u64()),
+            ..Default::default()
+        };
+
+        // Even though more blocks are available, only two are sequenced at a time.
+        sync_with_l1(5, &data, &hotshot, &ctx).await.unwrap();
+        let event = wait_for_new_batches(&l1, l1_initial_block.as_u64()).await.0;
+        assert_eq!(event.first_block_number, 0.into());
+        assert_eq!(event.num_blocks, 2.into());
+        assert_eq!(l1.hotshot.block_height().call().await.unwrap().as_u64(), 2);
+
+        // A budget too small for a single block makes no progress.
+        let ctx = SyncContext {
+            max_gas: Some(1),
+            ..Default::default()
+        };
+        sync_with_l1(5, &data, &hotshot, &ctx).await.unwrap_err();
+        assert_eq!(l1.hotshot.block_height().call().await.unwrap().as_u64(), 2);
+    }
+
+    fn commitment_task_counter(metrics: &PrometheusMetrics, name: &str) -> usize {
+        metrics
+            .get_subgroup(["commitment_task"])
+            .unwrap()
+            .get_counter(name)
+            .unwrap()
+            .get()
+    }
+
+    #[async_std::test]
+    async fn test_metrics() {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+
+        let l1 = TestL1System::deploy(anvil.provider()).await.unwrap();
+        let l1_initial_block = l1.provider.get_block_number().await.unwrap();
+        let adaptor_l1_signer = Arc::new(
+            init_signer(l1.provider.url(), TEST_MNEMONIC, l1.clients.funded[0].index)
+                .await
+                .unwrap(),
+        );
+
+        let node_state = NodeState::mock().with_l1(L1Client::new(
+            anvil.provider().url().clone(),
+            Address::default(),
+        ));
+        let mut data = MockDataSource::default();
+        for i in 0..3 {
+            data.leaves.push(Some(mock_leaf(i, &node_state)));
+        }
+
+        // Connect to the HotShot contract with the expected L1 client.
+        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer);
+        let metrics = PrometheusMetrics::default();
+        let ctx = SyncContext {
+            metrics: CommitmentTaskMetrics::new(&metrics),
+            ..Default::default()
+        };
+
+        sync_with_l1(3, &data, &hotshot, &ctx).await.unwrap();
+        let meta = wait_for_new_batches(&l1, l1_initial_block.as_u64()).await.1;
+        let receipt = l1
+            .provider
+            .get_transaction_receipt(meta.transaction_hash)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(commitment_task_counter(&metrics, "blocks_sequenced"), 3);
+        assert_eq!(
+            commitment_task_counter(&metrics, "gas_used"),
+            receipt.gas_used.unwrap().as_usize()
+        );
+        assert_eq!(
+            commitment_task_counter(&metrics, "replaced_transactions"),
+            0
+        );
+        assert_eq!(commitment_task_counter(&metrics, "failed_transactions"), 0);
+    }
+
+    #[async_std::test]
+    async fn test_replace_stuck_transaction() {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+
+        let l1 = TestL1System::deploy(anvil.provider()).await.unwrap();
+        let adaptor_l1_signer = Arc::new(
+            init_signer(l1.provider.url(), TEST_MNEMONIC, l1.clients.funded[0].index)
+                .await
+                .unwrap(),
+        );
+        let sender = adaptor_l1_signer.address();
+        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer);
+
+        let node_state = NodeState::mock().with_l1(L1Client::new(
+            anvil.provider().url().clone(),
+            Address::default(),
+        ));
+        let call = build_sequence_batches_txn(&hotshot, [mock_leaf(0, &node_state)]);
+        let gas = call.estimate_gas().await.unwrap();
+
+        // Stop mining, so that the first version of the transaction gets stuck in the mempool.
+        l1.provider
+            .request::<_, serde_json::Value>("evm_setAutomine", [false])
+            .await
+            .unwrap();
+
+        let metrics = PrometheusMetrics::default();
+        let task_metrics = CommitmentTaskMetrics::new(&metrics);
+        let opt = FeeOptions {
+            replacement_timeout: Duration::from_secs(1),
+            fee_bump_percent: 20,
+            ..Default::default()
+        };
+
+        // The pending transaction from our sender, if any.
+        let pending = || {
+            let provider = &l1.provider;
+            async move {
+                provider
+                    .txpool_content()
+                    .await
+                    .unwrap()
+                    .pending
+                    .get(&sender)
+                    .and_then(|txs| txs.values().next().cloned())
+            }
+        };
+
+        let mine = async {
+            // Wait for the original transaction.
+            let original = loop {
+                if let Some(tx) = pending().await {
+                    break tx;
+                }
+                sleep(Duration::from_millis(100)).await;
+            };
+            tracing::info!("original transaction: {original:?}");
+
+            // Wait for it to be replaced.
+            let replacement = loop {
+                match pending().await {
+                    Some(tx) if tx.hash != original.hash => break tx,
+                    _ => sleep(Duration::from_millis(100)).await,
+                }
+            };
+            tracing::info!("replacement transaction: {replacement:?}");
+
+            // Now let the replacement be mined.
+            l1.provider
+                .request::<_, serde_json::Value>("evm_mine", ())
+                .await
+                .unwrap();
+            (original, replacement)
+        };
+        let (receipt, (original, replacement)) = futures::join!(
+            send_with_replacement(&hotshot, &call, gas, &opt, None, &task_metrics),
+            mine
+        );
+        let receipt = receipt.unwrap();
+
+        // The replacement has the same nonce as the original, with fees bumped by at least 20%.
+        assert_eq!(replacement.nonce, original.nonce);
+        let bumped = |fee: Option<U256>| fee.unwrap() * 120 / 100;
+        assert!(replacement.max_fee_per_gas.unwrap() >= bumped(original.max_fee_per_gas));
+        assert!(
+            replacement.max_priority_fee_per_gas.unwrap()
+                >= bumped(original.max_priority_fee_per_gas)
+        );
+
+        // The replacement is the one that got mined.
+        assert_eq!(receipt.transaction_hash, replacement.hash);
+        assert!(l1
+            .provider
+            .get_transaction_receipt(original.hash)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(l1.hotshot.block_height().call().await.unwrap().as_u64(), 1);
+        assert!(commitment_task_counter(&metrics, "replaced_transactions") >= 1);
+    }
+
+    #[async_std::test]
+    async fn test_replace_abandoned_transaction() {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+
+        let l1 = TestL1System::deploy(anvil.provider()).await.unwrap();
+        let adaptor_l1_signer = Arc::new(
+            init_signer(l1.provider.url(), TEST_MNEMONIC, l1.clients.funded[0].index)
+                .await
+                .unwrap(),
+        );
+        let sender = adaptor_l1_signer.address();
+        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer);
+
+        let node_state = NodeState::mock().with_l1(L1Client::new(
+            anvil.provider().url().clone(),
+            Address::default(),
+        ));
+        let call = build_sequence_batches_txn(&hotshot, [mock_leaf(0, &node_state)]);
+        let gas = call.estimate_gas().await.unwrap();
+
+        // Stop mining, so that the first transaction gets stuck in the mempool.
+        l1.provider
+            .request::<_, serde_json::Value>("evm_setAutomine", [false])
+            .await
+            .unwrap();
+
+        let metrics = PrometheusMetrics::default();
+        let task_metrics = CommitmentTaskMetrics::new(&metrics);
+        let opt = FeeOptions {
+            replacement_timeout: Duration::from_secs(1),
+            fee_bump_percent: 20,
+            ..Default::default()
+        };
+
+        // The pending transaction from our sender, if any.
+        let pending = || {
+            let provider = &l1.provider;
+            async move {
+                provider
+                    .txpool_content()
+                    .await
+                    .unwrap()
+                    .pending
+                    .get(&sender)
+                    .and_then(|txs| txs.values().next().cloned())
+            }
+        };
+
+        // Give up on the first transaction when our turn ends, leaving it in the mempool.
+        let deadline = Instant::now() + Duration::from_secs(2);
+        send_with_replacement(&hotshot, &call, gas, &opt, Some(deadline), &task_metrics)
+            .await
+            .unwrap_err();
+        let abandoned = pending().await.unwrap();
+        tracing::info!("abandoned transaction: {abandoned:?}");
+
+        let mine = async {
+            // Wait for the abandoned transaction to be replaced.
+            let replacement = loop {
+                match pending().await {
+                    Some(tx) if tx.hash != abandoned.hash => break tx,
+                    _ => sleep(Duration::from_millis(100)).await,
+                }
+            };
+            tracing::info!("replacement transaction: {replacement:?}");
+
+            // Now let the replacement be mined.
+            l1.provider
+                .request::<_, serde_json::Value>("evm_mine", ())
+                .await
+                .unwrap();
+            replacement
+        };
+        let (receipt, replacement) = futures::join!(
+            send_with_replacement(&hotshot, &call, gas, &opt, None, &task_metrics),
+            mine
+        );
+        let receipt = receipt.unwrap();
+
+        // The next attempt reuses the nonce of the abandoned transaction rather than queueing up
+        // behind it, and it is the one that got mined.
+        assert_eq!(replacement.nonce, abandoned.nonce);
+        assert_eq!(receipt.transaction_hash, replacement.hash);
+        assert!(l1
+            .provider
+            .get_transaction_receipt(abandoned.hash)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(pending().await.is_none());
+        assert_eq!(l1.hotshot.block_height().call().await.unwrap().as_u64(), 1);
+    }
+
+    #[async_std::test]
+    async fn test_two_instances() {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+
+        let l1 = TestL1System::deploy(anvil.provider()).await.unwrap();
+        let node_state = NodeState::mock().with_l1(L1Client::new(
+            anvil.provider().url().clone(),
+            Address::default(),
+        ));
+        let mut data = MockDataSource::default();
+        for i in 0..6 {
+            data.leaves.push(Some(mock_leaf(i, &node_state)));
+        }
+
+        // Each instance uses its own L1 account, and sends at most two blocks at a time.
+        let slot_duration = Duration::from_secs(2);
+        let mut instances = vec![];
+        for instance_index in 0..2 {
+            let signer = Arc::new(
+                init_signer(
+                    l1.provider.url(),
+                    TEST_MNEMONIC,
+                    l1.clients.funded[instance_index].index,
+                )
+                .await
+                .unwrap(),
+            );
+            let metrics = PrometheusMetrics::default();
+            let ctx = SyncContext {
+                turns: Some(TurnOptions {
+                    instance_index,
+                    num_instances: 2,
+                    slot_duration,
+                }),
+                metrics: CommitmentTaskMetrics::new(&metrics),
+                ..Default::default()
+            };
+            instances.push((HotShot::new(l1.hotshot.address(), signer), ctx, metrics));
+        }
+
+        // Run both instances until all the blocks are sequenced. After each submission, an
+        // instance sleeps past the end of its slot, so that it does not send every batch itself.
+        async fn run(
+            data: &MockDataSource,
+            hotshot: &HotShot<Signer>,
+            ctx: &SyncContext,
+            slot_duration: Duration,
+        ) {
+            loop {
+                sync_with_l1(2, data, hotshot, ctx).await.unwrap();
+                sleep(slot_duration).await;
+            }
+        }
+        let done = async {
+            while l1.hotshot.block_height().call().await.unwrap().as_u64() < 6 {
+                sleep(Duration::from_millis(100)).await;
+            }
+        };
+        let [(hotshot0, ctx0, _), (hotshot1, ctx1, _)] = &instances[..] else {
+            unreachable!()
+        };
+        future::select(
+            Box::pin(future::join(
+                run(&data, hotshot0, ctx0, slot_duration),
+                run(&data, hotshot1, ctx1, slot_duration),
+            )),
+            Box::pin(done),
+        )
+        .await;
+
+        // Both instances took part, each block was sequenced exactly once, and nobody failed.
+        let sequenced = instances
+            .iter()
+            .map(|(_, _, metrics)| {
+                assert_eq!(commitment_task_counter(metrics, "failed_transactions"), 0);
+                commitment_task_counter(metrics, "blocks_sequenced")
+            })
+            .collect::<Vec<_>>();
+        tracing::info!("blocks sequenced by each instance: {sequenced:?}");
+        assert!(sequenced.iter().all(|&blocks| blocks > 0));
+        assert_eq!(sequenced.iter().sum::<usize>(), 6);
+        for i in 0..6 {
+            assert_eq!(
+                l1.hotshot.commitments(i.into()).call().await.unwrap(),
+                commitment_to_u256(data.leaves[i as usize].as_ref().unwrap().block_hash())
+            );
+        }
+    }
+
+    #[async_std::test]
+    async fn test_forged_leaves() {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+
+        let l1 = TestL1System::deploy(anvil.provider()).await.unwrap();
+        let l1_initial_block = l1.provider.get_block_number().await.unwrap();
+        let adaptor_l1_signer = Arc::new(
+            init_signer(l1.provider.url(), TEST_MNEMONIC, l1.clients.funded[0].index)
+                .await
+                .unwrap(),
+        );
+
+        let node_state = NodeState::mock().with_l1(L1Client::new(
+            anvil.provider().url().clone(),
+            Address::default(),
+        ));
+
+        // Create a sequence of leaves where the second is not justified by its QC.
+        let mut data = MockDataSource::default();
+        data.leaves.extend([
+            Some(mock_leaf(0, &node_state)),
+            Some(forged_leaf(1, &node_state)),
+        ]);
+
+        // Connect to the HotShot contract with the expected L1 client.
+        let hotshot = HotShot::new(l1.hotshot.address(), adaptor_l1_signer);
+        let ctx = SyncContext::default();
+
+        // Only the valid prefix is sequenced.
+        sync_with_l1(2, &data, &hotshot, &ctx).await.unwrap();
+        let event = wait_for_new_batches(&l1, l1_initial_block.as_u64()).await.0;
+        assert_eq!(event.first_block_number, 0.into());
+        assert_eq!(event.num_blocks, 1.into());
+
+        // Now the forged leaf is first, so we cannot make any progress.
+        sync_with_l1(2, &data, &hotshot, &ctx).await.unwrap_err();
+        assert_eq!(l1.hotshot.block_height().call().await.unwrap().as_u64(), 1);
+        assert_eq!(
+            l1.hotshot.commitments(1.into()).call().await.unwrap(),
+            0.into()
+        );
+
+        // Nor with a forged leaf whose QC claims to sign it, but whose signature is for the honest
+        // leaf.
+        data.leaves[1] = Some(relabeled_leaf(1, &node_state));
+        sync_with_l1(2, &data, &hotshot, &ctx).await.unwrap_err();
+        assert_eq!(l1.hotshot.block_height().call().await.unwrap().as_u64(), 1);
+
+        // The honest leaf can still be sequenced.
+        data.leaves[1] = Some(mock_leaf(1, &node_state));
+        sync_with_l1(2, &data, &hotshot, &ctx).await.unwrap();
+        assert_eq!(l1.hotshot.block_height().call().await.unwrap().as_u64(), 2);
+    }
+
+    #[test]
+    fn test_turn_taking() {
+        let slot = Duration::from_secs(10);
+        let turns = |instance_index| TurnOptions {
+            instance_index,
+            num_instances: 3,
+            slot_duration: slot,
+        };
+        let at = |secs| Duration::from_secs(secs);
+
+        // Slot 4 (40s-50s) belongs to instance 1, which may start a submission in the first half.
+        assert_eq!(turns(1).time_until_turn(at(40)), Duration::ZERO);
+        assert_eq!(turns(1).time_until_turn(at(44)), Duration::ZERO);
+        // In the second half of its slot, instance 1 waits for its next turn, in slot 7.
+        assert_eq!(turns(1).time_until_turn(at(45)), at(25));
+        // The other instances wait for the next slots.
+        assert_eq!(turns(2).time_until_turn(at(42)), at(8));
+        assert_eq!(turns(0).time_until_turn(at(42)), at(18));
+
+        // With a single instance, every slot is our turn.
+        let single = TurnOptions {
+            instance_index: 0,
+            num_instances: 1,
+            slot_duration: slot,
+        };
+        assert_eq!(single.time_until_turn(at(42)), Duration::ZERO);
+        assert_eq!(single.time_until_turn(at(47)), at(3));
+
+        // An instance which may submit has until the end of its slot.
+        assert_eq!(turns(1).time_left_in_turn(at(42)), Some(at(8)));
+        assert_eq!(turns(1).time_left_in_turn(at(45)), None);
+        assert_eq!(turns(2).time_left_in_turn(at(42)), None);
+    }
+
+    #[test]
+    fn test_verify_qc() {
+        let node_state = NodeState::mock();
+        let genesis = Leaf::genesis(&node_state).commit();
+        let verifier = QcVerifier::new(mock_stake_table(&mock_keys(0, MOCK_NODES)), genesis);
+
+        verifier.verify(&mock_leaf(0, &node_state)).unwrap();
+        verifier.verify(&forged_leaf(0, &node_state)).unwrap_err();
+        verifier.verify(&mock_leaf(1, &node_state)).unwrap();
+        verifier.verify(&forged_leaf(1, &node_state)).unwrap_err();
+        verifier
+            .verify(&relabeled_leaf(1, &node_state))
+            .unwrap_err();
+
+        // A QC for the right leaf in the wrong view.
+        let leaf = mock_leaf(0, &node_state);
+        let mut qc = leaf.qc().clone();
+        qc.view_number = ViewNumber::new(1);
+        verifier
+            .verify(&LeafQueryData::new(leaf.leaf().clone(), qc).unwrap())
+            .unwrap_err();
+
+        // A leaf at height 0 which is not the genesis leaf.
+        let mut leaf = Leaf::genesis(&node_state);
+        leaf.get_block_header_mut().timestamp += 1;
+        let mut qc = QuorumCertificate::genesis();
+        qc.data.leaf_commit = leaf.commit();
+        verifier
+            .verify(&LeafQueryData::new(leaf, qc).unwrap())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_verify_qc_signatures() {
+        let node_state = NodeState::mock();
+        let genesis = Leaf::genesis(&node_state).commit();
+        let keys = mock_keys(0, 5);
+        let verifier = QcVerifier::new(mock_stake_table(&keys), genesis);
+
+        // The genesis leaf needs no signature.
+        verifier.verify(&mock_leaf(0, &node_state)).unwrap();
+        // Any other leaf needs a signature from the stake table.
+        verifier
+            .verify(&signed_leaf(1, &node_state, &keys))
+            .unwrap();
+
+        // An unsigned genesis QC cannot justify a leaf after genesis.
+        verifier.verify(&unsigned_leaf(1, &node_state)).unwrap_err();
+        // Nor can a QC signed by nodes outside the stake table.
+        verifier
+            .verify(&signed_leaf(1, &node_state, &mock_keys(1, 5)))
+            .unwrap_err();
+        // Nor can a QC whose signature is for different data.
+        let leaf = signed_leaf(1, &node_state, &keys);
+        let mut qc = leaf.qc().clone();
+        qc.vote_commitment = signed_leaf(2, &node_state, &keys).qc().vote_commitment;
+        verifier
+            .verify(&LeafQueryData::new(leaf.leaf().clone(), qc).unwrap())
+            .unwrap_err();
+    }
 }
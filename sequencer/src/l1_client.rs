@@ -17,12 +17,16 @@
 //!   will still be able to propose on time.
 
 use crate::state::FeeInfo;
-use async_std::task::sleep;
+use async_std::{sync::RwLock, task::sleep};
 use commit::{Commitment, Committable, RawCommitmentBuilder};
 use ethers::prelude::*;
 use futures::join;
 use serde::{Deserialize, Serialize};
-use std::{cmp::Ordering, sync::Arc, time::Duration};
+use std::{
+    cmp::Ordering,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use url::Url;
 
 #[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Hash, PartialEq, Eq)]
@@ -93,6 +97,8 @@ pub struct L1Client {
     provider: Provider<Http>,
     /// `Address` of fee contract.
     _address: Address,
+    /// The most recent L1 head we have successfully fetched, and when we fetched it.
+    last_head: Arc<RwLock<Option<(u64, Instant)>>>,
 }
 
 impl L1Client {
@@ -102,6 +108,7 @@ impl L1Client {
             retry_delay: Duration::from_secs(1),
             provider: Provider::new(Http::new(url)),
             _address: contract_address,
+            last_head: Default::default(),
         }
     }
     /// Get a snapshot from the l1.
@@ -112,8 +119,8 @@ impl L1Client {
     /// Proxy to `Provider.get_block_number`.
     async fn get_block_number(&self) -> u64 {
         loop {
-            match self.provider.get_block_number().await {
-                Ok(n) => return n.as_u64(),
+            match self.probe().await {
+                Ok(n) => return n,
                 Err(e) => {
                     tracing::warn!("Blocknumber error: {}", e);
                     sleep(self.retry_delay).await;
@@ -121,6 +128,18 @@ impl L1Client {
             }
         }
     }
+
+    /// Fetch the current L1 head, without retrying on failure.
+    pub async fn probe(&self) -> Result<u64, ProviderError> {
+        let head = self.provider.get_block_number().await?.as_u64();
+        *self.last_head.write().await = Some((head, Instant::now()));
+        Ok(head)
+    }
+
+    /// The most recent L1 head fetched by this client, and when it was fetched.
+    pub async fn last_head(&self) -> Option<(u64, Instant)> {
+        *self.last_head.read().await
+    }
     /// Proxy to `get_finalized_block`.
     async fn get_finalized_block(&self) -> Option<L1BlockInfo> {
         loop {
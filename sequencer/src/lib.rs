@@ -1,10 +1,14 @@
 pub mod api;
+pub mod archive;
 pub mod block;
 pub mod catchup;
 mod chain_variables;
 pub mod context;
 mod header;
+pub mod health;
 pub mod hotshot_commitment;
+pub mod logging;
+pub mod network_metrics;
 pub mod options;
 pub mod state_signature;
 
@@ -20,6 +24,7 @@ use ethers::{
 // Should move `STAKE_TABLE_CAPACITY` in the sequencer repo when we have variate stake table support
 
 use l1_client::L1Client;
+use network_metrics::{ChannelMetrics, MeteredNetwork};
 
 use state_signature::static_stake_table_commitment;
 use url::Url;
@@ -27,14 +32,15 @@ pub mod l1_client;
 pub mod persistence;
 pub mod state;
 pub mod transaction;
-use async_trait::async_trait;
 
-use async_std::sync::RwLock;
 use derivative::Derivative;
 use hotshot::{
     traits::{
         election::static_committee::{GeneralStaticCommittee, StaticElectionConfig},
-        implementations::{MemoryNetwork, NetworkingMetricsValue, WebServerNetwork},
+        implementations::{
+            derive_libp2p_peer_id, CombinedNetworks, Libp2pNetwork, MemoryNetwork,
+            NetworkingMetricsValue, UnderlyingCombinedNetworks, WebServerNetwork,
+        },
     },
     types::SignatureKey,
     Networks,
@@ -44,30 +50,25 @@ use hotshot_orchestrator::{
     config::NetworkConfig,
 };
 use hotshot_types::{
-    consensus::CommitmentMap,
     constants::WebServerVersion,
-    data::{DAProposal, VidDisperseShare, ViewNumber},
-    event::HotShotAction,
+    data::ViewNumber,
     light_client::{StateKeyPair, StateSignKey},
-    message::Proposal,
     signature_key::{BLSPrivKey, BLSPubKey},
-    simple_certificate::QuorumCertificate,
     traits::{
         metrics::Metrics,
         network::ConnectedNetwork,
         node_implementation::{NodeImplementation, NodeType},
         states::InstanceState,
-        storage::Storage,
     },
-    utils::View,
     ValidatorConfig,
 };
-use persistence::SequencerPersistence;
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use persistence::{PersistentStorage, SequencerPersistence};
 use serde::{Deserialize, Serialize};
 use snafu::Snafu;
-use std::collections::HashMap;
 use std::marker::PhantomData;
-use std::{collections::BTreeMap, time::Duration};
+use std::net::SocketAddr;
+use std::time::Duration;
 use std::{fmt::Debug, sync::Arc};
 use versioned_binary_serialization::version::StaticVersionType;
 
@@ -79,6 +80,8 @@ pub use options::Options;
 pub use state::ValidatedState;
 pub use transaction::{NamespaceId, Transaction};
 pub mod network {
+    use anyhow::{bail, Context};
+    use async_trait::async_trait;
     use hotshot_types::message::Message;
 
     use super::*;
@@ -88,12 +91,101 @@ pub mod network {
         type QuorumChannel: ConnectedNetwork<Message<SeqTypes>, PubKey> + Debug;
     }
 
+    /// A network type which a node can join using only its [`NetworkParams`].
+    ///
+    /// This is implemented for all the network types which can be selected on the command line. The
+    /// in-memory network, which is only useful for tests, does not implement this trait.
+    #[async_trait]
+    pub trait Init: Type + Sized {
+        async fn connect(
+            params: &NetworkParams,
+            config: &NetworkConfig<PubKey, ElectionConfig>,
+            validator: &ValidatorConfig<PubKey>,
+            metrics: &dyn Metrics,
+        ) -> anyhow::Result<Networks<SeqTypes, Node<Self>>>;
+    }
+
     #[derive(Clone, Copy, Debug, Default)]
     pub struct Web;
 
     impl Type for Web {
-        type DAChannel = WebServerNetwork<SeqTypes, WebServerVersion>;
-        type QuorumChannel = WebServerNetwork<SeqTypes, WebServerVersion>;
+        type DAChannel = MeteredNetwork<WebServerNetwork<SeqTypes, WebServerVersion>>;
+        type QuorumChannel = MeteredNetwork<WebServerNetwork<SeqTypes, WebServerVersion>>;
+    }
+
+    #[async_trait]
+    impl Init for Web {
+        async fn connect(
+            params: &NetworkParams,
+            _config: &NetworkConfig<PubKey, ElectionConfig>,
+            validator: &ValidatorConfig<PubKey>,
+            metrics: &dyn Metrics,
+        ) -> anyhow::Result<Networks<SeqTypes, Node<Self>>> {
+            let (da, quorum) = web(params, validator);
+            Ok(metered(da, quorum, metrics))
+        }
+    }
+
+    /// A peer-to-peer network, in which nodes gossip directly with each other.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Libp2p;
+
+    impl Type for Libp2p {
+        type DAChannel = Libp2pNetwork<Message<SeqTypes>, PubKey>;
+        type QuorumChannel = Libp2pNetwork<Message<SeqTypes>, PubKey>;
+    }
+
+    #[async_trait]
+    impl Init for Libp2p {
+        async fn connect(
+            params: &NetworkParams,
+            config: &NetworkConfig<PubKey, ElectionConfig>,
+            validator: &ValidatorConfig<PubKey>,
+            metrics: &dyn Metrics,
+        ) -> anyhow::Result<Networks<SeqTypes, Node<Self>>> {
+            // HotShot's libp2p network does not yet report metrics to our registry. By creating and
+            // dropping a `NetworkingMetricsValue`, we ensure the networking metrics are created,
+            // but just not populated, so that monitoring software built to work with
+            // network-related metrics doesn't crash just because they are missing.
+            let _ = NetworkingMetricsValue::new(metrics);
+
+            let network = Arc::new(libp2p(params, config, validator).await?);
+            Ok(Networks {
+                da_network: network.clone(),
+                quorum_network: network,
+                _pd: Default::default(),
+            })
+        }
+    }
+
+    /// A combination of the web server and peer-to-peer networks.
+    ///
+    /// Messages are sent over the peer-to-peer network, falling back to the web server if the
+    /// peer-to-peer network fails to deliver them.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Combined;
+
+    impl Type for Combined {
+        type DAChannel = MeteredNetwork<CombinedNetworks<SeqTypes>>;
+        type QuorumChannel = MeteredNetwork<CombinedNetworks<SeqTypes>>;
+    }
+
+    #[async_trait]
+    impl Init for Combined {
+        async fn connect(
+            params: &NetworkParams,
+            config: &NetworkConfig<PubKey, ElectionConfig>,
+            validator: &ValidatorConfig<PubKey>,
+            metrics: &dyn Metrics,
+        ) -> anyhow::Result<Networks<SeqTypes, Node<Self>>> {
+            let (web_da, web_quorum) = web(params, validator);
+            let p2p = libp2p(params, config, validator).await?;
+            let da =
+                CombinedNetworks::new(Arc::new(UnderlyingCombinedNetworks(web_da, p2p.clone())));
+            let quorum =
+                CombinedNetworks::new(Arc::new(UnderlyingCombinedNetworks(web_quorum, p2p)));
+            Ok(metered(da, quorum, metrics))
+        }
     }
 
     #[derive(Clone, Copy, Debug, Default)]
@@ -103,6 +195,91 @@ pub mod network {
         type DAChannel = MemoryNetwork<Message<SeqTypes>, PubKey>;
         type QuorumChannel = MemoryNetwork<Message<SeqTypes>, PubKey>;
     }
+
+    fn web(
+        params: &NetworkParams,
+        validator: &ValidatorConfig<PubKey>,
+    ) -> (
+        WebServerNetwork<SeqTypes, WebServerVersion>,
+        WebServerNetwork<SeqTypes, WebServerVersion>,
+    ) {
+        let da = WebServerNetwork::create(
+            params.da_server_url.clone(),
+            params.webserver_poll_interval,
+            validator.public_key,
+            true,
+        );
+        let quorum = WebServerNetwork::create(
+            params.consensus_server_url.clone(),
+            params.webserver_poll_interval,
+            validator.public_key,
+            false,
+        );
+        (da, quorum)
+    }
+
+    /// Wrap the DA and quorum channels of a network so that each records [`ChannelMetrics`].
+    ///
+    /// The web server network doesn't record any metrics of its own, so we count the messages
+    /// passing through each channel which uses it, and time the requests it makes.
+    fn metered<N, C>(da: C, quorum: C, metrics: &dyn Metrics) -> Networks<SeqTypes, Node<N>>
+    where
+        N: Type<DAChannel = MeteredNetwork<C>, QuorumChannel = MeteredNetwork<C>>,
+    {
+        let networking = Arc::new(NetworkingMetricsValue::new(metrics));
+        let da = MeteredNetwork::new(da, ChannelMetrics::new(metrics, "da", networking.clone()));
+        let quorum =
+            MeteredNetwork::new(quorum, ChannelMetrics::new(metrics, "quorum", networking));
+        Networks {
+            da_network: Arc::new(da),
+            quorum_network: Arc::new(quorum),
+            _pd: Default::default(),
+        }
+    }
+
+    async fn libp2p(
+        params: &NetworkParams,
+        config: &NetworkConfig<PubKey, ElectionConfig>,
+        validator: &ValidatorConfig<PubKey>,
+    ) -> anyhow::Result<Libp2pNetwork<Message<SeqTypes>, PubKey>> {
+        let mut config = config.clone();
+        if !params.libp2p_bootstrap_nodes.is_empty() {
+            let libp2p_config = config
+                .libp2p_config
+                .as_mut()
+                .context("network config has no libp2p section")?;
+            libp2p_config.bootstrap_nodes = params
+                .libp2p_bootstrap_nodes
+                .iter()
+                .map(parse_bootstrap_node)
+                .collect::<anyhow::Result<_>>()?;
+        }
+        tracing::info!(
+            bind_address = %params.libp2p_bind_address,
+            peer_id = %derive_libp2p_peer_id::<PubKey>(&validator.private_key)?,
+            "joining libp2p network",
+        );
+        Libp2pNetwork::from_config::<SeqTypes>(
+            config,
+            params.libp2p_bind_address,
+            &validator.public_key,
+            &validator.private_key,
+        )
+        .await
+        .context("failed to join libp2p network")
+    }
+
+    /// Split a bootstrap node address into the peer ID and the address at which to reach it.
+    ///
+    /// The address must end in a `/p2p/<peer id>` component, as in
+    /// `/ip4/10.0.0.1/udp/1769/quic-v1/p2p/12D3KooW...`.
+    pub fn parse_bootstrap_node(addr: &Multiaddr) -> anyhow::Result<(PeerId, Multiaddr)> {
+        let mut base = addr.clone();
+        match base.pop() {
+            Some(Protocol::P2p(peer_id)) => Ok((peer_id, base)),
+            _ => bail!("bootstrap node address {addr} does not end with a peer ID"),
+        }
+    }
 }
 
 /// The Sequencer node is generic over the hotshot CommChannel.
@@ -138,86 +315,10 @@ pub type PrivKey = <PubKey as SignatureKey>::PrivateKey;
 
 type ElectionConfig = StaticElectionConfig;
 
-#[derive(Clone, Debug)]
-pub struct ToBeReplacedStorageState<TYPES: NodeType> {
-    vids: HashMap<TYPES::Time, Proposal<TYPES, VidDisperseShare<TYPES>>>,
-    das: HashMap<TYPES::Time, Proposal<TYPES, DAProposal<TYPES>>>,
-}
-
-impl<TYPES: NodeType> Default for ToBeReplacedStorageState<TYPES> {
-    fn default() -> Self {
-        Self {
-            vids: HashMap::new(),
-            das: HashMap::new(),
-        }
-    }
-}
-
-#[derive(Clone, Debug)]
-pub struct ToBeReplacedStorage<TYPES: NodeType> {
-    inner: Arc<RwLock<ToBeReplacedStorageState<TYPES>>>,
-}
-
-impl<TYPES: NodeType> Default for ToBeReplacedStorage<TYPES> {
-    fn default() -> Self {
-        Self {
-            inner: Arc::new(RwLock::new(ToBeReplacedStorageState::default())),
-        }
-    }
-}
-
-#[async_trait]
-impl Storage<SeqTypes> for ToBeReplacedStorage<SeqTypes> {
-    async fn append_vid(
-        &self,
-        proposal: &Proposal<SeqTypes, VidDisperseShare<SeqTypes>>,
-    ) -> anyhow::Result<()> {
-        let mut inner = self.inner.write().await;
-        inner
-            .vids
-            .insert(proposal.data.view_number, proposal.clone());
-        Ok(())
-    }
-
-    async fn append_da(
-        &self,
-        proposal: &Proposal<SeqTypes, DAProposal<SeqTypes>>,
-    ) -> anyhow::Result<()> {
-        let mut inner = self.inner.write().await;
-        inner
-            .das
-            .insert(proposal.data.view_number, proposal.clone());
-        Ok(())
-    }
-
-    async fn record_action(
-        &self,
-        _view: <SeqTypes as hotshot_types::traits::node_implementation::NodeType>::Time,
-        _action: HotShotAction,
-    ) -> anyhow::Result<()> {
-        Ok(())
-    }
-    async fn update_high_qc(&self, _high_qc: QuorumCertificate<SeqTypes>) -> anyhow::Result<()> {
-        Ok(())
-    }
-    /// Update the currently undecided state of consensus.  This includes the undecided leaf chain,
-    /// and the undecided state.
-    async fn update_undecided_state(
-        &self,
-        _leaves: CommitmentMap<Leaf>,
-        _state: BTreeMap<
-            <SeqTypes as hotshot_types::traits::node_implementation::NodeType>::Time,
-            View<SeqTypes>,
-        >,
-    ) -> anyhow::Result<()> {
-        Ok(())
-    }
-}
-
 impl<N: network::Type> NodeImplementation<SeqTypes> for Node<N> {
     type QuorumNetwork = N::QuorumChannel;
     type CommitteeNetwork = N::DAChannel;
-    type Storage = ToBeReplacedStorage<SeqTypes>;
+    type Storage = PersistentStorage;
 }
 
 #[derive(Debug, Clone)]
@@ -320,6 +421,23 @@ pub struct NetworkParams {
     pub private_staking_key: BLSPrivKey,
     pub private_state_key: StateSignKey,
     pub state_peers: Vec<Url>,
+    /// Query service used to verify a state snapshot, if this node should bootstrap from one.
+    ///
+    /// If set and the node has no saved consensus state, it fetches a snapshot of the decided state
+    /// from `state_peers` and checks it against the leaf from this query service, instead of
+    /// starting from genesis.
+    pub snapshot_trusted_url: Option<Url>,
+    /// Local address for the libp2p network to listen on.
+    ///
+    /// Only used if the node joins a network which includes libp2p.
+    pub libp2p_bind_address: SocketAddr,
+    /// Address at which other nodes can reach this node over libp2p, if different from the bind
+    /// address.
+    pub libp2p_advertise_address: Option<SocketAddr>,
+    /// Peers to bootstrap the libp2p network from.
+    ///
+    /// If empty, the bootstrap nodes provided by the orchestrator are used.
+    pub libp2p_bootstrap_nodes: Vec<Multiaddr>,
 }
 
 #[derive(Clone, Debug)]
@@ -333,26 +451,35 @@ pub struct L1Params {
     pub url: Url,
 }
 
-pub async fn init_node<Ver: StaticVersionType + 'static>(
+/// The genesis state of a chain which prefunds `prefunded_accounts`.
+pub fn genesis_state(prefunded_accounts: impl IntoIterator<Item = Address>) -> ValidatedState {
+    let mut state = ValidatedState::default();
+    for address in prefunded_accounts {
+        state.prefund_account(address.into(), U256::max_value().into());
+    }
+    state
+}
+
+pub async fn init_node<N: network::Init, Ver: StaticVersionType + 'static>(
     network_params: NetworkParams,
     metrics: &dyn Metrics,
     mut persistence: impl SequencerPersistence,
     builder_params: BuilderParams,
     l1_params: L1Params,
     bind_version: Ver,
-) -> anyhow::Result<SequencerContext<network::Web, Ver>> {
+) -> anyhow::Result<SequencerContext<N, Ver>> {
     // Orchestrator client
     let validator_args = ValidatorArgs {
-        url: network_params.orchestrator_url,
-        advertise_address: None,
+        url: network_params.orchestrator_url.clone(),
+        advertise_address: network_params.libp2p_advertise_address,
         network_config_file: None,
     };
 
     let orchestrator_client = OrchestratorClient::new(validator_args);
-    let state_key_pair = StateKeyPair::from_sign_key(network_params.private_state_key);
+    let state_key_pair = StateKeyPair::from_sign_key(network_params.private_state_key.clone());
     let my_config = ValidatorConfig {
         public_key: BLSPubKey::from_private(&network_params.private_staking_key),
-        private_key: network_params.private_staking_key,
+        private_key: network_params.private_staking_key.clone(),
         stake_value: 1,
         state_key_pair,
     };
@@ -385,27 +512,7 @@ pub async fn init_node<Ver: StaticVersionType + 'static>(
     let node_index = config.node_index;
 
     // Initialize networking.
-    let networks = Networks {
-        da_network: Arc::new(WebServerNetwork::create(
-            network_params.da_server_url,
-            network_params.webserver_poll_interval,
-            my_config.public_key,
-            true,
-        )),
-        quorum_network: Arc::new(WebServerNetwork::create(
-            network_params.consensus_server_url,
-            network_params.webserver_poll_interval,
-            my_config.public_key,
-            false,
-        )),
-        _pd: Default::default(),
-    };
-
-    // The web server network doesn't have any metrics. By creating and dropping a
-    // `NetworkingMetricsValue`, we ensure the networking metrics are created, but just not
-    // populated, so that monitoring software built to work with network-related metrics doesn't
-    // crash horribly just because we're not using the P2P network yet.
-    let _ = NetworkingMetricsValue::new(metrics);
+    let networks = N::connect(&network_params, &config, &my_config, metrics).await?;
 
     let wallet = MnemonicBuilder::<English>::default()
         .phrase::<&str>(&builder_params.mnemonic)
@@ -413,25 +520,39 @@ pub async fn init_node<Ver: StaticVersionType + 'static>(
         .build()?;
     tracing::info!("Builder account address {:?}", wallet.address());
 
-    let mut genesis_state = ValidatedState::default();
-    for address in builder_params.prefunded_accounts {
+    for address in &builder_params.prefunded_accounts {
         tracing::warn!("Prefunding account {:?} for demo", address);
-        genesis_state.prefund_account(address.into(), U256::max_value().into());
     }
+    let genesis_state = genesis_state(builder_params.prefunded_accounts);
 
     let l1_client = L1Client::new(l1_params.url, Address::default());
+    let peers = StatePeers::<Ver>::from_urls(network_params.state_peers);
+
+    let snapshot = match network_params.snapshot_trusted_url {
+        Some(trusted) => {
+            if persistence.load_anchor_leaf().await?.is_some() {
+                tracing::info!("found saved consensus state, not bootstrapping from snapshot");
+                None
+            } else {
+                tracing::info!(%trusted, "bootstrapping from state snapshot");
+                Some(peers.fetch_snapshot(trusted).await)
+            }
+        }
+        None => None,
+    };
 
     let instance_state = NodeState {
         l1_client,
         builder_address: wallet,
         genesis_state,
-        peers: Arc::new(StatePeers::<Ver>::from_urls(network_params.state_peers)),
+        peers: Arc::new(peers),
     };
 
     let mut ctx = SequencerContext::init(
         config.config,
         instance_state,
         persistence,
+        snapshot,
         networks,
         Some(network_params.state_relay_server_url),
         metrics,
@@ -448,7 +569,10 @@ pub async fn init_node<Ver: StaticVersionType + 'static>(
 #[cfg(any(test, feature = "testing"))]
 pub mod testing {
     use super::*;
-    use crate::{catchup::mock::MockStateCatchup, persistence::no_storage::NoStorage};
+    use crate::{
+        api::endpoints::StateSnapshot, catchup::mock::MockStateCatchup,
+        persistence::no_storage::NoStorage,
+    };
     use commit::Committable;
     use ethers::utils::{Anvil, AnvilInstance};
     use futures::{
@@ -460,11 +584,12 @@ pub mod testing {
         BlockPayload,
     };
     use hotshot::types::{EventType::Decide, Message};
+    use hotshot_orchestrator::config::Libp2pConfig;
     use hotshot_types::{
         event::LeafInfo,
         light_client::StateKeyPair,
         traits::{block_contents::BlockHeader, metrics::NoMetrics},
-        ExecutionType, HotShotConfig, PeerConfig, ValidatorConfig,
+        ExecutionType, HotShotConfig, PeerConfig,
     };
     use std::time::Duration;
 
@@ -571,19 +696,66 @@ pub mod testing {
             metrics: &dyn Metrics,
             bind_version: Ver,
         ) -> SequencerContext<network::Memory, Ver> {
-            let mut config = self.config.clone();
-            config.my_own_validator_config = ValidatorConfig {
-                public_key: config.known_nodes_with_stake[i].stake_table_entry.stake_key,
-                private_key: self.priv_keys[i].clone(),
-                stake_value: config.known_nodes_with_stake[i]
-                    .stake_table_entry
-                    .stake_amount
-                    .as_u64(),
-                state_key_pair: self.state_key_pairs[i].clone(),
+            let network = Arc::new(MemoryNetwork::new(
+                self.validator_config(i).public_key,
+                NetworkingMetricsValue::new(metrics),
+                self.master_map.clone(),
+                None,
+            ));
+            let networks = Networks {
+                da_network: network.clone(),
+                quorum_network: network,
+                _pd: Default::default(),
             };
+            self.init_node_with_networks(
+                i,
+                networks,
+                state,
+                persistence,
+                catchup,
+                metrics,
+                bind_version,
+            )
+            .await
+        }
+
+        /// Initialize node `i` on an arbitrary, already connected network.
+        #[allow(clippy::too_many_arguments)]
+        pub async fn init_node_with_networks<N: network::Type, Ver: StaticVersionType + 'static>(
+            &self,
+            i: usize,
+            networks: Networks<SeqTypes, Node<N>>,
+            state: ValidatedState,
+            persistence: impl SequencerPersistence,
+            catchup: impl StateCatchup + 'static,
+            metrics: &dyn Metrics,
+            bind_version: Ver,
+        ) -> SequencerContext<N, Ver> {
+            self.init_node_with_snapshot(
+                i,
+                networks,
+                state,
+                None,
+                persistence,
+                catchup,
+                metrics,
+                bind_version,
+            )
+            .await
+        }
 
+        /// Initialize node `i` on the in-memory network, bootstrapping it from `snapshot`.
+        pub async fn init_node_from_snapshot<Ver: StaticVersionType + 'static>(
+            &self,
+            i: usize,
+            snapshot: StateSnapshot,
+            persistence: impl SequencerPersistence,
+            catchup: impl StateCatchup + 'static,
+            metrics: &dyn Metrics,
+            bind_version: Ver,
+        ) -> SequencerContext<network::Memory, Ver> {
             let network = Arc::new(MemoryNetwork::new(
-                config.my_own_validator_config.public_key,
+                self.validator_config(i).public_key,
                 NetworkingMetricsValue::new(metrics),
                 self.master_map.clone(),
                 None,
@@ -593,11 +765,38 @@ pub mod testing {
                 quorum_network: network,
                 _pd: Default::default(),
             };
+            self.init_node_with_snapshot(
+                i,
+                networks,
+                ValidatedState::default(),
+                Some(snapshot),
+                persistence,
+                catchup,
+                metrics,
+                bind_version,
+            )
+            .await
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        async fn init_node_with_snapshot<N: network::Type, Ver: StaticVersionType + 'static>(
+            &self,
+            i: usize,
+            networks: Networks<SeqTypes, Node<N>>,
+            state: ValidatedState,
+            snapshot: Option<StateSnapshot>,
+            persistence: impl SequencerPersistence,
+            catchup: impl StateCatchup + 'static,
+            metrics: &dyn Metrics,
+            bind_version: Ver,
+        ) -> SequencerContext<N, Ver> {
+            let mut config = self.config.clone();
+            config.my_own_validator_config = self.validator_config(i);
 
             let wallet = Self::builder_wallet(i);
             tracing::info!("node {i} is builder {:x}", wallet.address());
             let node_state = NodeState::new(
-                L1Client::new(self.anvil.endpoint().parse().unwrap(), Address::default()),
+                L1Client::new(self.l1_url(), Address::default()),
                 wallet,
                 catchup,
             )
@@ -607,6 +806,7 @@ pub mod testing {
                 config,
                 node_state,
                 persistence,
+                snapshot,
                 networks,
                 None,
                 metrics,
@@ -617,6 +817,51 @@ pub mod testing {
             .unwrap()
         }
 
+        /// The URL of the L1 used by the test nodes.
+        pub fn l1_url(&self) -> Url {
+            self.anvil.endpoint().parse().unwrap()
+        }
+
+        pub fn validator_config(&self, i: usize) -> ValidatorConfig<PubKey> {
+            let stake_table_entry = &self.config.known_nodes_with_stake[i].stake_table_entry;
+            ValidatorConfig {
+                public_key: stake_table_entry.stake_key,
+                private_key: self.priv_keys[i].clone(),
+                stake_value: stake_table_entry.stake_amount.as_u64(),
+                state_key_pair: self.state_key_pairs[i].clone(),
+            }
+        }
+
+        /// The network config which the orchestrator would give to node `i`.
+        ///
+        /// The libp2p bootstrap nodes are left empty, so they must be provided separately via
+        /// [`NetworkParams::libp2p_bootstrap_nodes`].
+        pub fn network_config(&self, i: usize) -> NetworkConfig<PubKey, ElectionConfig> {
+            let mut config = self.config.clone();
+            config.my_own_validator_config = self.validator_config(i);
+            NetworkConfig {
+                config,
+                node_index: i as u64,
+                libp2p_config: Some(Libp2pConfig {
+                    bootstrap_nodes: vec![],
+                    node_index: i as u64,
+                    bootstrap_mesh_n_high: 4,
+                    bootstrap_mesh_n_low: 4,
+                    bootstrap_mesh_outbound_min: 2,
+                    bootstrap_mesh_n: 4,
+                    mesh_n_high: 4,
+                    mesh_n_low: 4,
+                    mesh_outbound_min: 2,
+                    mesh_n: 4,
+                    next_view_timeout: self.config.next_view_timeout,
+                    online_time: 10,
+                    num_txn_per_round: 0,
+                    server_mode: false,
+                }),
+                ..Default::default()
+            }
+        }
+
         pub fn builder_wallet(i: usize) -> Wallet<SigningKey> {
             MnemonicBuilder::<English>::default()
                 .phrase("test test test test test test test test test test test junk")
@@ -668,15 +913,21 @@ mod test {
     use super::*;
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
 
+    use crate::{catchup::mock::MockStateCatchup, persistence::no_storage::NoStorage};
     use es_version::SequencerVersion;
-    use futures::StreamExt;
+    use futures::{future::join_all, StreamExt};
     use hotshot::types::EventType::Decide;
     use hotshot_types::{
         event::LeafInfo,
-        traits::block_contents::{
-            vid_commitment, BlockHeader, BlockPayload, GENESIS_VID_NUM_STORAGE_NODES,
+        traits::{
+            block_contents::{
+                vid_commitment, BlockHeader, BlockPayload, GENESIS_VID_NUM_STORAGE_NODES,
+            },
+            metrics::NoMetrics,
         },
     };
+    use network::Init;
+    use portpicker::pick_unused_port;
     use testing::{wait_for_decide_on_handle, TestConfig};
 
     #[async_std::test]
@@ -704,6 +955,98 @@ mod test {
         wait_for_decide_on_handle(&mut events, &txn).await;
     }
 
+    #[async_std::test]
+    async fn test_libp2p_network() {
+        setup_logging();
+        setup_backtrace();
+
+        let ver = SequencerVersion::instance();
+        let config = TestConfig::default();
+        let num_nodes = config.num_nodes();
+
+        // Each node listens on its own local port, and every node bootstraps from all the others,
+        // so that the network can form without help from an orchestrator.
+        let addrs = (0..num_nodes)
+            .map(|_| SocketAddr::from(([127, 0, 0, 1], pick_unused_port().unwrap())))
+            .collect::<Vec<_>>();
+        let bootstrap_nodes = addrs
+            .iter()
+            .enumerate()
+            .map(|(i, addr)| {
+                let peer_id =
+                    derive_libp2p_peer_id::<PubKey>(&config.validator_config(i).private_key)
+                        .unwrap();
+                format!(
+                    "/ip4/{}/udp/{}/quic-v1/p2p/{peer_id}",
+                    addr.ip(),
+                    addr.port()
+                )
+                .parse()
+                .unwrap()
+            })
+            .collect::<Vec<Multiaddr>>();
+        for node in &bootstrap_nodes {
+            network::parse_bootstrap_node(node).unwrap();
+        }
+
+        let handles = join_all(addrs.into_iter().enumerate().map(|(i, addr)| {
+            let config = &config;
+            let bootstrap_nodes = bootstrap_nodes.clone();
+            async move {
+                let validator = config.validator_config(i);
+                let params = NetworkParams {
+                    // The web server URLs are unused by the libp2p network.
+                    da_server_url: "http://localhost:8081".parse().unwrap(),
+                    consensus_server_url: "http://localhost:8082".parse().unwrap(),
+                    orchestrator_url: "http://localhost:8080".parse().unwrap(),
+                    state_relay_server_url: "http://localhost:8083".parse().unwrap(),
+                    webserver_poll_interval: Duration::from_millis(100),
+                    private_staking_key: validator.private_key.clone(),
+                    private_state_key: validator.state_key_pair.sign_key_ref().clone(),
+                    state_peers: vec![],
+                    snapshot_trusted_url: None,
+                    libp2p_bind_address: addr,
+                    libp2p_advertise_address: None,
+                    libp2p_bootstrap_nodes: bootstrap_nodes,
+                };
+                let networks = network::Libp2p::connect(
+                    &params,
+                    &config.network_config(i),
+                    &validator,
+                    &NoMetrics,
+                )
+                .await
+                .unwrap();
+                config
+                    .init_node_with_networks(
+                        i,
+                        networks,
+                        ValidatedState::default(),
+                        NoStorage,
+                        MockStateCatchup::default(),
+                        &NoMetrics,
+                        ver,
+                    )
+                    .await
+            }
+        }))
+        .await;
+
+        let mut events = handles[0].get_event_stream();
+        for handle in handles.iter() {
+            handle.start_consensus().await;
+        }
+
+        // Submit a transaction to one node and wait for it to be decided, which requires the nodes
+        // to gossip with each other over libp2p.
+        let txn = Transaction::new(Default::default(), vec![1, 2, 3]);
+        handles[0]
+            .submit_transaction(txn.clone())
+            .await
+            .expect("Failed to submit transaction");
+        wait_for_decide_on_handle(&mut events, &txn).await;
+    }
+
     #[async_std::test]
     async fn test_header_invariants() {
         setup_logging();
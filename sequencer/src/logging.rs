@@ -0,0 +1,85 @@
+//! Logging with a filter which can be changed at runtime.
+//!
+//! [`init`] installs a global `tracing` subscriber configured the same way as
+//! [`setup_logging`](async_compatibility_layer::logging::setup_logging): the filter is read from
+//! `RUST_LOG` and the output format from `RUST_LOG_FORMAT`. Unlike that function, it keeps a handle
+//! to the filter, so that [`set_filter`] can later replace it (for example, from the admin API)
+//! without restarting the node.
+
+use anyhow::{bail, Context};
+use std::sync::OnceLock;
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+static FILTER: OnceLock<FilterHandle> = OnceLock::new();
+
+/// Install a global logger whose filter can be changed with [`set_filter`].
+///
+/// If a global logger has already been installed, this has no effect, and the filter cannot be
+/// changed at runtime.
+pub fn init() {
+    let (filter, handle) = reload::Layer::new(EnvFilter::from_default_env());
+    let registry = tracing_subscriber::registry().with(filter);
+    let res = match std::env::var("RUST_LOG_FORMAT").as_deref() {
+        Ok("json") => registry.with(fmt::layer().json()).try_init(),
+        Ok("compact") => registry.with(fmt::layer().compact()).try_init(),
+        _ => registry.with(fmt::layer()).try_init(),
+    };
+    if res.is_ok() {
+        FILTER.set(handle).ok();
+    }
+}
+
+/// The directives of the current log filter, if it can be changed at runtime.
+pub fn filter() -> Option<String> {
+    FILTER.get()?.with_current(|filter| filter.to_string()).ok()
+}
+
+/// Replace the log filter with `directives`, in the same format as `RUST_LOG`.
+pub fn set_filter(directives: &str) -> anyhow::Result<()> {
+    let filter = EnvFilter::try_new(directives).context("invalid log filter")?;
+    let Some(handle) = FILTER.get() else {
+        bail!("logging was not initialized with a reloadable filter");
+    };
+    reload_filter(handle, filter)?;
+    tracing::warn!(directives, "changed log filter");
+    Ok(())
+}
+
+fn reload_filter(handle: &FilterHandle, filter: EnvFilter) -> anyhow::Result<()> {
+    handle.reload(filter).context("failed to reload log filter")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tracing::Level;
+
+    #[test]
+    fn test_reload_filter() {
+        // Install a subscriber for this thread only, since other tests may have installed the
+        // global one.
+        let (filter, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let subscriber = tracing_subscriber::registry().with(filter);
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(tracing::enabled!(Level::INFO));
+            assert!(!tracing::enabled!(Level::DEBUG));
+
+            reload_filter(&handle, EnvFilter::try_new("debug").unwrap()).unwrap();
+            assert!(tracing::enabled!(Level::DEBUG));
+            assert_eq!(
+                handle.with_current(|filter| filter.to_string()).unwrap(),
+                "debug"
+            );
+        });
+    }
+
+    #[test]
+    fn test_set_invalid_filter() {
+        let err = set_filter("sequencer=not-a-level").unwrap_err();
+        assert!(err.to_string().contains("invalid log filter"), "{err:#}");
+    }
+}
@@ -1,28 +1,41 @@
-use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
-use clap::Parser;
+use async_compatibility_layer::logging::setup_backtrace;
 use es_version::SEQUENCER_VERSION;
-use futures::future::FutureExt;
+use futures::{
+    future::{self, Either, FutureExt},
+    stream::StreamExt,
+};
 use hotshot_types::traits::metrics::NoMetrics;
 use sequencer::{
     api::{self, data_source::DataSourceOptions},
     context::SequencerContext,
-    init_node, network,
-    options::{Modules, Options},
+    init_node, logging, network,
+    options::{Modules, NetworkType, Options},
     persistence, BuilderParams, L1Params, NetworkParams,
 };
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook_async_std::Signals;
 use versioned_binary_serialization::version::StaticVersionType;
 
 #[async_std::main]
 async fn main() -> anyhow::Result<()> {
-    setup_logging();
+    logging::init();
     setup_backtrace();
 
     tracing::info!("sequencer starting up");
-    let opt = Options::parse();
-    let mut modules = opt.modules();
+    let opt = Options::parse_with_config();
+    let modules = opt.modules();
     tracing::info!("modules: {:?}", modules);
 
-    let ctx = if let Some(storage) = modules.storage_fs.take() {
+    match opt.network {
+        NetworkType::Web => run::<network::Web>(modules, opt).await,
+        NetworkType::Libp2p => run::<network::Libp2p>(modules, opt).await,
+        NetworkType::Combined => run::<network::Combined>(modules, opt).await,
+    }
+}
+
+async fn run<N: network::Init>(mut modules: Modules, opt: Options) -> anyhow::Result<()> {
+    let shutdown_timeout = opt.shutdown_timeout;
+    let mut ctx: SequencerContext<N, _> = if let Some(storage) = modules.storage_fs.take() {
         init_with_storage(modules, opt, storage, SEQUENCER_VERSION).await?
     } else if let Some(storage) = modules.storage_sql.take() {
         init_with_storage(modules, opt, storage, SEQUENCER_VERSION).await?
@@ -38,18 +51,35 @@ async fn main() -> anyhow::Result<()> {
     };
 
     // Start doing consensus.
+    let mut signals = Signals::new([SIGTERM, SIGINT])?;
     ctx.start_consensus().await;
-    ctx.join().await;
+
+    // Run until we are asked to stop or a background task fails, then shut down without losing any
+    // data.
+    match future::select(Box::pin(signals.next()), Box::pin(ctx.task_exited())).await {
+        Either::Left((Some(signal), _)) => {
+            tracing::warn!(signal, "received signal, shutting down");
+        }
+        Either::Left((None, _)) => {
+            tracing::warn!("signal stream closed, shutting down");
+        }
+        Either::Right((task, _)) => {
+            tracing::error!(?task, "background task exited, shutting down");
+        }
+    }
+    signals.handle().close();
+    ctx.shut_down_gracefully(shutdown_timeout).await;
     Ok(())
 }
 
-async fn init_with_storage<S, Ver: StaticVersionType + 'static>(
+async fn init_with_storage<N, S, Ver: StaticVersionType + 'static>(
     modules: Modules,
     opt: Options,
     storage_opt: S,
     bind_version: Ver,
-) -> anyhow::Result<SequencerContext<network::Web, Ver>>
+) -> anyhow::Result<SequencerContext<N, Ver>>
 where
+    N: network::Init,
     S: DataSourceOptions,
 {
     let (private_staking_key, private_state_key) = opt.private_keys()?;
@@ -70,6 +100,10 @@ where
         private_staking_key,
         private_state_key,
         state_peers: opt.state_peers,
+        snapshot_trusted_url: opt.snapshot_trusted_url,
+        libp2p_bind_address: opt.libp2p_bind_address,
+        libp2p_advertise_address: opt.libp2p_advertise_address,
+        libp2p_bootstrap_nodes: opt.libp2p_bootstrap_nodes,
     };
 
     // Inititialize HotShot. If the user requested the HTTP module, we must initialize the handle in
@@ -94,6 +128,12 @@ where
             if let Some(catchup) = modules.catchup {
                 opt = opt.catchup(catchup);
             }
+            if let Some(health) = modules.health {
+                opt = opt.health(health);
+            }
+            if let Some(admin) = modules.admin {
+                opt = opt.admin(admin);
+            }
             if let Some(hotshot_events) = modules.hotshot_events {
                 opt = opt.hotshot_events(hotshot_events);
             }
@@ -102,7 +142,7 @@ where
             opt.serve(
                 move |metrics| {
                     async move {
-                        init_node(
+                        init_node::<N, _>(
                             network_params,
                             &*metrics,
                             storage,
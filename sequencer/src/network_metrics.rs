@@ -0,0 +1,352 @@
+//! Metrics for networks which don't report their own.
+//!
+//! The web server network does not populate any networking metrics on its own. To get visibility
+//! into it, we wrap each channel in a [`MeteredNetwork`], which counts messages and bytes and times
+//! requests as they pass through the [`ConnectedNetwork`] interface.
+//!
+//! Messages are serialized inside the HotShot network implementations, which do not report the
+//! serialized sizes, so we compute them here with [`bincode::serialized_size`]. This walks each
+//! message once but does not allocate a second copy of it, and it matches the size on the wire up
+//! to the small version prefix added by the network.
+
+use crate::{PubKey, SeqTypes};
+use async_trait::async_trait;
+use hotshot::traits::implementations::NetworkingMetricsValue;
+use hotshot_types::{
+    message::Message,
+    traits::{
+        metrics::{Counter, Histogram, Metrics},
+        network::{ConnectedNetwork, ConsensusIntentEvent, NetworkError},
+    },
+    BoxSyncFuture,
+};
+use std::{
+    collections::BTreeSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use versioned_binary_serialization::version::StaticVersionType;
+
+/// Metrics for a single network channel (DA or quorum).
+#[derive(Debug)]
+pub struct ChannelMetrics {
+    messages_sent: Box<dyn Counter>,
+    messages_received: Box<dyn Counter>,
+    bytes_sent: Box<dyn Counter>,
+    bytes_received: Box<dyn Counter>,
+    send_errors: Box<dyn Counter>,
+    recv_errors: Box<dyn Counter>,
+    send_latency: Box<dyn Histogram>,
+    poll_latency: Box<dyn Histogram>,
+
+    /// The standard HotShot networking metrics, shared by all channels.
+    ///
+    /// We populate the ones that make sense for a network without peer connections, so that
+    /// dashboards built around the P2P network still show something useful.
+    networking: Arc<NetworkingMetricsValue>,
+}
+
+impl ChannelMetrics {
+    /// Create metrics for the channel `channel` (e.g. "da" or "quorum").
+    ///
+    /// The metrics are created in a `networking_<channel>` subgroup of `metrics`.
+    pub fn new(
+        metrics: &dyn Metrics,
+        channel: &str,
+        networking: Arc<NetworkingMetricsValue>,
+    ) -> Self {
+        let metrics = metrics.subgroup(format!("networking_{channel}"));
+        Self {
+            messages_sent: metrics.create_counter("messages_sent".into(), None),
+            messages_received: metrics.create_counter("messages_received".into(), None),
+            bytes_sent: metrics.create_counter("bytes_sent".into(), Some("bytes".into())),
+            bytes_received: metrics.create_counter("bytes_received".into(), Some("bytes".into())),
+            send_errors: metrics.create_counter("send_errors".into(), None),
+            recv_errors: metrics.create_counter("recv_errors".into(), None),
+            send_latency: metrics.create_histogram("send_latency".into(), Some("s".into())),
+            poll_latency: metrics.create_histogram("poll_latency".into(), Some("s".into())),
+            networking,
+        }
+    }
+
+    /// Record the outcome of a send of a `bytes`-byte message which took `elapsed`.
+    ///
+    /// For the web server network, each send is a request to the web server, so `elapsed` is the
+    /// round trip time of a real request.
+    fn record_send(&self, elapsed: Duration, bytes: usize, direct: bool, ok: bool) {
+        if ok {
+            self.messages_sent.add(1);
+            self.bytes_sent.add(bytes);
+            self.send_latency.add_point(elapsed.as_secs_f64());
+            if direct {
+                self.networking.outgoing_direct_message_count.add(1);
+            } else {
+                self.networking.outgoing_broadcast_message_count.add(1);
+            }
+        } else {
+            self.send_errors.add(1);
+            self.networking.message_failed_to_send.add(1);
+        }
+    }
+
+    /// Record the messages returned by a poll of the network which took `elapsed`.
+    ///
+    /// For the web server network, `elapsed` includes the time spent waiting for new messages, so
+    /// it shows how long messages take to arrive as well as how long requests take.
+    fn record_recv(&self, elapsed: Duration, msgs: &[Message<SeqTypes>]) {
+        self.messages_received.add(msgs.len());
+        self.bytes_received
+            .add(msgs.iter().map(message_size).sum::<usize>());
+        self.poll_latency.add_point(elapsed.as_secs_f64());
+    }
+}
+
+/// The serialized size of `msg` in bytes.
+fn message_size(msg: &Message<SeqTypes>) -> usize {
+    match bincode::serialized_size(msg) {
+        Ok(size) => size as usize,
+        Err(err) => {
+            tracing::warn!("unable to compute size of message: {err:#}");
+            0
+        }
+    }
+}
+
+/// A network channel which records [`ChannelMetrics`] for all the messages passing through it.
+#[derive(Clone, Debug)]
+pub struct MeteredNetwork<N> {
+    inner: N,
+    metrics: Arc<ChannelMetrics>,
+}
+
+impl<N> MeteredNetwork<N> {
+    pub fn new(inner: N, metrics: ChannelMetrics) -> Self {
+        Self {
+            inner,
+            metrics: Arc::new(metrics),
+        }
+    }
+}
+
+#[async_trait]
+impl<N> ConnectedNetwork<Message<SeqTypes>, PubKey> for MeteredNetwork<N>
+where
+    N: ConnectedNetwork<Message<SeqTypes>, PubKey>,
+{
+    fn pause(&self) {
+        self.inner.pause()
+    }
+
+    fn resume(&self) {
+        self.inner.resume()
+    }
+
+    async fn wait_for_ready(&self) {
+        self.inner.wait_for_ready().await
+    }
+
+    async fn is_ready(&self) -> bool {
+        self.inner.is_ready().await
+    }
+
+    fn shut_down<'a, 'b>(&'a self) -> BoxSyncFuture<'b, ()>
+    where
+        'a: 'b,
+        Self: 'b,
+    {
+        self.inner.shut_down()
+    }
+
+    async fn broadcast_message<Ver: StaticVersionType + 'static>(
+        &self,
+        message: Message<SeqTypes>,
+        recipients: BTreeSet<PubKey>,
+        bind_version: Ver,
+    ) -> Result<(), NetworkError> {
+        let bytes = message_size(&message);
+        let start = Instant::now();
+        let res = self
+            .inner
+            .broadcast_message(message, recipients, bind_version)
+            .await;
+        self.metrics
+            .record_send(start.elapsed(), bytes, false, res.is_ok());
+        res
+    }
+
+    async fn da_broadcast_message<Ver: StaticVersionType + 'static>(
+        &self,
+        message: Message<SeqTypes>,
+        recipients: BTreeSet<PubKey>,
+        bind_version: Ver,
+    ) -> Result<(), NetworkError> {
+        let bytes = message_size(&message);
+        let start = Instant::now();
+        let res = self
+            .inner
+            .da_broadcast_message(message, recipients, bind_version)
+            .await;
+        self.metrics
+            .record_send(start.elapsed(), bytes, false, res.is_ok());
+        res
+    }
+
+    async fn direct_message<Ver: StaticVersionType + 'static>(
+        &self,
+        message: Message<SeqTypes>,
+        recipient: PubKey,
+        bind_version: Ver,
+    ) -> Result<(), NetworkError> {
+        let bytes = message_size(&message);
+        let start = Instant::now();
+        let res = self
+            .inner
+            .direct_message(message, recipient, bind_version)
+            .await;
+        self.metrics
+            .record_send(start.elapsed(), bytes, true, res.is_ok());
+        res
+    }
+
+    async fn recv_msgs(&self) -> Result<Vec<Message<SeqTypes>>, NetworkError> {
+        let start = Instant::now();
+        match self.inner.recv_msgs().await {
+            Ok(msgs) => {
+                self.metrics.record_recv(start.elapsed(), &msgs);
+                Ok(msgs)
+            }
+            Err(err) => {
+                self.metrics.recv_errors.add(1);
+                Err(err)
+            }
+        }
+    }
+
+    async fn inject_consensus_info(&self, event: ConsensusIntentEvent<PubKey>) {
+        self.inner.inject_consensus_info(event).await
+    }
+
+    fn update_view(&self, view: u64) {
+        self.inner.update_view(view)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{PrivKey, Transaction};
+    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use es_version::SEQUENCER_VERSION;
+    use hotshot::traits::implementations::{MasterMap, MemoryNetwork};
+    use hotshot_query_service::metrics::PrometheusMetrics;
+    use hotshot_types::{
+        data::ViewNumber,
+        message::{DataMessage, MessageKind},
+        traits::{
+            metrics::NoMetrics, node_implementation::ConsensusTime, signature_key::SignatureKey,
+        },
+    };
+
+    fn counter(metrics: &PrometheusMetrics, channel: &str, name: &str) -> usize {
+        metrics
+            .get_subgroup([format!("networking_{channel}")])
+            .unwrap()
+            .get_counter(name)
+            .unwrap()
+            .get()
+    }
+
+    fn histogram_count(metrics: &PrometheusMetrics, channel: &str, name: &str) -> usize {
+        metrics
+            .get_subgroup([format!("networking_{channel}")])
+            .unwrap()
+            .get_histogram(name)
+            .unwrap()
+            .sample_count()
+    }
+
+    fn test_message(sender: PubKey) -> Message<SeqTypes> {
+        Message {
+            sender,
+            kind: MessageKind::Data(DataMessage::SubmitTransaction(
+                Transaction::new(Default::default(), vec![1, 2, 3]),
+                ViewNumber::new(1),
+            )),
+        }
+    }
+
+    fn test_key() -> PubKey {
+        PubKey::from_private(&PrivKey::generate(&mut rand::thread_rng()))
+    }
+
+    #[test]
+    fn test_channel_metrics() {
+        setup_logging();
+        setup_backtrace();
+
+        let registry = PrometheusMetrics::default();
+        let networking = Arc::new(NetworkingMetricsValue::new(&registry));
+        let da = ChannelMetrics::new(&registry, "da", networking.clone());
+        let quorum = ChannelMetrics::new(&registry, "quorum", networking);
+
+        let msg = test_message(test_key());
+        let size = message_size(&msg);
+        assert!(size > 0);
+        da.record_send(Duration::from_millis(10), size, false, true);
+        da.record_send(Duration::from_millis(10), size, true, false);
+        quorum.record_recv(Duration::from_millis(10), &[msg.clone(), msg]);
+
+        assert_eq!(counter(&registry, "da", "messages_sent"), 1);
+        assert_eq!(counter(&registry, "da", "bytes_sent"), size);
+        assert_eq!(counter(&registry, "da", "send_errors"), 1);
+        assert_eq!(counter(&registry, "da", "messages_received"), 0);
+        assert_eq!(counter(&registry, "da", "bytes_received"), 0);
+        assert_eq!(histogram_count(&registry, "da", "send_latency"), 1);
+        assert_eq!(histogram_count(&registry, "da", "poll_latency"), 0);
+        assert_eq!(counter(&registry, "quorum", "messages_sent"), 0);
+        assert_eq!(counter(&registry, "quorum", "bytes_sent"), 0);
+        assert_eq!(counter(&registry, "quorum", "messages_received"), 2);
+        assert_eq!(counter(&registry, "quorum", "bytes_received"), 2 * size);
+        assert_eq!(histogram_count(&registry, "quorum", "poll_latency"), 1);
+    }
+
+    #[async_std::test]
+    async fn test_metered_network() {
+        setup_logging();
+        setup_backtrace();
+
+        let registry = PrometheusMetrics::default();
+        let networking = Arc::new(NetworkingMetricsValue::new(&registry));
+        let master_map = MasterMap::new();
+        let (sender, receiver) = (test_key(), test_key());
+        let metered = |key, channel| {
+            MeteredNetwork::new(
+                MemoryNetwork::new(
+                    key,
+                    NetworkingMetricsValue::new(&NoMetrics),
+                    master_map.clone(),
+                    None,
+                ),
+                ChannelMetrics::new(&registry, channel, networking.clone()),
+            )
+        };
+        let from = metered(sender, "from");
+        let to = metered(receiver, "to");
+
+        // Messages which pass through the wrapper are delivered and counted on both ends.
+        let msg = test_message(sender);
+        let size = message_size(&msg);
+        from.direct_message(msg.clone(), receiver, SEQUENCER_VERSION)
+            .await
+            .unwrap();
+        assert_eq!(to.recv_msgs().await.unwrap(), vec![msg]);
+
+        assert_eq!(counter(&registry, "from", "messages_sent"), 1);
+        assert_eq!(counter(&registry, "from", "bytes_sent"), size);
+        assert_eq!(counter(&registry, "to", "bytes_received"), size);
+        assert_eq!(histogram_count(&registry, "to", "poll_latency"), 1);
+        assert_eq!(counter(&registry, "from", "send_errors"), 0);
+        assert_eq!(counter(&registry, "to", "messages_received"), 1);
+        assert_eq!(counter(&registry, "to", "recv_errors"), 0);
+    }
+}
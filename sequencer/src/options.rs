@@ -1,20 +1,27 @@
 use crate::{api, persistence};
 use anyhow::{bail, Context};
-use clap::{error::ErrorKind, Args, FromArgMatches, Parser};
+use clap::{error::ErrorKind, Args, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use cld::ClDuration;
+use config::ConfigFile;
 use ethers::types::Address;
 use hotshot_types::light_client::StateSignKey;
 use hotshot_types::signature_key::BLSPrivKey;
+use libp2p::Multiaddr;
 use snafu::Snafu;
 use std::{
     collections::{HashMap, HashSet},
+    ffi::OsString,
     iter::once,
+    net::SocketAddr,
     path::PathBuf,
     str::FromStr,
     time::Duration,
 };
+use toml::{Table, Value};
 use url::Url;
 
+pub mod config;
+
 // This options struct is a bit unconventional. The sequencer has multiple optional modules which
 // can be added, in any combination, to the service. These include, for example, the API server.
 // Each of these modules has its own options, which are all required if the module is added but can
@@ -87,6 +94,56 @@ pub struct Options {
     )]
     pub webserver_poll_interval: Duration,
 
+    /// The type of network to use for consensus messages.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_NETWORK",
+        value_enum,
+        default_value = "web"
+    )]
+    pub network: NetworkType,
+
+    /// Local address for the libp2p network to listen on.
+    ///
+    /// Only used if NETWORK is `libp2p` or `combined`.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_LIBP2P_BIND_ADDRESS",
+        default_value = "0.0.0.0:1769"
+    )]
+    pub libp2p_bind_address: SocketAddr,
+
+    /// Address at which other nodes can reach this node over libp2p.
+    ///
+    /// This is reported to the orchestrator, which shares it with other nodes. It defaults to the
+    /// bind address, which is only suitable if this node is reachable at that address.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_LIBP2P_ADVERTISE_ADDRESS")]
+    pub libp2p_advertise_address: Option<SocketAddr>,
+
+    /// Peers to bootstrap the libp2p network from.
+    ///
+    /// Comma-separated list of multiaddrs, each ending in a `/p2p/<peer id>` component. If not
+    /// provided, the bootstrap nodes from the orchestrator's network config are used.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_LIBP2P_BOOTSTRAP_NODES",
+        value_delimiter = ','
+    )]
+    pub libp2p_bootstrap_nodes: Vec<Multiaddr>,
+
+    /// How long to wait for each step of a graceful shutdown.
+    ///
+    /// On SIGTERM or SIGINT, the node stops accepting transactions and waits up to this long for the
+    /// current view to finish, and then up to this long again for each of its storage backends to
+    /// flush and for any pending state signatures to be sent to the relay server.
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_SHUTDOWN_TIMEOUT",
+        default_value = "30s",
+        value_parser = parse_duration
+    )]
+    pub shutdown_timeout: Duration,
+
     /// Path to file containing private keys.
     ///
     /// The file should follow the .env format, with two keys:
@@ -103,7 +160,8 @@ pub struct Options {
     #[clap(
         long,
         env = "ESPRESSO_SEQUENCER_PRIVATE_STAKING_KEY",
-        conflicts_with = "key_file"
+        conflicts_with = "key_file",
+        hide_env_values = true
     )]
     pub private_staking_key: Option<BLSPrivKey>,
 
@@ -113,7 +171,8 @@ pub struct Options {
     #[clap(
         long,
         env = "ESPRESSO_SEQUENCER_PRIVATE_STATE_KEY",
-        conflicts_with = "key_file"
+        conflicts_with = "key_file",
+        hide_env_values = true
     )]
     pub private_state_key: Option<StateSignKey>,
 
@@ -137,7 +196,7 @@ pub struct Options {
     ///
     /// This is the address fees will be charged to.
     /// It must be funded with ETH in the Espresso fee ledger
-    #[clap(long, env = "ESPRESSO_SEQUENCER_ETH_MNEMONIC")]
+    #[clap(long, env = "ESPRESSO_SEQUENCER_ETH_MNEMONIC", hide_env_values = true)]
     pub eth_mnemonic: String,
 
     /// Index of a funded account derived from eth-mnemonic.
@@ -165,13 +224,106 @@ pub struct Options {
     /// Peer nodes use to fetch missing state
     #[clap(long, env = "ESPRESSO_SEQUENCER_STATE_PEERS", value_delimiter = ',')]
     pub state_peers: Vec<Url>,
+
+    /// Bootstrap from a snapshot of the decided state, verified against this query service.
+    ///
+    /// A node with no saved consensus state normally starts from genesis and fetches missing state
+    /// from its peers as needed. With this option, it instead downloads a snapshot of the latest
+    /// decided state from one of its state peers, checks the snapshot against the leaf at the same
+    /// height from this trusted query service, and starts from there.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_SNAPSHOT_TRUSTED_URL")]
+    pub snapshot_trusted_url: Option<Url>,
+
+    #[clap(flatten)]
+    pub config: config::ConfigArgs,
 }
 
 impl Options {
+    /// Parse options from the command line, the environment and a config file.
+    ///
+    /// In addition to top-level options, the config file can enable modules, with a table for each
+    /// module containing that module's options:
+    ///
+    /// ```toml
+    /// [modules.http]
+    /// port = 8080
+    ///
+    /// [modules.submit]
+    /// ```
+    ///
+    /// A module given on the command line takes options from its table in the config file as well,
+    /// with the command line taking precedence.
+    ///
+    /// Invalid options are reported and the process exits. If `--dump-config` is given, the
+    /// effective configuration is printed and the process exits.
+    pub fn parse_with_config() -> Self {
+        match Self::try_parse_with_config(std::env::args_os()) {
+            Ok((_, Some(config))) => config::dump(&config),
+            Ok((opt, None)) => opt,
+            Err(err) => err.exit(),
+        }
+    }
+
+    /// Parse options from `args`, the environment and a config file.
+    ///
+    /// If `--dump-config` is given, the effective configuration is returned along with the options.
+    pub fn try_parse_with_config(
+        args: impl IntoIterator<Item = impl Into<OsString>>,
+    ) -> Result<(Self, Option<Table>), clap::Error> {
+        let cmd = Self::command();
+        let mut args = args.into_iter().map(Into::into).collect::<Vec<_>>();
+        if let Some(mut file) = ConfigFile::from_args(&cmd, &args)? {
+            let modules = file.take_table("modules")?;
+            // Split off the raw module arguments, so that the module options are not mistaken for
+            // top-level options.
+            let raw = args.split_off(
+                args.iter()
+                    .position(|arg| arg == "--")
+                    .unwrap_or(args.len()),
+            );
+            args = file.merge(&cmd, args)?;
+            args.extend(merge_modules(&file, modules, raw)?);
+        }
+
+        let matches = cmd.clone().try_get_matches_from(args)?;
+        let opt = Self::from_arg_matches(&matches).map_err(|err| err.format(&mut cmd.clone()))?;
+        let dump = if opt.config.dump_config {
+            let mut config = config::effective_config(&cmd, &matches);
+            let modules = opt.module_config()?;
+            if !modules.is_empty() {
+                config.insert("modules".into(), Value::Table(modules));
+            }
+            Some(config)
+        } else {
+            None
+        };
+        Ok((opt, dump))
+    }
+
     pub fn modules(&self) -> Modules {
         ModuleArgs(self.modules.clone()).parse()
     }
 
+    /// The effective configuration of each module, in the format of a config file.
+    fn module_config(&self) -> Result<Table, clap::Error> {
+        let module_cmd = SequencerModule::command();
+        let mut modules = Table::new();
+        for segment in module_segments(self.modules.iter().map(OsString::from)) {
+            let Some(sub) = segment
+                .first()
+                .and_then(|name| module_cmd.find_subcommand(name))
+            else {
+                continue;
+            };
+            let matches = sub.clone().try_get_matches_from(&segment)?;
+            modules.insert(
+                sub.get_name().into(),
+                Value::Table(config::effective_config(sub, &matches)),
+            );
+        }
+        Ok(modules)
+    }
+
     pub fn private_keys(&self) -> anyhow::Result<(BLSPrivKey, StateSignKey)> {
         if let Some(path) = &self.key_file {
             let vars = dotenvy::from_path_iter(path)?.collect::<Result<HashMap<_, _>, _>>()?;
@@ -195,6 +347,18 @@ impl Options {
     }
 }
 
+/// The network types which can be selected on the command line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum NetworkType {
+    /// Communicate through the centralized DA and consensus web servers.
+    #[default]
+    Web,
+    /// Communicate directly with other nodes over a peer-to-peer network.
+    Libp2p,
+    /// Use the peer-to-peer network, falling back to the web servers.
+    Combined,
+}
+
 #[derive(Clone, Debug, Snafu)]
 pub struct ParseDurationError {
     reason: String,
@@ -208,6 +372,89 @@ pub fn parse_duration(s: &str) -> Result<Duration, ParseDurationError> {
         })
 }
 
+/// Split raw module arguments into the arguments for each module, starting with the module name.
+fn module_segments(raw: impl IntoIterator<Item = OsString>) -> Vec<Vec<OsString>> {
+    let mut segments = vec![];
+    let mut curr = vec![];
+    for arg in raw {
+        if arg == "--" {
+            segments.push(std::mem::take(&mut curr));
+        } else {
+            curr.push(arg);
+        }
+    }
+    segments.push(curr);
+    segments.retain(|segment| !segment.is_empty());
+    segments
+}
+
+/// Combine modules given on the command line in `raw` with modules from a config file.
+fn merge_modules(
+    file: &ConfigFile,
+    mut modules: Table,
+    raw: Vec<OsString>,
+) -> Result<Vec<OsString>, clap::Error> {
+    let module_cmd = SequencerModule::command();
+    let context = |name: &str| format!("`modules.{name}` in {}", file.name());
+    let table = |name: &str, value: Value| match value {
+        Value::Table(table) => Ok(table),
+        _ => Err(clap::Error::raw(
+            ErrorKind::InvalidValue,
+            format!("{} must be a table\n", context(name)),
+        )),
+    };
+
+    // Add options from the file to modules given on the command line.
+    let mut segments = module_segments(raw);
+    for segment in &mut segments {
+        let Some(sub) = module_cmd.find_subcommand(&segment[0]) else {
+            // Let the module parser report the unknown module.
+            continue;
+        };
+        let name = sub.get_name();
+        if let Some(value) = modules.remove(name) {
+            *segment = config::merge_table(
+                sub,
+                &table(name, value)?,
+                &context(name),
+                std::mem::take(segment),
+            )?;
+        }
+    }
+
+    // Add the remaining modules from the file.
+    for (name, value) in modules {
+        let Some(sub) = module_cmd.find_subcommand(&name) else {
+            return Err(clap::Error::raw(
+                ErrorKind::InvalidSubcommand,
+                format!("unknown module `{name}` in {}\n", file.name()),
+            ));
+        };
+        let table = table(&name, value)?;
+        segments.push(config::merge_table(
+            sub,
+            &table,
+            &context(&name),
+            vec![sub.get_name().into()],
+        )?);
+    }
+
+    // Modules must come after the modules they require, which come earlier in the declaration of
+    // `SequencerModule`.
+    let order = |segment: &Vec<OsString>| {
+        module_cmd
+            .get_subcommands()
+            .position(|sub| sub.get_name() == segment[0])
+            .unwrap_or(usize::MAX)
+    };
+    segments.sort_by_key(order);
+
+    Ok(segments
+        .into_iter()
+        .flat_map(|segment| once("--".into()).chain(segment))
+        .collect())
+}
+
 #[derive(Clone, Debug)]
 struct ModuleArgs(Vec<String>);
 
@@ -245,8 +492,19 @@ impl ModuleArgs {
                 SequencerModule::Query(m) => curr = m.add(&mut modules.query, &mut provided)?,
                 SequencerModule::Submit(m) => curr = m.add(&mut modules.submit, &mut provided)?,
                 SequencerModule::Status(m) => curr = m.add(&mut modules.status, &mut provided)?,
-                SequencerModule::State(m) => curr = m.add(&mut modules.state, &mut provided)?,
+                SequencerModule::State(m) => {
+                    // The state module works with either kind of storage.
+                    if modules.storage_fs.is_none() && modules.storage_sql.is_none() {
+                        return Err(clap::Error::raw(
+                            ErrorKind::MissingRequiredArgument,
+                            "module state is missing required module storage-fs or storage-sql",
+                        ));
+                    }
+                    curr = m.add(&mut modules.state, &mut provided)?
+                }
                 SequencerModule::Catchup(m) => curr = m.add(&mut modules.catchup, &mut provided)?,
+                SequencerModule::Health(m) => curr = m.add(&mut modules.health, &mut provided)?,
+                SequencerModule::Admin(m) => curr = m.add(&mut modules.admin, &mut provided)?,
                 SequencerModule::HotshotEvents(m) => {
                     curr = m.add(&mut modules.hotshot_events, &mut provided)?
                 }
@@ -280,8 +538,10 @@ module!("http", api::options::Http);
 module!("query", api::options::Query, requires: "http");
 module!("submit", api::options::Submit, requires: "http");
 module!("status", api::options::Status, requires: "http");
-module!("state", api::options::State, requires: "http", "storage-sql");
+module!("state", api::options::State, requires: "http");
 module!("catchup", api::options::Catchup, requires: "http");
+module!("health", api::options::Health, requires: "http");
+module!("admin", api::options::Admin, requires: "http");
 module!("hotshot-events", api::options::HotshotEvents, requires: "http");
 
 #[derive(Clone, Debug, Args)]
@@ -352,9 +612,18 @@ enum SequencerModule {
     ///
     /// This module requires the http module to be started.
     Catchup(Module<api::options::Catchup>),
+    /// Run the health API module.
+    ///
+    /// This module requires the http module to be started.
+    Health(Module<api::options::Health>),
+    /// Run the admin API module.
+    ///
+    /// This module requires the http module to be started.
+    Admin(Module<api::options::Admin>),
     /// Run the merklized state  API module.
     ///
-    /// This module requires the http and storage-sql modules to be started.
+    /// This module requires the http module and a storage module (storage-fs or storage-sql) to be
+    /// started.
     State(Module<api::options::State>),
     /// Run the hotshot events API module.
     ///
@@ -372,5 +641,103 @@ pub struct Modules {
     pub status: Option<api::options::Status>,
     pub state: Option<api::options::State>,
     pub catchup: Option<api::options::Catchup>,
+    pub health: Option<api::options::Health>,
+    pub admin: Option<api::options::Admin>,
     pub hotshot_events: Option<api::options::HotshotEvents>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn parse(config: &str, args: &[&str]) -> Result<(Options, Option<Table>), String> {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(config.as_bytes()).unwrap();
+        let path = file.path().display().to_string();
+        Options::try_parse_with_config(
+            ["sequencer", "--config", &path]
+                .into_iter()
+                .chain(args.iter().copied()),
+        )
+        .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_config_file_modules() {
+        let file = r#"
+            eth-mnemonic = "test test test test test test test test test test test junk"
+
+            [modules.submit]
+
+            [modules.http]
+            port = 1
+        "#;
+
+        // Modules from the file are enabled in dependency order, regardless of their order in the
+        // file.
+        let (opt, _) = parse(file, &[]).unwrap();
+        let modules = opt.modules();
+        assert_eq!(modules.http.unwrap().port, 1);
+        assert!(modules.submit.is_some());
+        assert!(modules.status.is_none());
+
+        // Modules on the command line take options from the file, unless they are overridden.
+        let (opt, _) = parse(file, &["--", "status", "--", "http", "--port", "2"]).unwrap();
+        let modules = opt.modules();
+        assert_eq!(modules.http.unwrap().port, 2);
+        assert!(modules.submit.is_some());
+        assert!(modules.status.is_some());
+
+        // The effective configuration includes modules, but not secrets.
+        let (_, dump) = parse(file, &["--dump-config"]).unwrap();
+        let dump = dump.unwrap();
+        assert_eq!(dump["eth-mnemonic"].as_str(), Some(config::REDACTED));
+        assert_eq!(dump["modules"]["http"]["port"].as_str(), Some("1"));
+        assert!(dump["modules"]["submit"].as_table().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_state_module_storage() {
+        let modules = |args: &[&str]| {
+            ModuleArgs(args.iter().map(|arg| arg.to_string()).collect())
+                .try_parse()
+                .map_err(|err| err.to_string())
+        };
+
+        let parsed = modules(&[
+            "http",
+            "--port",
+            "1",
+            "--",
+            "storage-fs",
+            "--path",
+            "storage",
+            "--",
+            "state",
+        ])
+        .unwrap();
+        assert!(parsed.state.is_some());
+        let parsed = modules(&["http", "--port", "1", "--", "storage-sql", "--", "state"]).unwrap();
+        assert!(parsed.state.is_some());
+
+        let err = modules(&["http", "--port", "1", "--", "state"]).unwrap_err();
+        assert!(err.contains("storage-fs or storage-sql"), "{err}");
+    }
+
+    #[test]
+    fn test_config_file_invalid_modules() {
+        let err = parse("[modules.nonexistent]", &[]).unwrap_err();
+        assert!(err.contains("unknown module `nonexistent`"), "{err}");
+
+        let err = parse("modules = 1", &[]).unwrap_err();
+        assert!(err.contains("must be a table"), "{err}");
+
+        let err = parse("[modules.http]\nprot = 1", &[]).unwrap_err();
+        assert!(
+            err.contains("unknown option `prot` in `modules.http`"),
+            "{err}"
+        );
+    }
+}
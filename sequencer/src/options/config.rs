@@ -0,0 +1,391 @@
+//! Configuration files.
+//!
+//! Any binary whose options are parsed with clap can also read them from a TOML file, by flattening
+//! [`ConfigArgs`] into its options and parsing them with [`parse`]. Keys in the file are the long
+//! names of command line options, as in
+//!
+//! ```toml
+//! l1-provider-url = "http://localhost:8545"
+//! state-peers = ["http://peer1:8080", "http://peer2:8080"]
+//! ```
+//!
+//! Values given on the command line take precedence over values from the environment, which take
+//! precedence over values from the file, which take precedence over built-in defaults.
+
+use clap::{
+    error::ErrorKind, parser::ValueSource, Arg, ArgAction, ArgMatches, Args, Command, Parser,
+};
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+use toml::{Table, Value};
+
+/// Placeholder for secret values in the effective configuration.
+pub const REDACTED: &str = "<redacted>";
+
+/// Options for loading and displaying the configuration.
+#[derive(Args, Clone, Debug, Default)]
+pub struct ConfigArgs {
+    /// Path to a TOML file to read options from.
+    ///
+    /// Keys in the file are the long names of command line options, for example
+    /// `l1-provider-url = "http://localhost:8545"`. Options given on the command line or in the
+    /// environment take precedence over the file.
+    #[clap(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Print the effective configuration, in the format of a config file, and exit.
+    ///
+    /// Secrets are redacted.
+    #[clap(long)]
+    pub dump_config: bool,
+}
+
+/// A config file.
+#[derive(Clone, Debug)]
+pub struct ConfigFile {
+    path: PathBuf,
+    table: Table,
+}
+
+impl ConfigFile {
+    /// Read and parse the config file at `path`.
+    pub fn load(path: &Path) -> Result<Self, clap::Error> {
+        let contents = fs::read_to_string(path).map_err(|err| {
+            error(
+                ErrorKind::Io,
+                format!("cannot read config file {}: {err}", path.display()),
+            )
+        })?;
+        let table = contents.parse::<Table>().map_err(|err| {
+            error(
+                ErrorKind::InvalidValue,
+                format!("invalid config file {}: {err}", path.display()),
+            )
+        })?;
+        Ok(Self {
+            path: path.into(),
+            table,
+        })
+    }
+
+    /// Load the config file given by the `--config` option in `args`, if there is one.
+    pub fn from_args(cmd: &Command, args: &[OsString]) -> Result<Option<Self>, clap::Error> {
+        let matches = cmd.clone().ignore_errors(true).try_get_matches_from(args)?;
+        match matches.try_get_one::<PathBuf>("config") {
+            Ok(Some(path)) => Ok(Some(Self::load(path)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// A description of this file, for error messages.
+    pub fn name(&self) -> String {
+        format!("config file {}", self.path.display())
+    }
+
+    /// Remove the table `name` from the file, so that it is not interpreted as an option.
+    pub fn take_table(&mut self, name: &str) -> Result<Table, clap::Error> {
+        match self.table.remove(name) {
+            Some(Value::Table(table)) => Ok(table),
+            Some(_) => Err(error(
+                ErrorKind::InvalidValue,
+                format!("`{name}` in {} must be a table", self.name()),
+            )),
+            None => Ok(Table::new()),
+        }
+    }
+
+    /// Add values from this file to `args`.
+    ///
+    /// `args` are command line arguments for `cmd`, starting with the program name. Values from the
+    /// file are added for each option which is not already set on the command line or in the
+    /// environment.
+    pub fn merge(&self, cmd: &Command, args: Vec<OsString>) -> Result<Vec<OsString>, clap::Error> {
+        merge_table(cmd, &self.table, &self.name(), args)
+    }
+}
+
+/// Add values from `table` to `args`.
+///
+/// This is like [`ConfigFile::merge`], for a table within a config file. `context` describes where
+/// the table came from, for error messages.
+pub fn merge_table(
+    cmd: &Command,
+    table: &Table,
+    context: &str,
+    mut args: Vec<OsString>,
+) -> Result<Vec<OsString>, clap::Error> {
+    let matches = cmd
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(&args)?;
+    let mut file_args = vec![];
+    for (key, value) in table {
+        let long = key.replace('_', "-");
+        let Some(arg) = cmd
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+        else {
+            return Err(error(
+                ErrorKind::UnknownArgument,
+                format!("unknown option `{key}` in {context}"),
+            ));
+        };
+        let id = arg.get_id().as_str();
+        if matches!(id, "config" | "dump_config") {
+            return Err(error(
+                ErrorKind::ArgumentConflict,
+                format!("`{key}` cannot be set in {context}"),
+            ));
+        }
+        if matches!(
+            matches.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+        file_args.extend(arg_values(arg, key, value, context)?);
+    }
+    // Insert the values from the file right after the program name, so that any trailing raw
+    // arguments stay at the end.
+    let at = args.len().min(1);
+    args.splice(at..at, file_args);
+    Ok(args)
+}
+
+/// Convert the value of `key` in a config file into command line arguments for `arg`.
+fn arg_values(
+    arg: &Arg,
+    key: &str,
+    value: &Value,
+    context: &str,
+) -> Result<Vec<OsString>, clap::Error> {
+    let long = arg.get_long().unwrap_or(key);
+    if !arg.get_action().takes_values() {
+        return match value {
+            Value::Boolean(true) => Ok(vec![format!("--{long}").into()]),
+            Value::Boolean(false) => Ok(vec![]),
+            _ => Err(error(
+                ErrorKind::InvalidValue,
+                format!("option `{key}` in {context} must be true or false"),
+            )),
+        };
+    }
+
+    let values = match value {
+        Value::Array(values) if matches!(arg.get_action(), ArgAction::Append) => {
+            values.iter().collect()
+        }
+        Value::Array(_) => {
+            return Err(error(
+                ErrorKind::InvalidValue,
+                format!("option `{key}` in {context} takes a single value, not a list"),
+            ))
+        }
+        value => vec![value],
+    };
+    values
+        .into_iter()
+        .map(|value| {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Integer(n) => n.to_string(),
+                Value::Float(x) => x.to_string(),
+                Value::Boolean(b) => b.to_string(),
+                _ => {
+                    return Err(error(
+                        ErrorKind::InvalidValue,
+                        format!("option `{key}` in {context} must be a string, number or boolean"),
+                    ))
+                }
+            };
+            Ok(format!("--{long}={value}").into())
+        })
+        .collect()
+}
+
+/// The effective configuration of `cmd`, in the format of a config file.
+///
+/// Options which are not set are omitted. Secrets, which are marked by hiding their environment
+/// values from the help text, are replaced with [`REDACTED`].
+pub fn effective_config(cmd: &Command, matches: &ArgMatches) -> Table {
+    let mut table = Table::new();
+    for arg in cmd.get_arguments() {
+        let id = arg.get_id().as_str();
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        if matches!(id, "config" | "dump_config" | "help" | "version")
+            || matches.value_source(id).is_none()
+        {
+            continue;
+        }
+        let value = match arg.get_action() {
+            ArgAction::SetTrue | ArgAction::SetFalse => Value::Boolean(matches.get_flag(id)),
+            _ if arg.is_hide_env_values_set() => Value::String(REDACTED.into()),
+            action => {
+                let mut values = matches
+                    .get_raw(id)
+                    .into_iter()
+                    .flatten()
+                    .map(|value| Value::String(value.to_string_lossy().into_owned()));
+                if matches!(action, ArgAction::Append) {
+                    Value::Array(values.collect())
+                } else {
+                    let Some(value) = values.next() else {
+                        continue;
+                    };
+                    value
+                }
+            }
+        };
+        table.insert(long.into(), value);
+    }
+    table
+}
+
+/// Parse options of type `T` from `args`, the environment and a config file.
+///
+/// The config file is given by the `--config` option in `args`. If `--dump-config` is given, the
+/// effective configuration is returned along with the options.
+pub fn try_parse_from<T: Parser>(
+    args: impl IntoIterator<Item = impl Into<OsString>>,
+) -> Result<(T, Option<Table>), clap::Error> {
+    let cmd = T::command();
+    let mut args = args.into_iter().map(Into::into).collect::<Vec<_>>();
+    if let Some(file) = ConfigFile::from_args(&cmd, &args)? {
+        args = file.merge(&cmd, args)?;
+    }
+    let matches = cmd.clone().try_get_matches_from(args)?;
+    let opt = T::from_arg_matches(&matches).map_err(|err| err.format(&mut cmd.clone()))?;
+    let dump = matches
+        .get_flag("dump_config")
+        .then(|| effective_config(&cmd, &matches));
+    Ok((opt, dump))
+}
+
+/// Parse options of type `T` from the command line, the environment and a config file.
+///
+/// Invalid options are reported and the process exits, as with [`Parser::parse`]. If
+/// `--dump-config` is given, the effective configuration is printed and the process exits.
+pub fn parse<T: Parser>() -> T {
+    match try_parse_from(std::env::args_os()) {
+        Ok((_, Some(config))) => dump(&config),
+        Ok((opt, None)) => opt,
+        Err(err) => err.exit(),
+    }
+}
+
+/// Print `config` and exit.
+pub fn dump(config: &Table) -> ! {
+    print!(
+        "{}",
+        toml::to_string(config).expect("config is serializable")
+    );
+    std::process::exit(0)
+}
+
+fn error(kind: ErrorKind, msg: String) -> clap::Error {
+    clap::Error::raw(kind, format!("{msg}\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[derive(Parser, Debug)]
+    struct TestOptions {
+        #[clap(long, default_value = "1")]
+        number: u64,
+
+        #[clap(long, env = "ESPRESSO_TEST_CONFIG_FROM_ENV")]
+        from_env: Option<String>,
+
+        #[clap(long, hide_env_values = true)]
+        secret: Option<String>,
+
+        #[clap(long, value_delimiter = ',')]
+        list: Vec<String>,
+
+        #[clap(long)]
+        flag: bool,
+
+        #[clap(flatten)]
+        config: ConfigArgs,
+    }
+
+    fn config_file(contents: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    fn parse(file: &NamedTempFile, args: &[&str]) -> Result<(TestOptions, Option<Table>), String> {
+        let path = file.path().display().to_string();
+        try_parse_from(
+            ["test", "--config", &path]
+                .into_iter()
+                .chain(args.iter().copied()),
+        )
+        .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_config_file() {
+        std::env::set_var("ESPRESSO_TEST_CONFIG_FROM_ENV", "env");
+        let file = config_file(
+            r#"
+            number = 2
+            from-env = "file"
+            secret = "hunter2"
+            list = ["a", "b"]
+            flag = true
+            "#,
+        );
+
+        // Values from the file override defaults, but not the environment.
+        let (opt, dump) = parse(&file, &[]).unwrap();
+        assert_eq!(opt.number, 2);
+        assert_eq!(opt.from_env.as_deref(), Some("env"));
+        assert_eq!(opt.secret.as_deref(), Some("hunter2"));
+        assert_eq!(opt.list, ["a", "b"]);
+        assert!(opt.flag);
+        assert_eq!(dump, None);
+
+        // The command line overrides the file.
+        let (opt, _) = parse(&file, &["--number", "3", "--list=c"]).unwrap();
+        assert_eq!(opt.number, 3);
+        assert_eq!(opt.list, ["c"]);
+
+        // The effective configuration includes values from all sources, but hides secrets.
+        let (_, dump) = parse(&file, &["--number", "3", "--dump-config"]).unwrap();
+        let dump = dump.unwrap();
+        assert_eq!(dump["number"].as_str(), Some("3"));
+        assert_eq!(dump["from-env"].as_str(), Some("env"));
+        assert_eq!(dump["secret"].as_str(), Some(REDACTED));
+        assert_eq!(dump["list"].as_array().unwrap().len(), 2);
+        assert_eq!(dump["flag"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn test_invalid_config_file() {
+        let err = parse(&config_file("nmber = 2"), &[]).unwrap_err();
+        assert!(err.contains("unknown option `nmber`"), "{err}");
+
+        let err = parse(&config_file("number = [1, 2]"), &[]).unwrap_err();
+        assert!(err.contains("takes a single value"), "{err}");
+
+        let err = parse(&config_file("flag = \"yes\""), &[]).unwrap_err();
+        assert!(err.contains("must be true or false"), "{err}");
+
+        let err = parse(&config_file("number = \"two\""), &[]).unwrap_err();
+        assert!(err.contains("invalid value 'two'"), "{err}");
+
+        let err = parse(&config_file("number = "), &[]).unwrap_err();
+        assert!(err.contains("invalid config file"), "{err}");
+    }
+}
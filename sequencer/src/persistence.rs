@@ -8,21 +8,37 @@
 //! an extension that node operators can opt into. This module defines the minimum level of
 //! persistence which is _required_ to run a node.
 
-use crate::{ElectionConfig, Leaf, NodeState, PubKey, SeqTypes, ValidatedState, ViewNumber};
+use crate::{
+    api::endpoints::StateSnapshot, ElectionConfig, Leaf, NodeState, Payload, PubKey, SeqTypes,
+    ValidatedState, ViewNumber,
+};
 use anyhow::Context;
-use async_std::sync::Arc;
+use async_std::sync::{Arc, RwLock};
 use async_trait::async_trait;
 use commit::Committable;
+use derivative::Derivative;
 use hotshot::{
     traits::ValidatedState as _,
     types::{Event, EventType},
     HotShotInitializer,
 };
 use hotshot_types::{
-    event::LeafInfo, simple_certificate::QuorumCertificate,
-    traits::node_implementation::ConsensusTime,
+    consensus::CommitmentMap,
+    data::{DAProposal, VidDisperseShare},
+    event::{HotShotAction, LeafInfo},
+    message::Proposal,
+    simple_certificate::QuorumCertificate,
+    traits::{node_implementation::ConsensusTime, storage::Storage, BlockPayload},
+    utils::View,
+    vid::VidSchemeType,
+};
+use jf_primitives::vid::VidScheme;
+use std::{
+    cmp::max,
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
 };
-use std::cmp::max;
 
 pub mod fs;
 pub mod no_storage;
@@ -69,6 +85,81 @@ pub trait SequencerPersistence: Send + Sync + 'static {
     /// Load the validated state after block `height`, if available.
     async fn load_validated_state(&self, height: u64) -> anyhow::Result<ValidatedState>;
 
+    /// Save this node's VID share for a view.
+    async fn append_vid(
+        &mut self,
+        proposal: &Proposal<SeqTypes, VidDisperseShare<SeqTypes>>,
+    ) -> anyhow::Result<()>;
+
+    /// Save a DA proposal received (or sent) by this node.
+    async fn append_da(
+        &mut self,
+        proposal: &Proposal<SeqTypes, DAProposal<SeqTypes>>,
+    ) -> anyhow::Result<()>;
+
+    /// Load the VID share saved with [`append_vid`](Self::append_vid) for `view`, if any.
+    async fn load_vid_share(
+        &self,
+        view: ViewNumber,
+    ) -> anyhow::Result<Option<Proposal<SeqTypes, VidDisperseShare<SeqTypes>>>>;
+
+    /// Load the DA proposal saved with [`append_da`](Self::append_da) for `view`, if any.
+    async fn load_da_proposal(
+        &self,
+        view: ViewNumber,
+    ) -> anyhow::Result<Option<Proposal<SeqTypes, DAProposal<SeqTypes>>>>;
+
+    /// Delete VID shares and DA proposals for all views up to and including `view`.
+    ///
+    /// This is called once `view` is decided, after which consensus no longer needs this data.
+    async fn collect_garbage(&mut self, view: ViewNumber) -> anyhow::Result<()>;
+
+    /// Save the highest known quorum certificate.
+    ///
+    /// If the view of the new QC is not greater than the view of the previously saved QC, storage
+    /// is not updated.
+    async fn update_high_qc(&mut self, high_qc: &QuorumCertificate<SeqTypes>)
+        -> anyhow::Result<()>;
+
+    /// Load the QC saved with [`update_high_qc`](Self::update_high_qc).
+    async fn load_high_qc(&self) -> anyhow::Result<Option<QuorumCertificate<SeqTypes>>>;
+
+    /// Save the undecided leaves and states, replacing any previously saved undecided state.
+    async fn update_undecided_state(
+        &mut self,
+        leaves: &CommitmentMap<Leaf>,
+        state: &BTreeMap<ViewNumber, View<SeqTypes>>,
+    ) -> anyhow::Result<()>;
+
+    /// Load the state saved with [`update_undecided_state`](Self::update_undecided_state).
+    async fn load_undecided_state(
+        &self,
+    ) -> anyhow::Result<Option<(CommitmentMap<Leaf>, BTreeMap<ViewNumber, View<SeqTypes>>)>>;
+
+    /// Make sure all data written so far is durably stored.
+    ///
+    /// This is called when the node shuts down. The default implementation does nothing, which is
+    /// appropriate for storage which is already durable after each write.
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Record an action taken by consensus.
+    ///
+    /// HotShot calls this before it sends a vote, so by saving the voted view here we ensure that
+    /// even if we crash right after voting, we will not vote again in the same view when we
+    /// restart.
+    async fn record_action(
+        &mut self,
+        view: ViewNumber,
+        action: HotShotAction,
+    ) -> anyhow::Result<()> {
+        if matches!(action, HotShotAction::Vote) {
+            self.save_voted_view(view).await?;
+        }
+        Ok(())
+    }
+
     /// Load the latest known consensus state.
     ///
     /// Returns an initializer to resume HotShot from the latest saved state (or start from genesis,
@@ -118,40 +209,156 @@ pub trait SequencerPersistence: Send + Sync + 'static {
             }
         };
 
+        let high_qc = match self.load_high_qc().await.context("loading high QC")? {
+            Some(high_qc) => {
+                tracing::info!(view = ?high_qc.view_number, "starting from saved high QC");
+                high_qc
+            }
+            None => {
+                tracing::info!("no saved high QC, starting from genesis QC");
+                QuorumCertificate::genesis()
+            }
+        };
+        let (undecided_leaves, undecided_state) = self
+            .load_undecided_state()
+            .await
+            .context("loading undecided state")?
+            .unwrap_or_default();
+
         // We start from the maximum view between `highest_voted_view` and `leaf.view_number`. This
         // prevents double votes from starting in a view in which we had already voted before the
         // restart, and prevents unnecessary catchup from starting in a view earlier than the anchor
         // leaf.
         let view = max(highest_voted_view, leaf.get_view_number());
-        tracing::info!(?leaf, ?view, "loaded consensus state");
+        tracing::info!(
+            ?leaf,
+            ?view,
+            num_undecided_leaves = undecided_leaves.len(),
+            num_undecided_views = undecided_state.len(),
+            "loaded consensus state"
+        );
 
         Ok(HotShotInitializer::from_reload(
             leaf,
             state,
             validated_state,
             view,
-            QuorumCertificate::genesis(),
-            Default::default(),
+            high_qc,
+            undecided_leaves.into_values().collect(),
+            undecided_state,
+        ))
+    }
+
+    /// Bootstrap consensus from a state snapshot fetched from peers.
+    ///
+    /// This is used instead of [`load_consensus_state`](Self::load_consensus_state) when a node
+    /// with no saved state joins a running network. The snapshot must already have been verified
+    /// against a trusted query service, which provides the QC for its leaf. Consensus starts from
+    /// the snapshot leaf, with that QC as the high QC.
+    ///
+    /// The leaf and QC are saved, so that if the node restarts it resumes from the snapshot leaf
+    /// rather than from genesis. The snapshot state itself is not saved: after a restart, the node
+    /// fetches the state it needs from its peers, as it would after any other restart.
+    async fn bootstrap_consensus_state(
+        &mut self,
+        state: NodeState,
+        snapshot: StateSnapshot,
+    ) -> anyhow::Result<HotShotInitializer<SeqTypes>> {
+        let StateSnapshot {
+            leaf,
+            state: validated_state,
+            qc,
+        } = snapshot;
+        let high_qc = qc.context("snapshot has no QC for its leaf")?;
+        self.save_anchor_leaf(&leaf)
+            .await
+            .context("saving snapshot leaf")?;
+        self.update_high_qc(&high_qc)
+            .await
+            .context("saving snapshot QC")?;
+
+        let view = leaf.get_view_number();
+        tracing::info!(?leaf, ?view, "starting from state snapshot");
+        Ok(HotShotInitializer::from_reload(
+            leaf,
+            state,
+            Some(Arc::new(validated_state)),
+            view,
+            high_qc,
+            vec![],
             Default::default(),
         ))
     }
+}
+
+/// HotShot consensus storage, backed by the node's [`SequencerPersistence`].
+///
+/// This is a cheap, clonable handle. HotShot writes to it as consensus progresses, while the node's
+/// event handler uses the same underlying persistence to save decided state.
+#[derive(Clone, Derivative)]
+#[derivative(Debug)]
+pub struct PersistentStorage {
+    #[derivative(Debug = "ignore")]
+    persistence: Arc<RwLock<dyn SequencerPersistence>>,
+    failures: Arc<WriteFailures>,
+}
+
+/// Failures writing to persistent storage, tracked for health reporting.
+#[derive(Debug, Default)]
+struct WriteFailures {
+    count: AtomicU64,
+    last: std::sync::Mutex<Option<Instant>>,
+}
+
+impl PersistentStorage {
+    pub fn new(persistence: impl SequencerPersistence) -> Self {
+        Self {
+            persistence: Arc::new(RwLock::new(persistence)),
+            failures: Default::default(),
+        }
+    }
+
+    /// The underlying persistence.
+    pub fn persistence(&self) -> &Arc<RwLock<dyn SequencerPersistence>> {
+        &self.persistence
+    }
 
     /// Update storage based on an event from consensus.
-    async fn handle_event(&mut self, event: &Event<SeqTypes>) {
+    ///
+    /// All the writes relevant to `event` are attempted, even if some of them fail. Failures are
+    /// logged and counted, but otherwise ignored. The persistence is locked separately for each
+    /// write, so HotShot is not blocked from saving proposals while we handle the event.
+    pub async fn handle_event(&self, event: &Event<SeqTypes>) {
         match &event.event {
             EventType::Decide { leaf_chain, .. } => {
                 if let Some(LeafInfo { leaf, .. }) = leaf_chain.first() {
-                    if let Err(err) = self.save_anchor_leaf(leaf).await {
+                    let res = self.persistence.write().await.save_anchor_leaf(leaf).await;
+                    if let Err(err) = self.record(res) {
                         tracing::error!(
                             ?leaf,
                             hash = %leaf.commit(),
                             "Failed to save anchor leaf. When restarting make sure anchor leaf is at least as recent as this leaf. {err:#}",
                         );
                     }
+
+                    let view = leaf.get_view_number();
+                    let res = self.persistence.write().await.collect_garbage(view).await;
+                    if let Err(err) = self.record(res) {
+                        tracing::warn!(
+                            ?view,
+                            "Failed to garbage collect consensus storage. {err:#}"
+                        );
+                    }
                 }
             }
             EventType::ViewFinished { view_number, .. } => {
-                if let Err(err) = self.save_voted_view(*view_number).await {
+                let res = self
+                    .persistence
+                    .write()
+                    .await
+                    .save_voted_view(*view_number)
+                    .await;
+                if let Err(err) = self.record(res) {
                     tracing::error!(
                         ?view_number,
                         "Failed to save highest view. When restarting, make sure view number is at least as recent as this. {err:#}",
@@ -161,6 +368,133 @@ pub trait SequencerPersistence: Send + Sync + 'static {
             _ => {}
         }
     }
+
+    /// Fill in data missing from a decide event using the VID shares and DA proposals saved by
+    /// this node.
+    ///
+    /// HotShot only keeps block payloads and VID shares in memory, so after a restart, leaves
+    /// decided shortly afterwards may be missing data which this node received and saved before the
+    /// restart. This restores that data, so that it is not lost to the rest of the node. It must be
+    /// called before the event is passed to [`handle_event`](Self::handle_event), which garbage
+    /// collects the saved data.
+    pub async fn restore_decided_data(&self, event: &mut Event<SeqTypes>) {
+        let EventType::Decide { leaf_chain, .. } = &mut event.event else {
+            return;
+        };
+        if leaf_chain
+            .iter()
+            .all(|info| info.leaf.get_block_payload().is_some() && info.vid_share.is_some())
+        {
+            return;
+        }
+
+        let persistence = self.persistence.read().await;
+        for info in Arc::make_mut(leaf_chain) {
+            let view = info.leaf.get_view_number();
+            if info.vid_share.is_none() {
+                match persistence.load_vid_share(view).await {
+                    Ok(share) => info.vid_share = share.map(|share| share.data),
+                    Err(err) => tracing::warn!(?view, "failed to load saved VID share: {err:#}"),
+                }
+            }
+            if info.leaf.get_block_payload().is_some() {
+                continue;
+            }
+            // We need the number of storage nodes, from the VID common data, to check the saved
+            // payload against the payload commitment in the leaf.
+            let Some(share) = &info.vid_share else {
+                continue;
+            };
+            let proposal = match persistence.load_da_proposal(view).await {
+                Ok(Some(proposal)) => proposal,
+                Ok(None) => continue,
+                Err(err) => {
+                    tracing::warn!(?view, "failed to load saved DA proposal: {err:#}");
+                    continue;
+                }
+            };
+            let payload = Payload::from_bytes(
+                proposal.data.encoded_transactions.iter().copied(),
+                &proposal.data.metadata,
+            );
+            let num_storage_nodes = VidSchemeType::get_num_storage_nodes(&share.common);
+            match info.leaf.fill_block_payload(payload, num_storage_nodes) {
+                Ok(()) => tracing::info!(?view, "restored block payload from storage"),
+                Err(err) => tracing::warn!(?view, "saved DA proposal does not match leaf: {err}"),
+            }
+        }
+    }
+
+    /// The number of writes which have failed since this node started.
+    pub fn write_failures(&self) -> u64 {
+        self.failures.count.load(Ordering::Relaxed)
+    }
+
+    /// The time of the most recent failed write, if any.
+    pub fn last_write_failure(&self) -> Option<Instant> {
+        *self.failures.last.lock().unwrap()
+    }
+
+    fn record<T>(&self, res: anyhow::Result<T>) -> anyhow::Result<T> {
+        if res.is_err() {
+            self.failures.count.fetch_add(1, Ordering::Relaxed);
+            *self.failures.last.lock().unwrap() = Some(Instant::now());
+        }
+        res
+    }
+}
+
+#[async_trait]
+impl Storage<SeqTypes> for PersistentStorage {
+    async fn append_vid(
+        &self,
+        proposal: &Proposal<SeqTypes, VidDisperseShare<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        let res = self.persistence.write().await.append_vid(proposal).await;
+        self.record(res)
+    }
+
+    async fn append_da(
+        &self,
+        proposal: &Proposal<SeqTypes, DAProposal<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        let res = self.persistence.write().await.append_da(proposal).await;
+        self.record(res)
+    }
+
+    async fn record_action(&self, view: ViewNumber, action: HotShotAction) -> anyhow::Result<()> {
+        let res = self
+            .persistence
+            .write()
+            .await
+            .record_action(view, action)
+            .await;
+        self.record(res)
+    }
+
+    async fn update_high_qc(&self, high_qc: QuorumCertificate<SeqTypes>) -> anyhow::Result<()> {
+        let res = self
+            .persistence
+            .write()
+            .await
+            .update_high_qc(&high_qc)
+            .await;
+        self.record(res)
+    }
+
+    async fn update_undecided_state(
+        &self,
+        leaves: CommitmentMap<Leaf>,
+        state: BTreeMap<ViewNumber, View<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        let res = self
+            .persistence
+            .write()
+            .await
+            .update_undecided_state(&leaves, &state)
+            .await;
+        self.record(res)
+    }
 }
 
 #[cfg(test)]
@@ -182,6 +516,13 @@ mod persistence_tests {
     use super::*;
     use crate::NodeState;
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use hotshot::{traits::BlockPayload, types::SignatureKey};
+    use hotshot_types::{
+        traits::block_contents::{BlockHeader, GENESIS_VID_NUM_STORAGE_NODES},
+        utils::ViewInner,
+        vid::vid_scheme,
+    };
+    use jf_primitives::vid::VidScheme;
     use testing::TestablePersistence;
 
     #[async_std::test]
@@ -211,6 +552,257 @@ mod persistence_tests {
         assert_eq!(storage.load_anchor_leaf().await.unwrap().unwrap(), leaf2);
     }
 
+    #[async_std::test]
+    pub async fn test_append_and_collect_garbage<P: TestablePersistence>() {
+        setup_logging();
+        setup_backtrace();
+
+        let tmp = P::tmp_storage().await;
+        let mut storage = P::connect(&tmp).await;
+
+        // Initially, there is no saved data.
+        let view = ViewNumber::new(1);
+        assert_eq!(storage.load_vid_share(view).await.unwrap(), None);
+        assert_eq!(storage.load_da_proposal(view).await.unwrap(), None);
+
+        // Store a VID share and a DA proposal for a few views.
+        let leaf = Leaf::genesis(&NodeState::mock());
+        let payload = leaf.get_block_payload().unwrap();
+        let mut vid = vid_scheme(2);
+        let disperse = vid
+            .disperse(payload.encode().unwrap().collect::<Vec<_>>())
+            .unwrap();
+        let (pub_key, priv_key) = PubKey::generated_from_seed_indexed([0; 32], 1);
+        let signature = PubKey::sign(&priv_key, &[]).unwrap();
+        let mut vids = vec![];
+        let mut das = vec![];
+        for i in 1..=3 {
+            let view = ViewNumber::new(i);
+            let vid = VidDisperseShare::<SeqTypes> {
+                view_number: view,
+                payload_commitment: disperse.commit,
+                share: disperse.shares[0].clone(),
+                common: disperse.common.clone(),
+                recipient_key: pub_key,
+            };
+            let vid = Proposal {
+                data: vid,
+                signature: signature.clone(),
+                _pd: Default::default(),
+            };
+            let da = Proposal {
+                data: DAProposal::<SeqTypes> {
+                    encoded_transactions: payload.encode().unwrap().collect(),
+                    metadata: leaf.get_block_header().metadata().clone(),
+                    view_number: view,
+                },
+                signature: signature.clone(),
+                _pd: Default::default(),
+            };
+            storage.append_vid(&vid).await.unwrap();
+            storage.append_da(&da).await.unwrap();
+            vids.push(vid);
+            das.push(da);
+        }
+        for (i, (vid, da)) in vids.iter().zip(&das).enumerate() {
+            let view = ViewNumber::new(i as u64 + 1);
+            assert_eq!(storage.load_vid_share(view).await.unwrap().unwrap(), *vid);
+            assert_eq!(storage.load_da_proposal(view).await.unwrap().unwrap(), *da);
+        }
+
+        // Collect garbage up to and including view 2. Only data for view 3 should remain.
+        storage.collect_garbage(ViewNumber::new(2)).await.unwrap();
+        for i in 1..=2 {
+            let view = ViewNumber::new(i);
+            assert_eq!(storage.load_vid_share(view).await.unwrap(), None);
+            assert_eq!(storage.load_da_proposal(view).await.unwrap(), None);
+        }
+        let view = ViewNumber::new(3);
+        assert_eq!(
+            storage.load_vid_share(view).await.unwrap().unwrap(),
+            vids[2]
+        );
+        assert_eq!(
+            storage.load_da_proposal(view).await.unwrap().unwrap(),
+            das[2]
+        );
+    }
+
+    #[async_std::test]
+    pub async fn test_restore_decided_data<P: TestablePersistence>() {
+        setup_logging();
+        setup_backtrace();
+
+        let tmp = P::tmp_storage().await;
+        let storage = PersistentStorage::new(P::connect(&tmp).await);
+
+        // Save a VID share and a DA proposal for the genesis view, as consensus would.
+        let node_state = NodeState::mock();
+        let leaf = Leaf::genesis(&node_state);
+        let view = leaf.get_view_number();
+        let payload = leaf.get_block_payload().unwrap();
+        let mut vid = vid_scheme(GENESIS_VID_NUM_STORAGE_NODES);
+        let disperse = vid
+            .disperse(payload.encode().unwrap().collect::<Vec<_>>())
+            .unwrap();
+        let (pub_key, priv_key) = PubKey::generated_from_seed_indexed([0; 32], 1);
+        let signature = PubKey::sign(&priv_key, &[]).unwrap();
+        let share = VidDisperseShare::<SeqTypes> {
+            view_number: view,
+            payload_commitment: disperse.commit,
+            share: disperse.shares[0].clone(),
+            common: disperse.common.clone(),
+            recipient_key: pub_key,
+        };
+        storage
+            .append_vid(&Proposal {
+                data: share.clone(),
+                signature: signature.clone(),
+                _pd: Default::default(),
+            })
+            .await
+            .unwrap();
+        storage
+            .append_da(&Proposal {
+                data: DAProposal::<SeqTypes> {
+                    encoded_transactions: payload.encode().unwrap().collect(),
+                    metadata: leaf.get_block_header().metadata().clone(),
+                    view_number: view,
+                },
+                signature,
+                _pd: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        // Simulate a decide after a restart, where consensus has lost the payload and VID share.
+        let mut stripped = serde_json::to_value(&leaf).unwrap();
+        stripped["block_payload"] = serde_json::Value::Null;
+        let stripped: Leaf = serde_json::from_value(stripped).unwrap();
+        assert_eq!(stripped.get_block_payload(), None);
+        let mut event = Event {
+            view_number: view,
+            event: EventType::Decide {
+                leaf_chain: Arc::new(vec![LeafInfo::new(
+                    stripped,
+                    Arc::new(ValidatedState::genesis(&node_state).0),
+                    None,
+                    None,
+                )]),
+                qc: Arc::new(QuorumCertificate::genesis()),
+                block_size: None,
+            },
+        };
+
+        // The missing data is restored from storage.
+        storage.restore_decided_data(&mut event).await;
+        let EventType::Decide { leaf_chain, .. } = &event.event else {
+            unreachable!();
+        };
+        assert_eq!(leaf_chain[0].leaf, leaf);
+        assert_eq!(leaf_chain[0].vid_share, Some(share));
+
+        // Handling the event collects the saved data, which is no longer needed.
+        storage.handle_event(&event).await;
+        assert_eq!(storage.write_failures(), 0);
+        let persistence = storage.persistence().read().await;
+        assert_eq!(persistence.load_vid_share(view).await.unwrap(), None);
+        assert_eq!(persistence.load_da_proposal(view).await.unwrap(), None);
+        assert_eq!(persistence.load_anchor_leaf().await.unwrap(), Some(leaf));
+    }
+
+    #[async_std::test]
+    pub async fn test_record_action<P: TestablePersistence>() {
+        setup_logging();
+        setup_backtrace();
+
+        let tmp = P::tmp_storage().await;
+        let mut storage = P::connect(&tmp).await;
+
+        // Recording a vote saves the voted view.
+        let view = ViewNumber::new(5);
+        storage
+            .record_action(view, HotShotAction::Vote)
+            .await
+            .unwrap();
+        assert_eq!(storage.load_voted_view().await.unwrap().unwrap(), view);
+
+        // Other actions do not.
+        storage
+            .record_action(view + 1, HotShotAction::Propose)
+            .await
+            .unwrap();
+        assert_eq!(storage.load_voted_view().await.unwrap().unwrap(), view);
+    }
+
+    #[async_std::test]
+    pub async fn test_high_qc<P: TestablePersistence>() {
+        setup_logging();
+        setup_backtrace();
+
+        let tmp = P::tmp_storage().await;
+        let mut storage = P::connect(&tmp).await;
+
+        // Initially, there is no saved QC.
+        assert_eq!(storage.load_high_qc().await.unwrap(), None);
+
+        // Store a QC.
+        let qc1 = QuorumCertificate::<SeqTypes>::genesis();
+        storage.update_high_qc(&qc1).await.unwrap();
+        assert_eq!(storage.load_high_qc().await.unwrap().unwrap(), qc1);
+
+        // Store a newer QC, make sure storage gets updated.
+        let mut qc2 = qc1.clone();
+        qc2.view_number = qc1.view_number + 1;
+        storage.update_high_qc(&qc2).await.unwrap();
+        assert_eq!(storage.load_high_qc().await.unwrap().unwrap(), qc2);
+
+        // Store an old QC, make sure storage is unchanged.
+        storage.update_high_qc(&qc1).await.unwrap();
+        assert_eq!(storage.load_high_qc().await.unwrap().unwrap(), qc2);
+    }
+
+    #[async_std::test]
+    pub async fn test_undecided_state<P: TestablePersistence>() {
+        setup_logging();
+        setup_backtrace();
+
+        let tmp = P::tmp_storage().await;
+        let mut storage = P::connect(&tmp).await;
+
+        // Initially, there is no saved state.
+        assert!(storage.load_undecided_state().await.unwrap().is_none());
+
+        // Store some undecided state.
+        let leaf = Leaf::genesis(&NodeState::mock());
+        let leaves = CommitmentMap::from_iter([(leaf.commit(), leaf.clone())]);
+        let state = BTreeMap::from_iter([(
+            ViewNumber::new(1),
+            View {
+                view_inner: ViewInner::Failed,
+            },
+        )]);
+        storage
+            .update_undecided_state(&leaves, &state)
+            .await
+            .unwrap();
+        let (loaded_leaves, loaded_state) = storage.load_undecided_state().await.unwrap().unwrap();
+        assert_eq!(loaded_leaves, leaves);
+        assert_eq!(
+            loaded_state.keys().collect::<Vec<_>>(),
+            state.keys().collect::<Vec<_>>()
+        );
+
+        // New state replaces the old state.
+        storage
+            .update_undecided_state(&Default::default(), &Default::default())
+            .await
+            .unwrap();
+        let (loaded_leaves, loaded_state) = storage.load_undecided_state().await.unwrap().unwrap();
+        assert!(loaded_leaves.is_empty());
+        assert!(loaded_state.is_empty());
+    }
+
     #[async_std::test]
     pub async fn test_voted_view<P: TestablePersistence>() {
         setup_logging();
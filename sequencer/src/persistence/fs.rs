@@ -1,13 +1,22 @@
 use super::{NetworkConfig, PersistenceOptions, SequencerPersistence};
-use crate::{Leaf, ValidatedState, ViewNumber};
+use crate::{Leaf, SeqTypes, ValidatedState, ViewNumber};
 use anyhow::{anyhow, bail, Context};
 use async_trait::async_trait;
 use clap::Parser;
-use hotshot_types::traits::node_implementation::ConsensusTime;
+use hotshot_types::{
+    consensus::CommitmentMap,
+    data::{DAProposal, VidDisperseShare},
+    message::Proposal,
+    simple_certificate::QuorumCertificate,
+    traits::node_implementation::ConsensusTime,
+    utils::View,
+};
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
+    collections::BTreeMap,
     fs::{self, File, OpenOptions},
     io::{Read, Seek, SeekFrom, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 /// Options for file system backed persistence.
@@ -53,6 +62,95 @@ impl Persistence {
     fn anchor_leaf_path(&self) -> PathBuf {
         self.0.join("anchor_leaf")
     }
+
+    fn high_qc_path(&self) -> PathBuf {
+        self.0.join("high_qc")
+    }
+
+    fn undecided_state_path(&self) -> PathBuf {
+        self.0.join("undecided_state")
+    }
+
+    /// Directory containing one file per view with this node's VID share for that view.
+    fn vid_dir_path(&self) -> PathBuf {
+        self.0.join("vid")
+    }
+
+    /// Directory containing one file per view with the DA proposal for that view.
+    fn da_dir_path(&self) -> PathBuf {
+        self.0.join("da")
+    }
+}
+
+/// Serialize `data` to the file at `path`, replacing its previous contents.
+fn save_file(path: &Path, data: &impl Serialize) -> anyhow::Result<()> {
+    let bytes = bincode::serialize(data).context("serialize")?;
+    // Write to a temporary file and then rename it, so that a crash during the write does not
+    // leave a corrupt file behind.
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, bytes).context("write")?;
+    fs::rename(&tmp_path, path).context("rename")?;
+    Ok(())
+}
+
+/// Load data saved with [`save_file`], if any.
+fn load_file<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Option<T>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let bytes = fs::read(path).context("read")?;
+    Ok(Some(bincode::deserialize(&bytes).context("deserialize")?))
+}
+
+/// Save `data` in `dir`, in a file named after `view`.
+fn save_view_file(dir: &Path, view: ViewNumber, data: &impl Serialize) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).context("create directory")?;
+    save_file(&dir.join(view.get_u64().to_string()), data)
+}
+
+/// Load data saved with [`save_view_file`], if any.
+fn load_view_file<T: DeserializeOwned>(dir: &Path, view: ViewNumber) -> anyhow::Result<Option<T>> {
+    load_file(&dir.join(view.get_u64().to_string()))
+}
+
+/// Delete all files in `dir` for views up to and including `view`.
+fn delete_view_files(dir: &Path, view: ViewNumber) -> anyhow::Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir).context("read directory")? {
+        let path = entry.context("read directory entry")?.path();
+        // Skip anything that isn't named after a view, such as temporary files.
+        let Some(file_view) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        if file_view <= view.get_u64() {
+            fs::remove_file(&path).with_context(|| format!("remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Sync every file in `dir` to disk, along with the directory itself.
+fn sync_dir(dir: &Path) -> anyhow::Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir).context("read directory")? {
+        let path = entry.context("read directory entry")?.path();
+        if path.is_file() {
+            File::open(&path)
+                .and_then(|file| file.sync_all())
+                .with_context(|| format!("sync {}", path.display()))?;
+        }
+    }
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .with_context(|| format!("sync {}", dir.display()))
 }
 
 #[async_trait]
@@ -150,6 +248,81 @@ impl SequencerPersistence for Persistence {
     async fn load_validated_state(&self, _height: u64) -> anyhow::Result<ValidatedState> {
         bail!("state persistence not implemented");
     }
+
+    async fn append_vid(
+        &mut self,
+        proposal: &Proposal<SeqTypes, VidDisperseShare<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        save_view_file(&self.vid_dir_path(), proposal.data.view_number, proposal)
+            .context("saving VID share")
+    }
+
+    async fn append_da(
+        &mut self,
+        proposal: &Proposal<SeqTypes, DAProposal<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        save_view_file(&self.da_dir_path(), proposal.data.view_number, proposal)
+            .context("saving DA proposal")
+    }
+
+    async fn load_vid_share(
+        &self,
+        view: ViewNumber,
+    ) -> anyhow::Result<Option<Proposal<SeqTypes, VidDisperseShare<SeqTypes>>>> {
+        load_view_file(&self.vid_dir_path(), view).context("loading VID share")
+    }
+
+    async fn load_da_proposal(
+        &self,
+        view: ViewNumber,
+    ) -> anyhow::Result<Option<Proposal<SeqTypes, DAProposal<SeqTypes>>>> {
+        load_view_file(&self.da_dir_path(), view).context("loading DA proposal")
+    }
+
+    async fn collect_garbage(&mut self, view: ViewNumber) -> anyhow::Result<()> {
+        delete_view_files(&self.vid_dir_path(), view).context("deleting VID shares")?;
+        delete_view_files(&self.da_dir_path(), view).context("deleting DA proposals")?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        sync_dir(&self.vid_dir_path()).context("syncing VID shares")?;
+        sync_dir(&self.da_dir_path()).context("syncing DA proposals")?;
+        sync_dir(&self.0).context("syncing consensus state")?;
+        Ok(())
+    }
+
+    async fn update_high_qc(
+        &mut self,
+        high_qc: &QuorumCertificate<SeqTypes>,
+    ) -> anyhow::Result<()> {
+        // As with the voted view, this check is not atomic with respect to the subsequent write,
+        // but we are the only writer and we have a mutable reference.
+        if let Some(prev) = self.load_high_qc().await? {
+            if prev.view_number >= high_qc.view_number {
+                return Ok(());
+            }
+        }
+        save_file(&self.high_qc_path(), high_qc).context("saving high QC")
+    }
+
+    async fn load_high_qc(&self) -> anyhow::Result<Option<QuorumCertificate<SeqTypes>>> {
+        load_file(&self.high_qc_path()).context("loading high QC")
+    }
+
+    async fn update_undecided_state(
+        &mut self,
+        leaves: &CommitmentMap<Leaf>,
+        state: &BTreeMap<ViewNumber, View<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        save_file(&self.undecided_state_path(), &(leaves, state)).context("saving undecided state")
+    }
+
+    async fn load_undecided_state(
+        &self,
+    ) -> anyhow::Result<Option<(CommitmentMap<Leaf>, BTreeMap<ViewNumber, View<SeqTypes>>)>> {
+        load_file(&self.undecided_state_path()).context("loading undecided state")
+    }
 }
 
 #[cfg(test)]
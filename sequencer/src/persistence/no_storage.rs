@@ -2,9 +2,17 @@
 #![cfg(any(test, feature = "testing"))]
 
 use super::{NetworkConfig, PersistenceOptions, SequencerPersistence};
-use crate::{Leaf, ValidatedState, ViewNumber};
+use crate::{Leaf, SeqTypes, ValidatedState, ViewNumber};
 use anyhow::bail;
 use async_trait::async_trait;
+use hotshot_types::{
+    consensus::CommitmentMap,
+    data::{DAProposal, VidDisperseShare},
+    message::Proposal,
+    simple_certificate::QuorumCertificate,
+    utils::View,
+};
+use std::collections::BTreeMap;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Options;
@@ -53,4 +61,58 @@ impl SequencerPersistence for NoStorage {
     async fn load_validated_state(&self, _height: u64) -> anyhow::Result<ValidatedState> {
         bail!("state persistence not implemented");
     }
+
+    async fn append_vid(
+        &mut self,
+        _proposal: &Proposal<SeqTypes, VidDisperseShare<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn append_da(
+        &mut self,
+        _proposal: &Proposal<SeqTypes, DAProposal<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn load_vid_share(
+        &self,
+        _view: ViewNumber,
+    ) -> anyhow::Result<Option<Proposal<SeqTypes, VidDisperseShare<SeqTypes>>>> {
+        Ok(None)
+    }
+
+    async fn load_da_proposal(
+        &self,
+        _view: ViewNumber,
+    ) -> anyhow::Result<Option<Proposal<SeqTypes, DAProposal<SeqTypes>>>> {
+        Ok(None)
+    }
+
+    async fn collect_garbage(&mut self, _view: ViewNumber) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn update_high_qc(&mut self, _: &QuorumCertificate<SeqTypes>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn load_high_qc(&self) -> anyhow::Result<Option<QuorumCertificate<SeqTypes>>> {
+        Ok(None)
+    }
+
+    async fn update_undecided_state(
+        &mut self,
+        _: &CommitmentMap<Leaf>,
+        _: &BTreeMap<ViewNumber, View<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn load_undecided_state(
+        &self,
+    ) -> anyhow::Result<Option<(CommitmentMap<Leaf>, BTreeMap<ViewNumber, View<SeqTypes>>)>> {
+        Ok(None)
+    }
 }
@@ -1,6 +1,7 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::bail;
+use async_std::task::sleep;
 use async_trait::async_trait;
 use clap::Parser;
 use hotshot_query_service::data_source::{
@@ -10,10 +11,27 @@ use hotshot_query_service::data_source::{
     },
     VersionedDataSource,
 };
-use hotshot_types::traits::node_implementation::ConsensusTime;
+use hotshot_query_service::merklized_state::MerklizedState;
+use hotshot_types::traits::{
+    metrics::{Counter, Gauge, Histogram, Metrics},
+    node_implementation::ConsensusTime,
+};
+use serde::{de::DeserializeOwned, Serialize};
 
 use super::{NetworkConfig, PersistenceOptions, SequencerPersistence};
-use crate::{options::parse_duration, Leaf, ValidatedState, ViewNumber};
+use crate::{
+    options::parse_duration,
+    state::{BlockMerkleTree, FeeMerkleTree},
+    Leaf, SeqTypes, ValidatedState, ViewNumber,
+};
+use hotshot_types::{
+    consensus::CommitmentMap,
+    data::{DAProposal, VidDisperseShare},
+    message::Proposal,
+    simple_certificate::QuorumCertificate,
+    utils::View,
+};
+use std::collections::BTreeMap;
 
 /// Options for Postgres-backed persistence.
 #[derive(Parser, Clone, Debug, Default)]
@@ -47,7 +65,11 @@ pub struct Options {
     pub user: Option<String>,
 
     /// Password for Postgres user.
-    #[clap(long, env = "ESPRESSO_SEQUENCER_POSTGRES_PASSWORD")]
+    #[clap(
+        long,
+        env = "ESPRESSO_SEQUENCER_POSTGRES_PASSWORD",
+        hide_env_values = true
+    )]
     pub password: Option<String>,
 
     /// Use TLS for an encrypted connection to the database.
@@ -62,6 +84,7 @@ pub struct Options {
     /// - batch_size: 1000
     /// - max_usage: 80%
     /// - interval: 1 hour
+    /// - state_retention: 300000 blocks
     #[clap(long, env = "ESPRESSO_SEQUENCER_POSTGRES_PRUNE")]
     pub prune: bool,
 
@@ -107,6 +130,13 @@ impl TryFrom<Options> for Config {
     }
 }
 
+impl Options {
+    /// Parameters for pruning old Merkle tree state, if pruning is enabled.
+    pub fn merkle_pruner_cfg(&self) -> Option<MerklePrunerCfg> {
+        self.prune.then(|| MerklePrunerCfg::from(&self.pruning))
+    }
+}
+
 /// Pruning parameters.
 #[derive(Parser, Clone, Debug, Default)]
 pub struct PruningOptions {
@@ -154,6 +184,14 @@ pub struct PruningOptions {
         value_parser = parse_duration,
     )]
     interval: Option<Duration>,
+
+    /// Number of recent blocks for which to keep the fee and block Merkle tree state.
+    ///
+    /// Versions of Merkle tree nodes which are not needed to serve state at any of these blocks are
+    /// deleted. The state at older blocks is no longer available. The Merkle tree state is only
+    /// stored, and so only pruned, when the query module is enabled with Postgres storage.
+    #[clap(long, env = "ESPRESSO_SEQUENCER_PRUNER_STATE_RETENTION")]
+    state_retention: Option<u64>,
 }
 
 impl From<PruningOptions> for PrunerCfg {
@@ -181,6 +219,292 @@ impl From<PruningOptions> for PrunerCfg {
     }
 }
 
+/// Parameters for pruning old Merkle tree state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MerklePrunerCfg {
+    /// Number of recent blocks for which to keep state.
+    pub retention: u64,
+    /// Maximum number of Merkle tree nodes to delete in a single transaction.
+    pub batch_size: u64,
+    /// Interval for running the pruner.
+    pub interval: Duration,
+}
+
+impl Default for MerklePrunerCfg {
+    fn default() -> Self {
+        Self {
+            retention: 300_000,
+            batch_size: 1000,
+            interval: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl From<&PruningOptions> for MerklePrunerCfg {
+    fn from(opt: &PruningOptions) -> Self {
+        let mut cfg = Self::default();
+        if let Some(retention) = opt.state_retention {
+            cfg.retention = retention;
+        }
+        if let Some(batch) = opt.batch_size {
+            cfg.batch_size = batch;
+        }
+        if let Some(interval) = opt.interval {
+            cfg.interval = interval;
+        }
+        cfg
+    }
+}
+
+/// Metrics for the Merkle tree pruner.
+#[derive(Debug)]
+struct MerklePrunerMetrics {
+    fee_nodes_pruned: Box<dyn Counter>,
+    block_nodes_pruned: Box<dyn Counter>,
+    hashes_pruned: Box<dyn Counter>,
+    cutoff_height: Box<dyn Gauge>,
+    run_duration: Box<dyn Histogram>,
+    errors: Box<dyn Counter>,
+}
+
+impl MerklePrunerMetrics {
+    fn new(metrics: &dyn Metrics) -> Self {
+        let metrics = metrics.subgroup("merkle_pruner".into());
+        Self {
+            fee_nodes_pruned: metrics.create_counter("fee_nodes_pruned".into(), None),
+            block_nodes_pruned: metrics.create_counter("block_nodes_pruned".into(), None),
+            hashes_pruned: metrics.create_counter("hashes_pruned".into(), None),
+            cutoff_height: metrics.create_gauge("cutoff_height".into(), None),
+            run_duration: metrics.create_histogram("run_duration".into(), Some("s".into())),
+            errors: metrics.create_counter("errors".into(), None),
+        }
+    }
+}
+
+/// Pruner for the fee and block Merkle trees.
+///
+/// The Merkle tree tables keep a version of each node for every block at which it changed (the
+/// `created` column), and the state at a given block is read by taking the latest version of each
+/// node created at or before that block. The pruner deletes every version which has been superseded
+/// by another version created at or before the start of the retention window. This keeps the
+/// latest state and everything needed to serve the state at any block in the window. Reading the
+/// state at a block before the window afterwards either gives the correct result or fails; it
+/// never gives a wrong result. Hashes which are no longer referenced by any node are deleted too.
+///
+/// The Merkle tree state is only stored in the database by the query service, so the pruner only
+/// runs when the query module is enabled with Postgres storage.
+#[derive(Debug)]
+pub struct MerklePruner {
+    storage: Persistence,
+    cfg: MerklePrunerCfg,
+    metrics: MerklePrunerMetrics,
+}
+
+impl MerklePruner {
+    pub fn new(storage: Persistence, cfg: MerklePrunerCfg, metrics: &dyn Metrics) -> Self {
+        Self {
+            storage,
+            cfg,
+            metrics: MerklePrunerMetrics::new(metrics),
+        }
+    }
+
+    /// Run the pruner every [`interval`](MerklePrunerCfg::interval), forever.
+    pub async fn run(mut self) {
+        loop {
+            sleep(self.cfg.interval).await;
+            if let Err(err) = self.prune().await {
+                tracing::warn!("error pruning Merkle tree state: {err:#}");
+                self.metrics.errors.add(1);
+            }
+        }
+    }
+
+    /// Delete Merkle tree state which is older than the retention window.
+    ///
+    /// Returns the number of node versions deleted.
+    pub async fn prune(&mut self) -> anyhow::Result<u64> {
+        let start = Instant::now();
+
+        // The block Merkle tree changes at every block, so its latest version tells us the latest
+        // block for which we have state.
+        let height: Option<i64> = self
+            .storage
+            .query_opt_static(&format!(
+                "SELECT max(created) AS height FROM {}",
+                BlockMerkleTree::state_type()
+            ))
+            .await?
+            .and_then(|row| row.get("height"));
+        let Some(cutoff) = height
+            .map(|height| height - self.cfg.retention as i64)
+            .filter(|cutoff| *cutoff > 0)
+        else {
+            tracing::debug!(?height, "no Merkle tree state to prune");
+            return Ok(0);
+        };
+        tracing::info!(cutoff, "pruning Merkle tree state");
+        self.metrics.cutoff_height.set(cutoff as usize);
+
+        let mut total = 0;
+        for (table, counter) in [
+            (FeeMerkleTree::state_type(), &self.metrics.fee_nodes_pruned),
+            (
+                BlockMerkleTree::state_type(),
+                &self.metrics.block_nodes_pruned,
+            ),
+        ] {
+            // Find the version of each node which is current at the cutoff once, rather than in
+            // every batch. Everything older than these versions is unreachable.
+            create_prune_keep_set(&mut self.storage, table, cutoff).await?;
+            loop {
+                let pruned =
+                    prune_merkle_nodes(&mut self.storage, table, self.cfg.batch_size).await?;
+                tracing::debug!(table, pruned, "pruned Merkle tree nodes");
+                counter.add(pruned as usize);
+                total += pruned;
+                if pruned < self.cfg.batch_size {
+                    break;
+                }
+            }
+        }
+        drop_prune_keep_set(&mut self.storage).await?;
+
+        // Deleting node versions may leave behind hashes which are no longer referenced by any
+        // node.
+        loop {
+            let pruned = prune_orphaned_hashes(&mut self.storage, self.cfg.batch_size).await?;
+            tracing::debug!(pruned, "pruned orphaned hashes");
+            self.metrics.hashes_pruned.add(pruned as usize);
+            if pruned < self.cfg.batch_size {
+                break;
+            }
+        }
+
+        self.metrics
+            .run_duration
+            .add_point(start.elapsed().as_secs_f64());
+        tracing::info!(cutoff, total, "pruned Merkle tree state");
+        Ok(total)
+    }
+}
+
+/// Temporary table holding, for each node position, the latest version created at or before the
+/// pruning cutoff.
+const PRUNE_KEEP_SET: &str = "merkle_prune_keep";
+
+/// Create [`PRUNE_KEEP_SET`] holding the versions of each node in `table` which are current at
+/// `cutoff`.
+///
+/// The table is local to the database session, so it is not visible to any other connection.
+async fn create_prune_keep_set(
+    storage: &mut Persistence,
+    table: &str,
+    cutoff: i64,
+) -> anyhow::Result<()> {
+    drop_prune_keep_set(storage).await?;
+    storage
+        .transaction()
+        .await?
+        .execute(
+            &format!(
+                "CREATE TEMP TABLE {PRUNE_KEEP_SET} AS
+                   SELECT pos, max(created) AS created FROM {table}
+                    WHERE created <= $1
+                    GROUP BY pos"
+            ),
+            [cutoff],
+        )
+        .await?;
+    storage
+        .transaction()
+        .await?
+        .execute(
+            &format!("ALTER TABLE {PRUNE_KEEP_SET} ADD PRIMARY KEY (pos)"),
+            [] as [i64; 0],
+        )
+        .await?;
+    storage.commit().await?;
+    Ok(())
+}
+
+async fn drop_prune_keep_set(storage: &mut Persistence) -> anyhow::Result<()> {
+    storage
+        .transaction()
+        .await?
+        .execute(
+            &format!("DROP TABLE IF EXISTS {PRUNE_KEEP_SET}"),
+            [] as [i64; 0],
+        )
+        .await?;
+    storage.commit().await?;
+    Ok(())
+}
+
+/// Delete up to `limit` versions of nodes in the Merkle tree `table` which have been superseded by
+/// the version in [`PRUNE_KEEP_SET`].
+async fn prune_merkle_nodes(
+    storage: &mut Persistence,
+    table: &str,
+    limit: u64,
+) -> anyhow::Result<u64> {
+    let stmt = format!(
+        "DELETE FROM {table} WHERE (pos, created) IN (
+            SELECT t.pos, t.created FROM {table} AS t
+              JOIN {PRUNE_KEEP_SET} AS latest
+                ON t.pos = latest.pos AND t.created < latest.created
+             LIMIT $1
+        )"
+    );
+    let pruned = storage
+        .transaction()
+        .await?
+        .execute(&stmt, [limit as i64])
+        .await?;
+    storage.commit().await?;
+    Ok(pruned)
+}
+
+/// Delete up to `limit` hashes which are not referenced by any node in either Merkle tree, either
+/// as the hash of the node itself or of one of its children.
+///
+/// Nothing stops a new node from referencing an existing hash in its `children`, so the hash and
+/// Merkle tree tables are locked against writes for the duration of each batch. This serializes
+/// pruning with state updates: a concurrent update either commits before the batch, in which case
+/// its references are seen, or waits until the batch is done, in which case it re-inserts any hash
+/// it needs.
+async fn prune_orphaned_hashes(storage: &mut Persistence, limit: u64) -> anyhow::Result<u64> {
+    let fee = FeeMerkleTree::state_type();
+    let block = BlockMerkleTree::state_type();
+    storage
+        .transaction()
+        .await?
+        .execute(
+            &format!("LOCK TABLE hash, {fee}, {block} IN SHARE ROW EXCLUSIVE MODE"),
+            [] as [i64; 0],
+        )
+        .await?;
+    // `children @> ARRAY[id]` rather than `id = ANY(children)`, so that the GIN indexes on
+    // `children` are used.
+    let stmt = format!(
+        "DELETE FROM hash WHERE id IN (
+            SELECT h.id FROM hash AS h
+             WHERE NOT EXISTS (SELECT 1 FROM {fee} WHERE hash_id = h.id)
+               AND NOT EXISTS (SELECT 1 FROM {block} WHERE hash_id = h.id)
+               AND NOT EXISTS (SELECT 1 FROM {fee} WHERE children @> ARRAY[h.id])
+               AND NOT EXISTS (SELECT 1 FROM {block} WHERE children @> ARRAY[h.id])
+             LIMIT $1
+        )"
+    );
+    let pruned = storage
+        .transaction()
+        .await?
+        .execute(&stmt, [limit as i64])
+        .await?;
+    storage.commit().await?;
+    Ok(pruned)
+}
+
 #[async_trait]
 impl PersistenceOptions for Options {
     type Persistence = Persistence;
@@ -287,6 +611,165 @@ impl SequencerPersistence for Persistence {
     async fn load_validated_state(&self, _height: u64) -> anyhow::Result<ValidatedState> {
         bail!("state persistence not implemented");
     }
+
+    async fn append_vid(
+        &mut self,
+        proposal: &Proposal<SeqTypes, VidDisperseShare<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        save_view_data(self, "vid_share", proposal.data.view_number, proposal).await
+    }
+
+    async fn append_da(
+        &mut self,
+        proposal: &Proposal<SeqTypes, DAProposal<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        save_view_data(self, "da_proposal", proposal.data.view_number, proposal).await
+    }
+
+    async fn load_vid_share(
+        &self,
+        view: ViewNumber,
+    ) -> anyhow::Result<Option<Proposal<SeqTypes, VidDisperseShare<SeqTypes>>>> {
+        load_view_data(self, "vid_share", view).await
+    }
+
+    async fn load_da_proposal(
+        &self,
+        view: ViewNumber,
+    ) -> anyhow::Result<Option<Proposal<SeqTypes, DAProposal<SeqTypes>>>> {
+        load_view_data(self, "da_proposal", view).await
+    }
+
+    async fn collect_garbage(&mut self, view: ViewNumber) -> anyhow::Result<()> {
+        let view = view.get_u64() as i64;
+        self.transaction()
+            .await?
+            .execute("DELETE FROM vid_share WHERE view <= $1", [view])
+            .await?;
+        self.transaction()
+            .await?
+            .execute("DELETE FROM da_proposal WHERE view <= $1", [view])
+            .await?;
+        self.commit().await?;
+        Ok(())
+    }
+
+    async fn update_high_qc(
+        &mut self,
+        high_qc: &QuorumCertificate<SeqTypes>,
+    ) -> anyhow::Result<()> {
+        let stmt = "
+            INSERT INTO high_qc (id, view, data) VALUES (0, $1, $2)
+            ON CONFLICT (id) DO UPDATE SET (view, data) = ROW (
+                GREATEST(high_qc.view, excluded.view),
+                CASE
+                    WHEN excluded.view > high_qc.view THEN excluded.data
+                    ELSE high_qc.data
+                END
+            )
+        ";
+        let bytes = bincode::serialize(high_qc)?;
+        self.transaction()
+            .await?
+            .execute_one_with_retries(
+                stmt,
+                [
+                    sql_param(&(high_qc.view_number.get_u64() as i64)),
+                    sql_param(&bytes),
+                ],
+            )
+            .await?;
+        self.commit().await?;
+        Ok(())
+    }
+
+    async fn load_high_qc(&self) -> anyhow::Result<Option<QuorumCertificate<SeqTypes>>> {
+        self.query_opt_static("SELECT data FROM high_qc WHERE id = 0")
+            .await?
+            .map(|row| {
+                let bytes: Vec<u8> = row.get("data");
+                Ok(bincode::deserialize(&bytes)?)
+            })
+            .transpose()
+    }
+
+    async fn update_undecided_state(
+        &mut self,
+        leaves: &CommitmentMap<Leaf>,
+        state: &BTreeMap<ViewNumber, View<SeqTypes>>,
+    ) -> anyhow::Result<()> {
+        let stmt = "
+            INSERT INTO undecided_state (id, leaves, state) VALUES (0, $1, $2)
+            ON CONFLICT (id) DO UPDATE SET (leaves, state) = ROW (excluded.leaves, excluded.state)
+        ";
+        let leaves_bytes = bincode::serialize(leaves)?;
+        let state_bytes = bincode::serialize(state)?;
+        self.transaction()
+            .await?
+            .execute_one_with_retries(stmt, [sql_param(&leaves_bytes), sql_param(&state_bytes)])
+            .await?;
+        self.commit().await?;
+        Ok(())
+    }
+
+    async fn load_undecided_state(
+        &self,
+    ) -> anyhow::Result<Option<(CommitmentMap<Leaf>, BTreeMap<ViewNumber, View<SeqTypes>>)>> {
+        self.query_opt_static("SELECT leaves, state FROM undecided_state WHERE id = 0")
+            .await?
+            .map(|row| {
+                let leaves: Vec<u8> = row.get("leaves");
+                let state: Vec<u8> = row.get("state");
+                Ok((
+                    bincode::deserialize(&leaves)?,
+                    bincode::deserialize(&state)?,
+                ))
+            })
+            .transpose()
+    }
+}
+
+/// Save serialized data for a view in `table`, replacing any existing data for that view.
+async fn save_view_data(
+    storage: &mut Persistence,
+    table: &str,
+    view: ViewNumber,
+    data: &impl Serialize,
+) -> anyhow::Result<()> {
+    let stmt = format!(
+        "INSERT INTO {table} (view, data) VALUES ($1, $2)
+         ON CONFLICT (view) DO UPDATE SET data = excluded.data"
+    );
+    let bytes = bincode::serialize(data)?;
+    storage
+        .transaction()
+        .await?
+        .execute_one_with_retries(
+            &stmt,
+            [sql_param(&(view.get_u64() as i64)), sql_param(&bytes)],
+        )
+        .await?;
+    storage.commit().await?;
+    Ok(())
+}
+
+/// Load data saved with [`save_view_data`], if any.
+async fn load_view_data<T: DeserializeOwned>(
+    storage: &Persistence,
+    table: &str,
+    view: ViewNumber,
+) -> anyhow::Result<Option<T>> {
+    storage
+        .query_opt(
+            &format!("SELECT data FROM {table} WHERE view = $1"),
+            [view.get_u64() as i64],
+        )
+        .await?
+        .map(|row| {
+            let bytes: Vec<u8> = row.get("data");
+            Ok(bincode::deserialize(&bytes)?)
+        })
+        .transpose()
 }
 
 fn sql_param<T: ToSql + Sync>(param: &T) -> &(dyn ToSql + Sync) {
@@ -331,3 +814,208 @@ mod generic_tests {
 
     instantiate_persistence_tests!(Persistence);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        api::{data_source::SequencerDataSource, sql::DataSource},
+        state::{FeeAccount, FeeAmount},
+        NodeState,
+    };
+    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use commit::Committable;
+    use ethers::types::Address;
+    use futures::TryStreamExt;
+    use hotshot_query_service::data_source::storage::sql::testing::TmpDb;
+    use hotshot_types::traits::metrics::NoMetrics;
+    use jf_primitives::merkle_tree::{
+        AppendableMerkleTreeScheme, MerkleTreeScheme, ToTraversalPath, UniversalMerkleTreeScheme,
+    };
+    use std::ops::RangeInclusive;
+
+    /// For each block in `heights`, the version of each node which is read for the state at that
+    /// block.
+    async fn snapshots(
+        storage: &Persistence,
+        table: &str,
+        heights: RangeInclusive<i64>,
+    ) -> Vec<Vec<(String, i64)>> {
+        let stmt = format!(
+            "SELECT pos::text AS pos, max(created) AS created FROM {table}
+              WHERE created <= $1
+              GROUP BY pos
+              ORDER BY pos"
+        );
+        let mut snapshots = vec![];
+        for height in heights {
+            let rows = storage
+                .query(&stmt, [height])
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            snapshots.push(
+                rows.into_iter()
+                    .map(|row| (row.get("pos"), row.get("created")))
+                    .collect(),
+            );
+        }
+        snapshots
+    }
+
+    async fn count(storage: &Persistence, table: &str) -> i64 {
+        storage
+            .query_opt_static(&format!("SELECT count(*) AS count FROM {table}"))
+            .await
+            .unwrap()
+            .unwrap()
+            .get("count")
+    }
+
+    /// The number of references from nodes in `table` to hashes which do not exist.
+    async fn dangling_hashes(storage: &Persistence, table: &str) -> i64 {
+        storage
+            .query_opt_static(&format!(
+                "SELECT count(*) AS count FROM {table} AS t
+                  CROSS JOIN LATERAL unnest(array_append(t.children, t.hash_id)) AS c (id)
+                  WHERE NOT EXISTS (SELECT 1 FROM hash WHERE hash.id = c.id)"
+            ))
+            .await
+            .unwrap()
+            .unwrap()
+            .get("count")
+    }
+
+    #[async_std::test]
+    async fn test_merkle_pruner() {
+        setup_logging();
+        setup_backtrace();
+
+        let db = TmpDb::init().await;
+        let opt = Options {
+            port: Some(db.port()),
+            host: Some(db.host()),
+            user: Some("postgres".into()),
+            password: Some("password".into()),
+            ..Default::default()
+        };
+
+        // Store 10 blocks worth of state, updating one of two fee accounts in each block, so that
+        // most nodes have several versions.
+        let mut ds = DataSource::create(opt.clone(), Default::default(), false)
+            .await
+            .unwrap();
+        let mut state = ValidatedState::default();
+        let header = Leaf::genesis(&NodeState::mock())
+            .get_block_header()
+            .commit();
+        let accounts =
+            [Address::from_low_u64_be(1), Address::from_low_u64_be(2)].map(FeeAccount::from);
+        for height in 1..=10u64 {
+            let account = accounts[height as usize % 2];
+            state
+                .fee_merkle_tree
+                .update(account, FeeAmount::from(height))
+                .unwrap();
+            let (_, proof) = state
+                .fee_merkle_tree
+                .universal_lookup(account)
+                .expect_ok()
+                .unwrap();
+            let path = <FeeAccount as ToTraversalPath<typenum::U256>>::to_traversal_path(
+                &account,
+                state.fee_merkle_tree.height(),
+            );
+            ds.store_state::<FeeMerkleTree>(proof.proof, path, height)
+                .await
+                .unwrap();
+
+            state.block_merkle_tree.push(header).unwrap();
+            let (_, proof) = state
+                .block_merkle_tree
+                .lookup(height - 1)
+                .expect_ok()
+                .unwrap();
+            let path = <u64 as ToTraversalPath<typenum::U3>>::to_traversal_path(
+                &(height - 1),
+                state.block_merkle_tree.height(),
+            );
+            ds.store_state::<BlockMerkleTree>(proof.proof, path, height)
+                .await
+                .unwrap();
+        }
+        ds.commit().await.unwrap();
+
+        let storage = opt.clone().create().await.unwrap();
+        let tables = [FeeMerkleTree::state_type(), BlockMerkleTree::state_type()];
+        let mut before = vec![];
+        for table in tables {
+            before.push((
+                count(&storage, table).await,
+                snapshots(&storage, table, 1..=10).await,
+            ));
+        }
+        let hashes_before = count(&storage, "hash").await;
+
+        // Keep the last 4 blocks, so the state is available from block 6 onwards.
+        let cfg = MerklePrunerCfg {
+            retention: 4,
+            batch_size: 5,
+            ..Default::default()
+        };
+        let mut pruner = MerklePruner::new(opt.create().await.unwrap(), cfg, &NoMetrics);
+        let pruned = pruner.prune().await.unwrap();
+        assert!(pruned > 0);
+
+        let mut total = 0;
+        for (table, (count_before, snapshots_before)) in tables.into_iter().zip(before) {
+            let count_after = count(&storage, table).await;
+            tracing::info!(table, count_before, count_after, "pruned");
+            assert!(count_after < count_before);
+            total += count_before - count_after;
+
+            let snapshots_after = snapshots(&storage, table, 1..=10).await;
+            for (height, (before, after)) in
+                (1..=10).zip(snapshots_before.into_iter().zip(snapshots_after))
+            {
+                if height >= 6 {
+                    // Every node read for the state in the retention window is still there.
+                    assert_eq!(after, before, "state at block {height} changed");
+                } else {
+                    // Before the window, each node which is still there is the same version that
+                    // was read before pruning. Missing nodes make reads fail, but no read ever
+                    // sees a different version of a node.
+                    let before = before.into_iter().collect::<BTreeMap<_, _>>();
+                    for (pos, created) in after {
+                        assert_eq!(
+                            before.get(&pos),
+                            Some(&created),
+                            "node {pos} at block {height} changed"
+                        );
+                    }
+                }
+            }
+        }
+        assert_eq!(total as u64, pruned);
+
+        // Hashes of pruned nodes are deleted, and every remaining node still refers to hashes which
+        // exist.
+        assert!(count(&storage, "hash").await < hashes_before);
+        for table in tables {
+            assert_eq!(dangling_hashes(&storage, table).await, 0);
+        }
+
+        // Pruning again does nothing, until new blocks arrive.
+        assert_eq!(pruner.prune().await.unwrap(), 0);
+
+        // The pruner does nothing if there are fewer blocks than the retention window.
+        let cfg = MerklePrunerCfg {
+            retention: 100,
+            ..Default::default()
+        };
+        let mut pruner = MerklePruner::new(storage, cfg, &NoMetrics);
+        assert_eq!(pruner.prune().await.unwrap(), 0);
+    }
+}
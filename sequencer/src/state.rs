@@ -238,30 +238,42 @@ fn charge_fee(
     }
 }
 
-/// Validate builder account by verifying signature and charging the account.
-fn validate_and_charge_builder(
-    fee_merkle_tree: &mut FeeMerkleTree,
-    delta: &mut Delta,
-    proposed_header: &Header,
-) -> anyhow::Result<()> {
+/// Verify that a header is signed by the builder account which pays its fee.
+pub fn verify_builder_signature(header: &Header) -> anyhow::Result<()> {
     // Beware of Malice!
-    let builder_signature = proposed_header
+    let builder_signature = header
         .builder_signature
         .ok_or_else(|| anyhow::anyhow!("Builder signature not found"))?;
 
-    let fee_info = proposed_header.fee_info;
-    // verify signature
     anyhow::ensure!(
         builder_signature
             .verify(
-                RecoveryMessage::Hash(types::H256(proposed_header.commit().into())),
-                fee_info.account.address()
+                RecoveryMessage::Hash(types::H256(header.commit().into())),
+                header.fee_info.account.address()
             )
             .is_ok(),
         "Invalid Builder Signature"
     );
+    Ok(())
+}
+
+/// Validate builder account by verifying signature and charging the account.
+fn validate_and_charge_builder(
+    fee_merkle_tree: &mut FeeMerkleTree,
+    delta: &mut Delta,
+    proposed_header: &Header,
+) -> anyhow::Result<()> {
+    verify_builder_signature(proposed_header)?;
+    charge_builder_fee(fee_merkle_tree, delta, proposed_header)
+}
 
-    // charge the fee to the builder
+/// Charge the fee for a header to the builder account, without checking the builder signature.
+pub fn charge_builder_fee(
+    fee_merkle_tree: &mut FeeMerkleTree,
+    delta: &mut Delta,
+    header: &Header,
+) -> anyhow::Result<()> {
+    let fee_info = header.fee_info;
     if charge_fee(fee_merkle_tree, fee_info).is_err() {
         bail!("Insufficient funds")
     };
@@ -274,36 +286,26 @@ fn validate_and_charge_builder(
 ///
 /// It assumes that all state required to validate and apply the header
 /// is available in the `validated_state`.
-fn validate_and_apply_header(
+pub fn validate_and_apply_header(
     validated_state: &mut ValidatedState,
     delta: &mut Delta,
     parent_leaf: &Leaf,
     proposed_header: &Header,
     l1_deposits: Vec<FeeInfo>,
-) -> Result<(), BlockError> {
+) -> anyhow::Result<()> {
     // validate proposed header against parent
-    match validate_and_apply_proposal(
+    validate_and_apply_proposal(
         validated_state,
         delta,
         parent_leaf,
         proposed_header,
         l1_deposits,
-    ) {
-        // Note that currently only block state is updated.
-        Ok(validated_state) => validated_state,
-        Err(e) => {
-            tracing::warn!("Invalid Proposal: {}", e);
-            return Err(BlockError::InvalidBlockHeader);
-        }
-    };
+    )
+    .context("Invalid Proposal")?;
 
     // Validate builder by verifying signature and charging account
-    if let Err(e) =
-        validate_and_charge_builder(&mut validated_state.fee_merkle_tree, delta, proposed_header)
-    {
-        tracing::warn!("Invalid Builder: {}", e);
-        return Err(BlockError::InvalidBlockHeader);
-    };
+    validate_and_charge_builder(&mut validated_state.fee_merkle_tree, delta, proposed_header)
+        .context("Invalid Builder")?;
 
     Ok(())
 }
@@ -396,13 +398,16 @@ impl HotShotState<SeqTypes> for ValidatedState {
         let mut delta = Delta::default();
 
         // Lastly validate and apply the header
-        validate_and_apply_header(
+        if let Err(err) = validate_and_apply_header(
             &mut validated_state,
             &mut delta,
             parent_leaf,
             proposed_header,
             l1_deposits,
-        )?;
+        ) {
+            tracing::warn!("{err:#}");
+            return Err(BlockError::InvalidBlockHeader);
+        }
 
         Ok((validated_state, delta))
     }
@@ -1,6 +1,7 @@
 //! Utilities for generating and storing the most recent light client state signatures.
 
 use crate::{Leaf, SeqTypes, StateKeyPair};
+use anyhow::bail;
 use ark_ff::PrimeField;
 use ark_serialize::CanonicalSerialize;
 use async_std::sync::RwLock;
@@ -49,6 +50,12 @@ pub struct StateSigner<Ver: StaticVersionType> {
 
     /// The state relay server url
     relay_server_client: Option<Client<ServerError, Ver>>,
+
+    /// Whether the most recent attempt to post a signature to the relay server succeeded.
+    relay_server_reachable: RwLock<Option<bool>>,
+
+    /// The most recent signature, if we failed to post it to the relay server.
+    pending: RwLock<Option<StateSignatureRequestBody>>,
 }
 
 impl<Ver: StaticVersionType> StateSigner<Ver> {
@@ -58,6 +65,8 @@ impl<Ver: StaticVersionType> StateSigner<Ver> {
             stake_table_comm,
             signatures: Default::default(),
             relay_server_client: Default::default(),
+            relay_server_reachable: Default::default(),
+            pending: Default::default(),
         }
     }
 
@@ -88,14 +97,11 @@ impl<Ver: StaticVersionType> StateSigner<Ver> {
                         state,
                         signature,
                     };
-                    if let Err(error) = client
-                        .post::<()>("api/state")
-                        .body_binary(&request_body)
-                        .unwrap()
-                        .send()
-                        .await
-                    {
+                    if let Err(error) = self.post(client, &request_body).await {
                         tracing::warn!("Error posting signature to the relay server: {:?}", error);
+                        *self.pending.write().await = Some(request_body);
+                    } else {
+                        *self.pending.write().await = None;
                     }
                 }
             }
@@ -105,6 +111,43 @@ impl<Ver: StaticVersionType> StateSigner<Ver> {
         }
     }
 
+    /// Retry posting the most recent signature, if we failed to post it to the relay server.
+    pub async fn post_pending(&self) -> anyhow::Result<()> {
+        let Some(client) = &self.relay_server_client else {
+            return Ok(());
+        };
+        let Some(request_body) = self.pending.write().await.take() else {
+            return Ok(());
+        };
+        if let Err(error) = self.post(client, &request_body).await {
+            *self.pending.write().await = Some(request_body);
+            bail!("Error posting signature to the relay server: {error:?}");
+        }
+        Ok(())
+    }
+
+    async fn post(
+        &self,
+        client: &Client<ServerError, Ver>,
+        request_body: &StateSignatureRequestBody,
+    ) -> Result<(), ServerError> {
+        let res = client
+            .post::<()>("api/state")
+            .body_binary(request_body)
+            .unwrap()
+            .send()
+            .await;
+        *self.relay_server_reachable.write().await = Some(res.is_ok());
+        res
+    }
+
+    /// Whether the relay server was reachable the last time we posted a signature to it.
+    ///
+    /// Returns `None` if there is no relay server or we have not yet tried to post to it.
+    pub async fn relay_server_reachable(&self) -> Option<bool> {
+        *self.relay_server_reachable.read().await
+    }
+
     /// Return a signature of a light client state at given height.
     pub async fn get_state_signature(&self, height: u64) -> Option<StateSignatureRequestBody> {
         let pool_guard = self.signatures.read().await;